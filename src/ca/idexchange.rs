@@ -20,7 +20,7 @@ use crate::ca::idcert::IdCert;
 use crate::repository::x509::{Time, ValidationError};
 use crate::uri;
 use crate::xml;
-use crate::xml::decode::{Error as XmlError, Name};
+use crate::xml::decode::{Error as XmlError, Limits, Name};
 
 // Constants for the RFC 8183 XML
 const VERSION: &str = "1";
@@ -365,6 +365,16 @@ impl ChildRequest {
         Self::validate_at(reader, Time::now())
     }
 
+    /// Parses and validates the message while enforcing XML limits.
+    ///
+    /// If the message exceeds any of the given limits, parsing fails with
+    /// the error specific to the limit.
+    pub fn validate_with_limits<R: io::BufRead>(
+        reader: R, limits: Limits
+    ) -> Result<Self, Error> {
+        Self::validate_at_with_limits(reader, Time::now(), limits)
+    }
+
     /// Writes the ChildRequest's XML representation.
     pub fn write_xml(&self, writer: &mut impl io::Write) -> Result<(), io::Error> {
         let mut writer = xml::encode::Writer::new(writer);
@@ -402,7 +412,14 @@ impl ChildRequest {
 
     /// Parses a <child_request /> message.
     fn validate_at<R: io::BufRead>(reader: R, when: Time) -> Result<Self, Error> {
-        let mut reader = xml::decode::Reader::new(reader);
+        Self::validate_at_with_limits(reader, when, Limits::default())
+    }
+
+    /// Parses the message at the given time while enforcing limits.
+    fn validate_at_with_limits<R: io::BufRead>(
+        reader: R, when: Time, limits: Limits
+    ) -> Result<Self, Error> {
+        let mut reader = xml::decode::Reader::with_limits(reader, limits);
 
         let mut child_handle: Option<ChildHandle> = None;
         let mut tag: Option<String> = None;
@@ -534,6 +551,16 @@ impl ParentResponse {
         Self::validate_at(reader, Time::now())
     }
 
+    /// Parses and validates the message while enforcing XML limits.
+    ///
+    /// If the message exceeds any of the given limits, parsing fails with
+    /// the error specific to the limit.
+    pub fn validate_with_limits<R: io::BufRead>(
+        reader: R, limits: Limits
+    ) -> Result<Self, Error> {
+        Self::validate_at_with_limits(reader, Time::now(), limits)
+    }
+
     /// Writes the ParentResponse's XML representation.
     pub fn write_xml(&self, writer: &mut impl io::Write) -> Result<(), io::Error> {
         let mut writer = xml::encode::Writer::new(writer);
@@ -557,7 +584,14 @@ impl ParentResponse {
 
     /// Parses a <parent_response /> message.
     fn validate_at<R: io::BufRead>(reader: R, when: Time) -> Result<Self, Error> {
-        let mut reader = xml::decode::Reader::new(reader);
+        Self::validate_at_with_limits(reader, when, Limits::default())
+    }
+
+    /// Parses the message at the given time while enforcing limits.
+    fn validate_at_with_limits<R: io::BufRead>(
+        reader: R, when: Time, limits: Limits
+    ) -> Result<Self, Error> {
+        let mut reader = xml::decode::Reader::with_limits(reader, limits);
 
         let mut child_handle: Option<ChildHandle> = None;
         let mut parent_handle: Option<ParentHandle> = None;
@@ -742,6 +776,16 @@ impl PublisherRequest {
         Self::validate_at(reader, Time::now())
     }
 
+    /// Parses and validates the message while enforcing XML limits.
+    ///
+    /// If the message exceeds any of the given limits, parsing fails with
+    /// the error specific to the limit.
+    pub fn validate_with_limits<R: io::BufRead>(
+        reader: R, limits: Limits
+    ) -> Result<Self, Error> {
+        Self::validate_at_with_limits(reader, Time::now(), limits)
+    }
+
     /// Writes the PublisherRequest's XML representation.
     pub fn write_xml(&self, writer: &mut impl io::Write) -> Result<(), io::Error> {
         let mut writer = xml::encode::Writer::new(writer);
@@ -779,7 +823,14 @@ impl PublisherRequest {
 
     /// Parses a <publisher_request /> message.
     fn validate_at<R: io::BufRead>(reader: R, when: Time) -> Result<Self, Error> {
-        let mut reader = xml::decode::Reader::new(reader);
+        Self::validate_at_with_limits(reader, when, Limits::default())
+    }
+
+    /// Parses the message at the given time while enforcing limits.
+    fn validate_at_with_limits<R: io::BufRead>(
+        reader: R, when: Time, limits: Limits
+    ) -> Result<Self, Error> {
+        let mut reader = xml::decode::Reader::with_limits(reader, limits);
 
         let mut publisher_handle: Option<PublisherHandle> = None;
         let mut tag: Option<String> = None;
@@ -930,6 +981,16 @@ impl RepositoryResponse {
         Self::validate_at(reader, Time::now())
     }
 
+    /// Parses and validates the message while enforcing XML limits.
+    ///
+    /// If the message exceeds any of the given limits, parsing fails with
+    /// the error specific to the limit.
+    pub fn validate_with_limits<R: io::BufRead>(
+        reader: R, limits: Limits
+    ) -> Result<Self, Error> {
+        Self::validate_at_with_limits(reader, Time::now(), limits)
+    }
+
     /// Writes the RepositoryResponse's XML representation.
     pub fn write_xml(&self, writer: &mut impl io::Write) -> Result<(), io::Error> {
         let mut writer = xml::encode::Writer::new(writer);
@@ -954,7 +1015,14 @@ impl RepositoryResponse {
 
     /// Parses a <repository_response /> message.
     fn validate_at<R: io::BufRead>(reader: R, when: Time) -> Result<Self, Error> {
-        let mut reader = xml::decode::Reader::new(reader);
+        Self::validate_at_with_limits(reader, when, Limits::default())
+    }
+
+    /// Parses the message at the given time while enforcing limits.
+    fn validate_at_with_limits<R: io::BufRead>(
+        reader: R, when: Time, limits: Limits
+    ) -> Result<Self, Error> {
+        let mut reader = xml::decode::Reader::with_limits(reader, limits);
 
         let mut tag: Option<String> = None;
         let mut publisher_handle: Option<PublisherHandle> = None;
@@ -7,7 +7,7 @@ use std::sync::Arc;
 use std::{fmt, io};
 
 use bytes::Bytes;
-use chrono::{DateTime, SecondsFormat, Utc};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
 use serde::de;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
@@ -24,7 +24,7 @@ use crate::repository::x509::Validity;
 use crate::repository::{Cert, Csr};
 use crate::uri;
 use crate::xml;
-use crate::xml::decode::{Content, Error as XmlError};
+use crate::xml::decode::{Content, Error as XmlError, Limits};
 use crate::xml::encode;
 
 use super::idcert::IdCert;
@@ -58,11 +58,28 @@ impl ProvisioningCms {
         message: Message,
         signing_key: &S::KeyId,
         signer: &S,
+    ) -> Result<Self, SigningError<S::Error>> {
+        Self::create_at(message, Time::now(), signing_key, signer)
+    }
+
+    /// Creates a provisioning CMS pretending it is now the given time.
+    ///
+    /// This is like [`create`][Self::create] but uses `now` instead of the
+    /// current system time.
+    pub fn create_at<S: Signer>(
+        message: Message,
+        now: Time,
+        signing_key: &S::KeyId,
+        signer: &S,
     ) -> Result<Self, SigningError<S::Error>> {
         let data = message.to_xml_bytes();
-        let validity = Validity::new(Time::five_minutes_ago(), Time::five_minutes_from_now());
+        let validity = Validity::new(
+            now - Duration::minutes(5), now + Duration::minutes(5)
+        );
 
-        let signed_msg = SignedMessage::create(data, validity, signing_key, signer)?;
+        let signed_msg = SignedMessage::create_at(
+            data, validity, now, signing_key, signer
+        )?;
 
         Ok(ProvisioningCms {
             signed_msg,
@@ -273,7 +290,17 @@ impl Message {
 impl Message {
     /// Parses an RFC 6492 <message />
     pub fn decode<R: io::BufRead>(reader: R) -> Result<Self, Error> {
-        let mut reader = xml::decode::Reader::new(reader);
+        Self::decode_with_limits(reader, Limits::default())
+    }
+
+    /// Parses an RFC 6492 <message /> while enforcing limits.
+    ///
+    /// If the message exceeds any of the given limits, parsing fails with
+    /// the error specific to the limit.
+    pub fn decode_with_limits<R: io::BufRead>(
+        reader: R, limits: Limits
+    ) -> Result<Self, Error> {
+        let mut reader = xml::decode::Reader::with_limits(reader, limits);
 
         let mut sender: Option<SenderHandle> = None;
         let mut recipient: Option<RecipientHandle> = None;
@@ -6,6 +6,7 @@ use std::str::FromStr;
 use std::sync::Arc;
 
 use bytes::Bytes;
+use chrono::Duration;
 use log::error;
 use serde::{
     Deserialize, Deserializer, Serialize, Serializer
@@ -25,7 +26,7 @@ use crate::rrdp;
 use crate::uri;
 use crate::xml;
 use crate::xml::decode::{
-    Content, Error as XmlError
+    Content, Error as XmlError, Limits
 };
 use crate::xml::encode;
 
@@ -67,16 +68,30 @@ impl PublicationCms {
         message: Message,
         issuing_key_id: &S::KeyId,
         signer: &S,
+    ) -> Result<Self, SigningError<S::Error>> {
+        Self::create_at(message, Time::now(), issuing_key_id, signer)
+    }
+
+    /// Creates a publication CMS pretending it is now the given time.
+    ///
+    /// This is like [`create`][Self::create] but uses `now` instead of the
+    /// current system time.
+    pub fn create_at<S: Signer>(
+        message: Message,
+        now: Time,
+        issuing_key_id: &S::KeyId,
+        signer: &S,
     ) -> Result<Self, SigningError<S::Error>> {
         let data = message.to_xml_bytes();
         let validity = Validity::new(
-            Time::five_minutes_ago(),
-            Time::five_minutes_from_now()
+            now - Duration::minutes(5),
+            now + Duration::minutes(5),
         );
 
-        let signed_msg = SignedMessage::create(
+        let signed_msg = SignedMessage::create_at(
             data,
             validity,
+            now,
             issuing_key_id,
             signer
         )?;
@@ -224,7 +239,17 @@ impl Message {
 impl Message {
     /// Parses an RFC 8181 <msg />
     pub fn decode<R: io::BufRead>(reader: R) -> Result<Self, Error> {
-        let mut reader = xml::decode::Reader::new(reader);
+        Self::decode_with_limits(reader, Limits::default())
+    }
+
+    /// Parses an RFC 8181 <msg /> while enforcing limits.
+    ///
+    /// If the message exceeds any of the given limits, parsing fails with
+    /// the error specific to the limit.
+    pub fn decode_with_limits<R: io::BufRead>(
+        reader: R, limits: Limits
+    ) -> Result<Self, Error> {
+        let mut reader = xml::decode::Reader::with_limits(reader, limits);
 
         let mut kind: Option<MessageKind> = None;
 
@@ -1560,6 +1585,25 @@ mod tests {
         assert_eq!(msg, re_decoded);
     }
 
+    #[test]
+    fn decode_with_limits() {
+        let xml = include_bytes!("../../test-data/ca/rfc8181/list.xml");
+        let mut limits = Limits::default();
+        limits.set_max_elements(Some(2));
+        Message::decode_with_limits(xml.as_ref(), limits).unwrap();
+        limits.set_max_elements(Some(1));
+        assert!(matches!(
+            Message::decode_with_limits(xml.as_ref(), limits),
+            Err(Error::XmlError(XmlError::TooManyElements))
+        ));
+        limits.set_max_elements(None);
+        limits.set_max_document_size(Some(16));
+        assert!(matches!(
+            Message::decode_with_limits(xml.as_ref(), limits),
+            Err(Error::XmlError(XmlError::DocumentTooLarge))
+        ));
+    }
+
     #[test]
     fn parse_and_encode_publish_multi_query() {
         let xml = include_bytes!("../../test-data/ca/rfc8181/publish-multi.xml");
@@ -269,6 +269,20 @@ impl SignedMessage {
         validity: Validity,
         issuing_key_id: &S::KeyId,
         signer: &S,
+    ) -> Result<Self, SigningError<S::Error>> {
+        Self::create_at(data, validity, Time::now(), issuing_key_id, signer)
+    }
+
+    /// Create a new signed message pretending it is now the given time.
+    ///
+    /// The time `now` is used as the signing time of the message and to
+    /// derive the CRL number of the included CRL.
+    pub fn create_at<S: Signer>(
+        data: Bytes,
+        validity: Validity,
+        now: Time,
+        issuing_key_id: &S::KeyId,
+        signer: &S,
     ) -> Result<Self, SigningError<S::Error>> {
         // Steps:
         // - create content to sign
@@ -282,7 +296,7 @@ impl SignedMessage {
         
         // Produce signed attributes
         let message_digest = digest_algorithm.digest(&data).into();
-        let signing_time = Some(Time::now());
+        let signing_time = Some(now);
         let binary_signing_time = None;
         
         let signed_attrs = SignedAttrs::new(
@@ -299,6 +313,7 @@ impl SignedMessage {
         
         let crl = SignedMessageCrl::create(
             &validity,
+            now,
             issuing_key_id,
             signer
         )?;
@@ -452,6 +467,7 @@ impl SignedMessageCrl {
     /// wrapper.
     fn create<S: Signer>(
         validity: &Validity,
+        now: Time,
         issuing_key_id: &S::KeyId,
         signer: &S,
     ) -> Result<Self, SigningError<S::Error>> {
@@ -472,7 +488,7 @@ impl SignedMessageCrl {
         // Because the number MUST always increase, let's just use  time in
         // milliseconds. We don't sign *that* quickly after all..
         let crl_number = Some(Serial::from(
-            Time::now().timestamp_millis() as u64
+            now.timestamp_millis() as u64
         ));
 
         let tbs = SignedMessageTbsCrl {
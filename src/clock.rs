@@ -0,0 +1,137 @@
+//! Sources for the current time.
+//!
+//! Many operations of the crate depend on the current time: certificates and
+//! signed objects are only valid during a certain period, manifests and CRLs
+//! become stale, RTR clients wait for a while between updates. Most of these
+//! operations come in two flavours: one that asks the system for the current
+//! time and one with an `_at` suffix that is given the time explicitly.
+//!
+//! Where a component waits for some period, such as the RTR client does
+//! between updates, the wait is also bounded by a monotonic clock so that
+//! the system time moving backwards cannot delay it.
+//!
+//! Components that keep state and need to ask for the time repeatedly
+//! instead accept a value implementing the [`Clock`] trait defined here.
+//! Normally, you would use [`SystemClock`] which simply asks the operating
+//! system. For tests or when looking at data “as of” a certain point in
+//! time, [`ManualClock`] provides a clock that only moves when told to.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Duration, Utc};
+
+
+//------------ Clock ---------------------------------------------------------
+
+/// A type that can tell the current time.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> DateTime<Utc> {
+        (*self).now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Box<C> {
+    fn now(&self) -> DateTime<Utc> {
+        self.as_ref().now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> DateTime<Utc> {
+        self.as_ref().now()
+    }
+}
+
+
+//------------ SystemClock ---------------------------------------------------
+
+/// A clock using the system’s time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+
+//------------ ManualClock ---------------------------------------------------
+
+/// A clock that only moves when told to.
+///
+/// The clock starts out at the time given upon creation and stays there
+/// until it is changed via [`set`][Self::set] or [`advance`][Self::advance].
+///
+/// Clones of a value share the same time. That is, changing the time on one
+/// clone changes it for all of them. This makes it possible to hand a clone
+/// to a component and then control the time it sees from the outside.
+#[derive(Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl ManualClock {
+    /// Creates a new clock set to the given time.
+    pub fn new(now: DateTime<Utc>) -> Self {
+        ManualClock { now: Arc::new(Mutex::new(now)) }
+    }
+
+    /// Sets the clock to the given time.
+    ///
+    /// The time may well be before the current time of the clock.
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().expect("poisoned lock") = now
+    }
+
+    /// Moves the clock forward by the given duration.
+    ///
+    /// If the duration is negative, the clock moves backward.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().expect("poisoned lock");
+        *now += duration
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().expect("poisoned lock")
+    }
+}
+
+impl fmt::Debug for ManualClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ManualClock").field(&self.now()).finish()
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+    use super::*;
+
+    #[test]
+    fn manual_clock() {
+        let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        let clock = ManualClock::new(start);
+        let shared = clock.clone();
+        assert_eq!(clock.now(), start);
+
+        clock.advance(Duration::hours(1));
+        assert_eq!(shared.now(), start + Duration::hours(1));
+
+        shared.set(start);
+        assert_eq!(clock.now(), start);
+
+        let dynamic: Arc<dyn Clock + Send + Sync> = Arc::new(shared);
+        assert_eq!(dynamic.now(), start);
+    }
+}
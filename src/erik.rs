@@ -0,0 +1,531 @@
+//! The Erik synchronization protocol.
+//!
+//! Erik is a proposed protocol for synchronizing RPKI data that, unlike
+//! rsync and RRDP, addresses all data by its content. A relay serves
+//! every object under a URI derived from its SHA-256 hash in the form of
+//! RFC 6920 named information. In addition, it serves one index per
+//! repository host – the _scope_ of the index – under a well-known path.
+//!
+//! The index, represented by [`ErikIndex`], lists a set of partitions by
+//! the hash of their content. Each partition, represented by
+//! [`ErikPartition`], in turn lists a set of manifests by their hash
+//! together with some information about them, such as the rsync URI they
+//! are published under. All other objects are found via the hashes given
+//! on the manifests. A relying party only needs to fetch the partitions
+//! and manifests that have changed since the last synchronization and only
+//! those objects listed on the changed manifests it doesn’t have yet.
+//!
+//! Manifests are assigned to partitions based on their authority key
+//! identifier, i.e., the key identifier of the issuing CA. The
+//! partition identifier is the first octet of the authority key
+//! identifier, see [`partition_id`].
+//!
+//! Both the index and the partitions are DER encoded using the following
+//! ASN.1 definitions:
+//!
+//! ```text
+//! ErikIndex ::= SEQUENCE {
+//!     version         [0] INTEGER DEFAULT 0,
+//!     indexScope      IA5String,
+//!     indexTime       GeneralizedTime,
+//!     hashAlg         OBJECT IDENTIFIER,
+//!     partitionList   SEQUENCE OF PartitionRef }
+//!
+//! PartitionRef ::= SEQUENCE {
+//!     identifier      INTEGER (0..255),
+//!     hash            OCTET STRING,
+//!     size            INTEGER }
+//!
+//! ErikPartition ::= SEQUENCE {
+//!     version         [0] INTEGER DEFAULT 0,
+//!     partitionTime   GeneralizedTime,
+//!     hashAlg         OBJECT IDENTIFIER,
+//!     manifestList    SEQUENCE OF ManifestRef }
+//!
+//! ManifestRef ::= SEQUENCE {
+//!     hash            OCTET STRING,
+//!     size            INTEGER,
+//!     aki             KeyIdentifier,
+//!     manifestNumber  INTEGER,
+//!     thisUpdate      GeneralizedTime,
+//!     location        IA5String }
+//! ```
+//!
+//! The only hash algorithm currently supported is SHA-256.
+//!
+//! The [`publish`] module computes the index and partitions from the
+//! content of a [`RepositoryStore`][crate::store::RepositoryStore] while
+//! the [`sync`] module provides the relying party side of the protocol,
+//! synchronizing a store with a relay.
+//!
+//! Since the protocol is still a draft, the details implemented here may
+//! change in the future.
+//!
+//! This module is only available with the `"erik"` feature.
+
+#![cfg(feature = "erik")]
+
+pub mod publish;
+pub mod sync;
+
+use std::convert::TryFrom;
+use bcder::{decode, encode};
+use bcder::{Ia5String, Mode, OctetString, Tag, xerr};
+use bcder::encode::{PrimitiveContent, Values};
+use bytes::Bytes;
+use crate::uri;
+use crate::repository::crypto::{DigestAlgorithm, KeyIdentifier};
+use crate::repository::x509::{Serial, Time};
+use crate::rrdp::Hash;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The path relative to a relay’s base URI for indexes.
+const INDEX_PATH: &str = ".well-known/erik/index/";
+
+/// The path relative to a relay’s base URI for objects.
+const OBJECT_PATH: &str = ".well-known/ni/sha-256/";
+
+
+//------------ URIs ----------------------------------------------------------
+
+/// Returns the URI of the index for a scope at a relay.
+///
+/// The base URI of the relay should end in a slash.
+pub fn index_uri(
+    relay: &uri::Https, scope: &str
+) -> Result<uri::Https, uri::Error> {
+    relay.join(format!("{}{}", INDEX_PATH, scope).as_bytes())
+}
+
+/// Returns the URI of the object with the given hash at a relay.
+///
+/// The base URI of the relay should end in a slash.
+pub fn object_uri(relay: &uri::Https, hash: &Hash) -> uri::Https {
+    relay.join(
+        format!(
+            "{}{}",
+            OBJECT_PATH,
+            base64::encode_config(hash.as_slice(), base64::URL_SAFE_NO_PAD)
+        ).as_bytes()
+    ).expect("base64url is valid in URIs")
+}
+
+/// Returns the identifier of the partition for a manifest.
+///
+/// The manifest is identified by its authority key identifier.
+pub fn partition_id(aki: &KeyIdentifier) -> u8 {
+    aki.as_slice()[0]
+}
+
+
+//------------ ErikIndex -----------------------------------------------------
+
+/// The index of all partitions for a scope.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ErikIndex {
+    /// The scope of the index.
+    scope: String,
+
+    /// The time the index was created.
+    time: Time,
+
+    /// The partitions.
+    partitions: Vec<PartitionRef>,
+}
+
+impl ErikIndex {
+    /// Creates a new index from its components.
+    ///
+    /// # Panics
+    ///
+    /// The function panics if `scope` contains non-ASCII characters.
+    pub fn new(
+        scope: String, time: Time, partitions: Vec<PartitionRef>
+    ) -> Self {
+        assert!(scope.is_ascii());
+        ErikIndex { scope, time, partitions }
+    }
+
+    /// Returns the scope of the index.
+    ///
+    /// This is the host name of the repository the index is for.
+    pub fn scope(&self) -> &str {
+        &self.scope
+    }
+
+    /// Returns the time the index was created.
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    /// Returns the partitions listed by the index.
+    pub fn partitions(&self) -> &[PartitionRef] {
+        &self.partitions
+    }
+}
+
+/// # Decoding and Encoding
+///
+impl ErikIndex {
+    /// Decodes an index from a source.
+    pub fn decode<S: decode::Source>(source: S) -> Result<Self, S::Err> {
+        Mode::Der.decode(source, Self::take_from)
+    }
+
+    /// Takes an encoded index from the beginning of a constructed value.
+    pub fn take_from<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<Self, S::Err> {
+        cons.take_sequence(|cons| {
+            cons.take_opt_constructed_if(Tag::CTX_0, |c| c.skip_u8_if(0))?;
+            let scope = Ia5String::take_from(cons)?;
+            let time = Time::take_from(cons)?;
+            DigestAlgorithm::take_oid_from(cons)?;
+            let partitions = cons.take_sequence(|cons| {
+                let mut res = Vec::new();
+                while let Some(item) = PartitionRef::take_opt_from(cons)? {
+                    res.push(item)
+                }
+                Ok(res)
+            })?;
+            Ok(ErikIndex {
+                scope: String::from_utf8_lossy(
+                    scope.into_bytes().as_ref()
+                ).into_owned(),
+                time,
+                partitions,
+            })
+        })
+    }
+
+    /// Returns a value encoder for a reference to the index.
+    pub fn encode_ref(&self) -> impl encode::Values + '_ {
+        encode::sequence((
+            OctetString::encode_slice_as(
+                self.scope.as_bytes(), Tag::IA5_STRING
+            ),
+            self.time.encode_generalized_time(),
+            DigestAlgorithm::default().encode_oid(),
+            encode::sequence(
+                encode::iter(self.partitions.iter().map(|item| {
+                    item.encode_ref()
+                }))
+            )
+        ))
+    }
+
+    /// Returns the DER encoded index.
+    pub fn to_bytes(&self) -> Bytes {
+        self.encode_ref().to_captured(Mode::Der).into_bytes()
+    }
+}
+
+
+//------------ PartitionRef --------------------------------------------------
+
+/// A reference to a partition in an index.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PartitionRef {
+    /// The identifier of the partition.
+    identifier: u8,
+
+    /// The hash of the encoded partition.
+    hash: Hash,
+
+    /// The size of the encoded partition in octets.
+    size: u64,
+}
+
+impl PartitionRef {
+    /// Creates a new partition reference from its components.
+    pub fn new(identifier: u8, hash: Hash, size: u64) -> Self {
+        PartitionRef { identifier, hash, size }
+    }
+
+    /// Returns the identifier of the partition.
+    pub fn identifier(&self) -> u8 {
+        self.identifier
+    }
+
+    /// Returns the hash of the encoded partition.
+    pub fn hash(&self) -> Hash {
+        self.hash
+    }
+
+    /// Returns the size of the encoded partition.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Takes an optional reference from a constructed value.
+    fn take_opt_from<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<Option<Self>, S::Err> {
+        cons.take_opt_sequence(|cons| {
+            Ok(PartitionRef {
+                identifier: cons.take_u8()?,
+                hash: take_hash(cons)?,
+                size: cons.take_u64()?,
+            })
+        })
+    }
+
+    /// Returns a value encoder for a reference to the reference.
+    fn encode_ref(&self) -> impl encode::Values + '_ {
+        encode::sequence((
+            self.identifier.encode(),
+            OctetString::encode_slice(self.hash.as_slice()),
+            self.size.encode(),
+        ))
+    }
+}
+
+
+//------------ ErikPartition -------------------------------------------------
+
+/// A partition listing a set of manifests.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ErikPartition {
+    /// The time the partition was created.
+    time: Time,
+
+    /// The manifests.
+    manifests: Vec<ManifestRef>,
+}
+
+impl ErikPartition {
+    /// Creates a new partition from its components.
+    pub fn new(time: Time, manifests: Vec<ManifestRef>) -> Self {
+        ErikPartition { time, manifests }
+    }
+
+    /// Returns the time the partition was created.
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    /// Returns the manifests listed by the partition.
+    pub fn manifests(&self) -> &[ManifestRef] {
+        &self.manifests
+    }
+}
+
+/// # Decoding and Encoding
+///
+impl ErikPartition {
+    /// Decodes a partition from a source.
+    pub fn decode<S: decode::Source>(source: S) -> Result<Self, S::Err> {
+        Mode::Der.decode(source, Self::take_from)
+    }
+
+    /// Takes an encoded partition from the beginning of a constructed value.
+    pub fn take_from<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<Self, S::Err> {
+        cons.take_sequence(|cons| {
+            cons.take_opt_constructed_if(Tag::CTX_0, |c| c.skip_u8_if(0))?;
+            let time = Time::take_from(cons)?;
+            DigestAlgorithm::take_oid_from(cons)?;
+            let manifests = cons.take_sequence(|cons| {
+                let mut res = Vec::new();
+                while let Some(item) = ManifestRef::take_opt_from(cons)? {
+                    res.push(item)
+                }
+                Ok(res)
+            })?;
+            Ok(ErikPartition { time, manifests })
+        })
+    }
+
+    /// Returns a value encoder for a reference to the partition.
+    pub fn encode_ref(&self) -> impl encode::Values + '_ {
+        encode::sequence((
+            self.time.encode_generalized_time(),
+            DigestAlgorithm::default().encode_oid(),
+            encode::sequence(
+                encode::iter(self.manifests.iter().map(|item| {
+                    item.encode_ref()
+                }))
+            )
+        ))
+    }
+
+    /// Returns the DER encoded partition.
+    pub fn to_bytes(&self) -> Bytes {
+        self.encode_ref().to_captured(Mode::Der).into_bytes()
+    }
+}
+
+
+//------------ ManifestRef ---------------------------------------------------
+
+/// A reference to a manifest in a partition.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ManifestRef {
+    /// The hash of the manifest.
+    hash: Hash,
+
+    /// The size of the manifest in octets.
+    size: u64,
+
+    /// The authority key identifier of the manifest.
+    aki: KeyIdentifier,
+
+    /// The manifest number.
+    manifest_number: Serial,
+
+    /// The this-update time of the manifest.
+    this_update: Time,
+
+    /// The rsync URI the manifest is published under.
+    location: uri::Rsync,
+}
+
+impl ManifestRef {
+    /// Creates a new manifest reference from its components.
+    pub fn new(
+        hash: Hash,
+        size: u64,
+        aki: KeyIdentifier,
+        manifest_number: Serial,
+        this_update: Time,
+        location: uri::Rsync,
+    ) -> Self {
+        ManifestRef {
+            hash, size, aki, manifest_number, this_update, location
+        }
+    }
+
+    /// Returns the hash of the manifest.
+    pub fn hash(&self) -> Hash {
+        self.hash
+    }
+
+    /// Returns the size of the manifest.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the authority key identifier of the manifest.
+    pub fn aki(&self) -> KeyIdentifier {
+        self.aki
+    }
+
+    /// Returns the manifest number.
+    pub fn manifest_number(&self) -> Serial {
+        self.manifest_number
+    }
+
+    /// Returns the this-update time of the manifest.
+    pub fn this_update(&self) -> Time {
+        self.this_update
+    }
+
+    /// Returns the rsync URI the manifest is published under.
+    pub fn location(&self) -> &uri::Rsync {
+        &self.location
+    }
+
+    /// Takes an optional reference from a constructed value.
+    fn take_opt_from<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<Option<Self>, S::Err> {
+        cons.take_opt_sequence(|cons| {
+            Ok(ManifestRef {
+                hash: take_hash(cons)?,
+                size: cons.take_u64()?,
+                aki: KeyIdentifier::take_from(cons)?,
+                manifest_number: Serial::take_from(cons)?,
+                this_update: Time::take_from(cons)?,
+                location: {
+                    let location = Ia5String::take_from(cons)?;
+                    match uri::Rsync::from_bytes(location.into_bytes()) {
+                        Ok(location) => location,
+                        Err(_) => {
+                            xerr!(return Err(decode::Malformed.into()))
+                        }
+                    }
+                }
+            })
+        })
+    }
+
+    /// Returns a value encoder for a reference to the reference.
+    fn encode_ref(&self) -> impl encode::Values + '_ {
+        encode::sequence((
+            OctetString::encode_slice(self.hash.as_slice()),
+            self.size.encode(),
+            self.aki.encode(),
+            self.manifest_number.encode(),
+            self.this_update.encode_generalized_time(),
+            (&self.location).encode(),
+        ))
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Takes a SHA-256 hash encoded as an octet string.
+fn take_hash<S: decode::Source>(
+    cons: &mut decode::Constructed<S>
+) -> Result<Hash, S::Err> {
+    let octets = OctetString::take_from(cons)?.into_bytes();
+    match Hash::try_from(octets.as_ref()) {
+        Ok(hash) => Ok(hash),
+        Err(_) => xerr!(Err(decode::Malformed.into()))
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let time = Time::utc(2024, 3, 1, 12, 0, 0);
+        let partition = ErikPartition::new(time, vec![
+            ManifestRef::new(
+                Hash::from_data(b"manifest"), 8,
+                KeyIdentifier::from([7; 20]), 12u64.into(), time,
+                uri::Rsync::from_str(
+                    "rsync://example.com/repo/ca/ca.mft"
+                ).unwrap(),
+            )
+        ]);
+        let encoded = partition.to_bytes();
+        assert_eq!(
+            ErikPartition::decode(encoded.as_ref()).unwrap(), partition
+        );
+
+        let index = ErikIndex::new("example.com".into(), time, vec![
+            PartitionRef::new(
+                7, Hash::from_data(&encoded), encoded.len() as u64
+            )
+        ]);
+        let index_data = index.to_bytes();
+        assert_eq!(ErikIndex::decode(index_data.as_ref()).unwrap(), index);
+        assert!(ErikIndex::decode(encoded.as_ref()).is_err());
+        assert!(ErikPartition::decode(index_data.as_ref()).is_err());
+    }
+
+    #[test]
+    fn uris() {
+        let relay = uri::Https::from_str("https://relay.example/").unwrap();
+        assert_eq!(
+            index_uri(&relay, "example.com").unwrap().as_str(),
+            "https://relay.example/.well-known/erik/index/example.com"
+        );
+        assert_eq!(
+            object_uri(&relay, &Hash::from_data(b"")).as_str(),
+            "https://relay.example/.well-known/ni/sha-256/\
+             47DEQpj8HBSa-_TImW-5JCeuQeRkm5NMpJWZG3hSuFU"
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Computing Erik indexes and partitions.
+//!
+//! A [`Publication`] contains everything a relay serves for a single
+//! scope: the index, the partitions, and all objects referenced by them.
+//! It is computed from the content of a repository store.
+
+use std::collections::{BTreeMap, HashMap};
+use bytes::Bytes;
+use log::info;
+use crate::uri;
+use crate::repository::manifest::Manifest;
+use crate::repository::x509::Time;
+use crate::rrdp::Hash;
+use crate::store::{RepositoryStore, StoreError};
+use super::{
+    ErikIndex, ErikPartition, ManifestRef, PartitionRef, index_uri,
+    object_uri, partition_id,
+};
+
+
+//------------ Publication ---------------------------------------------------
+
+/// The Erik data for a scope.
+///
+/// All data is kept in memory.
+#[derive(Clone, Debug)]
+pub struct Publication {
+    /// The index.
+    index: ErikIndex,
+
+    /// The encoded index.
+    index_data: Bytes,
+
+    /// All objects by their hash.
+    ///
+    /// This includes partitions, manifests, and the files listed on the
+    /// manifests.
+    objects: HashMap<Hash, Bytes>,
+}
+
+impl Publication {
+    /// Computes the Erik data for a scope from a repository store.
+    ///
+    /// All manifests found in the store within the directories given via
+    /// `dirs` that are published under an rsync URI with `scope` as their
+    /// host name are included. A manifest is recognized by the file
+    /// extension `.mft`. Objects that cannot be decoded as manifests or
+    /// have no authority key identifier are skipped. Of the files listed on
+    /// a manifest, all those are included that are present in the store
+    /// with the content given by the manifest’s hash.
+    ///
+    /// The index is created with the time given in `time`. The time of
+    /// each partition is the latest this-update time of its manifests so
+    /// that a partition only changes if its manifests do.
+    ///
+    /// # Panics
+    ///
+    /// The function panics if `scope` contains non-ASCII characters.
+    pub fn from_store(
+        store: &(impl RepositoryStore + ?Sized),
+        scope: &str,
+        dirs: &[uri::Rsync],
+        time: Time,
+    ) -> Result<Self, StoreError> {
+        let mut objects = HashMap::new();
+        let mut partitions = BTreeMap::<u8, Vec<ManifestRef>>::new();
+        for dir in dirs {
+            for location in store.list_tree(dir)? {
+                if !location.ends_with(".mft")
+                    || !location.canonical_authority().eq_ignore_ascii_case(
+                        scope
+                    )
+                {
+                    continue
+                }
+                let data = match store.get(&location)? {
+                    Some(data) => data,
+                    None => continue,
+                };
+                let manifest = match Manifest::decode(data.clone(), false) {
+                    Ok(manifest) => manifest,
+                    Err(_) => {
+                        info!("{}: failed to decode manifest", location);
+                        continue
+                    }
+                };
+                let aki = match manifest.cert().authority_key_identifier() {
+                    Some(aki) => aki,
+                    None => {
+                        info!(
+                            "{}: manifest without authority key identifier",
+                            location
+                        );
+                        continue
+                    }
+                };
+                Self::add_files(store, &location, &manifest, &mut objects)?;
+                let hash = Hash::from_data(&data);
+                partitions.entry(partition_id(&aki)).or_default().push(
+                    ManifestRef::new(
+                        hash, data.len() as u64, aki,
+                        manifest.content().manifest_number(),
+                        manifest.content().this_update(),
+                        location,
+                    )
+                );
+                objects.insert(hash, data);
+            }
+        }
+
+        let partitions = partitions.into_iter().map(|(id, mut manifests)| {
+            manifests.sort_by(|left, right| {
+                left.location().as_str().cmp(right.location().as_str())
+            });
+            let part_time = manifests.iter().map(|item| {
+                item.this_update()
+            }).max().unwrap_or(time);
+            let data = ErikPartition::new(part_time, manifests).to_bytes();
+            let hash = Hash::from_data(&data);
+            let res = PartitionRef::new(id, hash, data.len() as u64);
+            objects.insert(hash, data);
+            res
+        }).collect();
+        let index = ErikIndex::new(scope.into(), time, partitions);
+        let index_data = index.to_bytes();
+        Ok(Publication { index, index_data, objects })
+    }
+
+    /// Adds the files listed on a manifest to the objects.
+    fn add_files(
+        store: &(impl RepositoryStore + ?Sized),
+        location: &uri::Rsync,
+        manifest: &Manifest,
+        objects: &mut HashMap<Hash, Bytes>,
+    ) -> Result<(), StoreError> {
+        let dir = match location.parent() {
+            Some(dir) => dir,
+            None => return Ok(())
+        };
+        for item in manifest.content().iter() {
+            let (file, hash) = item.into_pair();
+            let uri = match dir.join(&file) {
+                Ok(uri) => uri,
+                Err(_) => {
+                    info!("{}: illegal file name on manifest", location);
+                    continue
+                }
+            };
+            let data = match store.get(&uri)? {
+                Some(data) => data,
+                None => {
+                    info!("{}: listed file {} missing", location, uri);
+                    continue
+                }
+            };
+            let computed = Hash::from_data(&data);
+            if computed.as_slice() != hash.as_ref() {
+                info!("{}: listed file {} has wrong hash", location, uri);
+                continue
+            }
+            objects.insert(computed, data);
+        }
+        Ok(())
+    }
+
+    /// Returns the index.
+    pub fn index(&self) -> &ErikIndex {
+        &self.index
+    }
+
+    /// Returns the encoded index.
+    pub fn index_data(&self) -> &Bytes {
+        &self.index_data
+    }
+
+    /// Returns the object with the given hash.
+    ///
+    /// Objects are the encoded partitions, the manifests, and the files
+    /// listed on the manifests.
+    pub fn get(&self, hash: &Hash) -> Option<&Bytes> {
+        self.objects.get(hash)
+    }
+
+    /// Returns the number of objects.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns whether there are no objects.
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Returns an iterator over all files served by a relay.
+    ///
+    /// The iterator returns the URI and content of the index and all
+    /// objects when served by the relay with the given base URI.
+    pub fn files<'a>(
+        &'a self, relay: &'a uri::Https
+    ) -> Result<
+        impl Iterator<Item = (uri::Https, Bytes)> + 'a, uri::Error
+    > {
+        let index = index_uri(relay, self.index.scope())?;
+        Ok(
+            Some((index, self.index_data.clone())).into_iter().chain(
+                self.objects.iter().map(move |(hash, data)| {
+                    (object_uri(relay, hash), data.clone())
+                })
+            )
+        )
+    }
+}
@@ -0,0 +1,585 @@
+//! Synchronizing a repository store via Erik.
+//!
+//! A [`Synchronizer`] fetches the index for a scope from a relay and
+//! compares it with the [`State`] of the last synchronization. It then
+//! fetches only those partitions and manifests that have changed and only
+//! those objects listed on the changed manifests that aren’t present in
+//! the store with the right content already. The files are fetched through
+//! the same [`Fetcher`] trait used for RRDP and the objects are written
+//! into a [`RepositoryStore`].
+//!
+//! All changes made by a synchronization are applied to the store in a
+//! single transaction. If it fails, the store remains unchanged.
+
+use std::{cmp, error, fmt, io};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::Read;
+use bytes::Bytes;
+use crate::uri;
+use crate::repository::manifest::Manifest;
+use crate::repository::x509::Time;
+use crate::rrdp::Hash;
+use crate::rrdp::update::Fetcher;
+use crate::store::{ChangeSet, RepositoryStore, StoreError, Transaction};
+use super::{ErikIndex, ErikPartition, ManifestRef, index_uri, object_uri};
+
+
+//------------ State ---------------------------------------------------------
+
+/// The state of a scope after a synchronization.
+///
+/// The state needs to be kept between synchronizations. It is used to
+/// decide which partitions and manifests have changed and which objects
+/// to remove from the store.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct State {
+    /// The time of the index used by the last synchronization.
+    index_time: Time,
+
+    /// The hashes of the partitions by their identifier.
+    partitions: HashMap<u8, Hash>,
+
+    /// The manifests by their location.
+    manifests: HashMap<uri::Rsync, ManifestState>,
+}
+
+impl State {
+    /// Returns the time of the index used by the last synchronization.
+    pub fn index_time(&self) -> Time {
+        self.index_time
+    }
+
+    /// Returns an iterator over the URIs of all manifests.
+    pub fn manifests(&self) -> impl Iterator<Item = &uri::Rsync> + '_ {
+        self.manifests.keys()
+    }
+
+    /// Returns an iterator over the URIs of all objects.
+    ///
+    /// These are all the files listed on the manifests but not the
+    /// manifests themselves.
+    pub fn objects(&self) -> impl Iterator<Item = &uri::Rsync> + '_ {
+        self.manifests.values().flat_map(|item| item.objects.iter())
+    }
+}
+
+
+//------------ ManifestState -------------------------------------------------
+
+/// The state of a single manifest.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct ManifestState {
+    /// The identifier of the partition the manifest was listed in.
+    partition: u8,
+
+    /// The hash of the manifest.
+    hash: Hash,
+
+    /// The URIs of all objects listed on the manifest.
+    objects: HashSet<uri::Rsync>,
+}
+
+
+//------------ Synchronizer --------------------------------------------------
+
+/// Synchronizes a repository store via Erik.
+#[derive(Clone, Debug)]
+pub struct Synchronizer<F, S> {
+    /// The fetcher for the index and objects.
+    fetcher: F,
+
+    /// The store to write objects to.
+    store: S,
+
+    /// The maximum size of a fetched file.
+    max_object_size: Option<u64>,
+}
+
+impl<F: Fetcher, S: RepositoryStore> Synchronizer<F, S> {
+    /// Creates a new synchronizer from a fetcher and a store.
+    pub fn new(fetcher: F, store: S) -> Self {
+        Synchronizer { fetcher, store, max_object_size: None }
+    }
+
+    /// Sets the maximum size of a fetched file.
+    ///
+    /// If the index or any object is larger, synchronization fails. Sizes
+    /// given in the index or a partition are enforced in addition. By
+    /// default, there is no limit.
+    pub fn set_max_object_size(&mut self, size: Option<u64>) {
+        self.max_object_size = size
+    }
+
+    /// Returns a reference to the fetcher.
+    pub fn fetcher(&self) -> &F {
+        &self.fetcher
+    }
+
+    /// Returns a reference to the store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Synchronizes a scope with the relay at the given base URI.
+    ///
+    /// The state of the last synchronization should be given in `state`.
+    /// If there is none, all partitions and manifests are fetched. Objects
+    /// are only fetched if they aren’t present in the store with the
+    /// right content.
+    ///
+    /// An error is returned if any file cannot be fetched or is not what
+    /// it should be, or if the changes cannot be applied to the store. In
+    /// this case, the store remains unchanged.
+    pub fn sync(
+        &self,
+        relay: &uri::Https,
+        scope: &str,
+        state: Option<&State>,
+    ) -> Result<Synchronization, SyncError> {
+        let uri = index_uri(relay, scope).map_err(|_| {
+            SyncError::BadScope(scope.into())
+        })?;
+        let data = self.fetch(&uri, self.max_object_size)?;
+        let index = ErikIndex::decode(data.as_ref()).map_err(|_| {
+            SyncError::Malformed(uri.clone())
+        })?;
+        if !index.scope().eq_ignore_ascii_case(scope) {
+            return Err(SyncError::ScopeMismatch(uri))
+        }
+        if let Some(state) = state {
+            if index.time() < state.index_time {
+                return Err(SyncError::IndexRegressed(uri))
+            }
+        }
+
+        let mut tx = self.store.transaction();
+        let mut res = State {
+            index_time: index.time(),
+            partitions: HashMap::new(),
+            manifests: HashMap::new(),
+        };
+        let mut fetched = 0;
+        for item in index.partitions() {
+            let id = item.identifier();
+            if res.partitions.insert(id, item.hash()).is_some() {
+                return Err(SyncError::Malformed(uri))
+            }
+            if let Some(state) = state {
+                if state.partitions.get(&id) == Some(&item.hash()) {
+                    res.manifests.extend(
+                        state.manifests.iter().filter(|(_, manifest)| {
+                            manifest.partition == id
+                        }).map(|(location, manifest)| {
+                            (location.clone(), manifest.clone())
+                        })
+                    );
+                    continue
+                }
+            }
+            let (part_uri, data) = self.fetch_object(
+                relay, item.hash(), Some(item.size())
+            )?;
+            fetched += 1;
+            let partition = ErikPartition::decode(data.as_ref()).map_err(|_| {
+                SyncError::Malformed(part_uri)
+            })?;
+            for manifest in partition.manifests() {
+                let manifest_state = self.sync_manifest(
+                    relay, scope, id, manifest, state, &mut tx, &mut fetched
+                )?;
+                res.manifests.insert(
+                    manifest.location().clone(), manifest_state
+                );
+            }
+        }
+
+        if let Some(state) = state {
+            for (location, old) in &state.manifests {
+                match res.manifests.get(location) {
+                    Some(new) => {
+                        for uri in old.objects.difference(&new.objects) {
+                            tx.delete(uri.clone());
+                        }
+                    }
+                    None => {
+                        tx.delete(location.clone());
+                        for uri in &old.objects {
+                            tx.delete(uri.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Synchronization {
+            state: res,
+            changes: tx.commit().map_err(SyncError::Store)?,
+            fetched,
+        })
+    }
+
+    /// Synchronizes a single manifest and the objects listed on it.
+    ///
+    /// The manifest is rejected if its location isn’t within `scope` as
+    /// the relay would otherwise be able to change the content of other
+    /// repositories in the store.
+    #[allow(clippy::too_many_arguments)]
+    fn sync_manifest(
+        &self,
+        relay: &uri::Https,
+        scope: &str,
+        partition: u8,
+        manifest_ref: &ManifestRef,
+        state: Option<&State>,
+        tx: &mut Transaction<S>,
+        fetched: &mut usize,
+    ) -> Result<ManifestState, SyncError> {
+        let location = manifest_ref.location();
+        if !location.canonical_authority().eq_ignore_ascii_case(scope) {
+            return Err(SyncError::BadManifest(location.clone()))
+        }
+        if let Some(old) = state.and_then(|state| {
+            state.manifests.get(location)
+        }) {
+            if old.hash == manifest_ref.hash() {
+                let mut res = old.clone();
+                res.partition = partition;
+                return Ok(res)
+            }
+        }
+
+        let data = match tx.get(location).map_err(SyncError::Store)? {
+            Some(data) if manifest_ref.hash().matches(&data) => data,
+            _ => {
+                let (_, data) = self.fetch_object(
+                    relay, manifest_ref.hash(), Some(manifest_ref.size())
+                )?;
+                *fetched += 1;
+                tx.put(location.clone(), data.clone());
+                data
+            }
+        };
+        let bad_manifest = || SyncError::BadManifest(location.clone());
+        let manifest = Manifest::decode(data, false).map_err(|_| {
+            bad_manifest()
+        })?;
+        if manifest.cert().authority_key_identifier()
+                != Some(manifest_ref.aki())
+            || manifest.content().manifest_number()
+                != manifest_ref.manifest_number()
+            || manifest.content().this_update() != manifest_ref.this_update()
+            || manifest.cert().signed_object().map(|uri| {
+                uri != location
+            }).unwrap_or(false)
+        {
+            return Err(bad_manifest())
+        }
+        let dir = location.parent().ok_or_else(bad_manifest)?;
+
+        let mut objects = HashSet::new();
+        for item in manifest.content().iter() {
+            let (file, hash) = item.into_pair();
+            let uri = dir.join(&file).map_err(|_| bad_manifest())?;
+            let hash = Hash::try_from(hash.as_ref()).map_err(|_| {
+                bad_manifest()
+            })?;
+            let present = match tx.get(&uri).map_err(SyncError::Store)? {
+                Some(data) => hash.matches(&data),
+                None => false,
+            };
+            if !present {
+                let (_, data) = self.fetch_object(
+                    relay, hash, self.max_object_size
+                )?;
+                *fetched += 1;
+                tx.put(uri.clone(), data);
+            }
+            objects.insert(uri);
+        }
+        Ok(ManifestState {
+            partition,
+            hash: manifest_ref.hash(),
+            objects
+        })
+    }
+
+    /// Fetches an object by its hash.
+    ///
+    /// Returns the URI the object was fetched from and its content.
+    fn fetch_object(
+        &self, relay: &uri::Https, hash: Hash, size: Option<u64>
+    ) -> Result<(uri::Https, Bytes), SyncError> {
+        let uri = object_uri(relay, &hash);
+        let limit = match (size, self.max_object_size) {
+            (Some(size), Some(max)) => Some(cmp::min(size, max)),
+            (size, max) => size.or(max),
+        };
+        let data = self.fetch(&uri, limit)?;
+        if !hash.matches(&data) {
+            return Err(SyncError::HashMismatch(uri))
+        }
+        Ok((uri, data))
+    }
+
+    /// Fetches a file reading at most `limit` octets.
+    fn fetch(
+        &self, uri: &uri::Https, limit: Option<u64>
+    ) -> Result<Bytes, SyncError> {
+        let fetch_err = |err| SyncError::Fetch(uri.clone(), err);
+        let response = self.fetcher.fetch(uri).map_err(fetch_err)?;
+        let mut data = Vec::new();
+        match limit {
+            Some(limit) => {
+                response.take(limit.saturating_add(1)).read_to_end(
+                    &mut data
+                ).map_err(fetch_err)?;
+                if data.len() as u64 > limit {
+                    return Err(SyncError::TooLarge(uri.clone()))
+                }
+            }
+            None => {
+                let mut response = response;
+                response.read_to_end(&mut data).map_err(fetch_err)?;
+            }
+        }
+        Ok(data.into())
+    }
+}
+
+
+//------------ Synchronization -----------------------------------------------
+
+/// The outcome of a successful synchronization.
+#[derive(Clone, Debug)]
+pub struct Synchronization {
+    /// The new state of the scope.
+    ///
+    /// This needs to be given to the next synchronization.
+    pub state: State,
+
+    /// The changes applied to the store.
+    pub changes: ChangeSet,
+
+    /// The number of objects fetched.
+    ///
+    /// This includes partitions and manifests but not the index.
+    pub fetched: usize,
+}
+
+
+//------------ SyncError -----------------------------------------------------
+
+/// An error happened while synchronizing via Erik.
+#[derive(Debug)]
+pub enum SyncError {
+    /// The scope cannot be used in a URI.
+    BadScope(String),
+
+    /// Fetching a file failed.
+    Fetch(uri::Https, io::Error),
+
+    /// A file is larger than allowed.
+    TooLarge(uri::Https),
+
+    /// An object doesn’t match its hash.
+    HashMismatch(uri::Https),
+
+    /// The index or a partition is malformed.
+    Malformed(uri::Https),
+
+    /// The index is for a different scope.
+    ScopeMismatch(uri::Https),
+
+    /// The index is older than the one of the last synchronization.
+    IndexRegressed(uri::Https),
+
+    /// A manifest is malformed or doesn’t match its reference.
+    BadManifest(uri::Rsync),
+
+    /// Accessing the store failed.
+    Store(StoreError),
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncError::BadScope(ref scope) => {
+                write!(f, "illegal scope '{}'", scope)
+            }
+            SyncError::Fetch(ref uri, ref err) => {
+                write!(f, "failed to fetch {}: {}", uri, err)
+            }
+            SyncError::TooLarge(ref uri) => {
+                write!(f, "{} is too large", uri)
+            }
+            SyncError::HashMismatch(ref uri) => {
+                write!(f, "hash mismatch for {}", uri)
+            }
+            SyncError::Malformed(ref uri) => {
+                write!(f, "{} is malformed", uri)
+            }
+            SyncError::ScopeMismatch(ref uri) => {
+                write!(f, "{} is for a different scope", uri)
+            }
+            SyncError::IndexRegressed(ref uri) => {
+                write!(f, "{} is older than the last index", uri)
+            }
+            SyncError::BadManifest(ref uri) => {
+                write!(f, "bad manifest {}", uri)
+            }
+            SyncError::Store(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for SyncError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(all(test, feature = "softkeys", feature = "validation"))]
+mod test {
+    use std::str::FromStr;
+    use chrono::Duration;
+    use crate::rrdp::update::LocalFetcher;
+    use crate::store::MemoryStore;
+    use crate::validation::testrepo::{TestRepo, Which, rsync};
+    use super::super::publish::Publication;
+    use super::*;
+
+    fn relay() -> uri::Https {
+        uri::Https::from_str("https://relay.example.net/").unwrap()
+    }
+
+    /// Creates a fetcher serving the current content of the repository.
+    fn serve(repo: &TestRepo, time: Time) -> LocalFetcher {
+        let store = MemoryStore::new();
+        for (uri, data) in repo.archive.iter() {
+            store.put(uri.clone(), data.clone()).unwrap();
+        }
+        let publication = Publication::from_store(
+            &store, "example.com", &[rsync("rsync://example.com/repo/")],
+            time
+        ).unwrap();
+        let mut res = LocalFetcher::new();
+        for (uri, data) in publication.files(&relay()).unwrap() {
+            res.insert(uri, data);
+        }
+        res
+    }
+
+    #[test]
+    fn sync() {
+        let mut repo = TestRepo::new();
+        let now = TestRepo::base_time();
+        let store = MemoryStore::new();
+
+        let sync = Synchronizer::new(serve(&repo, now), &store);
+        let first = sync.sync(&relay(), "example.com", None).unwrap();
+        assert_eq!(first.changes.len(), 7);
+        for (uri, data) in repo.archive.iter() {
+            if rsync("rsync://example.com/repo/").is_parent_of(uri) {
+                assert_eq!(store.get(uri).unwrap().as_ref(), Some(data));
+            }
+        }
+        assert_eq!(first.state.manifests().count(), 2);
+        assert_eq!(first.state.objects().count(), 5);
+
+        // Nothing changed.
+        let second = sync.sync(
+            &relay(), "example.com", Some(&first.state)
+        ).unwrap();
+        assert!(second.changes.is_empty());
+        assert_eq!(second.fetched, 0);
+        assert_eq!(second.state, first.state);
+
+        // The child CA drops the ASPA. We should only fetch the partition,
+        // manifest, and CRL.
+        let roa_uri = Which::Child.ca_repository().join(b"roa.roa").unwrap();
+        let aspa_uri = Which::Child.ca_repository().join(
+            b"aspa.asa"
+        ).unwrap();
+        let roa = repo.archive.get(&roa_uri).unwrap().clone();
+        repo.publish(
+            Which::Child, 2, now, now + Duration::days(7),
+            vec![("roa.roa", roa)]
+        );
+        let later = now + Duration::hours(1);
+        let sync = Synchronizer::new(serve(&repo, later), &store);
+        let third = sync.sync(
+            &relay(), "example.com", Some(&first.state)
+        ).unwrap();
+        assert_eq!(third.fetched, 3);
+        assert_eq!(third.changes.len(), 3);
+        assert_eq!(store.get(&aspa_uri).unwrap(), None);
+        assert!(store.get(&roa_uri).unwrap().is_some());
+
+        // Going back to the old index fails.
+        let sync = Synchronizer::new(serve(&repo, now), &store);
+        assert!(matches!(
+            sync.sync(&relay(), "example.com", Some(&third.state)),
+            Err(SyncError::IndexRegressed(_))
+        ));
+    }
+
+    #[test]
+    fn bad_object() {
+        let repo = TestRepo::new();
+        let mut fetcher = serve(&repo, TestRepo::base_time());
+        let roa = repo.archive.get(
+            &Which::Child.ca_repository().join(b"roa.roa").unwrap()
+        ).unwrap();
+        fetcher.insert(
+            object_uri(&relay(), &Hash::from_data(roa)),
+            Bytes::from_static(b"not a roa")
+        );
+        let store = MemoryStore::new();
+        let mut sync = Synchronizer::new(fetcher, &store);
+        assert!(matches!(
+            sync.sync(&relay(), "example.com", None),
+            Err(SyncError::HashMismatch(_))
+        ));
+        assert!(store.is_empty());
+
+        sync.set_max_object_size(Some(10));
+        assert!(matches!(
+            sync.sync(&relay(), "example.com", None),
+            Err(SyncError::TooLarge(_))
+        ));
+        assert!(matches!(
+            sync.sync(&relay(), "example.org", None),
+            Err(SyncError::Fetch(..))
+        ));
+    }
+
+    #[test]
+    fn foreign_manifest() {
+        // A relay for example.net lists the manifests of example.com.
+        let repo = TestRepo::new();
+        let now = TestRepo::base_time();
+        let mut fetcher = serve(&repo, now);
+        let store = MemoryStore::new();
+        for (uri, data) in repo.archive.iter() {
+            store.put(uri.clone(), data.clone()).unwrap();
+        }
+        let publication = Publication::from_store(
+            &store, "example.com", &[rsync("rsync://example.com/repo/")],
+            now
+        ).unwrap();
+        let index = ErikIndex::new(
+            "example.net".into(), now, publication.index().partitions().into()
+        );
+        fetcher.insert(
+            index_uri(&relay(), "example.net").unwrap(), index.to_bytes()
+        );
+
+        let target = MemoryStore::new();
+        let sync = Synchronizer::new(fetcher, &target);
+        assert!(matches!(
+            sync.sync(&relay(), "example.net", None),
+            Err(SyncError::BadManifest(_))
+        ));
+        assert!(target.is_empty());
+    }
+}
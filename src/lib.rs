@@ -16,8 +16,16 @@
 //!   repository objects, such as certificates, manifests, or ROAs;
 //! * `"rrdp"`: support for the RRDP protocol for synchronising RPKI
 //!   repositories;
+//! * `"erik"`: support for the experimental Erik protocol for
+//!   synchronising RPKI repositories – enabling this feature also enables
+//!   the `"repository"` and `"rrdp"` features;
+//! * `"rsync"`: a native async client for fetching repository content
+//!   from rsync servers;
 //! * `"rtr"`: support for the RPKI-to-router protocol (RTR);
 //! * `"slurm"`: support for local exceptions aka SLURM;
+//! * `"validation"`: support for validating complete RPKI repositories
+//!   starting from a set of trust anchors – enabling this feature also
+//!   enables the `"repository"` feature;
 //! * `"serde-support"`: support for Serde serialization and deserialization
 //!   for many of the crate’s types;
 //! * `"softkeys"`: enables an OpenSSL-based signer for creating repository
@@ -31,10 +39,15 @@
 #![allow(clippy::unknown_clippy_lints)]
 
 pub mod ca;
+pub mod clock;
+pub mod erik;
 pub mod repository;
 pub mod rrdp;
+pub mod rsync;
 pub mod rtr;
 pub mod slurm;
+pub mod store;
 pub mod uri;
+pub mod validation;
 pub mod xml;
 
@@ -14,12 +14,12 @@ use bcder::{Captured, Mode, Oid, Tag};
 use bcder::encode::Values;
 use super::oid;
 use super::cert::{Cert, ResourceCert};
-use super::crypto::{Signer, SigningError};
+use super::crypto::{Signer, SigningError, VerificationCache};
 use super::resources::{
     AddressFamily, AsBlock, AsBlocks, AsBlocksBuilder, Asn, AsResources
 };
 use super::sigobj::{SignedObject, SignedObjectBuilder};
-use super::x509::ValidationError;
+use super::x509::{Time, ValidationError};
 
 
 //------------ Aspa ----------------------------------------------------------
@@ -45,13 +45,39 @@ impl Aspa {
     }
 
     pub fn process<F>(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        check_crl: F
+    ) -> Result<(ResourceCert, AsProviderAttestation), ValidationError>
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        self.process_at(issuer, strict, Time::now(), check_crl)
+    }
+
+    pub fn process_at<F>(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        now: Time,
+        check_crl: F
+    ) -> Result<(ResourceCert, AsProviderAttestation), ValidationError>
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        self.process_at_cached(issuer, strict, now, None, check_crl)
+    }
+
+    /// Processes the ASPA using a verification cache if given.
+    pub fn process_at_cached<F>(
         mut self,
         issuer: &ResourceCert,
         strict: bool,
+        now: Time,
+        cache: Option<&VerificationCache>,
         check_crl: F
     ) -> Result<(ResourceCert, AsProviderAttestation), ValidationError>
     where F: FnOnce(&Cert) -> Result<(), ValidationError> {
-        let cert = self.signed.validate(issuer, strict)?;
+        let cert = self.signed.validate_at_cached(
+            issuer, strict, now, cache
+        )?;
         check_crl(cert.as_ref())?;
         self.content.validate(&cert)?;
         Ok((cert, self.content))
@@ -71,6 +97,11 @@ impl Aspa {
     pub fn cert(&self) -> &Cert {
         self.signed.cert()
     }
+
+    /// Returns a reference to the content of this ASPA.
+    pub fn content(&self) -> &AsProviderAttestation {
+        &self.content
+    }
 }
 
 
@@ -110,6 +141,18 @@ pub struct AsProviderAttestation {
     provider_as_set: ProviderAsSet,
 }
 
+impl AsProviderAttestation {
+    /// Returns the customer AS of the attestation.
+    pub fn customer_as(&self) -> Asn {
+        self.customer_as
+    }
+
+    /// Returns a reference to the set of provider ASes.
+    pub fn provider_as_set(&self) -> &ProviderAsSet {
+        &self.provider_as_set
+    }
+}
+
 impl AsProviderAttestation {
     fn take_from<S: decode::Source>(
         cons: &mut decode::Constructed<S>
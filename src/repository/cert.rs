@@ -29,7 +29,8 @@ use bcder::{
 use bytes::Bytes;
 use crate::uri;
 use super::crypto::{
-    KeyIdentifier, PublicKey, SignatureAlgorithm, Signer, SigningError
+    KeyIdentifier, PublicKey, SignatureAlgorithm, Signer, SigningError,
+    VerificationCache
 };
 use super::oid;
 use super::resources::{
@@ -135,6 +136,11 @@ impl Cert {
     pub fn to_captured(&self) -> Captured {
         Captured::from_values(Mode::Der, self.encode_ref())
     }
+
+    /// Returns a reference to the signed data wrapper.
+    pub fn signed_data(&self) -> &SignedData {
+        &self.signed_data
+    }
 }
 
 
@@ -207,9 +213,23 @@ impl Cert {
         issuer: &ResourceCert,
         strict: bool,
         now: Time,
+    ) -> Result<ResourceCert, ValidationError> {
+        self.validate_ca_at_cached(issuer, strict, now, None)
+    }
+
+    /// Validates the certificate as a CA certificate using a cache.
+    ///
+    /// If `cache` is given, the signature is only verified if it isn’t
+    /// already present in the cache.
+    pub fn validate_ca_at_cached(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        now: Time,
+        cache: Option<&VerificationCache>,
     ) -> Result<ResourceCert, ValidationError> {
         self.inspect_ca_at(strict, now)?;
-        self.verify_ca(issuer, strict)
+        self.verify_ca_cached(issuer, strict, cache)
     }
 
     /// Validates the certificate as an EE RPKI-internal certificate.
@@ -234,9 +254,23 @@ impl Cert {
         issuer: &ResourceCert,
         strict: bool,
         now: Time,
+    ) -> Result<ResourceCert, ValidationError>  {
+        self.validate_ee_at_cached(issuer, strict, now, None)
+    }
+
+    /// Validates the certificate as an EE certificate using a cache.
+    ///
+    /// If `cache` is given, the signature is only verified if it isn’t
+    /// already present in the cache.
+    pub fn validate_ee_at_cached(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        now: Time,
+        cache: Option<&VerificationCache>,
     ) -> Result<ResourceCert, ValidationError>  {
         self.inspect_ee_at(strict, now)?;
-        self.verify_ee(issuer, strict)
+        self.verify_ee_cached(issuer, strict, cache)
     }
 
     pub fn validate_detached_ee(
@@ -270,9 +304,23 @@ impl Cert {
         issuer: &ResourceCert,
         strict: bool,
         now: Time,
+    ) -> Result<(), ValidationError> {
+        self.validate_router_at_cached(issuer, strict, now, None)
+    }
+
+    /// Validates the certificate as a router certificate using a cache.
+    ///
+    /// If `cache` is given, the signature is only verified if it isn’t
+    /// already present in the cache.
+    pub fn validate_router_at_cached(
+        &self,
+        issuer: &ResourceCert,
+        strict: bool,
+        now: Time,
+        cache: Option<&VerificationCache>,
     ) -> Result<(), ValidationError> {
         self.inspect_router_at(strict, now)?;
-        self.verify_router(issuer, strict)
+        self.verify_router_cached(issuer, strict, cache)
     }
 
 
@@ -545,25 +593,52 @@ impl Cert {
 
     pub fn verify_ca(
         self, issuer: &ResourceCert, strict: bool
+    ) -> Result<ResourceCert, ValidationError> {
+        self.verify_ca_cached(issuer, strict, None)
+    }
+
+    fn verify_ca_cached(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        cache: Option<&VerificationCache>,
     ) -> Result<ResourceCert, ValidationError> {
         self.verify_issuer_claim(issuer, strict)?;
-        self.verify_signature(issuer, strict)?;
+        self.verify_signature_cached(issuer, strict, cache)?;
         self.verify_resources(issuer, strict)
     }
 
     pub fn verify_ee(
         self, issuer: &ResourceCert, strict: bool
+    ) -> Result<ResourceCert, ValidationError> {
+        self.verify_ee_cached(issuer, strict, None)
+    }
+
+    fn verify_ee_cached(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        cache: Option<&VerificationCache>,
     ) -> Result<ResourceCert, ValidationError> {
         self.verify_issuer_claim(issuer, strict)?;
-        self.verify_signature(issuer, strict)?;
+        self.verify_signature_cached(issuer, strict, cache)?;
         self.verify_resources(issuer, strict)
     }
 
     pub fn verify_router(
         &self, issuer: &ResourceCert, strict: bool
+    ) -> Result<(), ValidationError> {
+        self.verify_router_cached(issuer, strict, None)
+    }
+
+    fn verify_router_cached(
+        &self,
+        issuer: &ResourceCert,
+        strict: bool,
+        cache: Option<&VerificationCache>,
     ) -> Result<(), ValidationError> {
         self.verify_issuer_claim(issuer, strict)?;
-        self.verify_signature(issuer, strict)?;
+        self.verify_signature_cached(issuer, strict, cache)?;
         self.verify_as_resources(issuer, strict)
     }
 
@@ -687,7 +762,7 @@ impl Cert {
     }
 
     /// Inspects a router certificate’s extended key usage.
-    fn inspect_router_eku(
+    pub(crate) fn inspect_router_eku(
         &self, _strict: bool
     ) -> Result<(), ValidationError> {
         match self.extended_key_usage() {
@@ -741,10 +816,20 @@ impl Cert {
     pub fn verify_signature(
         &self,
         issuer: &Cert,
-        _strict: bool
+        strict: bool
     ) -> Result<(), ValidationError> {
-        self.signed_data.verify_signature(
-            issuer.subject_public_key_info()
+        self.verify_signature_cached(issuer, strict, None)
+    }
+
+    /// Validates the certificate’s signature using a cache if given.
+    pub fn verify_signature_cached(
+        &self,
+        issuer: &Cert,
+        _strict: bool,
+        cache: Option<&VerificationCache>,
+    ) -> Result<(), ValidationError> {
+        self.signed_data.verify_signature_cached(
+            issuer.subject_public_key_info(), cache
         )
     }
 
@@ -1000,6 +1085,13 @@ impl TbsCert {
 /// # Data Access
 ///
 impl TbsCert {
+    /// Returns the signature algorithm given in the certificate.
+    ///
+    /// This should be identical to the algorithm of the outer signature.
+    pub fn signature(&self) -> SignatureAlgorithm {
+        self.signature
+    }
+
     /// Returns the serial number of the certificate.
     pub fn serial_number(&self) -> Serial {
         self.serial_number
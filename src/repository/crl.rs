@@ -23,7 +23,8 @@ use bcder::encode::PrimitiveContent;
 use crate::uri;
 use super::oid;
 use super::crypto::{
-    KeyIdentifier, PublicKey, SignatureAlgorithm, Signer, SigningError
+    KeyIdentifier, PublicKey, SignatureAlgorithm, Signer, SigningError,
+    VerificationCache
 };
 use super::x509::{
     Name, RepresentationError, Serial, SignedData, Time, ValidationError,
@@ -123,11 +124,20 @@ impl Crl {
     pub fn validate(
         &self,
         public_key: &PublicKey
+    ) -> Result<(), ValidationError> {
+        self.validate_cached(public_key, None)
+    }
+
+    /// Validates the list using a verification cache if given.
+    pub fn validate_cached(
+        &self,
+        public_key: &PublicKey,
+        cache: Option<&VerificationCache>,
     ) -> Result<(), ValidationError> {
         if self.tbs.signature != self.signed_data.signature().algorithm() {
             return Err(ValidationError)
         }
-        self.signed_data.verify_signature(public_key)
+        self.signed_data.verify_signature_cached(public_key, cache)
     }
 
     pub fn encode_ref(&self) -> impl encode::Values + '_ {
@@ -302,7 +312,12 @@ impl<C> TbsCertList<C> {
 
     /// Returns whether the CRL’s nextUpdate time has passed.
     pub fn is_stale(&self) -> bool {
-        self.next_update < Time::now()
+        self.is_stale_at(Time::now())
+    }
+
+    /// Returns whether the CRL’s nextUpdate time has passed at given time.
+    pub fn is_stale_at(&self, now: Time) -> bool {
+        self.next_update < now
     }
 
     /// Sets the time of next update.
@@ -0,0 +1,220 @@
+//! Caching the results of signature verification.
+//!
+//! Most objects in the RPKI do not change from one validation run to the
+//! next. Verifying their signatures again and again is costly, so the
+//! [`VerificationCache`] remembers which signatures have been verified
+//! successfully.
+//!
+//! An entry in the cache is the SHA-256 digest over the public key and its
+//! algorithm, the signature algorithm, the signed data, and the signature.
+//! A signature is only considered verified if exactly the same data was
+//! signed with exactly the same signature and algorithm and checked against
+//! exactly the same key before. Only successful verifications are cached.
+//!
+//! The cache only covers the cryptographic operation. Everything else –
+//! in particular validity times and revocation status – still needs to be
+//! checked every time.
+
+use std::{fs, io};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use bcder::Mode;
+use bcder::encode::Values;
+use super::digest::DigestAlgorithm;
+use super::keys::{PublicKey, VerificationError};
+use super::signature::Signature;
+
+
+//------------ VerificationCache ---------------------------------------------
+
+/// A cache of successfully verified signatures.
+///
+/// The cache can be shared between threads. It can be written to a file
+/// via [`save`][Self::save] and read back via [`load`][Self::load].
+///
+/// Entries are never removed automatically. Instead, the cache tracks
+/// which entries have been used since it was created or last pruned and
+/// [`prune`][Self::prune] removes all others. Calling it after each
+/// validation run keeps the cache to the set of objects currently in use.
+#[derive(Debug, Default)]
+pub struct VerificationCache {
+    /// The entries and whether they have been used since the last prune.
+    entries: Mutex<HashMap<CacheKey, bool>>,
+}
+
+/// The key of a cache entry.
+type CacheKey = [u8; KEY_LEN];
+
+/// The length of a cache key.
+const KEY_LEN: usize = 32;
+
+/// The start of a cache file.
+const FILE_MAGIC: &[u8] = b"RPKI-VERIFICATION-CACHE-2\n";
+
+impl VerificationCache {
+    /// Creates a new, empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a cache from a file.
+    ///
+    /// All entries loaded are considered unused.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, io::Error> {
+        let mut data = Vec::new();
+        fs::File::open(path)?.read_to_end(&mut data)?;
+        let data = match data.strip_prefix(FILE_MAGIC) {
+            Some(data) if data.len() % KEY_LEN == 0 => data,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData, "invalid verification cache"
+                ))
+            }
+        };
+        let entries = data.chunks(KEY_LEN).map(|chunk| {
+            let mut key = [0u8; KEY_LEN];
+            key.copy_from_slice(chunk);
+            (key, false)
+        }).collect();
+        Ok(VerificationCache { entries: Mutex::new(entries) })
+    }
+
+    /// Writes the cache to a file.
+    ///
+    /// The file is first written under a temporary name and then moved
+    /// into place, so the file at `path` is always complete.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), io::Error> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        {
+            let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+            file.write_all(FILE_MAGIC)?;
+            for key in self.lock().keys() {
+                file.write_all(key)?;
+            }
+            file.into_inner()?.sync_all()?;
+        }
+        fs::rename(tmp, path)
+    }
+
+    /// Returns the number of entries in the cache.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Removes all entries from the cache.
+    pub fn clear(&self) {
+        self.lock().clear()
+    }
+
+    /// Removes all entries not used since the last prune.
+    ///
+    /// All remaining entries are marked as unused.
+    pub fn prune(&self) {
+        let mut entries = self.lock();
+        entries.retain(|_, used| *used);
+        entries.values_mut().for_each(|used| *used = false);
+    }
+
+    /// Verifies a signature unless it has been verified before.
+    ///
+    /// This is the equivalent of [`PublicKey::verify`] using the cache.
+    pub fn verify(
+        &self,
+        public_key: &PublicKey,
+        message: &[u8],
+        signature: &Signature,
+    ) -> Result<(), VerificationError> {
+        let key = Self::key(public_key, message, signature);
+        if let Some(used) = self.lock().get_mut(&key) {
+            *used = true;
+            return Ok(())
+        }
+        public_key.verify(message, signature)?;
+        self.lock().insert(key, true);
+        Ok(())
+    }
+
+    /// Returns the cache key for a signature.
+    fn key(
+        public_key: &PublicKey, message: &[u8], signature: &Signature
+    ) -> CacheKey {
+        let key_info = public_key.to_info_bytes();
+        let algorithm = signature.algorithm().x509_encode().to_captured(
+            Mode::Der
+        );
+        let mut context = DigestAlgorithm::default().start();
+        for &part in &[
+            key_info.as_ref(), algorithm.as_slice(), message,
+            signature.value().as_ref()
+        ] {
+            context.update(&(part.len() as u64).to_be_bytes());
+            context.update(part);
+        }
+        let mut res = [0u8; KEY_LEN];
+        res.copy_from_slice(context.finish().as_ref());
+        res
+    }
+
+    /// Locks the entries.
+    ///
+    /// Since a panic can’t leave the entries in an inconsistent state, a
+    /// poisoned lock is simply ignored.
+    fn lock(
+        &self
+    ) -> std::sync::MutexGuard<'_, HashMap<CacheKey, bool>> {
+        self.entries.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(all(test, feature = "softkeys"))]
+mod signer_test {
+    use bytes::Bytes;
+    use crate::repository::crypto::{PublicKeyFormat, Signer};
+    use crate::repository::crypto::signature::SignatureAlgorithm;
+    use crate::repository::crypto::softsigner::OpenSslSigner;
+    use super::*;
+
+    #[test]
+    fn verify_and_persist() {
+        let signer = OpenSslSigner::new();
+        let key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let pubkey = signer.get_key_info(&key).unwrap();
+        let signature = signer.sign(
+            &key, SignatureAlgorithm::default(), b"foo"
+        ).unwrap();
+        let bad = Signature::new(
+            SignatureAlgorithm::default(), Bytes::from_static(b"bar")
+        );
+
+        let cache = VerificationCache::new();
+        assert!(cache.verify(&pubkey, b"foo", &signature).is_ok());
+        assert!(cache.verify(&pubkey, b"bar", &signature).is_err());
+        assert!(cache.verify(&pubkey, b"foo", &bad).is_err());
+        assert_eq!(cache.len(), 1);
+        assert!(cache.verify(&pubkey, b"foo", &signature).is_ok());
+
+        let dir = std::env::temp_dir().join(format!(
+            "rpki-verification-cache-{}", std::process::id()
+        ));
+        cache.save(&dir).unwrap();
+        let loaded = VerificationCache::load(&dir).unwrap();
+        fs::remove_file(&dir).unwrap();
+        assert_eq!(loaded.len(), 1);
+
+        // Entries loaded are unused and pruned unless used.
+        loaded.prune();
+        assert!(loaded.is_empty());
+    }
+}
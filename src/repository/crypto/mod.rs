@@ -1,6 +1,7 @@
 //! Signing related implementations.
 //!
 
+pub use self::cache::VerificationCache;
 pub use self::digest::{Digest, DigestAlgorithm};
 pub use self::keys::{
     KeyIdentifier, PublicKey, PublicKeyFormat, VerificationError
@@ -8,6 +9,7 @@ pub use self::keys::{
 pub use self::signer::{Signer, SigningError};
 pub use self::signature::{Signature, SignatureAlgorithm};
 
+pub mod cache;
 pub mod digest;
 pub mod keys;
 pub mod signer;
@@ -0,0 +1,1532 @@
+//! Checking repository objects for conformance.
+//!
+//! Validation of repository objects stops at the first problem it finds and,
+//! when not in strict mode, silently accepts a number of known violations
+//! of the specifications that are common in the wild. This is fine for a
+//! relying party but not very helpful for someone who wants to check their
+//! own objects before publishing them.
+//!
+//! The [`Linter`] in this module instead inspects an encoded object and
+//! collects every deviation from RFC 6487 (certificates and CRLs), RFC 6488
+//! (signed objects), RFC 9286 (manifests), RFC 9582 (ROAs), and the ASPA
+//! profile draft it can find into a [`Report`]. Each [`Finding`] is given a
+//! [`Severity`] that tells whether the object would be rejected by this
+//! crate, is accepted despite the violation, or merely deviates from a
+//! recommendation.
+//!
+//! Since some checks concern the encoding itself, the linter works on the
+//! encoded octets of an object rather than on a decoded value.
+
+use std::fmt;
+use std::collections::HashSet;
+use bcder::{decode, Mode, Oid, Tag};
+use bcder::decode::Source;
+use super::oid;
+use super::aspa::Aspa;
+use super::cert::{Cert, KeyUsage};
+use super::crl::Crl;
+use super::manifest::Manifest;
+use super::roa::Roa;
+use super::sigobj::SignedObject;
+use super::x509::{Name, Time};
+
+
+//------------ Linter --------------------------------------------------------
+
+/// A conformance checker for repository objects.
+///
+/// By default, the linter ignores anything that depends on the current
+/// time, i.e., whether certificates are expired or manifests and CRLs are
+/// stale. A linter created via [`at`][Self::at] checks these against the
+/// given time, too.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Linter {
+    /// The time to check time-dependent properties against.
+    now: Option<Time>,
+}
+
+impl Linter {
+    /// Creates a linter that ignores time-dependent properties.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a linter that checks time-dependent properties at `now`.
+    pub fn at(now: Time) -> Self {
+        Linter { now: Some(now) }
+    }
+
+    /// Checks an encoded resource certificate.
+    ///
+    /// If `kind` is `None`, the kind of certificate is derived from the
+    /// certificate itself.
+    pub fn lint_cert(&self, data: &[u8], kind: Option<CertKind>) -> Report {
+        let mut report = Report::new();
+        if let Some(cert) = self.decode_cert(data, &mut report) {
+            let kind = kind.unwrap_or_else(|| CertKind::guess(&cert));
+            self.check_cert(&cert, data, kind, &mut report);
+        }
+        report
+    }
+
+    /// Checks an encoded certificate revocation list.
+    pub fn lint_crl(&self, data: &[u8]) -> Report {
+        let mut report = Report::new();
+        let crl = match Crl::decode(data) {
+            Ok(crl) => crl,
+            Err(err) => match Mode::Ber.decode(data, Crl::take_from) {
+                Ok(crl) => {
+                    report.push(
+                        Severity::Error, RFC_6487_5,
+                        "CRL is not DER encoded"
+                    );
+                    crl
+                }
+                Err(_) => {
+                    report.push(
+                        Severity::Error, RFC_6487_5,
+                        format!("CRL cannot be decoded: {}", err)
+                    );
+                    return report
+                }
+            }
+        };
+        self.check_crl(&crl, data, &mut report);
+        report
+    }
+
+    /// Checks an encoded signed object of any type.
+    ///
+    /// This only performs the checks of RFC 6488 that are common to all
+    /// signed objects and does not look at the content.
+    pub fn lint_signed_object(&self, data: &[u8]) -> Report {
+        let mut report = Report::new();
+        self.check_signed_object(data, &mut report);
+        report
+    }
+
+    /// Checks an encoded manifest.
+    pub fn lint_manifest(&self, data: &[u8]) -> Report {
+        let mut report = Report::new();
+        let signed = match self.check_signed_object(data, &mut report) {
+            Some(signed) => signed,
+            None => return report
+        };
+        if signed.content_type() != &oid::CT_RPKI_MANIFEST {
+            report.push(
+                Severity::Error, RFC_9286_4,
+                "content type is not a manifest"
+            );
+            return report
+        }
+        let manifest = match Manifest::decode(data, false) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                report.push(
+                    Severity::Error, RFC_9286_4,
+                    format!("manifest content cannot be decoded: {}", err)
+                );
+                return report
+            }
+        };
+        self.check_manifest(&manifest, &mut report);
+        report
+    }
+
+    /// Checks an encoded ROA.
+    pub fn lint_roa(&self, data: &[u8]) -> Report {
+        let mut report = Report::new();
+        let signed = match self.check_signed_object(data, &mut report) {
+            Some(signed) => signed,
+            None => return report
+        };
+        if signed.content_type() != &oid::ROUTE_ORIGIN_AUTHZ {
+            report.push(
+                Severity::Error, RFC_9582_3,
+                "content type is not a ROA"
+            );
+            return report
+        }
+        let roa = match Roa::decode(data, false) {
+            Ok(roa) => roa,
+            Err(err) => {
+                report.push(
+                    Severity::Error, RFC_9582_4,
+                    format!("ROA content cannot be decoded: {}", err)
+                );
+                return report
+            }
+        };
+        self.check_roa(&roa, &mut report);
+        report
+    }
+
+    /// Checks an encoded ASPA.
+    pub fn lint_aspa(&self, data: &[u8]) -> Report {
+        let mut report = Report::new();
+        let signed = match self.check_signed_object(data, &mut report) {
+            Some(signed) => signed,
+            None => return report
+        };
+        if signed.content_type() != &oid::CT_ASPA {
+            report.push(
+                Severity::Error, ASPA_PROFILE,
+                "content type is not an ASPA"
+            );
+            return report
+        }
+        let aspa = match Aspa::decode(data, false) {
+            Ok(aspa) => aspa,
+            Err(err) => {
+                report.push(
+                    Severity::Error, ASPA_PROFILE,
+                    format!("ASPA content cannot be decoded: {}", err)
+                );
+                return report
+            }
+        };
+        self.check_aspa(&aspa, &mut report);
+        report
+    }
+}
+
+
+/// # Certificates
+///
+impl Linter {
+    /// Decodes a certificate, falling back to BER if necessary.
+    fn decode_cert(&self, data: &[u8], report: &mut Report) -> Option<Cert> {
+        match Cert::decode(data) {
+            Ok(cert) => Some(cert),
+            Err(err) => match Mode::Ber.decode(data, Cert::take_from) {
+                Ok(cert) => {
+                    report.push(
+                        Severity::Error, RFC_6487_4,
+                        "certificate is not DER encoded"
+                    );
+                    Some(cert)
+                }
+                Err(_) => {
+                    report.push(
+                        Severity::Error, RFC_6487_4,
+                        format!("certificate cannot be decoded: {}", err)
+                    );
+                    None
+                }
+            }
+        }
+    }
+
+    /// Performs all checks on a decoded certificate.
+    fn check_cert(
+        &self, cert: &Cert, data: &[u8], kind: CertKind, report: &mut Report
+    ) {
+        // 4.3. Signature Algorithm. Both mentions must be identical.
+        if cert.signature() != cert.signed_data().signature().algorithm() {
+            report.push(
+                Severity::Error, RFC_6487_4_3,
+                "signature algorithm differs from outer signature algorithm"
+            );
+        }
+
+        // 4.4. Issuer and 4.5. Subject.
+        check_name(cert.issuer(), "issuer", RFC_6487_4_4, false, report);
+        check_name(
+            cert.subject(), "subject", RFC_6487_4_5,
+            kind == CertKind::Router, report
+        );
+
+        // 4.6. Validity.
+        let validity = cert.validity();
+        if validity.not_before() >= validity.not_after() {
+            report.push(
+                Severity::Error, RFC_6487_4_6,
+                "notBefore is not before notAfter"
+            );
+        }
+        if let Some(now) = self.now {
+            if validity.validate_at(now).is_err() {
+                report.push(
+                    Severity::Error, RFC_6487_4_6,
+                    "certificate is not valid at the given time"
+                );
+            }
+        }
+
+        // 4.7. Subject Public Key Info.
+        let key_ok = match kind {
+            CertKind::Router => {
+                cert.subject_public_key_info().allow_router_cert()
+            }
+            _ => cert.subject_public_key_info().allow_rpki_cert()
+        };
+        if !key_ok {
+            report.push(
+                Severity::Error, RFC_6487_4_7,
+                "public key algorithm not allowed"
+            );
+        }
+
+        // 4.8. Extensions, as far as the encoding is concerned.
+        match cert_extensions(data) {
+            Ok(extensions) => check_cert_extensions(&extensions, report),
+            Err(_) => {
+                report.push(
+                    Severity::Error, RFC_6487_4_8,
+                    "extensions cannot be decoded"
+                );
+            }
+        }
+
+        // 4.8.1. Basic Constraints.
+        if kind.is_ca() {
+            if cert.basic_ca() != Some(true) {
+                report.push(
+                    Severity::Error, RFC_6487_4_8_1,
+                    "CA certificate without cA basic constraint"
+                );
+            }
+        }
+        else if cert.basic_ca().is_some() {
+            report.push(
+                Severity::Error, RFC_6487_4_8_1,
+                "basic constraints present in EE certificate"
+            );
+        }
+
+        // 4.8.2. Subject Key Identifier.
+        if cert.subject_key_identifier()
+            != cert.subject_public_key_info().key_identifier()
+        {
+            report.push(
+                Severity::Error, RFC_6487_4_8_2,
+                "subject key identifier does not match the public key"
+            );
+        }
+
+        // 4.8.3. Authority Key Identifier.
+        match cert.authority_key_identifier() {
+            Some(aki) => {
+                if kind == CertKind::Ta
+                    && aki != cert.subject_key_identifier()
+                {
+                    report.push(
+                        Severity::Error, RFC_6487_4_8_3,
+                        "trust anchor authority key identifier differs \
+                         from subject key identifier"
+                    );
+                }
+            }
+            None => {
+                if kind != CertKind::Ta {
+                    report.push(
+                        Severity::Error, RFC_6487_4_8_3,
+                        "authority key identifier missing"
+                    );
+                }
+            }
+        }
+
+        // 4.8.4. Key Usage.
+        let expected = if kind.is_ca() { KeyUsage::Ca } else { KeyUsage::Ee };
+        if cert.key_usage() != expected {
+            report.push(
+                Severity::Error, RFC_6487_4_8_4,
+                "key usage does not match certificate kind"
+            );
+        }
+
+        // 4.8.5. Extended Key Usage.
+        if kind == CertKind::Router {
+            if cert.inspect_router_eku(false).is_err() {
+                report.push(
+                    Severity::Error, RFC_8209_3_1_3_2,
+                    "extended key usage without id-kp-bgpsec-router"
+                );
+            }
+        }
+        else if cert.extended_key_usage().is_some() {
+            report.push(
+                Severity::Error, RFC_6487_4_8_5,
+                "extended key usage present"
+            );
+        }
+
+        // 4.8.6. CRL Distribution Points and 4.8.7. Authority Information
+        // Access.
+        if kind == CertKind::Ta {
+            if cert.crl_uri().is_some() {
+                report.push(
+                    Severity::Error, RFC_6487_4_8_6,
+                    "CRL distribution point in trust anchor certificate"
+                );
+            }
+            if cert.ca_issuer().is_some() {
+                report.push(
+                    Severity::Error, RFC_6487_4_8_7,
+                    "authority information access in trust anchor \
+                     certificate"
+                );
+            }
+        }
+        else {
+            if cert.crl_uri().is_none() {
+                report.push(
+                    Severity::Error, RFC_6487_4_8_6,
+                    "CRL distribution point missing"
+                );
+            }
+            if cert.ca_issuer().is_none() {
+                report.push(
+                    Severity::Error, RFC_6487_4_8_7,
+                    "authority information access missing"
+                );
+            }
+        }
+
+        // 4.8.8. Subject Information Access.
+        match kind {
+            CertKind::Ta | CertKind::Ca => {
+                if cert.ca_repository().is_none() {
+                    report.push(
+                        Severity::Error, RFC_6487_4_8_8,
+                        "caRepository access method missing"
+                    );
+                }
+                if cert.rpki_manifest().is_none() {
+                    report.push(
+                        Severity::Error, RFC_6487_4_8_8,
+                        "rpkiManifest access method missing"
+                    );
+                }
+                if cert.signed_object().is_some() {
+                    report.push(
+                        Severity::Error, RFC_6487_4_8_8,
+                        "signedObject access method in CA certificate"
+                    );
+                }
+            }
+            CertKind::Ee => {
+                if cert.ca_repository().is_some()
+                    || cert.rpki_manifest().is_some()
+                {
+                    report.push(
+                        Severity::Error, RFC_6487_4_8_8,
+                        "CA access methods in EE certificate"
+                    );
+                }
+                if cert.signed_object().is_none() {
+                    report.push(
+                        Severity::Error, RFC_6487_4_8_8,
+                        "signedObject access method missing"
+                    );
+                }
+            }
+            CertKind::Router => {
+                if cert.ca_repository().is_some()
+                    || cert.rpki_manifest().is_some()
+                    || cert.signed_object().is_some()
+                    || cert.rpki_notify().is_some()
+                {
+                    report.push(
+                        Severity::Error, RFC_8209_3_1_3,
+                        "subject information access in router certificate"
+                    );
+                }
+            }
+        }
+
+        // 4.8.10. IP Resources and 4.8.11. AS Resources.
+        if kind == CertKind::Ta
+            && (
+                cert.v4_resources().is_inherited()
+                || cert.v6_resources().is_inherited()
+                || cert.as_resources().is_inherited()
+            )
+        {
+            report.push(
+                Severity::Error, RFC_6487_4_8_10,
+                "inherited resources in trust anchor certificate"
+            );
+        }
+        if kind == CertKind::Router {
+            if cert.v4_resources().is_present()
+                || cert.v6_resources().is_present()
+            {
+                report.push(
+                    Severity::Error, RFC_8209_3_1_3,
+                    "IP resources in router certificate"
+                );
+            }
+            if !cert.as_resources().is_present() {
+                report.push(
+                    Severity::Error, RFC_8209_3_1_3,
+                    "AS resources missing in router certificate"
+                );
+            }
+        }
+    }
+}
+
+
+/// # CRLs
+///
+impl Linter {
+    /// Performs all checks on a decoded CRL.
+    fn check_crl(&self, crl: &Crl, data: &[u8], report: &mut Report) {
+        if crl.signature() != crl.signed_data().signature().algorithm() {
+            report.push(
+                Severity::Error, RFC_6487_5,
+                "signature algorithm differs from outer signature algorithm"
+            );
+        }
+        check_name(crl.issuer(), "issuer", RFC_6487_5, false, report);
+        if crl.this_update() >= crl.next_update() {
+            report.push(
+                Severity::RelaxedAccepted, RFC_6487_5,
+                "thisUpdate is not before nextUpdate"
+            );
+        }
+        if let Some(now) = self.now {
+            if crl.is_stale_at(now) {
+                report.push(
+                    Severity::Error, RFC_9286_6_6,
+                    "CRL is stale at the given time"
+                );
+            }
+            if crl.this_update() > now {
+                report.push(
+                    Severity::Warning, RFC_6487_5,
+                    "thisUpdate is in the future"
+                );
+            }
+        }
+        match crl_extensions(data) {
+            Ok(extensions) => {
+                for ext in extensions {
+                    check_default_critical(&ext, report);
+                    if ext.critical == Some(true) {
+                        report.push(
+                            Severity::RelaxedAccepted, RFC_6487_5,
+                            format!(
+                                "CRL extension {} is marked critical",
+                                ext_name(&ext.oid)
+                            )
+                        );
+                    }
+                }
+            }
+            Err(_) => {
+                report.push(
+                    Severity::Error, RFC_6487_5,
+                    "CRL extensions cannot be decoded"
+                );
+            }
+        }
+    }
+}
+
+
+/// # Signed Objects
+///
+impl Linter {
+    /// Checks the generic parts of a signed object.
+    ///
+    /// Returns the decoded object if decoding succeeded.
+    fn check_signed_object(
+        &self, data: &[u8], report: &mut Report
+    ) -> Option<SignedObject> {
+        let signed = match SignedObject::decode(data, true) {
+            Ok(signed) => signed,
+            Err(err) => match SignedObject::decode(data, false) {
+                Ok(signed) => {
+                    report.push(
+                        Severity::RelaxedAccepted, RFC_6488_2,
+                        "signed object is not DER encoded"
+                    );
+                    signed
+                }
+                Err(_) => {
+                    report.push(
+                        Severity::Error, RFC_6488_2,
+                        format!("signed object cannot be decoded: {}", err)
+                    );
+                    return None
+                }
+            }
+        };
+
+        // 3.1.c. The SID must match the certificate’s key identifier and
+        // 3.2. the signature must verify.
+        if signed.verify_compliance(false).is_err()
+            || signed.verify_signature(false).is_err()
+        {
+            report.push(
+                Severity::Error, RFC_6488_3,
+                "signature or signer identifier does not match EE certificate"
+            );
+        }
+
+        // The EE certificate is an RPKI EE certificate.
+        let cert = signed.cert();
+        let cert_data = cert.to_captured();
+        self.check_cert(cert, cert_data.as_slice(), CertKind::Ee, report);
+        Some(signed)
+    }
+
+    /// Checks the content of a manifest.
+    fn check_manifest(&self, manifest: &Manifest, report: &mut Report) {
+        let content = manifest.content();
+        if content.this_update() >= content.next_update() {
+            report.push(
+                Severity::Error, RFC_9286_4_2_1,
+                "thisUpdate is not before nextUpdate"
+            );
+        }
+        if let Some(now) = self.now {
+            if content.is_stale_at(now) {
+                report.push(
+                    Severity::Error, RFC_9286_6_3,
+                    "manifest is stale at the given time"
+                );
+            }
+        }
+
+        let cert = manifest.cert();
+        if cert.v4_resources().is_present()
+            && !cert.v4_resources().is_inherited()
+            || cert.v6_resources().is_present()
+            && !cert.v6_resources().is_inherited()
+            || cert.as_resources().is_present()
+            && !cert.as_resources().is_inherited()
+        {
+            report.push(
+                Severity::RelaxedAccepted, RFC_9286_5_1,
+                "EE certificate does not use inherit for its resources"
+            );
+        }
+        let validity = cert.validity();
+        if validity.not_before() > content.this_update()
+            || validity.not_after() < content.next_update()
+        {
+            report.push(
+                Severity::Warning, RFC_9286_5_1,
+                "EE certificate validity does not cover thisUpdate to \
+                 nextUpdate"
+            );
+        }
+
+        let mut names = HashSet::new();
+        for item in content.iter() {
+            let name = item.file();
+            if !is_usable_file_name(name.as_ref()) {
+                report.push(
+                    Severity::Error, RFC_9286_4_2_2,
+                    format!(
+                        "unusable file name '{}'",
+                        String::from_utf8_lossy(name.as_ref())
+                    )
+                );
+            }
+            else if !is_valid_file_name(name.as_ref()) {
+                report.push(
+                    Severity::RelaxedAccepted, RFC_9286_4_2_2,
+                    format!(
+                        "illegal file name '{}'",
+                        String::from_utf8_lossy(name.as_ref())
+                    )
+                );
+            }
+            else if !is_known_extension(name.as_ref()) {
+                report.push(
+                    Severity::Warning, RFC_9286_4_2_2,
+                    format!(
+                        "unknown file extension in '{}'",
+                        String::from_utf8_lossy(name.as_ref())
+                    )
+                );
+            }
+            if !names.insert(name.clone()) {
+                report.push(
+                    Severity::Error, RFC_9286_4_2_2,
+                    format!(
+                        "duplicate file name '{}'",
+                        String::from_utf8_lossy(name.as_ref())
+                    )
+                );
+            }
+            if item.hash().len() != content.file_hash_alg().digest_len() {
+                report.push(
+                    Severity::Error, RFC_9286_4_2_1,
+                    format!(
+                        "hash for '{}' has wrong length",
+                        String::from_utf8_lossy(name.as_ref())
+                    )
+                );
+            }
+        }
+    }
+
+    /// Checks the content of a ROA.
+    fn check_roa(&self, roa: &Roa, report: &mut Report) {
+        let cert = roa.cert();
+        let content = roa.content();
+        if cert.v4_resources().is_inherited()
+            || cert.v6_resources().is_inherited()
+        {
+            report.push(
+                Severity::RelaxedAccepted, RFC_9582_5,
+                "EE certificate uses inherit for IP resources"
+            );
+        }
+        if cert.as_resources().is_present() {
+            report.push(
+                Severity::RelaxedAccepted, RFC_9582_5,
+                "EE certificate contains AS resources"
+            );
+        }
+        if content.v4_addrs().is_empty() && content.v6_addrs().is_empty() {
+            report.push(
+                Severity::RelaxedAccepted, RFC_9582_4_3_3,
+                "ROA does not contain any prefixes"
+            );
+        }
+
+        // Prefixes must be covered by the EE certificate.
+        for &(addrs, resources, v4) in &[
+            (content.v4_addrs(), cert.v4_resources(), true),
+            (content.v6_addrs(), cert.v6_resources(), false),
+        ] {
+            let blocks = match resources.to_blocks() {
+                Ok(blocks) => blocks,
+                Err(_) => continue, // inherited, we can’t tell.
+            };
+            for addr in addrs.iter() {
+                if !blocks.contains_roa(&addr) {
+                    let prefix = addr.prefix();
+                    report.push(
+                        Severity::Error, RFC_9582_5,
+                        if v4 {
+                            format!(
+                                "prefix {}/{} not covered by EE certificate",
+                                prefix.to_v4(), prefix.addr_len()
+                            )
+                        }
+                        else {
+                            format!(
+                                "prefix {}/{} not covered by EE certificate",
+                                prefix.to_v6(), prefix.addr_len()
+                            )
+                        }
+                    );
+                }
+            }
+        }
+
+        // Prefixes should be in canonical form: sorted and unique.
+        for addrs in &[content.v4_addrs(), content.v6_addrs()] {
+            let list: Vec<_> = addrs.iter().map(|addr| {
+                (addr.prefix().addr(), addr.prefix().addr_len())
+            }).collect();
+            if list.windows(2).any(|pair| pair[0] >= pair[1]) {
+                report.push(
+                    Severity::Warning, RFC_9582_4_3_3,
+                    "prefixes are not sorted and unique"
+                );
+            }
+        }
+    }
+
+    /// Checks the content of an ASPA.
+    fn check_aspa(&self, aspa: &Aspa, report: &mut Report) {
+        let cert = aspa.cert();
+        let content = aspa.content();
+        if cert.v4_resources().is_present()
+            || cert.v6_resources().is_present()
+        {
+            report.push(
+                Severity::RelaxedAccepted, ASPA_PROFILE,
+                "EE certificate contains IP resources"
+            );
+        }
+        if cert.as_resources().is_inherited() {
+            report.push(
+                Severity::RelaxedAccepted, ASPA_PROFILE,
+                "EE certificate uses inherit for AS resources"
+            );
+        }
+        else if let Ok(blocks) = cert.as_resources().to_blocks() {
+            if let Ok(customer) = content.as_resources().to_blocks() {
+                if !blocks.contains(&customer) {
+                    report.push(
+                        Severity::Error, ASPA_PROFILE,
+                        "customer AS not covered by EE certificate"
+                    );
+                }
+            }
+        }
+        let providers: Vec<_> = content.provider_as_set().iter().map(|item| {
+            item.provider()
+        }).collect();
+        if providers.is_empty() {
+            report.push(
+                Severity::RelaxedAccepted, ASPA_PROFILE,
+                "provider AS set is empty"
+            );
+        }
+        if providers.contains(&content.customer_as()) {
+            report.push(
+                Severity::RelaxedAccepted, ASPA_PROFILE,
+                "customer AS is listed as its own provider"
+            );
+        }
+        if providers.windows(2).any(|pair| pair[0] >= pair[1]) {
+            report.push(
+                Severity::RelaxedAccepted, ASPA_PROFILE,
+                "provider AS set is not sorted and unique"
+            );
+        }
+    }
+}
+
+
+//------------ CertKind ------------------------------------------------------
+
+/// The kind of resource certificate to check for.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CertKind {
+    /// A trust anchor certificate.
+    Ta,
+
+    /// A CA certificate issued by another CA.
+    Ca,
+
+    /// An EE certificate of a signed object.
+    Ee,
+
+    /// A BGPsec router certificate.
+    Router,
+}
+
+impl CertKind {
+    /// Guesses the kind of a certificate.
+    ///
+    /// Self-signed CA certificates are considered trust anchors, other
+    /// certificates with the cA basic constraint are CA certificates. Of
+    /// the rest, those with an extended key usage are router certificates
+    /// and all others EE certificates.
+    pub fn guess(cert: &Cert) -> Self {
+        if cert.basic_ca() == Some(true) {
+            if cert.is_self_signed() {
+                CertKind::Ta
+            }
+            else {
+                CertKind::Ca
+            }
+        }
+        else if cert.extended_key_usage().is_some() {
+            CertKind::Router
+        }
+        else {
+            CertKind::Ee
+        }
+    }
+
+    /// Returns whether this is a kind of CA certificate.
+    pub fn is_ca(self) -> bool {
+        matches!(self, CertKind::Ta | CertKind::Ca)
+    }
+}
+
+
+//------------ Report --------------------------------------------------------
+
+/// The findings of checking an object.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// The findings in the order they were found.
+    findings: Vec<Finding>,
+}
+
+impl Report {
+    /// Creates a new, empty report.
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a finding to the report.
+    fn push(
+        &mut self,
+        severity: Severity,
+        rule: &'static str,
+        message: impl Into<String>
+    ) {
+        self.findings.push(Finding {
+            severity, rule, message: message.into()
+        })
+    }
+
+    /// Returns whether the object is free of any findings.
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Returns whether there are findings of severity error.
+    pub fn has_errors(&self) -> bool {
+        self.findings.iter().any(|item| item.severity == Severity::Error)
+    }
+
+    /// Returns the findings.
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
+    /// Returns an iterator over the findings of the given severity.
+    pub fn with_severity(
+        &self, severity: Severity
+    ) -> impl Iterator<Item = &Finding> + '_ {
+        self.findings.iter().filter(move |item| item.severity == severity)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for item in &self.findings {
+            writeln!(f, "{}", item)?;
+        }
+        Ok(())
+    }
+}
+
+
+//------------ Finding -------------------------------------------------------
+
+/// A single deviation from the specifications.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Finding {
+    /// How bad is it?
+    pub severity: Severity,
+
+    /// The RFC and section that has been violated.
+    pub rule: &'static str,
+
+    /// A description of the deviation.
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.rule, self.message)
+    }
+}
+
+
+//------------ Severity ------------------------------------------------------
+
+/// The severity of a finding.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    /// A violation of the specification this crate rejects the object for.
+    Error,
+
+    /// A violation of a recommendation of the specification.
+    Warning,
+
+    /// A violation of the specification this crate accepts nonetheless.
+    ///
+    /// This includes violations only accepted when not validating in
+    /// strict mode as well as those not checked during validation at all.
+    RelaxedAccepted,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::RelaxedAccepted => "relaxed-accepted",
+        })
+    }
+}
+
+
+//------------ Rules ---------------------------------------------------------
+
+const RFC_6487_4: &str = "RFC 6487, section 4";
+const RFC_6487_4_3: &str = "RFC 6487, section 4.3";
+const RFC_6487_4_4: &str = "RFC 6487, section 4.4";
+const RFC_6487_4_5: &str = "RFC 6487, section 4.5";
+const RFC_6487_4_6: &str = "RFC 6487, section 4.6";
+const RFC_6487_4_7: &str = "RFC 6487, section 4.7";
+const RFC_6487_4_8: &str = "RFC 6487, section 4.8";
+const RFC_6487_4_8_1: &str = "RFC 6487, section 4.8.1";
+const RFC_6487_4_8_2: &str = "RFC 6487, section 4.8.2";
+const RFC_6487_4_8_3: &str = "RFC 6487, section 4.8.3";
+const RFC_6487_4_8_4: &str = "RFC 6487, section 4.8.4";
+const RFC_6487_4_8_5: &str = "RFC 6487, section 4.8.5";
+const RFC_6487_4_8_6: &str = "RFC 6487, section 4.8.6";
+const RFC_6487_4_8_7: &str = "RFC 6487, section 4.8.7";
+const RFC_6487_4_8_8: &str = "RFC 6487, section 4.8.8";
+const RFC_6487_4_8_10: &str = "RFC 6487, section 4.8.10";
+const RFC_6487_5: &str = "RFC 6487, section 5";
+const RFC_6488_2: &str = "RFC 6488, section 2";
+const RFC_6488_3: &str = "RFC 6488, section 3";
+const RFC_8209_3_1_3: &str = "RFC 8209, section 3.1.3";
+const RFC_8209_3_1_3_2: &str = "RFC 8209, section 3.1.3.2";
+const RFC_9286_4: &str = "RFC 9286, section 4";
+const RFC_9286_4_2_1: &str = "RFC 9286, section 4.2.1";
+const RFC_9286_4_2_2: &str = "RFC 9286, section 4.2.2";
+const RFC_9286_5_1: &str = "RFC 9286, section 5.1";
+const RFC_9286_6_3: &str = "RFC 9286, section 6.3";
+const RFC_9286_6_6: &str = "RFC 9286, section 6.6";
+const RFC_9582_3: &str = "RFC 9582, section 3";
+const RFC_9582_4: &str = "RFC 9582, section 4";
+const RFC_9582_4_3_3: &str = "RFC 9582, section 4.3.3";
+const RFC_9582_5: &str = "RFC 9582, section 5";
+const ASPA_PROFILE: &str = "draft-ietf-sidrops-aspa-profile";
+
+
+//------------ Names ---------------------------------------------------------
+
+/// Checks an issuer or subject name.
+///
+/// RFC 6487 requires exactly one common name and at most one serial number
+/// attribute, both as PrintableString. Router certificates may also use
+/// UTF8String.
+fn check_name(
+    name: &Name,
+    what: &str,
+    rule: &'static str,
+    router: bool,
+    report: &mut Report
+) {
+    let attrs = match name_attributes(name) {
+        Ok(attrs) => attrs,
+        Err(_) => {
+            report.push(
+                Severity::Error, rule, format!("{} cannot be decoded", what)
+            );
+            return
+        }
+    };
+    let mut cn = 0;
+    let mut sn = 0;
+    for (id, tag) in attrs {
+        if id == oid::AT_COMMON_NAME {
+            cn += 1;
+        }
+        else if id == oid::AT_SERIAL_NUMBER {
+            sn += 1;
+        }
+        else {
+            report.push(
+                Severity::RelaxedAccepted, rule,
+                format!("{} contains attribute {}", what, id)
+            );
+            continue
+        }
+        if tag != Tag::PRINTABLE_STRING
+            && !(router && tag == Tag::UTF8_STRING)
+        {
+            report.push(
+                Severity::RelaxedAccepted, rule,
+                format!("{} attribute {} is not a PrintableString", what, id)
+            );
+        }
+    }
+    if cn != 1 {
+        report.push(
+            Severity::RelaxedAccepted, rule,
+            format!("{} does not contain exactly one common name", what)
+        );
+    }
+    if sn > 1 {
+        report.push(
+            Severity::RelaxedAccepted, rule,
+            format!("{} contains more than one serial number", what)
+        );
+    }
+}
+
+/// Returns the attribute types and value tags of a name.
+fn name_attributes(
+    name: &Name
+) -> Result<Vec<(Oid<bytes::Bytes>, Tag)>, decode::Error> {
+    Mode::Ber.decode(name.as_slice(), |cons| {
+        let mut res = Vec::new();
+        cons.take_sequence(|cons| {
+            while let Some(()) = cons.take_opt_set(|cons| {
+                while let Some(()) = cons.take_opt_sequence(|cons| {
+                    let id = Oid::take_from(cons)?;
+                    let tag = cons.take_value(|tag, content| {
+                        skip_content(content)?;
+                        Ok(tag)
+                    })?;
+                    res.push((id, tag));
+                    Ok(())
+                })? { }
+                Ok(())
+            })? { }
+            Ok(())
+        })?;
+        Ok(res)
+    })
+}
+
+
+//------------ Extensions ----------------------------------------------------
+
+/// The encoding level information about an extension.
+struct Extension {
+    /// The object identifier of the extension.
+    oid: Oid<bytes::Bytes>,
+
+    /// The critical flag if it was present in the encoding.
+    critical: Option<bool>,
+}
+
+/// Returns the extensions of an encoded certificate.
+fn cert_extensions(data: &[u8]) -> Result<Vec<Extension>, decode::Error> {
+    Mode::Ber.decode(data, |cons| {
+        cons.take_sequence(|cons| {
+            let res = cons.take_sequence(|cons| {
+                // version
+                cons.take_opt_constructed_if(Tag::CTX_0, |cons| {
+                    cons.skip_all()
+                })?;
+                // serialNumber, signature, issuer, validity, subject,
+                // subjectPublicKeyInfo
+                for _ in 0..6 {
+                    if cons.skip_one()?.is_none() {
+                        return Err(decode::Malformed)
+                    }
+                }
+                // issuerUniqueID, subjectUniqueID
+                cons.take_opt_value_if(Tag::CTX_1, skip_content)?;
+                cons.take_opt_value_if(Tag::CTX_2, skip_content)?;
+                let res = cons.take_opt_constructed_if(Tag::CTX_3, |cons| {
+                    cons.take_sequence(take_extensions)
+                })?;
+                Ok(res.unwrap_or_default())
+            })?;
+            cons.skip_all()?;
+            Ok(res)
+        })
+    })
+}
+
+/// Returns the extensions of an encoded CRL.
+fn crl_extensions(data: &[u8]) -> Result<Vec<Extension>, decode::Error> {
+    Mode::Ber.decode(data, |cons| {
+        cons.take_sequence(|cons| {
+            let res = cons.take_sequence(|cons| {
+                // version, signature, issuer, thisUpdate, nextUpdate
+                for _ in 0..5 {
+                    if cons.skip_one()?.is_none() {
+                        return Err(decode::Malformed)
+                    }
+                }
+                // revokedCertificates
+                cons.take_opt_sequence(|cons| cons.skip_all())?;
+                let res = cons.take_opt_constructed_if(Tag::CTX_0, |cons| {
+                    cons.take_sequence(take_extensions)
+                })?;
+                Ok(res.unwrap_or_default())
+            })?;
+            cons.skip_all()?;
+            Ok(res)
+        })
+    })
+}
+
+/// Takes a sequence of extensions.
+fn take_extensions<S: Source>(
+    cons: &mut decode::Constructed<S>
+) -> Result<Vec<Extension>, S::Err> {
+    let mut res = Vec::new();
+    while let Some(ext) = cons.take_opt_sequence(|cons| {
+        let oid = Oid::take_from(cons)?;
+        let critical = cons.take_opt_bool()?;
+        cons.skip_all()?;
+        Ok(Extension { oid, critical })
+    })? {
+        res.push(ext)
+    }
+    Ok(res)
+}
+
+/// Skips over the content of a value.
+fn skip_content<S: Source>(
+    content: &mut decode::Content<S>
+) -> Result<(), S::Err> {
+    match *content {
+        decode::Content::Primitive(ref mut prim) => prim.skip_all(),
+        decode::Content::Constructed(ref mut cons) => cons.skip_all(),
+    }
+}
+
+/// Checks the encoding of certificate extensions.
+fn check_cert_extensions(extensions: &[Extension], report: &mut Report) {
+    let mut seen = HashSet::new();
+    for ext in extensions {
+        check_default_critical(ext, report);
+        if !seen.insert(ext.oid.as_ref().to_vec()) {
+            report.push(
+                Severity::Error, RFC_6487_4_8,
+                format!("duplicate extension {}", ext_name(&ext.oid))
+            );
+        }
+        let critical = ext.critical.unwrap_or(false);
+        match cert_ext_critical(&ext.oid) {
+            Some(expected) => {
+                if critical != expected {
+                    report.push(
+                        Severity::RelaxedAccepted, RFC_6487_4_8,
+                        format!(
+                            "extension {} must {}be marked critical",
+                            ext_name(&ext.oid),
+                            if expected { "" } else { "not " }
+                        )
+                    );
+                }
+            }
+            None => {
+                report.push(
+                    if critical {
+                        Severity::Error
+                    }
+                    else {
+                        Severity::RelaxedAccepted
+                    },
+                    RFC_6487_4_8,
+                    format!("unexpected extension {}", ext.oid)
+                );
+            }
+        }
+    }
+}
+
+/// Checks that an explicit critical flag isn’t the default value.
+///
+/// DER demands that default values are omitted.
+fn check_default_critical(ext: &Extension, report: &mut Report) {
+    if ext.critical == Some(false) {
+        report.push(
+            Severity::RelaxedAccepted, RFC_6487_4_8,
+            format!(
+                "extension {} explicitly encodes critical as false \
+                 in violation of DER",
+                ext_name(&ext.oid)
+            )
+        );
+    }
+}
+
+/// Returns whether a known certificate extension must be critical.
+///
+/// Returns `None` for extensions that are not allowed in resource
+/// certificates.
+fn cert_ext_critical(id: &Oid<bytes::Bytes>) -> Option<bool> {
+    if *id == oid::CE_BASIC_CONSTRAINTS
+        || *id == oid::CE_KEY_USAGE
+        || *id == oid::CE_CERTIFICATE_POLICIES
+        || *id == oid::PE_IP_ADDR_BLOCK
+        || *id == oid::PE_IP_ADDR_BLOCK_V2
+        || *id == oid::PE_AUTONOMOUS_SYS_IDS
+        || *id == oid::PE_AUTONOMOUS_SYS_IDS_V2
+    {
+        Some(true)
+    }
+    else if *id == oid::CE_SUBJECT_KEY_IDENTIFIER
+        || *id == oid::CE_AUTHORITY_KEY_IDENTIFIER
+        || *id == oid::CE_EXTENDED_KEY_USAGE
+        || *id == oid::CE_CRL_DISTRIBUTION_POINTS
+        || *id == oid::PE_AUTHORITY_INFO_ACCESS
+        || *id == oid::PE_SUBJECT_INFO_ACCESS
+    {
+        Some(false)
+    }
+    else {
+        None
+    }
+}
+
+/// Returns a human readable name for an extension.
+fn ext_name(id: &Oid<bytes::Bytes>) -> String {
+    let name = if *id == oid::CE_BASIC_CONSTRAINTS {
+        "basicConstraints"
+    }
+    else if *id == oid::CE_KEY_USAGE { "keyUsage" }
+    else if *id == oid::CE_CERTIFICATE_POLICIES { "certificatePolicies" }
+    else if *id == oid::PE_IP_ADDR_BLOCK
+        || *id == oid::PE_IP_ADDR_BLOCK_V2
+    {
+        "ipAddrBlocks"
+    }
+    else if *id == oid::PE_AUTONOMOUS_SYS_IDS
+        || *id == oid::PE_AUTONOMOUS_SYS_IDS_V2
+    {
+        "autonomousSysIds"
+    }
+    else if *id == oid::CE_SUBJECT_KEY_IDENTIFIER {
+        "subjectKeyIdentifier"
+    }
+    else if *id == oid::CE_AUTHORITY_KEY_IDENTIFIER {
+        "authorityKeyIdentifier"
+    }
+    else if *id == oid::CE_EXTENDED_KEY_USAGE { "extKeyUsage" }
+    else if *id == oid::CE_CRL_DISTRIBUTION_POINTS {
+        "cRLDistributionPoints"
+    }
+    else if *id == oid::PE_AUTHORITY_INFO_ACCESS { "authorityInfoAccess" }
+    else if *id == oid::PE_SUBJECT_INFO_ACCESS { "subjectInfoAccess" }
+    else if *id == oid::CE_CRL_NUMBER { "cRLNumber" }
+    else {
+        return id.to_string()
+    };
+    name.into()
+}
+
+
+//------------ File Names ----------------------------------------------------
+
+/// Returns whether a manifest file name has the required format.
+///
+/// RFC 9286 requires file names to match `[a-zA-Z0-9_-]+\.[a-z]{3}`.
+pub(crate) fn is_valid_file_name(name: &[u8]) -> bool {
+    if name.len() < 5 {
+        return false
+    }
+    let (base, ext) = name.split_at(name.len() - 4);
+    base.iter().all(|&ch| {
+        ch.is_ascii_alphanumeric() || ch == b'_' || ch == b'-'
+    })
+    && ext[0] == b'.'
+    && ext[1..].iter().all(u8::is_ascii_lowercase)
+}
+
+/// Returns whether a manifest file name can be used to refer to a file.
+///
+/// This rejects names that would refer to something other than a file in
+/// the publication point as well as names with control characters. Unlike
+/// the format checked by [`is_valid_file_name`], this is enforced even if
+/// validation is not strict.
+pub(crate) fn is_usable_file_name(name: &[u8]) -> bool {
+    !name.is_empty()
+    && name != b"."
+    && name != b".."
+    && !name.iter().any(|&ch| {
+        ch == b'/' || ch == b'\\' || ch.is_ascii_control()
+    })
+}
+
+/// Returns whether a file name has an extension registered for RPKI.
+fn is_known_extension(name: &[u8]) -> bool {
+    const KNOWN: &[&[u8]] = &[
+        b".asa", b".cer", b".crl", b".gbr", b".mft", b".roa", b".sig",
+        b".tak",
+    ];
+    KNOWN.iter().any(|ext| name.ends_with(ext))
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn file_names() {
+        assert!(is_valid_file_name(b"foo.roa"));
+        assert!(is_valid_file_name(b"a-B_9.cer"));
+        assert!(!is_valid_file_name(b".roa"));
+        assert!(!is_valid_file_name(b"foo.ROA"));
+        assert!(!is_valid_file_name(b"foo/bar.roa"));
+        assert!(!is_valid_file_name(b"foo.roaa"));
+        assert!(is_known_extension(b"foo.asa"));
+        assert!(!is_known_extension(b"foo.txt"));
+    }
+
+    #[test]
+    fn garbage() {
+        let report = Linter::new().lint_cert(b"foo", None);
+        assert!(report.has_errors());
+        let report = Linter::new().lint_roa(b"foo");
+        assert!(report.has_errors());
+    }
+}
+
+#[cfg(all(test, feature="softkeys"))]
+mod signer_test {
+    use std::str::FromStr;
+    use std::net::Ipv4Addr;
+    use bytes::Bytes;
+    use crate::uri;
+    use crate::repository::cert::{Overclaim, TbsCert};
+    use crate::repository::crl::TbsCertList;
+    use crate::repository::crypto::{DigestAlgorithm, PublicKeyFormat, Signer};
+    use crate::repository::crypto::softsigner::{KeyId, OpenSslSigner};
+    use crate::repository::manifest::{FileAndHash, ManifestContent};
+    use crate::repository::resources::{Asn, Prefix};
+    use crate::repository::roa::RoaBuilder;
+    use crate::repository::sigobj::SignedObjectBuilder;
+    use crate::repository::x509::Validity;
+    use super::*;
+
+    fn rsync(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    fn ta_cert(
+        signer: &OpenSslSigner, key: &KeyId, key_usage: KeyUsage
+    ) -> Bytes {
+        let pubkey = signer.get_key_info(key).unwrap();
+        let mut cert = TbsCert::new(
+            12u64.into(), pubkey.to_subject_name(),
+            Validity::from_secs(86400), None, pubkey, key_usage,
+            Overclaim::Refuse
+        );
+        cert.set_basic_ca(Some(true));
+        cert.set_ca_repository(Some(rsync("rsync://example.com/m/")));
+        cert.set_rpki_manifest(Some(rsync("rsync://example.com/m/ca.mft")));
+        cert.build_v4_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_v6_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_as_resource_blocks(|b| b.push((Asn::MIN, Asn::MAX)));
+        cert.into_cert(signer, key).unwrap().to_captured().into_bytes()
+    }
+
+    fn sigobj(name: &str) -> SignedObjectBuilder {
+        SignedObjectBuilder::new(
+            13u64.into(), Validity::from_secs(86400),
+            rsync("rsync://example.com/m/ca.crl"),
+            rsync("rsync://example.com/ta/ta.cer"),
+            rsync("rsync://example.com/m/").join(name.as_bytes()).unwrap()
+        )
+    }
+
+    fn assert_no_errors(report: &Report) {
+        assert!(!report.has_errors(), "{}", report);
+    }
+
+    #[test]
+    fn clean_ta_cert() {
+        let signer = OpenSslSigner::new();
+        let key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let cert = ta_cert(&signer, &key, KeyUsage::Ca);
+        let report = Linter::new().lint_cert(&cert, None);
+        assert!(report.is_clean(), "{}", report);
+        let report = Linter::at(Time::now()).lint_cert(
+            &cert, Some(CertKind::Ta)
+        );
+        assert!(report.is_clean(), "{}", report);
+    }
+
+    #[test]
+    fn ta_cert_deviations() {
+        let signer = OpenSslSigner::new();
+        let key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let cert = ta_cert(&signer, &key, KeyUsage::Ee);
+        let report = Linter::new().lint_cert(&cert, Some(CertKind::Ta));
+        assert!(report.findings().iter().any(|item| {
+            item.rule == RFC_6487_4_8_4 && item.severity == Severity::Error
+        }), "{}", report);
+
+        // Linting as a CA certificate misses AKI, CRLDP, and AIA.
+        let cert = ta_cert(&signer, &key, KeyUsage::Ca);
+        let report = Linter::new().lint_cert(&cert, Some(CertKind::Ca));
+        assert_eq!(report.with_severity(Severity::Error).count(), 3);
+
+        // An expired certificate.
+        let report = Linter::at(
+            Time::now() + chrono::Duration::days(2)
+        ).lint_cert(&cert, None);
+        assert!(report.findings().iter().any(|item| {
+            item.rule == RFC_6487_4_6
+        }), "{}", report);
+    }
+
+    #[test]
+    fn crl() {
+        let signer = OpenSslSigner::new();
+        let key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let pubkey = signer.get_key_info(&key).unwrap();
+        let crl = TbsCertList::new(
+            Default::default(), pubkey.to_subject_name(),
+            Time::now(), Time::tomorrow(), Vec::new(),
+            pubkey.key_identifier(), 12u64.into()
+        ).into_crl(&signer, &key).unwrap().to_captured();
+        let report = Linter::at(Time::now()).lint_crl(crl.as_slice());
+        assert!(report.is_clean(), "{}", report);
+        let report = Linter::at(
+            Time::now() + chrono::Duration::days(2)
+        ).lint_crl(crl.as_slice());
+        assert!(report.has_errors());
+    }
+
+    #[test]
+    fn roa() {
+        let signer = OpenSslSigner::new();
+        let key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let mut roa = RoaBuilder::new(64496.into());
+        roa.push_v4_addr(Ipv4Addr::new(192, 0, 2, 0), 24, None);
+        let roa = roa.finalize(
+            sigobj("roa.roa"), &signer, &key
+        ).unwrap().to_captured();
+        let report = Linter::new().lint_roa(roa.as_slice());
+        assert_no_errors(&report);
+        assert!(Linter::new().lint_manifest(roa.as_slice()).has_errors());
+
+        // Prefixes out of order.
+        let mut roa = RoaBuilder::new(64496.into());
+        roa.push_v4_addr(Ipv4Addr::new(198, 51, 100, 0), 24, None);
+        roa.push_v4_addr(Ipv4Addr::new(192, 0, 2, 0), 24, None);
+        let roa = roa.finalize(
+            sigobj("roa.roa"), &signer, &key
+        ).unwrap().to_captured();
+        let report = Linter::new().lint_roa(roa.as_slice());
+        assert_no_errors(&report);
+        assert!(report.findings().iter().any(|item| {
+            item.rule == RFC_9582_4_3_3 && item.severity == Severity::Warning
+        }), "{}", report);
+    }
+
+    #[test]
+    fn manifest() {
+        let signer = OpenSslSigner::new();
+        let key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let hash = Bytes::copy_from_slice(
+            DigestAlgorithm::default().digest(b"foo").as_ref()
+        );
+        let files = [
+            FileAndHash::new(Bytes::from_static(b"ca.crl"), hash.clone()),
+            FileAndHash::new(
+                Bytes::from_static(b"foo/bar.roa"), hash.clone()
+            ),
+            FileAndHash::new(
+                Bytes::from_static(b"bad+name.roa"), hash.clone()
+            ),
+            FileAndHash::new(Bytes::from_static(b"ca.crl"), hash),
+        ];
+        let mut builder = sigobj("ca.mft");
+        builder.set_v4_resources_inherit();
+        builder.set_v6_resources_inherit();
+        builder.set_as_resources_inherit();
+        let manifest = ManifestContent::new(
+            1u64.into(), Time::now(), Time::tomorrow(),
+            DigestAlgorithm::default(), files.iter()
+        ).into_manifest(builder, &signer, &key).unwrap().to_captured();
+        let report = Linter::at(Time::now()).lint_manifest(
+            manifest.as_slice()
+        );
+        // The unusable and the duplicate name are errors, the illegal
+        // name is accepted unless strict.
+        assert_eq!(
+            report.with_severity(Severity::Error).count(), 2, "{}", report
+        );
+        assert_eq!(
+            report.with_severity(Severity::RelaxedAccepted).count(), 1,
+            "{}", report
+        );
+    }
+}
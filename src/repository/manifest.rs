@@ -20,7 +20,9 @@ use bytes::Bytes;
 use crate::uri;
 use super::oid;
 use super::cert::{Cert, ResourceCert};
-use super::crypto::{DigestAlgorithm, Signer, SigningError};
+use super::crypto::{
+    DigestAlgorithm, Signer, SigningError, VerificationCache
+};
 use super::sigobj::{SignedObject, SignedObjectBuilder};
 use super::x509::{Serial, Time, ValidationError};
 
@@ -73,7 +75,18 @@ impl Manifest {
         strict: bool,
         now: Time
     ) -> Result<(ResourceCert, ManifestContent), ValidationError> {
-        let cert = self.signed.validate_at(cert, strict, now)?;
+        self.validate_at_cached(cert, strict, now, None)
+    }
+
+    /// Validates the manifest using a verification cache if given.
+    pub fn validate_at_cached(
+        self,
+        cert: &ResourceCert,
+        strict: bool,
+        now: Time,
+        cache: Option<&VerificationCache>,
+    ) -> Result<(ResourceCert, ManifestContent), ValidationError> {
+        let cert = self.signed.validate_at_cached(cert, strict, now, cache)?;
 
         // RFC 6486, section 4.4:
         //
@@ -313,7 +326,12 @@ impl ManifestContent {
     ///
     /// A manifest is stale if it’s nextUpdate time has passed.
     pub fn is_stale(&self) -> bool {
-        self.next_update < Time::now()
+        self.is_stale_at(Time::now())
+    }
+
+    /// Returns whether the manifest is stale at the given time.
+    pub fn is_stale_at(&self, now: Time) -> bool {
+        self.next_update < now
     }
 }
 
@@ -538,6 +556,8 @@ mod test {
             include_bytes!("../../test-data/ta.mft").as_ref(),
             false
         ).unwrap();
+        assert!(!obj.is_stale_at(at));
+        assert!(obj.is_stale_at(Time::utc(2029, 5, 1, 0, 0, 0)));
         obj.validate_at(&issuer, false, at).unwrap();
         let obj = Manifest::decode(
             include_bytes!("../../test-data/ca1.mft").as_ref(),
@@ -8,10 +8,10 @@ use bcder::{Captured, Mode, OctetString, Oid, Tag, xerr};
 use bcder::encode::{PrimitiveContent, Values};
 use super::oid;
 use super::cert::{Cert, ResourceCert};
-use super::crypto::{Signer, SigningError};
+use super::crypto::{Signer, SigningError, VerificationCache};
 use super::resources::{Addr, AddressFamily, Asn, IpResources, Prefix};
 use super::sigobj::{SignedObject, SignedObjectBuilder};
-use super::x509::ValidationError;
+use super::x509::{Time, ValidationError};
 
 
 //------------ Roa -----------------------------------------------------------
@@ -38,13 +38,39 @@ impl Roa {
     }
 
     pub fn process<F>(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        check_crl: F
+    ) -> Result<(ResourceCert, RouteOriginAttestation), ValidationError>
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        self.process_at(issuer, strict, Time::now(), check_crl)
+    }
+
+    pub fn process_at<F>(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        now: Time,
+        check_crl: F
+    ) -> Result<(ResourceCert, RouteOriginAttestation), ValidationError>
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        self.process_at_cached(issuer, strict, now, None, check_crl)
+    }
+
+    /// Processes the ROA using a verification cache if given.
+    pub fn process_at_cached<F>(
         mut self,
         issuer: &ResourceCert,
         strict: bool,
+        now: Time,
+        cache: Option<&VerificationCache>,
         check_crl: F
     ) -> Result<(ResourceCert, RouteOriginAttestation), ValidationError>
     where F: FnOnce(&Cert) -> Result<(), ValidationError> {
-        let cert = self.signed.validate(issuer, strict)?;
+        let cert = self.signed.validate_at_cached(
+            issuer, strict, now, cache
+        )?;
         check_crl(cert.as_ref())?;
         self.content.validate(&cert)?;
         Ok((cert, self.content))
@@ -64,6 +90,11 @@ impl Roa {
     pub fn cert(&self) -> &Cert {
         self.signed.cert()
     }
+
+    /// Returns a reference to the content of this ROA.
+    pub fn content(&self) -> &RouteOriginAttestation {
+        &self.content
+    }
 }
 
 
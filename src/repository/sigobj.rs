@@ -13,7 +13,7 @@ use super::oid;
 use super::cert::{Cert, KeyUsage, Overclaim, ResourceCert, TbsCert};
 use super::crypto::{
     Digest, DigestAlgorithm, KeyIdentifier, Signature, SignatureAlgorithm,
-    Signer, SigningError
+    Signer, SigningError, VerificationCache
 };
 use super::resources::{
     AsBlocksBuilder, AsResources, AsResourcesBuilder, IpBlocksBuilder,
@@ -173,9 +173,33 @@ impl SignedObject {
         strict: bool,
         check_crl: F
     ) -> Result<(ResourceCert, Bytes), ValidationError>
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        self.process_at(issuer, strict, Time::now(), check_crl)
+    }
+
+    pub fn process_at<F>(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        now: Time,
+        check_crl: F
+    ) -> Result<(ResourceCert, Bytes), ValidationError>
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        self.process_at_cached(issuer, strict, now, None, check_crl)
+    }
+
+    /// Processes the signed object using a verification cache if given.
+    pub fn process_at_cached<F>(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        now: Time,
+        cache: Option<&VerificationCache>,
+        check_crl: F
+    ) -> Result<(ResourceCert, Bytes), ValidationError>
     where F: FnOnce(&Cert) -> Result<(), ValidationError> {
         let res = self.content.clone();
-        let cert = self.validate(issuer, strict)?;
+        let cert = self.validate_at_cached(issuer, strict, now, cache)?;
         check_crl(cert.as_ref())?;
         Ok((cert, res.into_bytes()))
     }
@@ -198,16 +222,31 @@ impl SignedObject {
         issuer: &ResourceCert,
         strict: bool,
         now: Time,
+    ) -> Result<ResourceCert, ValidationError> {
+        self.validate_at_cached(issuer, strict, now, None)
+    }
+
+    /// Validates the signed object using a verification cache if given.
+    ///
+    /// If `cache` is given, neither the signature of the object nor that
+    /// of its EE certificate are verified if they are already present in
+    /// the cache. All other checks are performed as usual.
+    pub fn validate_at_cached(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        now: Time,
+        cache: Option<&VerificationCache>,
     ) -> Result<ResourceCert, ValidationError> {
         self.verify_compliance(strict)?;
-        self.verify_signature(strict)?;
-        self.cert.validate_ee_at(issuer, strict, now)
+        self.verify_signature_cached(strict, cache)?;
+        self.cert.validate_ee_at_cached(issuer, strict, now, cache)
     }
 
     /// Validates that the signed object complies with the specification.
     ///
     /// This is item 1 of [RFC 6488]`s section 3.
-    fn verify_compliance(
+    pub(crate) fn verify_compliance(
         &self,
         _strict: bool
     ) -> Result<(), ValidationError> {
@@ -225,7 +264,16 @@ impl SignedObject {
     /// Verifies the signature of the object against contained certificate.
     ///
     /// This is item 2 of [RFC 6488]’s section 3.
-    fn verify_signature(&self, _strict: bool) -> Result<(), ValidationError> {
+    pub(crate) fn verify_signature(
+        &self, strict: bool
+    ) -> Result<(), ValidationError> {
+        self.verify_signature_cached(strict, None)
+    }
+
+    /// Verifies the signature using a verification cache if given.
+    fn verify_signature_cached(
+        &self, _strict: bool, cache: Option<&VerificationCache>
+    ) -> Result<(), ValidationError> {
         let digest = {
             let mut context = self.digest_algorithm.start();
             self.content.iter().for_each(|x| context.update(x));
@@ -235,10 +283,11 @@ impl SignedObject {
             return Err(ValidationError)
         }
         let msg = self.signed_attrs.encode_verify();
-        self.cert.subject_public_key_info().verify(
-            &msg,
-            &self.signature
-        ).map_err(Into::into)
+        let key = self.cert.subject_public_key_info();
+        match cache {
+            Some(cache) => cache.verify(key, &msg, &self.signature),
+            None => key.verify(&msg, &self.signature),
+        }.map_err(Into::into)
     }
 
     /// Returns a value encoder for a reference to a signed object.
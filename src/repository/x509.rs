@@ -14,8 +14,10 @@ use bcder::encode::PrimitiveContent;
 use chrono::{
     Datelike, DateTime, Duration, LocalResult, Timelike, TimeZone, Utc
 };
+use crate::clock::Clock;
 use super::crypto::{
-    PublicKey, Signature, SignatureAlgorithm, Signer, VerificationError
+    PublicKey, Signature, SignatureAlgorithm, Signer, VerificationCache,
+    VerificationError
 };
 use super::oid;
 
@@ -244,6 +246,11 @@ impl Name {
     pub fn encode_ref(&self) -> impl encode::Values + '_ {
         &self.0
     }
+
+    /// Returns the encoded name as a slice.
+    pub fn as_slice(&self) -> &[u8] {
+        self.0.as_slice()
+    }
 }
 
 //--- PartialEq and Eq
@@ -582,6 +589,22 @@ impl SignedData {
         ).map_err(Into::into)
     }
 
+    /// Verifies the signature using a verification cache if given.
+    pub fn verify_signature_cached(
+        &self,
+        public_key: &PublicKey,
+        cache: Option<&VerificationCache>,
+    ) -> Result<(), ValidationError> {
+        match cache {
+            Some(cache) => {
+                cache.verify(
+                    public_key, self.data.as_ref(), &self.signature
+                ).map_err(Into::into)
+            }
+            None => self.verify_signature(public_key)
+        }
+    }
+
     pub fn encode_ref(&self) -> impl encode::Values + '_ {
         encode::sequence((
             &self.data,
@@ -639,6 +662,11 @@ impl Time {
         Self::new(Utc::now())
     }
 
+    /// Returns the current time as reported by the given clock.
+    pub fn from_clock(clock: &(impl Clock + ?Sized)) -> Self {
+        Self::new(clock.now())
+    }
+
     pub fn five_minutes_ago() -> Self {
         Self::now() - Duration::minutes(5)
     }
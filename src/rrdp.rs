@@ -9,8 +9,20 @@
 //! [`ProcessDelta`] traits since these files can become rather big.
 //!
 //! The module does not provide an HTTP client. Rather, it relies on the
-//! `std::io::Read` trait for processing. As such, it is also not compatible
-//! with async processing.
+//! `std::io::Read` trait for processing. If the `"async"` feature is
+//! enabled, the [`async_io`] module provides equivalent functions for
+//! Tokio’s async I/O traits. The [`update`] module contains the logic for
+//! updating a repository store via RRDP using a pluggable fetcher while
+//! the [`stream`] module allows writing large snapshots and deltas into a
+//! store with limited memory. The [`server`] module provides the server
+//! side, generating RRDP files from changes to a repository, and the
+//! [`squash`] module combines snapshots and runs of deltas. Finally, the
+//! [`monitor`] module watches RRDP feeds for anomalies, the [`tree`]
+//! module converts rsync directory trees into RRDP, and the [`mirror`]
+//! module does the reverse, writing RRDP content into such a tree.
+//!
+//! Parsing transparently handles gzip or deflate compressed files. The
+//! [`compress`] module describes the details.
 //!
 //! A note on terminology: to avoid confusion, the term ‘file’ refers to the
 //! RRDP data itself, i.e., the notification, snapshot, and delta files. The
@@ -21,6 +33,16 @@
 
 #![cfg(feature = "rrdp")]
 
+pub mod async_io;
+pub mod compress;
+pub mod mirror;
+pub mod monitor;
+pub mod server;
+pub mod squash;
+pub mod stream;
+pub mod tree;
+pub mod update;
+
 use std::{error, fmt, hash, io, str};
 use std::io::Read;
 use std::convert::TryFrom;
@@ -31,7 +53,7 @@ use log::info;
 use ring::digest;
 use uuid::Uuid;
 use crate::{uri, xml};
-use crate::xml::decode::{Content, Error as XmlError, Reader, Name};
+use crate::xml::decode::{Content, Error as XmlError, Limits, Reader, Name};
 
 #[cfg(feature = "serde")] use std::str::FromStr;
 #[cfg(feature = "serde")] use serde::{
@@ -150,6 +172,28 @@ impl NotificationFile {
         true
     }
 
+    /// Returns whether all referenced files have the same origin.
+    ///
+    /// The URIs of the snapshot and all deltas are compared with the URI
+    /// the notification file was fetched from, `notify_uri`. They have the
+    /// same origin if scheme, host, and port are the same. See
+    /// [`uri::Https::same_origin`] for details.
+    pub fn has_same_origin(&self, notify_uri: &uri::Https) -> bool {
+        self.foreign_uri(notify_uri).is_none()
+    }
+
+    /// Returns the first referenced URI with a different origin.
+    ///
+    /// Returns `None` if the snapshot and all deltas have the same origin
+    /// as `notify_uri`.
+    pub fn foreign_uri(
+        &self, notify_uri: &uri::Https
+    ) -> Option<&uri::Https> {
+        std::iter::once(self.snapshot.uri()).chain(
+            self.deltas.iter().map(|delta| delta.uri())
+        ).find(|uri| !notify_uri.same_origin(uri))
+    }
+
 }
 
 /// # XML support
@@ -157,7 +201,19 @@ impl NotificationFile {
 impl NotificationFile {
     /// Parses the notification file from its XML representation.
     pub fn parse<R: io::BufRead>(reader: R) -> Result<Self, XmlError> {
-        let mut reader = Reader::new(reader);
+        Self::parse_with_limits(reader, Limits::default())
+    }
+
+    /// Parses the notification file while enforcing limits.
+    ///
+    /// If the file exceeds any of the given limits, parsing fails with the
+    /// error specific to the limit.
+    pub fn parse_with_limits<R: io::BufRead>(
+        reader: R, limits: Limits
+    ) -> Result<Self, XmlError> {
+        let mut reader = Reader::with_limits(
+            compress::Decompress::new(reader), limits
+        );
 
         let mut session_id = None;
         let mut serial = None;
@@ -291,6 +347,13 @@ impl NotificationFile {
             })?;
         writer.done()
     }
+
+    /// Writes the notification file’s XML representation compressed.
+    pub fn write_compressed(
+        &self, compression: compress::Compression, writer: &mut impl io::Write
+    ) -> Result<(), io::Error> {
+        compression.write(writer, |mut writer| self.write_xml(&mut writer))
+    }
 }
 
 
@@ -486,6 +549,15 @@ pub enum DeltaElement {
 }
 
 impl DeltaElement {
+    /// Returns the URI of the object the element refers to.
+    pub fn uri(&self) -> &uri::Rsync {
+        match self {
+            DeltaElement::Publish(p) => p.uri(),
+            DeltaElement::Update(u) => u.uri(),
+            DeltaElement::Withdraw(w) => w.uri(),
+        }
+    }
+
     /// Writes the element’s XML.
     fn write_xml(
         &self,
@@ -575,6 +647,16 @@ impl Snapshot {
     /// Parses the snapshot from its XML representation.
     pub fn parse<R: io::BufRead>(
         reader: R
+    ) -> Result<Self, ProcessError> {
+        Self::parse_with_limits(reader, Limits::default())
+    }
+
+    /// Parses the snapshot while enforcing limits.
+    ///
+    /// If the file exceeds any of the given limits, parsing fails with the
+    /// error specific to the limit.
+    pub fn parse_with_limits<R: io::BufRead>(
+        reader: R, limits: Limits
     ) -> Result<Self, ProcessError> {
         let mut builder = SnapshotBuilder {
             session_id: None,
@@ -582,7 +664,7 @@ impl Snapshot {
             elements: vec![]
         };
 
-        builder.process(reader)?;
+        builder.process_with_limits(reader, limits)?;
         builder.try_into()
     }
 
@@ -604,6 +686,13 @@ impl Snapshot {
             })?;
         writer.done()
     }
+
+    /// Writes the snapshot’s XML representation compressed.
+    pub fn write_compressed(
+        &self, compression: compress::Compression, writer: &mut impl io::Write
+    ) -> Result<(), io::Error> {
+        compression.write(writer, |mut writer| self.write_xml(&mut writer))
+    }
 }
 
 
@@ -700,83 +789,114 @@ pub trait ProcessSnapshot {
         &mut self,
         reader: R
     ) -> Result<(), Self::Err> {
-        let mut reader = Reader::new(reader);
-        
-        let mut session_id = None;
-        let mut serial = None;
-        let mut outer = reader.start(|element| {
-            if element.name() != SNAPSHOT {
-                info!("Bad outer: not snapshot, but {:?}", element.name());
-                return Err(XmlError::Malformed)
-            }
-            element.attributes(|name, value| match name {
-                b"version" => {
-                    if value.ascii_into::<u8>()? != 1 {
-                        info!("Bad version");
-                        return Err(XmlError::Malformed)
-                    }
-                    Ok(())
-                }
-                b"session_id" => {
-                    session_id = Some(value.ascii_into()?);
-                    Ok(())
-                }
-                b"serial" => {
-                    serial = Some(value.ascii_into()?);
-                    Ok(())
-                }
-                _ => {
-                    info!("Bad attribute on snapshot.");
-                    Err(XmlError::Malformed)
-                }
-            })
-        }).map_err(Into::into)?;
+        self.process_with_limits(reader, Limits::default())
+    }
 
-        match (session_id, serial) {
-            (Some(session_id), Some(serial)) => {
-                self.meta(session_id, serial)?;
+    /// Processes a snapshot file while enforcing limits.
+    ///
+    /// This is identical to `process` except that processing fails with
+    /// the error specific to a limit if the file exceeds it.
+    fn process_with_limits<R: io::BufRead>(
+        &mut self,
+        reader: R,
+        limits: Limits,
+    ) -> Result<(), Self::Err> {
+        let mut reader = Reader::with_limits(
+            compress::Decompress::new(reader), limits
+        );
+        let (mut outer, session_id, serial) = snapshot_start(&mut reader)?;
+        self.meta(session_id, serial)?;
+        while snapshot_element(self, &mut outer, &mut reader)? { }
+        outer.take_end(&mut reader).map_err(Into::into)?;
+        reader.end().map_err(Into::into)?;
+        Ok(())
+    }
+}
+
+/// Parses the start of a snapshot file.
+///
+/// Returns the content of the outer element, the session ID, and the
+/// serial number.
+fn snapshot_start<R: io::BufRead>(
+    reader: &mut Reader<R>
+) -> Result<(Content, Uuid, u64), ProcessError> {
+    let mut session_id = None;
+    let mut serial = None;
+    let outer = reader.start(|element| {
+        if element.name() != SNAPSHOT {
+            info!("Bad outer: not snapshot, but {:?}", element.name());
+            return Err(XmlError::Malformed)
+        }
+        element.attributes(|name, value| match name {
+            b"version" => {
+                if value.ascii_into::<u8>()? != 1 {
+                    info!("Bad version");
+                    return Err(XmlError::Malformed)
+                }
+                Ok(())
+            }
+            b"session_id" => {
+                session_id = Some(value.ascii_into()?);
+                Ok(())
+            }
+            b"serial" => {
+                serial = Some(value.ascii_into()?);
+                Ok(())
             }
             _ => {
-                info!("Missing session or serial");
-                return Err(ProcessError::malformed().into())
+                info!("Bad attribute on snapshot.");
+                Err(XmlError::Malformed)
             }
+        })
+    })?;
+
+    match (session_id, serial) {
+        (Some(session_id), Some(serial)) => Ok((outer, session_id, serial)),
+        _ => {
+            info!("Missing session or serial");
+            Err(ProcessError::malformed())
         }
+    }
+}
 
-        loop {
-            let mut uri = None;
-            let inner = outer.take_opt_element(&mut reader, |element| {
-                if element.name() != PUBLISH {
-                info!("Bad inner: not publish");
-                    return Err(ProcessError::malformed())
-                }
-                element.attributes(|name, value| match name {
-                    b"uri" => {
-                        uri = Some(value.ascii_into()?);
-                        Ok(())
-                    }
-                    _ => {
-                        info!("Bad attribute on publish.");
-                        Err(ProcessError::malformed())
-                    }
-                })
-            })?;
-            let mut inner = match inner {
-                Some(inner) => inner,
-                None => break
-            };
-            let uri = match uri {
-                Some(uri) => uri,
-                None => return Err(ProcessError::malformed().into())
-            };
-            ObjectReader::process(&mut inner, &mut reader, |reader| {
-                self.publish(uri, reader)
-            })?;
+/// Processes the next element of a snapshot file.
+///
+/// Returns whether there was an element. If not, the end of the outer
+/// element has been reached.
+fn snapshot_element<P: ProcessSnapshot + ?Sized, R: io::BufRead>(
+    processor: &mut P,
+    outer: &mut Content,
+    reader: &mut Reader<R>,
+) -> Result<bool, P::Err> {
+    let mut uri = None;
+    let inner = outer.take_opt_element(reader, |element| {
+        if element.name() != PUBLISH {
+            info!("Bad inner: not publish");
+            return Err(ProcessError::malformed())
         }
-
-        outer.take_end(&mut reader).map_err(Into::into)?;
-        reader.end().map_err(Into::into)?;
-        Ok(())
-    }
+        element.attributes(|name, value| match name {
+            b"uri" => {
+                uri = Some(value.ascii_into()?);
+                Ok(())
+            }
+            _ => {
+                info!("Bad attribute on publish.");
+                Err(ProcessError::malformed())
+            }
+        })
+    })?;
+    let mut inner = match inner {
+        Some(inner) => inner,
+        None => return Ok(false)
+    };
+    let uri = match uri {
+        Some(uri) => uri,
+        None => return Err(ProcessError::malformed().into())
+    };
+    ObjectReader::process(&mut inner, reader, |reader| {
+        processor.publish(uri, reader)
+    })?;
+    Ok(true)
 }
 
 
@@ -840,6 +960,16 @@ impl Delta {
     /// Parses the delta from its XML representation.
     pub fn parse<R: io::BufRead>(
         reader: R
+    ) -> Result<Self, ProcessError> {
+        Self::parse_with_limits(reader, Limits::default())
+    }
+
+    /// Parses the delta while enforcing limits.
+    ///
+    /// If the file exceeds any of the given limits, parsing fails with the
+    /// error specific to the limit.
+    pub fn parse_with_limits<R: io::BufRead>(
+        reader: R, limits: Limits
     ) -> Result<Self, ProcessError> {
         let mut builder = DeltaBuilder {
             session_id: None,
@@ -847,7 +977,7 @@ impl Delta {
             elements: vec![]
         };
 
-        builder.process(reader)?;
+        builder.process_with_limits(reader, limits)?;
         builder.try_into()
     }
 
@@ -869,6 +999,13 @@ impl Delta {
             })?;
         writer.done()
     }
+
+    /// Writes the delta’s XML representation compressed.
+    pub fn write_compressed(
+        &self, compression: compress::Compression, writer: &mut impl io::Write
+    ) -> Result<(), io::Error> {
+        compression.write(writer, |mut writer| self.write_xml(&mut writer))
+    }
 }
 
 
@@ -1010,92 +1147,123 @@ pub trait ProcessDelta {
         &mut self,
         reader: R
     ) -> Result<(), Self::Err> {
-        let mut reader = Reader::new(reader);
-        
-        let mut session_id = None;
-        let mut serial = None;
-        let mut outer = reader.start(|element| {
-            if element.name() != DELTA {
-                return Err(ProcessError::malformed())
-            }
-            element.attributes(|name, value| match name {
-                b"version" => {
-                    if value.ascii_into::<u8>()? != 1 {
-                        return Err(ProcessError::malformed())
-                    }
-                    Ok(())
-                }
-                b"session_id" => {
-                    session_id = Some(value.ascii_into()?);
-                    Ok(())
-                }
-                b"serial" => {
-                    serial = Some(value.ascii_into()?);
-                    Ok(())
+        self.process_with_limits(reader, Limits::default())
+    }
+
+    /// Processes a delta file while enforcing limits.
+    ///
+    /// This is identical to `process` except that processing fails with
+    /// the error specific to a limit if the file exceeds it.
+    fn process_with_limits<R: io::BufRead>(
+        &mut self,
+        reader: R,
+        limits: Limits,
+    ) -> Result<(), Self::Err> {
+        let mut reader = Reader::with_limits(
+            compress::Decompress::new(reader), limits
+        );
+        let (mut outer, session_id, serial) = delta_start(&mut reader)?;
+        self.meta(session_id, serial)?;
+        while delta_element(self, &mut outer, &mut reader)? { }
+        outer.take_end(&mut reader).map_err(Into::into)?;
+        reader.end().map_err(Into::into)?;
+        Ok(())
+    }
+}
+
+/// Parses the start of a delta file.
+///
+/// Returns the content of the outer element, the session ID, and the
+/// serial number.
+fn delta_start<R: io::BufRead>(
+    reader: &mut Reader<R>
+) -> Result<(Content, Uuid, u64), ProcessError> {
+    let mut session_id = None;
+    let mut serial = None;
+    let outer = reader.start(|element| {
+        if element.name() != DELTA {
+            return Err(ProcessError::malformed())
+        }
+        element.attributes(|name, value| match name {
+            b"version" => {
+                if value.ascii_into::<u8>()? != 1 {
+                    return Err(ProcessError::malformed())
                 }
-                _ => Err(ProcessError::malformed())
-            })
-        })?;
+                Ok(())
+            }
+            b"session_id" => {
+                session_id = Some(value.ascii_into()?);
+                Ok(())
+            }
+            b"serial" => {
+                serial = Some(value.ascii_into()?);
+                Ok(())
+            }
+            _ => Err(ProcessError::malformed())
+        })
+    })?;
+
+    match (session_id, serial) {
+        (Some(session_id), Some(serial)) => Ok((outer, session_id, serial)),
+        _ => Err(ProcessError::malformed())
+    }
+}
 
-        match (session_id, serial) {
-            (Some(session_id), Some(serial)) => {
-                self.meta(session_id, serial)?;
+/// Processes the next element of a delta file.
+///
+/// Returns whether there was an element. If not, the end of the outer
+/// element has been reached.
+fn delta_element<P: ProcessDelta + ?Sized, R: io::BufRead>(
+    processor: &mut P,
+    outer: &mut Content,
+    reader: &mut Reader<R>,
+) -> Result<bool, P::Err> {
+    let mut action = None;
+    let mut uri = None;
+    let mut hash = None;
+    let inner = outer.take_opt_element(reader, |element| {
+        match element.name() {
+            PUBLISH => action = Some(Action::Publish),
+            WITHDRAW => action = Some(Action::Withdraw),
+            _ => return Err(ProcessError::malformed()),
+        };
+        element.attributes(|name, value| match name {
+            b"uri" => {
+                uri = Some(value.ascii_into()?);
+                Ok(())
+            }
+            b"hash" => {
+                hash = Some(value.ascii_into()?);
+                Ok(())
             }
-            _ => return Err(ProcessError::malformed().into())
+            _ => Err(ProcessError::malformed())
+        })
+    })?;
+    let mut inner = match inner {
+        Some(inner) => inner,
+        None => return Ok(false)
+    };
+    let uri = match uri {
+        Some(uri) => uri,
+        None => return Err(ProcessError::malformed().into())
+    };
+    match action.unwrap() { // Or we'd have exited already.
+        Action::Publish => {
+            ObjectReader::process(
+                &mut inner, reader,
+                |reader| processor.publish(uri, hash, reader)
+            )?;
         }
-
-        loop {
-            let mut action = None;
-            let mut uri = None;
-            let mut hash = None;
-            let inner = outer.take_opt_element(&mut reader, |element| {
-                match element.name() {
-                    PUBLISH => action = Some(Action::Publish),
-                    WITHDRAW => action = Some(Action::Withdraw),
-                    _ => return Err(ProcessError::malformed()),
-                };
-                element.attributes(|name, value| match name {
-                    b"uri" => {
-                        uri = Some(value.ascii_into()?);
-                        Ok(())
-                    }
-                    b"hash" => {
-                        hash = Some(value.ascii_into()?);
-                        Ok(())
-                    }
-                    _ => Err(ProcessError::malformed())
-                })
-            })?;
-            let mut inner = match inner {
-                Some(inner) => inner,
-                None => break
-            };
-            let uri = match uri {
-                Some(uri) => uri,
+        Action::Withdraw => {
+            let hash = match hash {
+                Some(hash) => hash,
                 None => return Err(ProcessError::malformed().into())
             };
-            match action.unwrap() { // Or we'd have exited already.
-                Action::Publish => {
-                    ObjectReader::process(
-                        &mut inner, &mut reader,
-                        |reader| self.publish(uri, hash, reader)
-                    )?;
-                }
-                Action::Withdraw => {
-                    let hash = match hash {
-                        Some(hash) => hash,
-                        None => return Err(ProcessError::malformed().into())
-                    };
-                    self.withdraw(uri, hash)?;
-                    inner.take_end(&mut reader).map_err(Into::into)?;
-                }
-            }
+            processor.withdraw(uri, hash)?;
+            inner.take_end(reader).map_err(Into::into)?;
         }
-        outer.take_end(&mut reader).map_err(Into::into)?;
-        reader.end().map_err(Into::into)?;
-        Ok(())
     }
-
+    Ok(true)
 }
 
 
@@ -1566,6 +1734,26 @@ mod test {
         ).unwrap();
     }
 
+    #[test]
+    fn notification_origin() {
+        let notify = NotificationFile::parse(
+            include_bytes!("../test-data/ripe-notification.xml").as_ref()
+        ).unwrap();
+        assert!(notify.has_same_origin(
+            &uri::Https::from_str(
+                "https://RRDP.ripe.net/notification.xml"
+            ).unwrap()
+        ));
+        assert_eq!(
+            notify.foreign_uri(
+                &uri::Https::from_str(
+                    "https://rrdp.ripe.net:8443/notification.xml"
+                ).unwrap()
+            ),
+            Some(notify.snapshot().uri())
+        );
+    }
+
     #[test]
     fn lolz_notification() {
         assert!(
@@ -1575,6 +1763,65 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_with_limits() {
+        let notify = include_bytes!("../test-data/ripe-notification.xml");
+        let notify = notify.as_ref();
+        let mut limits = Limits::default();
+        limits.set_max_document_size(Some(notify.len()));
+        NotificationFile::parse_with_limits(notify, limits).unwrap();
+        limits.set_max_document_size(Some(notify.len() - 1));
+        assert!(matches!(
+            NotificationFile::parse_with_limits(notify, limits),
+            Err(XmlError::DocumentTooLarge)
+        ));
+
+        let mut limits = Limits::default();
+        limits.set_max_elements(Some(10));
+        assert!(matches!(
+            NotificationFile::parse_with_limits(notify, limits),
+            Err(XmlError::TooManyElements)
+        ));
+
+        let mut limits = Limits::default();
+        limits.set_max_depth(Some(1));
+        assert!(matches!(
+            NotificationFile::parse_with_limits(notify, limits),
+            Err(XmlError::TooDeep)
+        ));
+        limits.set_max_depth(Some(2));
+        NotificationFile::parse_with_limits(notify, limits).unwrap();
+
+        let mut limits = Limits::default();
+        limits.set_max_attribute_len(Some(64));
+        assert!(matches!(
+            NotificationFile::parse_with_limits(notify, limits),
+            Err(XmlError::AttributeTooLong)
+        ));
+
+        let mut limits = Limits::default();
+        limits.set_max_object_size(Some(100));
+        assert!(matches!(
+            Snapshot::parse_with_limits(
+                include_bytes!("../test-data/ripe-snapshot.xml").as_ref(),
+                limits
+            ),
+            Err(ProcessError::Xml(XmlError::ObjectTooLarge))
+        ));
+        assert!(matches!(
+            Delta::parse_with_limits(
+                include_bytes!("../test-data/ripe-delta.xml").as_ref(),
+                limits
+            ),
+            Err(ProcessError::Xml(XmlError::ObjectTooLarge))
+        ));
+        limits.set_max_object_size(Some(1_000_000));
+        Snapshot::parse_with_limits(
+            include_bytes!("../test-data/ripe-snapshot.xml").as_ref(),
+            limits
+        ).unwrap();
+    }
+
     #[test]
     fn gaps_notification() {
         let mut notification_without_gaps =  NotificationFile::parse(
@@ -1703,6 +1950,49 @@ mod test {
         assert_eq!(delta, delta_parsed);
     }
 
+    #[test]
+    fn compressed_from_to_xml() {
+        use self::compress::Compression;
+
+        let notification = NotificationFile::parse(
+            include_bytes!("../test-data/ripe-notification.xml").as_ref()
+        ).unwrap();
+        let snapshot = Snapshot::parse(
+            include_bytes!("../test-data/ripe-snapshot.xml").as_ref()
+        ).unwrap();
+        let delta = Delta::parse(
+            include_bytes!("../test-data/ripe-delta.xml").as_ref()
+        ).unwrap();
+
+        for &compression in &[Compression::Gzip, Compression::Deflate] {
+            let mut vec = vec![];
+            notification.write_compressed(compression, &mut vec).unwrap();
+            assert_eq!(
+                NotificationFile::parse(vec.as_slice()).unwrap(),
+                notification
+            );
+
+            let mut vec = vec![];
+            snapshot.write_compressed(compression, &mut vec).unwrap();
+            assert_eq!(Snapshot::parse(vec.as_slice()).unwrap(), snapshot);
+
+            let mut vec = vec![];
+            delta.write_compressed(compression, &mut vec).unwrap();
+            assert_eq!(Delta::parse(vec.as_slice()).unwrap(), delta);
+        }
+
+        // A compression bomb is rejected.
+        let mut vec = vec![];
+        Compression::Gzip.write(&mut vec, |mut writer| {
+            writer.write_all(&vec![b' '; 1_000_000])?;
+            delta.write_xml(&mut writer)
+        }).unwrap();
+        assert!(Delta::parse(vec.as_slice()).is_err());
+        assert!(Delta::parse(
+            compress::Decompress::with_max_ratio(vec.as_slice(), None)
+        ).is_ok());
+    }
+
     #[test]
     fn snapshot_content() {
         const CONTENT: &[u8] = b"foo bar\n";
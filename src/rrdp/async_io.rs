@@ -0,0 +1,339 @@
+//! Asynchronous processing of RRDP files.
+//!
+//! This module provides async versions of the functions for parsing and
+//! writing RRDP files. They read from a Tokio [`AsyncBufRead`] and write to
+//! an [`AsyncWrite`]. Snapshot and delta files are processed via the
+//! [`ProcessSnapshot`] and [`ProcessDelta`] traits using
+//! [`process_snapshot`] and [`process_delta`], respectively.
+//!
+//! Since the XML parser only supports synchronous input, snapshot and
+//! delta files are read one element at a time via an
+//! [`AsyncReader`][crate::xml::decode::AsyncReader] and each element is
+//! processed once it has been read completely. Similarly, they are written
+//! element by element. Notification files, which are comparatively small,
+//! are read and written in one go.
+//!
+//! As with the synchronous functions, compressed input is decompressed
+//! transparently.
+//!
+//! The module is only available with the `"async"` feature.
+
+#![cfg(feature = "async")]
+
+use std::io;
+use std::convert::TryInto;
+use tokio::io::{AsyncBufRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use crate::xml::decode::{AsyncReader, Error as XmlError, Limits};
+use crate::xml::encode::AsyncWriter;
+use super::{
+    Delta, DeltaBuilder, NotificationFile, ProcessDelta, ProcessError,
+    ProcessSnapshot, Snapshot, SnapshotBuilder, DELTA, NS, SNAPSHOT,
+    delta_element, delta_start, snapshot_element, snapshot_start,
+};
+use super::compress::AsyncDecompress;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The amount of buffered data after which it is passed on when writing.
+const WRITE_BUF_SIZE: usize = 64 * 1024;
+
+
+//------------ Processing ----------------------------------------------------
+
+/// Processes a snapshot file read from an async reader.
+///
+/// This is the async equivalent of [`ProcessSnapshot::process`].
+pub async fn process_snapshot<P, R>(
+    processor: &mut P, reader: R
+) -> Result<(), P::Err>
+where P: ProcessSnapshot + ?Sized, R: AsyncBufRead + Unpin {
+    process_snapshot_with_limits(processor, reader, Limits::default()).await
+}
+
+/// Processes a snapshot file read from an async reader enforcing limits.
+///
+/// This is the async equivalent of
+/// [`ProcessSnapshot::process_with_limits`].
+pub async fn process_snapshot_with_limits<P, R>(
+    processor: &mut P, reader: R, limits: Limits,
+) -> Result<(), P::Err>
+where P: ProcessSnapshot + ?Sized, R: AsyncBufRead + Unpin {
+    let mut reader = AsyncReader::with_limits(
+        AsyncDecompress::new(reader), limits
+    );
+    let mut started = false;
+    while let Some(mut fragment) = reader.next_fragment().await.map_err(
+        ProcessError::from
+    )? {
+        let (mut outer, session_id, serial) = snapshot_start(&mut fragment)?;
+        if !started {
+            processor.meta(session_id, serial)?;
+            started = true;
+        }
+        if !snapshot_element(processor, &mut outer, &mut fragment)? {
+            outer.take_end(&mut fragment).map_err(ProcessError::from)?;
+            fragment.end().map_err(ProcessError::from)?;
+        }
+    }
+    Ok(())
+}
+
+/// Processes a delta file read from an async reader.
+///
+/// This is the async equivalent of [`ProcessDelta::process`].
+pub async fn process_delta<P, R>(
+    processor: &mut P, reader: R
+) -> Result<(), P::Err>
+where P: ProcessDelta + ?Sized, R: AsyncBufRead + Unpin {
+    process_delta_with_limits(processor, reader, Limits::default()).await
+}
+
+/// Processes a delta file read from an async reader enforcing limits.
+///
+/// This is the async equivalent of [`ProcessDelta::process_with_limits`].
+pub async fn process_delta_with_limits<P, R>(
+    processor: &mut P, reader: R, limits: Limits,
+) -> Result<(), P::Err>
+where P: ProcessDelta + ?Sized, R: AsyncBufRead + Unpin {
+    let mut reader = AsyncReader::with_limits(
+        AsyncDecompress::new(reader), limits
+    );
+    let mut started = false;
+    while let Some(mut fragment) = reader.next_fragment().await.map_err(
+        ProcessError::from
+    )? {
+        let (mut outer, session_id, serial) = delta_start(&mut fragment)?;
+        if !started {
+            processor.meta(session_id, serial)?;
+            started = true;
+        }
+        if !delta_element(processor, &mut outer, &mut fragment)? {
+            outer.take_end(&mut fragment).map_err(ProcessError::from)?;
+            fragment.end().map_err(ProcessError::from)?;
+        }
+    }
+    Ok(())
+}
+
+
+//------------ NotificationFile ----------------------------------------------
+
+impl NotificationFile {
+    /// Parses a notification file read from an async reader.
+    pub async fn parse_async<R: AsyncBufRead + Unpin>(
+        reader: R
+    ) -> Result<Self, XmlError> {
+        Self::parse_async_with_limits(reader, Limits::default()).await
+    }
+
+    /// Parses a notification file from an async reader enforcing limits.
+    ///
+    /// The file is read completely before being parsed. If a maximum
+    /// document size is given, at most one byte more than that is read.
+    pub async fn parse_async_with_limits<R: AsyncBufRead + Unpin>(
+        reader: R, limits: Limits
+    ) -> Result<Self, XmlError> {
+        let mut reader = AsyncDecompress::new(reader);
+        let mut data = Vec::new();
+        let res = match limits.max_document_size() {
+            Some(max) => {
+                let max = (max as u64).saturating_add(1);
+                (&mut reader).take(max).read_to_end(&mut data).await
+            }
+            None => reader.read_to_end(&mut data).await
+        };
+        if let Err(err) = res {
+            return Err(quick_xml::Error::Io(err).into())
+        }
+        Self::parse_with_limits(data.as_slice(), limits)
+    }
+
+    /// Writes the notification file as RFC 8182 XML to an async writer.
+    pub async fn write_xml_async(
+        &self, writer: &mut (impl AsyncWrite + Unpin)
+    ) -> Result<(), io::Error> {
+        let mut data = Vec::new();
+        self.write_xml(&mut data)?;
+        writer.write_all(&data).await?;
+        writer.flush().await
+    }
+}
+
+
+//------------ Snapshot ------------------------------------------------------
+
+impl Snapshot {
+    /// Parses a snapshot read from an async reader.
+    pub async fn parse_async<R: AsyncBufRead + Unpin>(
+        reader: R
+    ) -> Result<Self, ProcessError> {
+        Self::parse_async_with_limits(reader, Limits::default()).await
+    }
+
+    /// Parses a snapshot read from an async reader enforcing limits.
+    pub async fn parse_async_with_limits<R: AsyncBufRead + Unpin>(
+        reader: R, limits: Limits
+    ) -> Result<Self, ProcessError> {
+        let mut builder = SnapshotBuilder {
+            session_id: None,
+            serial: None,
+            elements: vec![]
+        };
+        process_snapshot_with_limits(&mut builder, reader, limits).await?;
+        builder.try_into()
+    }
+
+    /// Writes the snapshot’s XML representation to an async writer.
+    pub async fn write_xml_async(
+        &self, writer: &mut (impl AsyncWrite + Unpin)
+    ) -> Result<(), io::Error> {
+        let mut writer = AsyncWriter::new(writer);
+        writer.start_element(SNAPSHOT.into_unqualified(), |element| {
+            element.attr("xmlns", NS)?
+                .attr("version", "1")?
+                .attr("session_id", &self.session_id)?
+                .attr("serial", &self.serial)
+        })?;
+        for el in &self.elements {
+            writer.content(|content| el.write_xml(content))?;
+            if writer.buffered() >= WRITE_BUF_SIZE {
+                writer.write_buffered().await?;
+            }
+        }
+        writer.end_element()?;
+        writer.done().await?;
+        Ok(())
+    }
+}
+
+
+//------------ Delta ---------------------------------------------------------
+
+impl Delta {
+    /// Parses a delta read from an async reader.
+    pub async fn parse_async<R: AsyncBufRead + Unpin>(
+        reader: R
+    ) -> Result<Self, ProcessError> {
+        Self::parse_async_with_limits(reader, Limits::default()).await
+    }
+
+    /// Parses a delta read from an async reader enforcing limits.
+    pub async fn parse_async_with_limits<R: AsyncBufRead + Unpin>(
+        reader: R, limits: Limits
+    ) -> Result<Self, ProcessError> {
+        let mut builder = DeltaBuilder {
+            session_id: None,
+            serial: None,
+            elements: vec![]
+        };
+        process_delta_with_limits(&mut builder, reader, limits).await?;
+        builder.try_into()
+    }
+
+    /// Writes the delta’s XML representation to an async writer.
+    pub async fn write_xml_async(
+        &self, writer: &mut (impl AsyncWrite + Unpin)
+    ) -> Result<(), io::Error> {
+        let mut writer = AsyncWriter::new(writer);
+        writer.start_element(DELTA.into_unqualified(), |element| {
+            element.attr("xmlns", NS)?
+                .attr("version", "1")?
+                .attr("session_id", &self.session_id)?
+                .attr("serial", &self.serial)
+        })?;
+        for el in &self.elements {
+            writer.content(|content| el.write_xml(content))?;
+            if writer.buffered() >= WRITE_BUF_SIZE {
+                writer.write_buffered().await?;
+            }
+        }
+        writer.end_element()?;
+        writer.done().await?;
+        Ok(())
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rrdp::compress::Compression;
+
+    /// Returns a reader that hands out data in tiny chunks.
+    fn chunked(data: &[u8]) -> tokio::io::BufReader<&[u8]> {
+        tokio::io::BufReader::with_capacity(7, data)
+    }
+
+    #[tokio::test]
+    async fn parse_and_write() {
+        let data = include_bytes!("../../test-data/ripe-snapshot.xml");
+        let snapshot = Snapshot::parse(data.as_ref()).unwrap();
+        assert_eq!(
+            Snapshot::parse_async(chunked(data)).await.unwrap(), snapshot
+        );
+        let mut sync = Vec::new();
+        snapshot.write_xml(&mut sync).unwrap();
+        let mut written = Vec::new();
+        snapshot.write_xml_async(&mut written).await.unwrap();
+        assert_eq!(sync, written);
+
+        let data = include_bytes!("../../test-data/ripe-delta.xml");
+        let delta = Delta::parse(data.as_ref()).unwrap();
+        assert_eq!(Delta::parse_async(chunked(data)).await.unwrap(), delta);
+        let mut sync = Vec::new();
+        delta.write_xml(&mut sync).unwrap();
+        let mut written = Vec::new();
+        delta.write_xml_async(&mut written).await.unwrap();
+        assert_eq!(sync, written);
+
+        let data = include_bytes!("../../test-data/ripe-notification.xml");
+        let notify = NotificationFile::parse(data.as_ref()).unwrap();
+        assert_eq!(
+            NotificationFile::parse_async(chunked(data)).await.unwrap(),
+            notify
+        );
+        let mut written = Vec::new();
+        notify.write_xml_async(&mut written).await.unwrap();
+        assert_eq!(
+            NotificationFile::parse(written.as_slice()).unwrap(), notify
+        );
+
+        let mut compressed = Vec::new();
+        delta.write_compressed(Compression::Gzip, &mut compressed).unwrap();
+        assert_eq!(
+            Delta::parse_async(chunked(&compressed)).await.unwrap(), delta
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed() {
+        let data = include_bytes!("../../test-data/ripe-delta.xml");
+        for &len in &[0, 10, data.len() / 2, data.len() - 3] {
+            assert!(Delta::parse_async(chunked(&data[..len])).await.is_err());
+        }
+
+        let mut extra = data.to_vec();
+        extra.extend_from_slice(b"<publish/>");
+        assert!(Delta::parse_async(chunked(&extra)).await.is_err());
+
+        let mut limits = Limits::default();
+        limits.set_max_elements(Some(3));
+        assert!(matches!(
+            Delta::parse_async_with_limits(chunked(data), limits).await,
+            Err(ProcessError::Xml(XmlError::TooManyElements))
+        ));
+
+        let empty = Snapshot::parse_async(chunked(
+            b"<?xml version=\"1.0\"?><!-- empty -->\
+              <snapshot xmlns=\"http://www.ripe.net/rpki/rrdp\" \
+              version=\"1\" \
+              session_id=\"a2d845c4-5b91-4015-a2b7-988c03ce232a\" \
+              serial=\"12\"/>\n"
+        )).await.unwrap();
+        assert_eq!(empty.serial(), 12);
+        assert!(empty.elements().is_empty());
+    }
+}
@@ -0,0 +1,1153 @@
+//! Compressed RRDP files.
+//!
+//! RRDP files are sometimes served with a gzip or deflate content
+//! encoding or are stored compressed. The parsing functions of this crate
+//! therefore transparently decompress their input via [`Decompress`]. The
+//! format is detected from the first few bytes of the input: gzip data
+//! starts with the gzip magic bytes and deflate data is expected to be
+//! wrapped in the zlib format as used by the HTTP deflate content
+//! encoding. All other input is passed through unchanged.
+//!
+//! In order to protect against small inputs decompressing into huge
+//! amounts of data, decompression fails once the output is more than a
+//! maximum ratio larger than the compressed input consumed so far. This
+//! ratio defaults to [`DEFAULT_MAX_RATIO`]. A different ratio can be used
+//! by wrapping the input in a [`Decompress`] created via
+//! [`Decompress::with_max_ratio`] before handing it to the parsing
+//! function.
+//!
+//! Compressed files can be created via the `write_compressed` methods of
+//! the file types using one of the formats of [`Compression`].
+//!
+//! With the `"async"` feature, [`AsyncDecompress`] provides the same for
+//! async readers.
+
+use std::{error, fmt, io, slice};
+use std::io::Read;
+#[cfg(feature = "async")] use std::pin::Pin;
+#[cfg(feature = "async")] use std::task::{Context, Poll};
+use flate2::bufread::{MultiGzDecoder, ZlibDecoder};
+#[cfg(feature = "async")] use flate2::{Crc, FlushDecompress, Status};
+use flate2::write::{GzEncoder, ZlibEncoder};
+#[cfg(feature = "async")] use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The default maximum ratio between decompressed and compressed size.
+pub const DEFAULT_MAX_RATIO: u64 = 100;
+
+
+//------------ Compression ---------------------------------------------------
+
+/// The compression format to use when writing a file.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Compression {
+    /// The gzip format of RFC 1952.
+    Gzip,
+
+    /// The zlib format of RFC 1950 used by the HTTP deflate encoding.
+    Deflate,
+}
+
+impl Compression {
+    /// Writes data produced by `op` compressed to `writer`.
+    pub(crate) fn write<W: io::Write>(
+        self,
+        writer: &mut W,
+        op: impl FnOnce(&mut dyn io::Write) -> Result<(), io::Error>,
+    ) -> Result<(), io::Error> {
+        let level = flate2::Compression::default();
+        match self {
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(writer, level);
+                op(&mut encoder)?;
+                encoder.finish()?;
+            }
+            Compression::Deflate => {
+                let mut encoder = ZlibEncoder::new(writer, level);
+                op(&mut encoder)?;
+                encoder.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+//------------ Decompress ----------------------------------------------------
+
+/// A reader that decompresses its input if necessary.
+///
+/// The reader detects whether its input is gzip or zlib compressed when
+/// data is first read. Compressed input is decompressed while enforcing
+/// a maximum decompression ratio. If the ratio is exceeded, reading fails
+/// with an IO error of kind `InvalidData` wrapping a [`RatioExceeded`]
+/// error. Input that is not compressed is passed through unchanged.
+pub struct Decompress<R> {
+    /// The actual reader.
+    inner: Inner<R>,
+
+    /// The maximum ratio between output and input size.
+    max_ratio: Option<u64>,
+
+    /// The buffer for decompressed data.
+    buf: Box<[u8]>,
+
+    /// The start of the unconsumed data in `buf`.
+    pos: usize,
+
+    /// The end of the unconsumed data in `buf`.
+    end: usize,
+
+    /// The number of decompressed bytes produced so far.
+    produced: u64,
+}
+
+/// The state of a decompressing reader.
+enum Inner<R> {
+    /// The format hasn’t been determined yet.
+    ///
+    /// This is only an option so we can move the reader out.
+    Undecided(Option<Prefixed<R>>),
+
+    /// The input is not compressed.
+    Plain(Prefixed<R>),
+
+    /// The input is gzip compressed.
+    Gzip(MultiGzDecoder<Counter<Prefixed<R>>>),
+
+    /// The input is zlib compressed.
+    Deflate(ZlibDecoder<Counter<Prefixed<R>>>),
+}
+
+impl<R: io::BufRead> Decompress<R> {
+    /// The size of the buffer for decompressed data.
+    const BUF_SIZE: usize = 8192;
+
+    /// Creates a new reader using the default maximum ratio.
+    pub fn new(reader: R) -> Self {
+        Self::with_max_ratio(reader, Some(DEFAULT_MAX_RATIO))
+    }
+
+    /// Creates a new reader using the given maximum ratio.
+    ///
+    /// If `max_ratio` is `None`, decompression is not limited.
+    pub fn with_max_ratio(reader: R, max_ratio: Option<u64>) -> Self {
+        Decompress {
+            inner: Inner::Undecided(Some(Prefixed::new(reader))),
+            max_ratio,
+            buf: Box::new([]),
+            pos: 0,
+            end: 0,
+            produced: 0,
+        }
+    }
+
+    /// Returns the compression format of the input.
+    ///
+    /// Returns `None` if the input is not compressed or if the format has
+    /// not been determined yet because nothing has been read.
+    pub fn compression(&self) -> Option<Compression> {
+        match self.inner {
+            Inner::Gzip(_) => Some(Compression::Gzip),
+            Inner::Deflate(_) => Some(Compression::Deflate),
+            _ => None
+        }
+    }
+
+    /// Determines the format of the input if that hasn’t happened yet.
+    fn decide(&mut self) -> Result<(), io::Error> {
+        let reader = match self.inner {
+            Inner::Undecided(ref mut reader) => reader,
+            _ => return Ok(())
+        };
+        let mut reader = match reader.take() {
+            Some(reader) => reader,
+            None => return Ok(())
+        };
+        let compression = match reader.detect() {
+            Ok(compression) => compression,
+            Err(err) => {
+                self.inner = Inner::Undecided(Some(reader));
+                return Err(err)
+            }
+        };
+        self.inner = match compression {
+            None => Inner::Plain(reader),
+            Some(Compression::Gzip) => {
+                Inner::Gzip(MultiGzDecoder::new(Counter::new(reader)))
+            }
+            Some(Compression::Deflate) => {
+                Inner::Deflate(ZlibDecoder::new(Counter::new(reader)))
+            }
+        };
+        if compression.is_some() {
+            self.buf = vec![0; Self::BUF_SIZE].into_boxed_slice();
+        }
+        Ok(())
+    }
+
+    /// Decompresses more data into the buffer.
+    ///
+    /// Must only be called if the buffer is empty.
+    fn decompress(&mut self) -> Result<(), io::Error> {
+        let (read, consumed) = match self.inner {
+            Inner::Gzip(ref mut decoder) => {
+                (decoder.read(&mut self.buf)?, decoder.get_ref().count)
+            }
+            Inner::Deflate(ref mut decoder) => {
+                (decoder.read(&mut self.buf)?, decoder.get_ref().count)
+            }
+            _ => return Ok(())
+        };
+        self.produced += read as u64;
+        if let Some(max_ratio) = self.max_ratio {
+            if self.produced > consumed.max(1).saturating_mul(max_ratio) {
+                return Err(RatioExceeded.into())
+            }
+        }
+        self.pos = 0;
+        self.end = read;
+        Ok(())
+    }
+}
+
+impl<R: io::BufRead> io::Read for Decompress<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let data = io::BufRead::fill_buf(self)?;
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        io::BufRead::consume(self, len);
+        Ok(len)
+    }
+}
+
+impl<R: io::BufRead> io::BufRead for Decompress<R> {
+    fn fill_buf(&mut self) -> Result<&[u8], io::Error> {
+        self.decide()?;
+        if let Inner::Plain(ref mut reader) = self.inner {
+            return reader.fill_buf()
+        }
+        if self.pos == self.end {
+            self.decompress()?;
+        }
+        Ok(&self.buf[self.pos..self.end])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Inner::Plain(ref mut reader) = self.inner {
+            reader.consume(amt)
+        }
+        else {
+            self.pos = (self.pos + amt).min(self.end)
+        }
+    }
+}
+
+impl<R> fmt::Debug for Decompress<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format = match self.inner {
+            Inner::Undecided(_) => "undecided",
+            Inner::Plain(_) => "plain",
+            Inner::Gzip(_) => "gzip",
+            Inner::Deflate(_) => "deflate",
+        };
+        f.debug_struct("Decompress")
+            .field("format", &format)
+            .field("max_ratio", &self.max_ratio)
+            .field("produced", &self.produced)
+            .finish()
+    }
+}
+
+/// Detects the compression format from the start of the data.
+///
+/// The data needs to contain at least the first two bytes of the input
+/// unless the input is shorter.
+fn detect(data: &[u8]) -> Option<Compression> {
+    if data.starts_with(&[0x1f, 0x8b]) {
+        Some(Compression::Gzip)
+    }
+    else if data.len() >= 2
+        && data[0] & 0x0f == 8
+        && (u16::from(data[0]) << 8 | u16::from(data[1])) % 31 == 0
+    {
+        // A zlib header: compression method 8 and a valid check value.
+        // Neither XML nor a UTF byte order mark can start like this.
+        Some(Compression::Deflate)
+    }
+    else {
+        None
+    }
+}
+
+
+//------------ AsyncDecompress -----------------------------------------------
+
+/// An async reader that decompresses its input if necessary.
+///
+/// This is the async equivalent of [`Decompress`] and behaves the same
+/// way. Input is inflated into a fixed size buffer and the ratio is
+/// checked after each step, so a compression bomb is detected no matter
+/// how large the chunks are that the underlying reader provides.
+#[cfg(feature = "async")]
+pub struct AsyncDecompress<R> {
+    /// The actual reader.
+    reader: Prefixed<R>,
+
+    /// Has the format been determined yet?
+    decided: bool,
+
+    /// The decoder if the input is compressed.
+    decoder: Option<AsyncDecoder>,
+
+    /// The maximum ratio between output and input size.
+    max_ratio: Option<u64>,
+
+    /// The buffer for decompressed data.
+    buf: Box<[u8]>,
+
+    /// The start of the unconsumed data in `buf`.
+    pos: usize,
+
+    /// The end of the unconsumed data in `buf`.
+    end: usize,
+
+    /// Has the end of the compressed data been reached?
+    finished: bool,
+
+    /// The number of compressed bytes consumed so far.
+    consumed: u64,
+
+    /// The number of decompressed bytes produced so far.
+    produced: u64,
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncBufRead + Unpin> AsyncDecompress<R> {
+    /// The size of the buffer for decompressed data.
+    const BUF_SIZE: usize = 8192;
+
+    /// Creates a new reader using the default maximum ratio.
+    pub fn new(reader: R) -> Self {
+        Self::with_max_ratio(reader, Some(DEFAULT_MAX_RATIO))
+    }
+
+    /// Creates a new reader using the given maximum ratio.
+    ///
+    /// If `max_ratio` is `None`, decompression is not limited.
+    pub fn with_max_ratio(reader: R, max_ratio: Option<u64>) -> Self {
+        AsyncDecompress {
+            reader: Prefixed::new(reader),
+            decided: false,
+            decoder: None,
+            max_ratio,
+            buf: Box::new([]),
+            pos: 0,
+            end: 0,
+            finished: false,
+            consumed: 0,
+            produced: 0,
+        }
+    }
+
+    /// Returns the compression format of the input.
+    ///
+    /// Returns `None` if the input is not compressed or if the format has
+    /// not been determined yet because nothing has been read.
+    pub fn compression(&self) -> Option<Compression> {
+        self.decoder.as_ref().map(|decoder| decoder.compression)
+    }
+
+    /// Performs the next decompression step.
+    ///
+    /// The step either consumes input belonging to a gzip header or
+    /// trailer or inflates input into the buffer. Must only be called if
+    /// there is a decoder and the buffer is empty.
+    fn poll_decompress(
+        &mut self, cx: &mut Context
+    ) -> Poll<Result<(), io::Error>> {
+        let data = match Pin::new(&mut self.reader).poll_fill_buf(cx) {
+            Poll::Ready(Ok(data)) => data,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        };
+        let decoder = match self.decoder.as_mut() {
+            Some(decoder) => decoder,
+            None => return Poll::Ready(Ok(()))
+        };
+        let (read, written, finished) = decoder.step(data, &mut self.buf)?;
+        Pin::new(&mut self.reader).consume(read);
+        self.consumed += read as u64;
+        self.produced += written as u64;
+        self.pos = 0;
+        self.end = written;
+        self.finished = finished;
+        if let Some(max_ratio) = self.max_ratio {
+            let limit = self.consumed.max(1).saturating_mul(max_ratio);
+            if self.produced > limit {
+                return Poll::Ready(Err(RatioExceeded.into()))
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncBufRead + Unpin> AsyncRead for AsyncDecompress<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<Result<(), io::Error>> {
+        let data = match self.as_mut().poll_fill_buf(cx) {
+            Poll::Ready(Ok(data)) => data,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        };
+        let len = data.len().min(buf.remaining());
+        buf.put_slice(&data[..len]);
+        self.consume(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncBufRead + Unpin> AsyncBufRead for AsyncDecompress<R> {
+    fn poll_fill_buf(
+        self: Pin<&mut Self>, cx: &mut Context
+    ) -> Poll<Result<&[u8], io::Error>> {
+        let this = self.get_mut();
+        if !this.decided {
+            let compression = match this.reader.poll_detect(cx) {
+                Poll::Ready(Ok(compression)) => compression,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            };
+            this.decoder = compression.map(AsyncDecoder::new);
+            if this.decoder.is_some() {
+                this.buf = vec![0; Self::BUF_SIZE].into_boxed_slice();
+            }
+            this.decided = true;
+        }
+        if this.decoder.is_none() {
+            return Pin::new(&mut this.reader).poll_fill_buf(cx)
+        }
+        while this.pos == this.end && !this.finished {
+            match this.poll_decompress(cx) {
+                Poll::Ready(Ok(())) => { }
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(&this.buf[this.pos..this.end]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        if this.decoder.is_none() {
+            Pin::new(&mut this.reader).consume(amt)
+        }
+        else {
+            this.pos = (this.pos + amt).min(this.end)
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R> fmt::Debug for AsyncDecompress<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format = match self.decoder {
+            _ if !self.decided => "undecided",
+            None => "plain",
+            Some(ref decoder) => match decoder.compression {
+                Compression::Gzip => "gzip",
+                Compression::Deflate => "deflate",
+            }
+        };
+        f.debug_struct("AsyncDecompress")
+            .field("format", &format)
+            .field("max_ratio", &self.max_ratio)
+            .field("produced", &self.produced)
+            .finish()
+    }
+}
+
+
+//------------ AsyncDecoder --------------------------------------------------
+
+/// The decoder used by an async decompressing reader.
+///
+/// Because `flate2::Decompress` only understands the zlib format and raw
+/// deflate data, the decoder processes the framing of gzip members
+/// itself.
+#[cfg(feature = "async")]
+struct AsyncDecoder {
+    /// The compression format of the input.
+    compression: Compression,
+
+    /// The decompressor for the deflate data.
+    inflate: flate2::Decompress,
+
+    /// Where in the input we currently are.
+    state: DecoderState,
+
+    /// The checksum of the data of the current gzip member.
+    crc: Crc,
+}
+
+/// The position of an async decoder in its input.
+#[cfg(feature = "async")]
+enum DecoderState {
+    /// Reading the header of a gzip member.
+    Header(GzipHeader),
+
+    /// Inflating deflate data.
+    Body,
+
+    /// Reading the trailer of a gzip member.
+    ///
+    /// Contains the trailer and the number of its bytes read so far.
+    Trailer([u8; 8], usize),
+
+    /// A gzip member has ended and another one may follow.
+    MemberEnd,
+}
+
+#[cfg(feature = "async")]
+impl AsyncDecoder {
+    /// Creates a new decoder for the given format.
+    fn new(compression: Compression) -> Self {
+        let (inflate, state) = match compression {
+            Compression::Gzip => {
+                (
+                    flate2::Decompress::new(false),
+                    DecoderState::Header(GzipHeader::default())
+                )
+            }
+            Compression::Deflate => {
+                (flate2::Decompress::new(true), DecoderState::Body)
+            }
+        };
+        AsyncDecoder { compression, inflate, state, crc: Crc::new() }
+    }
+
+    /// Performs one decoding step.
+    ///
+    /// Uses data from `input`, which is empty at the end of input, and
+    /// writes decompressed data to `output`. Returns the number of bytes
+    /// read from `input`, the number of bytes written to `output`, and
+    /// whether the end of the compressed data has been reached.
+    fn step(
+        &mut self, input: &[u8], output: &mut [u8]
+    ) -> Result<(usize, usize, bool), io::Error> {
+        match self.state {
+            DecoderState::Header(ref mut header) => {
+                if input.is_empty() {
+                    return Err(io::ErrorKind::UnexpectedEof.into())
+                }
+                let read = header.feed(input)?;
+                if header.is_complete() {
+                    self.inflate.reset(false);
+                    self.crc.reset();
+                    self.state = DecoderState::Body;
+                }
+                Ok((read, 0, false))
+            }
+            DecoderState::Body => {
+                let flush = if input.is_empty() {
+                    FlushDecompress::Finish
+                }
+                else {
+                    FlushDecompress::None
+                };
+                let total_in = self.inflate.total_in();
+                let total_out = self.inflate.total_out();
+                let status = self.inflate.decompress(
+                    input, output, flush
+                ).map_err(|err| {
+                    io::Error::new(io::ErrorKind::InvalidData, err)
+                })?;
+                let read = (self.inflate.total_in() - total_in) as usize;
+                let written = (self.inflate.total_out() - total_out) as usize;
+                if let Status::StreamEnd = status {
+                    if self.compression == Compression::Gzip {
+                        self.crc.update(&output[..written]);
+                        self.state = DecoderState::Trailer([0; 8], 0);
+                        return Ok((read, written, false))
+                    }
+                    return Ok((read, written, true))
+                }
+                if read == 0 && written == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into())
+                }
+                self.crc.update(&output[..written]);
+                Ok((read, written, false))
+            }
+            DecoderState::Trailer(ref mut trailer, ref mut len) => {
+                if input.is_empty() {
+                    return Err(io::ErrorKind::UnexpectedEof.into())
+                }
+                let read = (trailer.len() - *len).min(input.len());
+                trailer[*len..*len + read].copy_from_slice(&input[..read]);
+                *len += read;
+                if *len == trailer.len() {
+                    let crc = u32::from_le_bytes(
+                        [trailer[0], trailer[1], trailer[2], trailer[3]]
+                    );
+                    let size = u32::from_le_bytes(
+                        [trailer[4], trailer[5], trailer[6], trailer[7]]
+                    );
+                    if crc != self.crc.sum() || size != self.crc.amount() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "gzip checksum mismatch"
+                        ))
+                    }
+                    self.state = DecoderState::MemberEnd;
+                }
+                Ok((read, 0, false))
+            }
+            DecoderState::MemberEnd => {
+                if input.is_empty() {
+                    return Ok((0, 0, true))
+                }
+                self.state = DecoderState::Header(GzipHeader::default());
+                Ok((0, 0, false))
+            }
+        }
+    }
+}
+
+
+//------------ GzipHeader ----------------------------------------------------
+
+/// An incremental parser for the header of a gzip member.
+///
+/// The header is skipped without keeping any of the optional fields.
+#[cfg(feature = "async")]
+#[derive(Default)]
+struct GzipHeader {
+    /// The fixed part of the header.
+    fixed: [u8; 10],
+
+    /// The flags of the optional fields not yet processed.
+    flags: u8,
+
+    /// The field currently processed.
+    field: HeaderField,
+}
+
+/// The field of a gzip header currently processed.
+#[cfg(feature = "async")]
+enum HeaderField {
+    /// The fixed part with the number of bytes read so far.
+    Fixed(usize),
+
+    /// The length of the extra field with its first byte if read already.
+    ExtraLen(Option<u8>),
+
+    /// A field of the given remaining length to be skipped.
+    Skip(usize),
+
+    /// A zero-terminated field to be skipped.
+    Terminated,
+
+    /// The header is complete.
+    Done,
+}
+
+#[cfg(feature = "async")]
+impl Default for HeaderField {
+    fn default() -> Self {
+        HeaderField::Fixed(0)
+    }
+}
+
+#[cfg(feature = "async")]
+impl GzipHeader {
+    /// The flag for the presence of the extra field.
+    const FEXTRA: u8 = 0x04;
+
+    /// The flag for the presence of the file name.
+    const FNAME: u8 = 0x08;
+
+    /// The flag for the presence of the comment.
+    const FCOMMENT: u8 = 0x10;
+
+    /// The flag for the presence of the header checksum.
+    const FHCRC: u8 = 0x02;
+
+    /// Returns whether the complete header has been read.
+    fn is_complete(&self) -> bool {
+        matches!(self.field, HeaderField::Done)
+    }
+
+    /// Processes header data and returns the number of bytes used.
+    fn feed(&mut self, data: &[u8]) -> Result<usize, io::Error> {
+        let mut read = 0;
+        while read < data.len() && !self.is_complete() {
+            let data = &data[read..];
+            match self.field {
+                HeaderField::Fixed(len) => {
+                    let take = (self.fixed.len() - len).min(data.len());
+                    self.fixed[len..len + take].copy_from_slice(
+                        &data[..take]
+                    );
+                    read += take;
+                    if len + take < self.fixed.len() {
+                        self.field = HeaderField::Fixed(len + take);
+                        continue
+                    }
+                    if self.fixed[..3] != [0x1f, 0x8b, 8] {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "invalid gzip header"
+                        ))
+                    }
+                    self.flags = self.fixed[3];
+                    self.next_field();
+                }
+                HeaderField::ExtraLen(None) => {
+                    self.field = HeaderField::ExtraLen(Some(data[0]));
+                    read += 1;
+                }
+                HeaderField::ExtraLen(Some(low)) => {
+                    read += 1;
+                    self.skip(usize::from(low) | usize::from(data[0]) << 8);
+                }
+                HeaderField::Skip(len) => {
+                    let take = len.min(data.len());
+                    read += take;
+                    self.skip(len - take);
+                }
+                HeaderField::Terminated => {
+                    match data.iter().position(|&ch| ch == 0) {
+                        Some(pos) => {
+                            read += pos + 1;
+                            self.next_field();
+                        }
+                        None => read += data.len()
+                    }
+                }
+                HeaderField::Done => { }
+            }
+        }
+        Ok(read)
+    }
+
+    /// Skips the given number of bytes before moving to the next field.
+    fn skip(&mut self, len: usize) {
+        if len == 0 {
+            self.next_field()
+        }
+        else {
+            self.field = HeaderField::Skip(len)
+        }
+    }
+
+    /// Moves to the next optional field present in the header.
+    fn next_field(&mut self) {
+        self.field = if self.take_flag(Self::FEXTRA) {
+            HeaderField::ExtraLen(None)
+        }
+        else if
+            self.take_flag(Self::FNAME) || self.take_flag(Self::FCOMMENT)
+        {
+            HeaderField::Terminated
+        }
+        else if self.take_flag(Self::FHCRC) {
+            HeaderField::Skip(2)
+        }
+        else {
+            HeaderField::Done
+        }
+    }
+
+    /// Removes a flag and returns whether it was set.
+    fn take_flag(&mut self, flag: u8) -> bool {
+        let res = self.flags & flag != 0;
+        self.flags &= !flag;
+        res
+    }
+}
+
+
+//------------ Prefixed ------------------------------------------------------
+
+/// A reader that may have the first byte of its input set aside.
+///
+/// Detecting the compression format needs the first two bytes of the
+/// input. If the underlying reader only provides a single byte at first,
+/// that byte is consumed and kept here so the next one can be read.
+struct Prefixed<R> {
+    /// The first byte of the input if it has been set aside.
+    first: Option<u8>,
+
+    /// The actual reader.
+    reader: R,
+}
+
+impl<R> Prefixed<R> {
+    fn new(reader: R) -> Self {
+        Prefixed { first: None, reader }
+    }
+}
+
+impl<R: io::BufRead> Prefixed<R> {
+    /// Detects the compression format of the input.
+    fn detect(&mut self) -> Result<Option<Compression>, io::Error> {
+        let first = match self.first {
+            Some(first) => first,
+            None => {
+                let data = self.reader.fill_buf()?;
+                if data.len() != 1 {
+                    return Ok(detect(data))
+                }
+                let first = data[0];
+                self.reader.consume(1);
+                self.first = Some(first);
+                first
+            }
+        };
+        let data = self.reader.fill_buf()?;
+        Ok(match data.first() {
+            Some(&second) => detect(&[first, second]),
+            None => detect(&[first])
+        })
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncBufRead + Unpin> Prefixed<R> {
+    /// Detects the compression format of the input.
+    fn poll_detect(
+        &mut self, cx: &mut Context
+    ) -> Poll<Result<Option<Compression>, io::Error>> {
+        if self.first.is_none() {
+            let data = match Pin::new(&mut self.reader).poll_fill_buf(cx) {
+                Poll::Ready(Ok(data)) => data,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            };
+            if data.len() != 1 {
+                return Poll::Ready(Ok(detect(data)))
+            }
+            let first = data[0];
+            Pin::new(&mut self.reader).consume(1);
+            self.first = Some(first);
+        }
+        let first = self.first.unwrap_or_default();
+        let data = match Pin::new(&mut self.reader).poll_fill_buf(cx) {
+            Poll::Ready(Ok(data)) => data,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        };
+        Poll::Ready(Ok(match data.first() {
+            Some(&second) => detect(&[first, second]),
+            None => detect(&[first])
+        }))
+    }
+}
+
+impl<R: io::BufRead> io::Read for Prefixed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let data = io::BufRead::fill_buf(self)?;
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        io::BufRead::consume(self, len);
+        Ok(len)
+    }
+}
+
+impl<R: io::BufRead> io::BufRead for Prefixed<R> {
+    fn fill_buf(&mut self) -> Result<&[u8], io::Error> {
+        match self.first {
+            Some(ref first) => Ok(slice::from_ref(first)),
+            None => self.reader.fill_buf()
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.first.is_some() {
+            if amt > 0 {
+                self.first = None
+            }
+        }
+        else {
+            self.reader.consume(amt)
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncBufRead + Unpin> AsyncRead for Prefixed<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<Result<(), io::Error>> {
+        let data = match self.as_mut().poll_fill_buf(cx) {
+            Poll::Ready(Ok(data)) => data,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        };
+        let len = data.len().min(buf.remaining());
+        buf.put_slice(&data[..len]);
+        self.consume(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncBufRead + Unpin> AsyncBufRead for Prefixed<R> {
+    fn poll_fill_buf(
+        self: Pin<&mut Self>, cx: &mut Context
+    ) -> Poll<Result<&[u8], io::Error>> {
+        let this = self.get_mut();
+        match this.first {
+            Some(ref first) => Poll::Ready(Ok(slice::from_ref(first))),
+            None => Pin::new(&mut this.reader).poll_fill_buf(cx)
+        }
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        if this.first.is_some() {
+            if amt > 0 {
+                this.first = None
+            }
+        }
+        else {
+            Pin::new(&mut this.reader).consume(amt)
+        }
+    }
+}
+
+
+//------------ Counter -------------------------------------------------------
+
+/// A reader that counts the bytes consumed.
+struct Counter<R> {
+    /// The actual reader.
+    reader: R,
+
+    /// The number of bytes consumed so far.
+    count: u64,
+}
+
+impl<R> Counter<R> {
+    fn new(reader: R) -> Self {
+        Counter { reader, count: 0 }
+    }
+}
+
+impl<R: io::BufRead> io::Read for Counter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let res = self.reader.read(buf)?;
+        self.count += res as u64;
+        Ok(res)
+    }
+}
+
+impl<R: io::BufRead> io::BufRead for Counter<R> {
+    fn fill_buf(&mut self) -> Result<&[u8], io::Error> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.count += amt as u64;
+        self.reader.consume(amt)
+    }
+}
+
+
+//------------ RatioExceeded -------------------------------------------------
+
+/// Decompressed data exceeded the maximum ratio.
+#[derive(Clone, Copy, Debug)]
+pub struct RatioExceeded;
+
+impl From<RatioExceeded> for io::Error {
+    fn from(err: RatioExceeded) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+impl fmt::Display for RatioExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("maximum decompression ratio exceeded")
+    }
+}
+
+impl error::Error for RatioExceeded { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn compress(compression: Compression, data: &[u8]) -> Vec<u8> {
+        let mut res = Vec::new();
+        compression.write(&mut res, |writer| {
+            writer.write_all(data)
+        }).unwrap();
+        res
+    }
+
+    fn read_all(reader: impl io::BufRead) -> Result<Vec<u8>, io::Error> {
+        let mut reader = reader;
+        let mut res = Vec::new();
+        reader.read_to_end(&mut res)?;
+        Ok(res)
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = b"<snapshot>some data</snapshot>".repeat(20);
+        for &compression in &[Compression::Gzip, Compression::Deflate] {
+            let compressed = compress(compression, &data);
+            assert_ne!(compressed, data);
+            let mut reader = Decompress::new(compressed.as_slice());
+            assert_eq!(read_all(&mut reader).unwrap(), data);
+            assert_eq!(reader.compression(), Some(compression));
+
+            // A reader providing only one byte at a time.
+            let mut reader = Decompress::new(
+                io::BufReader::with_capacity(1, compressed.as_slice())
+            );
+            assert_eq!(read_all(&mut reader).unwrap(), data);
+            assert_eq!(reader.compression(), Some(compression));
+        }
+        let mut reader = Decompress::new(data.as_slice());
+        assert_eq!(read_all(&mut reader).unwrap(), data);
+        assert_eq!(reader.compression(), None);
+        let mut reader = Decompress::new(
+            io::BufReader::with_capacity(1, data.as_slice())
+        );
+        assert_eq!(read_all(&mut reader).unwrap(), data);
+        assert_eq!(reader.compression(), None);
+        assert_eq!(read_all(Decompress::new(b"".as_ref())).unwrap(), b"");
+        assert_eq!(read_all(Decompress::new(b"<".as_ref())).unwrap(), b"<");
+    }
+
+    #[test]
+    fn max_ratio() {
+        let data = vec![b' '; 1_000_000];
+        let compressed = compress(Compression::Gzip, &data);
+        let err = read_all(Decompress::new(compressed.as_slice()))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.get_ref().unwrap().is::<RatioExceeded>());
+        assert_eq!(
+            read_all(
+                Decompress::with_max_ratio(compressed.as_slice(), None)
+            ).unwrap(),
+            data
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_round_trip() {
+        use tokio::io::AsyncReadExt;
+
+        let data = b"<snapshot>some data</snapshot>".repeat(20);
+        for &compression in &[Compression::Gzip, Compression::Deflate] {
+            for &capacity in &[1, 5, 8192] {
+                let compressed = compress(compression, &data);
+                let mut reader = AsyncDecompress::new(
+                    tokio::io::BufReader::with_capacity(
+                        capacity, compressed.as_slice()
+                    )
+                );
+                let mut res = Vec::new();
+                reader.read_to_end(&mut res).await.unwrap();
+                assert_eq!(res, data);
+                assert_eq!(reader.compression(), Some(compression));
+            }
+        }
+
+        // Plain data provided one byte at a time.
+        let mut reader = AsyncDecompress::new(
+            tokio::io::BufReader::with_capacity(1, data.as_slice())
+        );
+        let mut res = Vec::new();
+        reader.read_to_end(&mut res).await.unwrap();
+        assert_eq!(res, data);
+        assert_eq!(reader.compression(), None);
+
+        // Multiple gzip members.
+        let mut compressed = compress(Compression::Gzip, b"<snapshot>");
+        compressed.extend_from_slice(
+            &compress(Compression::Gzip, b"</snapshot>")
+        );
+        let mut res = Vec::new();
+        AsyncDecompress::new(compressed.as_slice())
+            .read_to_end(&mut res).await.unwrap();
+        assert_eq!(res, b"<snapshot></snapshot>");
+
+        // A gzip header with all optional fields provided one byte at a
+        // time. The header checksum is added manually since the builder
+        // can’t create one.
+        let mut encoder = flate2::GzBuilder::new()
+            .extra(b"extra".as_ref()).filename("file.xml")
+            .comment("comment")
+            .write(Vec::new(), flate2::Compression::default());
+        io::Write::write_all(&mut encoder, &data).unwrap();
+        let mut compressed = encoder.finish().unwrap();
+        compressed[3] |= 0x02;
+        let header_len = 10 + 2 + 5 + 9 + 8;
+        compressed.splice(
+            header_len..header_len, [0xab, 0xcd].iter().copied()
+        );
+        let mut res = Vec::new();
+        AsyncDecompress::new(
+            tokio::io::BufReader::with_capacity(1, compressed.as_slice())
+        ).read_to_end(&mut res).await.unwrap();
+        assert_eq!(res, data);
+
+        // A corrupted checksum.
+        let mut compressed = compress(Compression::Gzip, &data);
+        let len = compressed.len();
+        compressed[len - 8] ^= 0xff;
+        let err = AsyncDecompress::new(compressed.as_slice())
+            .read_to_end(&mut Vec::new()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Truncated data.
+        let compressed = compress(Compression::Deflate, &data);
+        let err = AsyncDecompress::new(&compressed[..compressed.len() / 2])
+            .read_to_end(&mut Vec::new()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_max_ratio() {
+        use tokio::io::AsyncReadExt;
+
+        // The complete bomb is provided as a single chunk.
+        let data = vec![0; 10_000_000];
+        for &compression in &[Compression::Gzip, Compression::Deflate] {
+            let compressed = compress(compression, &data);
+            let mut reader = AsyncDecompress::new(compressed.as_slice());
+            let err = reader.read_to_end(&mut Vec::new()).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.get_ref().unwrap().is::<RatioExceeded>());
+            assert!(
+                reader.produced <= reader.consumed * DEFAULT_MAX_RATIO
+                    + AsyncDecompress::<&[u8]>::BUF_SIZE as u64
+            );
+            assert!(reader.produced < data.len() as u64 / 2);
+
+            let mut res = Vec::new();
+            AsyncDecompress::with_max_ratio(compressed.as_slice(), None)
+                .read_to_end(&mut res).await.unwrap();
+            assert_eq!(res, data);
+        }
+    }
+}
@@ -0,0 +1,538 @@
+//! Mirroring an RRDP repository into an rsync-style directory tree.
+//!
+//! Tools that expect repositories laid out the way rsync fetches them can
+//! use a [`Mirror`] to provide the content of a repository only available
+//! via RRDP. The objects are written to the path `host/module/path` below
+//! a base directory using an [`FsStore`]. URIs that cannot safely be
+//! turned into a path below the base directory, such as those containing
+//! `..` segments or path components starting with a dot, are rejected.
+//!
+//! Each snapshot or delta is first processed completely and its changes
+//! applied to the directory only if processing succeeded. Snapshot and
+//! delta files are fully read into memory before the changes are applied.
+//!
+//! The mirror keeps the session ID and serial number of the last file it
+//! applied in a hidden file in the base directory so that only deltas
+//! directly following the current state can be applied. While changes are
+//! being applied, this file marks the mirror as being updated. If the
+//! process terminates before the update is complete, the mirror’s state is
+//! therefore unknown afterwards and [`Mirror::state`] fails with
+//! [`MirrorError::Interrupted`]. Such a mirror can only be recovered by
+//! applying a snapshot.
+
+use std::{error, fmt, fs, io};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+use uuid::Uuid;
+use crate::uri;
+use crate::store::{
+    FsStore, RepositoryStore, StoreError, Transaction
+};
+use super::{Hash, ObjectReader, ProcessDelta, ProcessSnapshot};
+
+
+//------------ Mirror --------------------------------------------------------
+
+/// An rsync-style directory tree mirroring an RRDP repository.
+///
+/// The directory should only be used for a single RRDP repository since
+/// applying a snapshot removes all objects not contained in it.
+#[derive(Debug)]
+pub struct Mirror {
+    /// The store for the objects.
+    store: FsStore,
+}
+
+impl Mirror {
+    /// The name of the file keeping the state.
+    const STATE_FILE: &'static str = ".rrdp-state";
+
+    /// The content of the state file while an update is applied.
+    const UPDATING: &'static str = "updating\n";
+
+    /// Creates a new mirror using the given base directory.
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        Mirror { store: FsStore::new(base) }
+    }
+
+    /// Returns a reference to the underlying store.
+    pub fn store(&self) -> &FsStore {
+        &self.store
+    }
+
+    /// Returns the session ID and serial number of the mirror.
+    ///
+    /// Returns `Ok(None)` if nothing has been applied to the mirror yet.
+    /// Fails with [`MirrorError::Interrupted`] if an earlier update didn’t
+    /// complete.
+    pub fn state(&self) -> Result<Option<(Uuid, u64)>, MirrorError> {
+        let data = match self.read_state()? {
+            Some(data) => data,
+            None => return Ok(None)
+        };
+        if data == Self::UPDATING {
+            return Err(MirrorError::Interrupted)
+        }
+        let mut parts = data.split_whitespace();
+        let session = parts.next().and_then(|s| Uuid::from_str(s).ok());
+        let serial = parts.next().and_then(|s| u64::from_str(s).ok());
+        match (session, serial, parts.next()) {
+            (Some(session), Some(serial), None) => {
+                Ok(Some((session, serial)))
+            }
+            _ => Err(MirrorError::BadState)
+        }
+    }
+
+    /// Applies a snapshot to the mirror.
+    ///
+    /// Afterwards, the directory contains exactly the objects of the
+    /// snapshot. Returns the session ID and serial number of the snapshot.
+    ///
+    /// A snapshot can be applied regardless of the mirror’s current state,
+    /// including after an interrupted update.
+    pub fn apply_snapshot(
+        &self, reader: impl io::BufRead
+    ) -> Result<(Uuid, u64), MirrorError> {
+        let mut target = Target::new(&self.store);
+        ProcessSnapshot::process(&mut target, reader)?;
+        let (session_id, serial) = target.meta.ok_or(
+            MirrorError::BadState
+        )?;
+
+        let mut tx = target.tx;
+        let keep: HashSet<_> = tx.changes().iter().map(|(uri, _)| {
+            uri
+        }).cloned().collect();
+        for uri in self.store.list_all()? {
+            if !keep.contains(&uri) {
+                tx.delete(uri)
+            }
+        }
+        self.commit(tx, session_id, serial)?;
+        Ok((session_id, serial))
+    }
+
+    /// Applies a delta to the mirror.
+    ///
+    /// The delta must be for the session of the mirror and its serial
+    /// number must directly follow that of the mirror. The hashes of
+    /// updated and withdrawn objects must match the objects’ current
+    /// content. If any of these conditions isn’t met, the directory stays
+    /// unchanged and an error is returned.
+    ///
+    /// Returns the new serial number.
+    pub fn apply_delta(
+        &self, reader: impl io::BufRead
+    ) -> Result<u64, MirrorError> {
+        let (session_id, serial) = match self.state()? {
+            Some(state) => state,
+            None => return Err(MirrorError::NoState)
+        };
+        let mut target = Target::new(&self.store);
+        ProcessDelta::process(&mut target, reader)?;
+        let (delta_session, delta_serial) = target.meta.ok_or(
+            MirrorError::BadState
+        )?;
+        if delta_session != session_id {
+            return Err(MirrorError::SessionMismatch)
+        }
+        if Some(delta_serial) != serial.checked_add(1) {
+            return Err(MirrorError::SerialMismatch {
+                expected: serial.wrapping_add(1), found: delta_serial
+            })
+        }
+        self.commit(target.tx, session_id, delta_serial)?;
+        Ok(delta_serial)
+    }
+
+    /// Commits a transaction and updates the state accordingly.
+    ///
+    /// The state file is marked as updating before the changes are
+    /// applied. If applying fails, the previous state file is restored.
+    fn commit(
+        &self, tx: Transaction<'_, FsStore>, session_id: Uuid, serial: u64
+    ) -> Result<(), MirrorError> {
+        let old = self.read_state()?;
+        self.write_state(Self::UPDATING)?;
+        if let Err(err) = tx.commit() {
+            // If restoring fails, the mirror stays marked as updating
+            // which is safe.
+            let _ = match old {
+                Some(old) => self.write_state(&old),
+                None => {
+                    fs::remove_file(self.state_path()).map_err(|err| {
+                        MirrorError::Store(err.into())
+                    })
+                }
+            };
+            return Err(err.into())
+        }
+        self.write_state(&format!("{} {}\n", session_id, serial))
+    }
+
+    /// Returns the path of the state file.
+    fn state_path(&self) -> PathBuf {
+        self.store.base().join(Self::STATE_FILE)
+    }
+
+    /// Reads the content of the state file if there is one.
+    fn read_state(&self) -> Result<Option<String>, MirrorError> {
+        match fs::read_to_string(self.state_path()) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(MirrorError::Store(err.into()))
+        }
+    }
+
+    /// Atomically writes the state file.
+    fn write_state(&self, content: &str) -> Result<(), MirrorError> {
+        let path = self.state_path();
+        let tmp = path.with_extension("tmp");
+        fs::create_dir_all(self.store.base()).and_then(|_| {
+            fs::write(&tmp, content)
+        }).and_then(|_| {
+            fs::rename(&tmp, &path)
+        }).map_err(|err| MirrorError::Store(err.into()))
+    }
+}
+
+
+//------------ Target --------------------------------------------------------
+
+/// A transaction that also remembers the session ID and serial number.
+struct Target<'a> {
+    /// The store the transaction is for.
+    store: &'a FsStore,
+
+    /// The transaction collecting the changes.
+    tx: Transaction<'a, FsStore>,
+
+    /// The session ID and serial number of the processed file.
+    meta: Option<(Uuid, u64)>,
+}
+
+impl<'a> Target<'a> {
+    fn new(store: &'a FsStore) -> Self {
+        Target { store, tx: store.transaction(), meta: None }
+    }
+}
+
+impl<'a> ProcessSnapshot for Target<'a> {
+    type Err = StoreError;
+
+    fn meta(
+        &mut self, session_id: Uuid, serial: u64
+    ) -> Result<(), Self::Err> {
+        self.meta = Some((session_id, serial));
+        Ok(())
+    }
+
+    fn publish(
+        &mut self, uri: uri::Rsync, data: &mut ObjectReader,
+    ) -> Result<(), Self::Err> {
+        // Fail early rather than when committing.
+        self.store.path(&uri)?;
+        ProcessSnapshot::publish(&mut self.tx, uri, data)
+    }
+}
+
+impl<'a> ProcessDelta for Target<'a> {
+    type Err = StoreError;
+
+    fn meta(
+        &mut self, session_id: Uuid, serial: u64
+    ) -> Result<(), Self::Err> {
+        self.meta = Some((session_id, serial));
+        Ok(())
+    }
+
+    fn publish(
+        &mut self,
+        uri: uri::Rsync,
+        hash: Option<Hash>,
+        data: &mut ObjectReader,
+    ) -> Result<(), Self::Err> {
+        self.store.path(&uri)?;
+        ProcessDelta::publish(&mut self.tx, uri, hash, data)
+    }
+
+    fn withdraw(
+        &mut self, uri: uri::Rsync, hash: Hash,
+    ) -> Result<(), Self::Err> {
+        ProcessDelta::withdraw(&mut self.tx, uri, hash)
+    }
+}
+
+
+//------------ MirrorError ---------------------------------------------------
+
+/// An error happened while updating a mirror.
+#[derive(Debug)]
+pub enum MirrorError {
+    /// Accessing the directory or processing a file failed.
+    Store(StoreError),
+
+    /// The state of the mirror or the file’s meta data is broken.
+    BadState,
+
+    /// A delta was applied to a mirror without state.
+    NoState,
+
+    /// An earlier update didn’t complete and a snapshot needs to be applied.
+    Interrupted,
+
+    /// A delta belongs to a different session.
+    SessionMismatch,
+
+    /// A delta does not follow the mirror’s serial number.
+    SerialMismatch {
+        /// The serial number the delta should have had.
+        expected: u64,
+
+        /// The serial number the delta actually had.
+        found: u64,
+    },
+}
+
+impl From<StoreError> for MirrorError {
+    fn from(err: StoreError) -> Self {
+        MirrorError::Store(err)
+    }
+}
+
+impl fmt::Display for MirrorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MirrorError::Store(ref err) => err.fmt(f),
+            MirrorError::BadState => f.write_str("broken mirror state"),
+            MirrorError::NoState => {
+                f.write_str("cannot apply delta to empty mirror")
+            }
+            MirrorError::Interrupted => {
+                f.write_str("earlier update of mirror was interrupted")
+            }
+            MirrorError::SessionMismatch => {
+                f.write_str("delta for different session")
+            }
+            MirrorError::SerialMismatch { expected, found } => {
+                write!(
+                    f, "expected delta with serial {}, found {}",
+                    expected, found
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for MirrorError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use super::*;
+    use crate::rrdp::{
+        Delta, PublishElement, Snapshot, UpdateElement, WithdrawElement
+    };
+    use crate::store::test::rsync;
+
+    fn snapshot_xml(snapshot: Snapshot) -> Vec<u8> {
+        let mut res = Vec::new();
+        snapshot.write_xml(&mut res).unwrap();
+        res
+    }
+
+    fn delta_xml(delta: Delta) -> Vec<u8> {
+        let mut res = Vec::new();
+        delta.write_xml(&mut res).unwrap();
+        res
+    }
+
+    #[test]
+    fn snapshot_and_deltas() {
+        let dir = std::env::temp_dir().join(format!(
+            "rpki-rrdp-mirror-{}", std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        let mirror = Mirror::new(&dir);
+        assert!(mirror.state().unwrap().is_none());
+
+        let session = Uuid::from_u128(7);
+        let snapshot = snapshot_xml(Snapshot::new(session, 10, vec![
+            PublishElement::new(
+                rsync("rsync://example.com/repo/a.cer"),
+                Bytes::from_static(b"a"),
+            ),
+            PublishElement::new(
+                rsync("rsync://example.com/repo/ca/b.roa"),
+                Bytes::from_static(b"b"),
+            ),
+        ]));
+
+        // A stale object gets removed by the snapshot.
+        mirror.store().put(
+            rsync("rsync://example.com/repo/stale.crl"),
+            Bytes::from_static(b"x"),
+        ).unwrap();
+        assert_eq!(
+            mirror.apply_snapshot(snapshot.as_slice()).unwrap(),
+            (session, 10)
+        );
+        assert_eq!(mirror.state().unwrap(), Some((session, 10)));
+        assert_eq!(mirror.store().list_all().unwrap().len(), 2);
+        assert_eq!(
+            fs::read(dir.join("example.com/repo/ca/b.roa")).unwrap(), b"b"
+        );
+
+        let delta = delta_xml(Delta::new(session, 11, vec![
+            UpdateElement::new(
+                rsync("rsync://example.com/repo/a.cer"),
+                Hash::from_data(b"a"),
+                Bytes::from_static(b"a2"),
+            ).into(),
+            WithdrawElement::new(
+                rsync("rsync://example.com/repo/ca/b.roa"),
+                Hash::from_data(b"b"),
+            ).into(),
+        ]));
+        assert_eq!(mirror.apply_delta(delta.as_slice()).unwrap(), 11);
+        assert_eq!(
+            fs::read(dir.join("example.com/repo/a.cer")).unwrap(), b"a2"
+        );
+        assert!(!dir.join("example.com/repo/ca/b.roa").exists());
+
+        // A withdraw with the wrong hash fails the whole delta.
+        let bad = delta_xml(Delta::new(session, 12, vec![
+            PublishElement::new(
+                rsync("rsync://example.com/repo/c.roa"),
+                Bytes::from_static(b"c"),
+            ).into(),
+            WithdrawElement::new(
+                rsync("rsync://example.com/repo/a.cer"),
+                Hash::from_data(b"a"),
+            ).into(),
+        ]));
+        assert!(matches!(
+            mirror.apply_delta(bad.as_slice()),
+            Err(MirrorError::Store(StoreError::HashMismatch(_)))
+        ));
+        assert!(!dir.join("example.com/repo/c.roa").exists());
+        assert!(dir.join("example.com/repo/a.cer").exists());
+        assert_eq!(mirror.state().unwrap(), Some((session, 11)));
+
+        // Wrong serial and wrong session.
+        assert!(matches!(
+            mirror.apply_delta(
+                delta_xml(Delta::new(session, 15, vec![])).as_slice()
+            ),
+            Err(MirrorError::SerialMismatch { expected: 12, found: 15 })
+        ));
+        assert!(matches!(
+            mirror.apply_delta(
+                delta_xml(Delta::new(Uuid::from_u128(8), 12, vec![]))
+                    .as_slice()
+            ),
+            Err(MirrorError::SessionMismatch)
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn interrupted_update() {
+        let dir = std::env::temp_dir().join(format!(
+            "rpki-rrdp-mirror-interrupted-{}", std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        let mirror = Mirror::new(&dir);
+        let session = Uuid::from_u128(7);
+        let snapshot = snapshot_xml(Snapshot::new(session, 10, vec![
+            PublishElement::new(
+                rsync("rsync://example.com/repo/a.cer"),
+                Bytes::from_static(b"a"),
+            ),
+        ]));
+        mirror.apply_snapshot(snapshot.as_slice()).unwrap();
+
+        // Applying fails because a directory is in the way. The objects
+        // and the state stay unchanged.
+        fs::create_dir_all(dir.join("example.com/repo/b.roa")).unwrap();
+        let delta = delta_xml(Delta::new(session, 11, vec![
+            UpdateElement::new(
+                rsync("rsync://example.com/repo/a.cer"),
+                Hash::from_data(b"a"),
+                Bytes::from_static(b"a2"),
+            ).into(),
+            PublishElement::new(
+                rsync("rsync://example.com/repo/b.roa"),
+                Bytes::from_static(b"b"),
+            ).into(),
+        ]));
+        assert!(matches!(
+            mirror.apply_delta(delta.as_slice()),
+            Err(MirrorError::Store(StoreError::Io(_)))
+        ));
+        assert_eq!(
+            fs::read(dir.join("example.com/repo/a.cer")).unwrap(), b"a"
+        );
+        assert_eq!(mirror.state().unwrap(), Some((session, 10)));
+        fs::remove_dir(dir.join("example.com/repo/b.roa")).unwrap();
+
+        // Simulate the process terminating while applying the delta.
+        mirror.write_state(Mirror::UPDATING).unwrap();
+        mirror.store().put(
+            rsync("rsync://example.com/repo/a.cer"),
+            Bytes::from_static(b"a2"),
+        ).unwrap();
+        assert!(matches!(mirror.state(), Err(MirrorError::Interrupted)));
+        assert!(matches!(
+            mirror.apply_delta(delta.as_slice()),
+            Err(MirrorError::Interrupted)
+        ));
+
+        // A snapshot recovers.
+        let snapshot = snapshot_xml(Snapshot::new(session, 12, vec![
+            PublishElement::new(
+                rsync("rsync://example.com/repo/a.cer"),
+                Bytes::from_static(b"a3"),
+            ),
+        ]));
+        mirror.apply_snapshot(snapshot.as_slice()).unwrap();
+        assert_eq!(mirror.state().unwrap(), Some((session, 12)));
+        assert_eq!(
+            fs::read(dir.join("example.com/repo/a.cer")).unwrap(), b"a3"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reject_unsafe_uris() {
+        let dir = std::env::temp_dir().join(format!(
+            "rpki-rrdp-mirror-unsafe-{}", std::process::id()
+        ));
+        let mirror = Mirror::new(&dir);
+        let snapshot = snapshot_xml(Snapshot::new(
+            Uuid::from_u128(1), 1, vec![
+                PublishElement::new(
+                    rsync("rsync://example.com/repo/ok.cer"),
+                    Bytes::from_static(b"ok"),
+                ),
+                PublishElement::new(
+                    rsync("rsync://example.com/repo/.hidden/x.cer"),
+                    Bytes::from_static(b"x"),
+                ),
+            ]
+        ));
+        assert!(matches!(
+            mirror.apply_snapshot(snapshot.as_slice()),
+            Err(MirrorError::Store(StoreError::BadUri(_)))
+        ));
+        assert!(!dir.join("example.com/repo/ok.cer").exists());
+        assert!(mirror.state().unwrap().is_none());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,531 @@
+//! Monitoring the health of RRDP feeds.
+//!
+//! A [`Monitor`] keeps the history of notification files observed for any
+//! number of RRDP repositories and compares each new notification file
+//! with what it has seen before. Anything that looks wrong is reported as
+//! an [`Anomaly`]: session resets, serial numbers going backwards, gaps or
+//! duplicates in the list of deltas, deltas whose hash changed, snapshot
+//! files reused for different serial numbers, and repositories whose
+//! serial number has not advanced for too long.
+//!
+//! The monitor does not fetch anything itself. The caller passes in each
+//! notification file together with the time it was fetched.
+
+use std::fmt;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+use crate::uri;
+use super::{Hash, NotificationFile};
+
+
+//------------ Configuration Defaults ----------------------------------------
+
+/// The default time after which an unchanged serial is reported.
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(24 * 3600);
+
+/// The default number of observations kept per repository.
+pub const DEFAULT_HISTORY_LEN: usize = 32;
+
+
+//------------ Monitor -------------------------------------------------------
+
+/// A monitor for a set of RRDP feeds.
+///
+/// Feeds are identified by the URI of their notification file.
+#[derive(Clone, Debug)]
+pub struct Monitor {
+    /// The state of each feed.
+    feeds: HashMap<uri::Https, Feed>,
+
+    /// The time after which an unchanged serial is reported.
+    stall_timeout: Duration,
+
+    /// The number of observations kept per feed.
+    history_len: usize,
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Monitor {
+    /// Creates a new monitor with default settings.
+    pub fn new() -> Self {
+        Monitor {
+            feeds: HashMap::new(),
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+            history_len: DEFAULT_HISTORY_LEN,
+        }
+    }
+
+    /// Sets the time after which an unchanged serial is reported.
+    pub fn set_stall_timeout(&mut self, timeout: Duration) {
+        self.stall_timeout = timeout
+    }
+
+    /// Sets the number of observations kept per feed.
+    ///
+    /// Snapshot hashes are only compared with those in the history, so
+    /// this also limits how far back reuse of snapshot files is detected.
+    pub fn set_history_len(&mut self, len: usize) {
+        self.history_len = len
+    }
+
+    /// Returns an iterator over the notification URIs of all feeds.
+    pub fn feeds(&self) -> impl Iterator<Item = &uri::Https> {
+        self.feeds.keys()
+    }
+
+    /// Returns the history of a feed, oldest observation first.
+    pub fn history(
+        &self, notify_uri: &uri::Https
+    ) -> Option<impl Iterator<Item = &Observation>> {
+        self.feeds.get(notify_uri).map(|feed| feed.history.iter())
+    }
+
+    /// Removes a feed from the monitor.
+    pub fn remove(&mut self, notify_uri: &uri::Https) {
+        self.feeds.remove(notify_uri);
+    }
+
+    /// Records a notification file and returns the anomalies found.
+    ///
+    /// The notification file was fetched from `notify_uri` at time `when`.
+    /// The anomalies are also stored with the observation in the history
+    /// of the feed.
+    pub fn observe(
+        &mut self,
+        notify_uri: &uri::Https,
+        notify: &NotificationFile,
+        when: SystemTime,
+    ) -> Vec<Anomaly> {
+        let mut anomalies = check_deltas(notify);
+        let feed = self.feeds.entry(notify_uri.clone()).or_insert_with(|| {
+            Feed::new(notify, when)
+        });
+        feed.check(notify, when, self.stall_timeout, &mut anomalies);
+        feed.history.push_back(Observation {
+            when,
+            session_id: notify.session_id(),
+            serial: notify.serial(),
+            snapshot_hash: notify.snapshot().hash(),
+            anomalies: anomalies.clone(),
+        });
+        while feed.history.len() > self.history_len {
+            feed.history.pop_front();
+        }
+        anomalies
+    }
+}
+
+
+//------------ Feed ----------------------------------------------------------
+
+/// The state of a single feed.
+#[derive(Clone, Debug)]
+struct Feed {
+    /// The session ID of the last notification file.
+    session_id: Uuid,
+
+    /// The serial number of the last notification file.
+    serial: u64,
+
+    /// The time the serial number last advanced.
+    advanced: SystemTime,
+
+    /// The hashes of the deltas in the last notification file.
+    deltas: HashMap<u64, Hash>,
+
+    /// The past observations, oldest first.
+    history: VecDeque<Observation>,
+}
+
+impl Feed {
+    /// Creates a new feed from its first notification file.
+    fn new(notify: &NotificationFile, when: SystemTime) -> Self {
+        Feed {
+            session_id: notify.session_id(),
+            serial: notify.serial(),
+            advanced: when,
+            deltas: HashMap::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Compares a new notification file with the state of the feed.
+    ///
+    /// Adds all anomalies to `anomalies` and updates the state.
+    fn check(
+        &mut self,
+        notify: &NotificationFile,
+        when: SystemTime,
+        stall_timeout: Duration,
+        anomalies: &mut Vec<Anomaly>,
+    ) {
+        if notify.session_id() != self.session_id {
+            anomalies.push(Anomaly::SessionReset {
+                previous: self.session_id, current: notify.session_id()
+            });
+            self.deltas.clear();
+            self.advanced = when;
+        }
+        else if notify.serial() < self.serial {
+            anomalies.push(Anomaly::SerialRegressed {
+                previous: self.serial, current: notify.serial()
+            });
+            self.advanced = when;
+        }
+        else if notify.serial() > self.serial {
+            self.advanced = when;
+        }
+        else if let Ok(stalled) = when.duration_since(self.advanced) {
+            if stalled > stall_timeout {
+                anomalies.push(Anomaly::Stalled {
+                    serial: notify.serial(), since: self.advanced
+                });
+            }
+        }
+
+        for delta in notify.deltas() {
+            if let Some(previous) = self.deltas.get(&delta.serial()) {
+                if *previous != delta.hash() {
+                    anomalies.push(Anomaly::DeltaHashChanged {
+                        serial: delta.serial(),
+                        previous: *previous,
+                        current: delta.hash(),
+                    });
+                }
+            }
+        }
+        self.deltas = notify.deltas().iter().map(|delta| {
+            (delta.serial(), delta.hash())
+        }).collect();
+
+        let snapshot_hash = notify.snapshot().hash();
+        let reused = self.history.iter().rev().find(|item| {
+            item.snapshot_hash == snapshot_hash
+                && (item.session_id, item.serial)
+                    != (notify.session_id(), notify.serial())
+        });
+        if let Some(item) = reused {
+            anomalies.push(Anomaly::SnapshotHashReused {
+                hash: snapshot_hash,
+                previous_serial: item.serial,
+                serial: notify.serial(),
+            });
+        }
+
+        self.session_id = notify.session_id();
+        self.serial = notify.serial();
+    }
+}
+
+
+//------------ Observation ---------------------------------------------------
+
+/// A notification file observed by the monitor.
+#[derive(Clone, Debug)]
+pub struct Observation {
+    /// The time the notification file was fetched.
+    when: SystemTime,
+
+    /// The session ID of the notification file.
+    session_id: Uuid,
+
+    /// The serial number of the notification file.
+    serial: u64,
+
+    /// The hash of the snapshot.
+    snapshot_hash: Hash,
+
+    /// The anomalies found.
+    anomalies: Vec<Anomaly>,
+}
+
+impl Observation {
+    /// Returns the time the notification file was fetched.
+    pub fn when(&self) -> SystemTime {
+        self.when
+    }
+
+    /// Returns the session ID of the notification file.
+    pub fn session_id(&self) -> Uuid {
+        self.session_id
+    }
+
+    /// Returns the serial number of the notification file.
+    pub fn serial(&self) -> u64 {
+        self.serial
+    }
+
+    /// Returns the hash of the snapshot.
+    pub fn snapshot_hash(&self) -> Hash {
+        self.snapshot_hash
+    }
+
+    /// Returns the anomalies found in the notification file.
+    pub fn anomalies(&self) -> &[Anomaly] {
+        &self.anomalies
+    }
+}
+
+
+//------------ Anomaly -------------------------------------------------------
+
+/// Something unexpected found in a notification file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Anomaly {
+    /// The session ID has changed.
+    SessionReset {
+        /// The session ID of the previous notification file.
+        previous: Uuid,
+
+        /// The session ID of the current notification file.
+        current: Uuid,
+    },
+
+    /// The serial number is smaller than before within the same session.
+    SerialRegressed {
+        /// The serial number of the previous notification file.
+        previous: u64,
+
+        /// The serial number of the current notification file.
+        current: u64,
+    },
+
+    /// Deltas are missing between two listed deltas.
+    DeltaGap {
+        /// The serial number of the delta before the gap.
+        after: u64,
+
+        /// The serial number of the delta after the gap.
+        next: u64,
+    },
+
+    /// The delta for the serial number of the notification is missing.
+    ///
+    /// This is reported only if there are any deltas at all.
+    MissingLatestDelta {
+        /// The serial number of the newest delta.
+        newest: u64,
+    },
+
+    /// The same serial number is listed for multiple deltas.
+    DuplicateSerial(u64),
+
+    /// The hash of a delta is different than in the last observation.
+    DeltaHashChanged {
+        /// The serial number of the delta.
+        serial: u64,
+
+        /// The previous hash of the delta.
+        previous: Hash,
+
+        /// The current hash of the delta.
+        current: Hash,
+    },
+
+    /// A snapshot file has been used for different serial numbers.
+    SnapshotHashReused {
+        /// The hash of the snapshot.
+        hash: Hash,
+
+        /// The serial number the snapshot was previously used for.
+        previous_serial: u64,
+
+        /// The current serial number.
+        serial: u64,
+    },
+
+    /// The serial number has not advanced for too long.
+    Stalled {
+        /// The current serial number.
+        serial: u64,
+
+        /// The time the serial number was first seen.
+        since: SystemTime,
+    },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Anomaly::SessionReset { previous, current } => {
+                write!(f, "session reset from {} to {}", previous, current)
+            }
+            Anomaly::SerialRegressed { previous, current } => {
+                write!(
+                    f, "serial regressed from {} to {}", previous, current
+                )
+            }
+            Anomaly::DeltaGap { after, next } => {
+                write!(f, "deltas missing between {} and {}", after, next)
+            }
+            Anomaly::MissingLatestDelta { newest } => {
+                write!(f, "newest delta {} is not the current serial", newest)
+            }
+            Anomaly::DuplicateSerial(serial) => {
+                write!(f, "multiple deltas with serial {}", serial)
+            }
+            Anomaly::DeltaHashChanged { serial, previous, current } => {
+                write!(
+                    f, "hash of delta {} changed from {} to {}",
+                    serial, previous, current
+                )
+            }
+            Anomaly::SnapshotHashReused {
+                hash, previous_serial, serial
+            } => {
+                write!(
+                    f, "snapshot {} of serial {} reused for serial {}",
+                    hash, previous_serial, serial
+                )
+            }
+            Anomaly::Stalled { serial, .. } => {
+                write!(f, "serial {} has not advanced", serial)
+            }
+        }
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Checks the list of deltas of a notification file.
+fn check_deltas(notify: &NotificationFile) -> Vec<Anomaly> {
+    let mut res = Vec::new();
+    let mut serials: Vec<_> = notify.deltas().iter().map(|delta| {
+        delta.serial()
+    }).collect();
+    serials.sort_unstable();
+    for pair in serials.windows(2) {
+        if pair[0] == pair[1] {
+            if res.last() != Some(&Anomaly::DuplicateSerial(pair[0])) {
+                res.push(Anomaly::DuplicateSerial(pair[0]))
+            }
+        }
+        else if pair[0] + 1 != pair[1] {
+            res.push(Anomaly::DeltaGap { after: pair[0], next: pair[1] })
+        }
+    }
+    if let Some(&newest) = serials.last() {
+        if newest != notify.serial() {
+            res.push(Anomaly::MissingLatestDelta { newest })
+        }
+    }
+    res
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+    use crate::rrdp::{DeltaInfo, UriAndHash};
+
+    fn https(s: &str) -> uri::Https {
+        uri::Https::from_str(s).unwrap()
+    }
+
+    fn notify(
+        session: u128, serial: u64, snapshot: &[u8], deltas: &[(u64, &[u8])]
+    ) -> NotificationFile {
+        NotificationFile::new(
+            Uuid::from_u128(session), serial,
+            UriAndHash::new(
+                https("https://example.com/snapshot.xml"),
+                Hash::from_data(snapshot)
+            ),
+            deltas.iter().map(|&(serial, data)| {
+                DeltaInfo::new(
+                    serial, https("https://example.com/delta.xml"),
+                    Hash::from_data(data)
+                )
+            }).collect()
+        )
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn healthy() {
+        let uri = https("https://example.com/notification.xml");
+        let mut monitor = Monitor::new();
+        assert!(monitor.observe(
+            &uri, &notify(1, 2, b"s2", &[(2, b"d2")]), at(0)
+        ).is_empty());
+        assert!(monitor.observe(
+            &uri, &notify(1, 3, b"s3", &[(2, b"d2"), (3, b"d3")]), at(60)
+        ).is_empty());
+        assert!(monitor.observe(
+            &uri, &notify(1, 3, b"s3", &[(2, b"d2"), (3, b"d3")]), at(120)
+        ).is_empty());
+        assert_eq!(monitor.history(&uri).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn anomalies() {
+        let uri = https("https://example.com/notification.xml");
+        let mut monitor = Monitor::new();
+        monitor.set_stall_timeout(Duration::from_secs(100));
+        monitor.set_history_len(3);
+        monitor.observe(&uri, &notify(1, 3, b"s3", &[(3, b"d3")]), at(0));
+
+        assert_eq!(
+            monitor.observe(
+                &uri, &notify(1, 5, b"s5", &[(3, b"x"), (5, b"d5")]), at(10)
+            ),
+            [
+                Anomaly::DeltaGap { after: 3, next: 5 },
+                Anomaly::DeltaHashChanged {
+                    serial: 3,
+                    previous: Hash::from_data(b"d3"),
+                    current: Hash::from_data(b"x"),
+                }
+            ]
+        );
+        assert_eq!(
+            monitor.observe(
+                &uri, &notify(1, 4, b"s3", &[(4, b"d4"), (4, b"d4")]), at(20)
+            ),
+            [
+                Anomaly::DuplicateSerial(4),
+                Anomaly::SerialRegressed { previous: 5, current: 4 },
+                Anomaly::SnapshotHashReused {
+                    hash: Hash::from_data(b"s3"),
+                    previous_serial: 3,
+                    serial: 4
+                }
+            ]
+        );
+        assert_eq!(
+            monitor.observe(
+                &uri, &notify(1, 4, b"s4", &[(3, b"d3")]), at(200)
+            ),
+            [
+                Anomaly::MissingLatestDelta { newest: 3 },
+                Anomaly::Stalled { serial: 4, since: at(20) },
+            ]
+        );
+        assert_eq!(
+            monitor.observe(&uri, &notify(2, 1, b"n1", &[]), at(210)),
+            [
+                Anomaly::SessionReset {
+                    previous: Uuid::from_u128(1),
+                    current: Uuid::from_u128(2)
+                }
+            ]
+        );
+        let history: Vec<_> = monitor.history(&uri).unwrap().collect();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].serial(), 4);
+        assert_eq!(history[2].anomalies().len(), 1);
+    }
+}
@@ -0,0 +1,607 @@
+//! Generating RRDP files for a repository.
+//!
+//! This module provides the server side of RRDP. A [`Server`] keeps the
+//! current content of a repository together with the session ID and
+//! serial number of its RRDP session. Changes are applied as a set of
+//! publish, update, and withdraw elements. Each set of changes results in
+//! a new delta and a new snapshot. The notification file describing the
+//! current state can be produced at any time.
+//!
+//! The server keeps the serialized RRDP files in memory. It does not
+//! write them anywhere. Instead, the files are made available as values
+//! of [`RrdpFile`] which provide the URI and content of a file. Files that
+//! are not referenced by the notification file anymore are returned when
+//! applying changes so they can be removed from the web server.
+//!
+//! The URIs of the files are formed by appending the session ID, the
+//! serial number, and the file name `snapshot.xml` or `delta.xml` to the
+//! base URI of the server.
+
+use std::{error, fmt};
+use std::collections::{HashMap, HashSet, VecDeque};
+use bytes::Bytes;
+use uuid::Uuid;
+use crate::uri;
+use super::{
+    Delta, DeltaElement, DeltaInfo, Hash, NotificationFile, PublishElement,
+    Snapshot, UriAndHash,
+};
+
+
+//------------ Server --------------------------------------------------------
+
+/// The RRDP state of a repository.
+///
+/// The server starts out with either an empty repository via [`new`] or
+/// with the content of an existing snapshot via [`from_snapshot`] or
+/// [`restore`]. Changes are added via [`apply`].
+///
+/// Old deltas are removed once their combined size exceeds the size of
+/// the current snapshot since at that point it is cheaper for a client to
+/// fetch the snapshot instead.
+///
+/// [`new`]: Self::new
+/// [`from_snapshot`]: Self::from_snapshot
+/// [`restore`]: Self::restore
+/// [`apply`]: Self::apply
+#[derive(Clone, Debug)]
+pub struct Server {
+    /// The base URI for all RRDP files.
+    base_uri: uri::Https,
+
+    /// The session ID of the current RRDP session.
+    session_id: Uuid,
+
+    /// The current serial number.
+    serial: u64,
+
+    /// The current content of the repository.
+    objects: HashMap<uri::Rsync, Bytes>,
+
+    /// The snapshot file for the current serial number.
+    snapshot: RrdpFile,
+
+    /// The available delta files, newest first.
+    deltas: VecDeque<RrdpFile>,
+}
+
+impl Server {
+    /// Creates a new server for an empty repository.
+    ///
+    /// The RRDP session will have the given session ID and start with
+    /// serial number 1.
+    pub fn new(base_uri: uri::Https, session_id: Uuid) -> Self {
+        Self::from_snapshot(base_uri, Snapshot::new(session_id, 1, vec![]))
+    }
+
+    /// Creates a new server from an existing snapshot.
+    ///
+    /// The session ID and serial number are taken from the snapshot. No
+    /// deltas will be available initially.
+    pub fn from_snapshot(base_uri: uri::Https, snapshot: Snapshot) -> Self {
+        let session_id = snapshot.session_id();
+        let serial = snapshot.serial();
+        let objects = snapshot.into_elements().into_iter().map(|item| {
+            item.unpack()
+        }).collect();
+        let mut res = Server {
+            snapshot: RrdpFile::empty(base_uri.clone()),
+            base_uri, session_id, serial, objects,
+            deltas: VecDeque::new(),
+        };
+        res.snapshot = res.make_snapshot();
+        res
+    }
+
+    /// Restores a server from an existing snapshot and its deltas.
+    ///
+    /// The deltas must belong to the snapshot’s session and form an
+    /// unbroken sequence ending with the snapshot’s serial number. They
+    /// are serialized again, so their hashes will only match those of the
+    /// original files if these were created by this type, too. Deltas that
+    /// exceed the size limit are dropped.
+    pub fn restore(
+        base_uri: uri::Https,
+        snapshot: Snapshot,
+        mut deltas: Vec<Delta>,
+    ) -> Result<Self, ServerError> {
+        deltas.sort_by_key(|delta| delta.serial());
+        let mut expected = snapshot.serial();
+        for delta in deltas.iter().rev() {
+            if delta.session_id() != snapshot.session_id()
+                || delta.serial() != expected
+            {
+                return Err(ServerError::InconsistentDeltas)
+            }
+            expected = expected.saturating_sub(1);
+        }
+        let mut res = Self::from_snapshot(base_uri, snapshot);
+        for delta in deltas {
+            let file = res.make_delta(&delta);
+            res.deltas.push_front(file);
+        }
+        res.prune_deltas();
+        Ok(res)
+    }
+
+    /// Returns the base URI of the RRDP files.
+    pub fn base_uri(&self) -> &uri::Https {
+        &self.base_uri
+    }
+
+    /// Returns the session ID of the current RRDP session.
+    pub fn session_id(&self) -> Uuid {
+        self.session_id
+    }
+
+    /// Returns the current serial number.
+    pub fn serial(&self) -> u64 {
+        self.serial
+    }
+
+    /// Returns the current content of an object.
+    pub fn get(&self, uri: &uri::Rsync) -> Option<&Bytes> {
+        self.objects.get(uri)
+    }
+
+    /// Returns the number of objects in the repository.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns whether the repository is empty.
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Returns an iterator over all objects in the repository.
+    pub fn objects(&self) -> impl Iterator<Item = (&uri::Rsync, &Bytes)> {
+        self.objects.iter()
+    }
+
+    /// Returns the snapshot file for the current serial number.
+    pub fn snapshot(&self) -> &RrdpFile {
+        &self.snapshot
+    }
+
+    /// Returns an iterator over the available delta files, newest first.
+    pub fn deltas(&self) -> impl Iterator<Item = &RrdpFile> {
+        self.deltas.iter()
+    }
+
+    /// Returns the notification file for the current state.
+    pub fn notification(&self) -> NotificationFile {
+        NotificationFile::new(
+            self.session_id,
+            self.serial,
+            UriAndHash::new(self.snapshot.uri.clone(), self.snapshot.hash),
+            self.deltas.iter().map(|delta| {
+                DeltaInfo::new(delta.serial, delta.uri.clone(), delta.hash)
+            }).collect()
+        )
+    }
+
+    /// Returns the XML representation of the current notification file.
+    pub fn notification_xml(&self) -> Bytes {
+        let mut res = Vec::new();
+        self.notification().write_xml(&mut res).unwrap(); // safe
+        res.into()
+    }
+
+    /// Applies a set of changes.
+    ///
+    /// Publish elements must not refer to existing objects. Update and
+    /// withdraw elements must refer to existing objects and their hash
+    /// must match the object’s current content. Each object may only be
+    /// referred to once. If any of these conditions is not met, an error
+    /// is returned and the server remains unchanged.
+    ///
+    /// If there are changes, the serial number is increased and a new
+    /// delta and snapshot are created. The method returns the files that
+    /// are no longer referenced by the notification file, i.e., the
+    /// previous snapshot and all deltas that have been dropped.
+    pub fn apply(
+        &mut self,
+        elements: impl IntoIterator<Item = DeltaElement>,
+    ) -> Result<Vec<RrdpFile>, ServerError> {
+        let elements: Vec<_> = elements.into_iter().collect();
+        if elements.is_empty() {
+            return Ok(Vec::new())
+        }
+        self.check_elements(&elements)?;
+
+        for element in &elements {
+            match element {
+                DeltaElement::Publish(item) => {
+                    self.objects.insert(
+                        item.uri().clone(), item.data().clone()
+                    );
+                }
+                DeltaElement::Update(item) => {
+                    self.objects.insert(
+                        item.uri().clone(), item.data().clone()
+                    );
+                }
+                DeltaElement::Withdraw(item) => {
+                    self.objects.remove(item.uri());
+                }
+            }
+        }
+        self.serial += 1;
+        let delta = self.make_delta(
+            &Delta::new(self.session_id, self.serial, elements)
+        );
+        self.deltas.push_front(delta);
+
+        let mut res = vec![self.make_snapshot()];
+        std::mem::swap(&mut res[0], &mut self.snapshot);
+        res.extend(self.prune_deltas());
+        Ok(res)
+    }
+
+    /// Starts a new RRDP session.
+    ///
+    /// The content of the repository is kept but the serial number is
+    /// reset to 1 and all deltas are dropped. Returns the files that are
+    /// no longer referenced by the notification file.
+    pub fn reset_session(&mut self, session_id: Uuid) -> Vec<RrdpFile> {
+        self.session_id = session_id;
+        self.serial = 1;
+        let mut res = vec![self.make_snapshot()];
+        std::mem::swap(&mut res[0], &mut self.snapshot);
+        res.extend(self.deltas.drain(..));
+        res
+    }
+
+    /// Checks that a set of elements can be applied.
+    fn check_elements(
+        &self, elements: &[DeltaElement]
+    ) -> Result<(), ServerError> {
+        let mut seen = HashSet::new();
+        for element in elements {
+            let uri = element.uri();
+            if !seen.insert(uri) {
+                return Err(ServerError::DuplicateUri(uri.clone()))
+            }
+            let current = self.objects.get(uri);
+            let hash = match element {
+                DeltaElement::Publish(_) => {
+                    if current.is_some() {
+                        return Err(ServerError::ObjectExists(uri.clone()))
+                    }
+                    continue
+                }
+                DeltaElement::Update(item) => item.hash(),
+                DeltaElement::Withdraw(item) => item.hash(),
+            };
+            match current {
+                Some(data) => {
+                    if !hash.matches(data) {
+                        return Err(ServerError::HashMismatch(uri.clone()))
+                    }
+                }
+                None => {
+                    return Err(ServerError::ObjectMissing(uri.clone()))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the snapshot file for the current state.
+    fn make_snapshot(&self) -> RrdpFile {
+        let mut elements: Vec<_> = self.objects.iter().map(|(uri, data)| {
+            PublishElement::new(uri.clone(), data.clone())
+        }).collect();
+        elements.sort_by(|left, right| {
+            left.uri().as_str().cmp(right.uri().as_str())
+        });
+        let mut data = Vec::new();
+        Snapshot::new(
+            self.session_id, self.serial, elements
+        ).write_xml(&mut data).unwrap(); // safe
+        RrdpFile::new(
+            self.serial, self.file_uri(self.serial, "snapshot.xml"),
+            data.into()
+        )
+    }
+
+    /// Creates the delta file for a delta.
+    fn make_delta(&self, delta: &Delta) -> RrdpFile {
+        let mut data = Vec::new();
+        delta.write_xml(&mut data).unwrap(); // safe
+        RrdpFile::new(
+            delta.serial(), self.file_uri(delta.serial(), "delta.xml"),
+            data.into()
+        )
+    }
+
+    /// Returns the URI for an RRDP file.
+    fn file_uri(&self, serial: u64, name: &str) -> uri::Https {
+        self.base_uri.join(
+            format!("{}/{}/{}", self.session_id, serial, name).as_bytes()
+        ).unwrap() // safe: all components are plain ASCII.
+    }
+
+    /// Drops deltas until their combined size is below the snapshot size.
+    ///
+    /// Returns the dropped deltas.
+    fn prune_deltas(&mut self) -> Vec<RrdpFile> {
+        let limit = self.snapshot.len();
+        let mut total = 0;
+        let keep = self.deltas.iter().take_while(|delta| {
+            total += delta.len();
+            total <= limit
+        }).count();
+        self.deltas.split_off(keep).into()
+    }
+}
+
+
+//------------ RrdpFile ------------------------------------------------------
+
+/// A snapshot or delta file created by a server.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RrdpFile {
+    /// The serial number of the file.
+    serial: u64,
+
+    /// The URI of the file.
+    uri: uri::Https,
+
+    /// The SHA-256 hash of the file’s content.
+    hash: Hash,
+
+    /// The content of the file.
+    data: Bytes,
+}
+
+impl RrdpFile {
+    /// Creates a new file, calculating the hash.
+    fn new(serial: u64, uri: uri::Https, data: Bytes) -> Self {
+        RrdpFile { serial, uri, hash: Hash::from_data(&data), data }
+    }
+
+    /// Creates an empty placeholder file.
+    fn empty(uri: uri::Https) -> Self {
+        Self::new(0, uri, Bytes::new())
+    }
+
+    /// Returns the serial number of the file.
+    pub fn serial(&self) -> u64 {
+        self.serial
+    }
+
+    /// Returns the URI of the file.
+    pub fn uri(&self) -> &uri::Https {
+        &self.uri
+    }
+
+    /// Returns the SHA-256 hash of the file’s content.
+    pub fn hash(&self) -> Hash {
+        self.hash
+    }
+
+    /// Returns the content of the file.
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+
+    /// Returns the size of the file in bytes.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns whether the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+
+//------------ ServerError ---------------------------------------------------
+
+/// An error happened while changing the state of a server.
+#[derive(Debug)]
+pub enum ServerError {
+    /// An object to be published already exists.
+    ObjectExists(uri::Rsync),
+
+    /// An object to be updated or withdrawn does not exist.
+    ObjectMissing(uri::Rsync),
+
+    /// The hash of an object to be updated or withdrawn does not match.
+    HashMismatch(uri::Rsync),
+
+    /// An object was referred to more than once.
+    DuplicateUri(uri::Rsync),
+
+    /// The deltas given for restoring do not match the snapshot.
+    InconsistentDeltas,
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ServerError::ObjectExists(ref uri) => {
+                write!(f, "{}: object already exists", uri)
+            }
+            ServerError::ObjectMissing(ref uri) => {
+                write!(f, "{}: object does not exist", uri)
+            }
+            ServerError::HashMismatch(ref uri) => {
+                write!(f, "{}: hash mismatch", uri)
+            }
+            ServerError::DuplicateUri(ref uri) => {
+                write!(f, "{}: multiple changes for object", uri)
+            }
+            ServerError::InconsistentDeltas => {
+                f.write_str("deltas inconsistent with snapshot")
+            }
+        }
+    }
+}
+
+impl error::Error for ServerError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+    use crate::rrdp::{UpdateElement, WithdrawElement};
+
+    fn rsync(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    fn server() -> Server {
+        Server::new(
+            uri::Https::from_str("https://rrdp.example.com/rrdp/").unwrap(),
+            Uuid::from_u128(0x1234)
+        )
+    }
+
+    #[test]
+    fn apply() {
+        // Start with a large object so that deltas aren’t pruned.
+        let mut server = Server::from_snapshot(
+            uri::Https::from_str("https://rrdp.example.com/rrdp/").unwrap(),
+            Snapshot::new(Uuid::from_u128(0x1234), 1, vec![
+                PublishElement::new(
+                    rsync("rsync://example.com/repo/c.crl"),
+                    Bytes::from(vec![0u8; 4096])
+                )
+            ])
+        );
+        let a = rsync("rsync://example.com/repo/a.cer");
+        let b = rsync("rsync://example.com/repo/b.roa");
+        assert_eq!(server.serial(), 1);
+        assert!(server.apply(None).unwrap().is_empty());
+        assert_eq!(server.serial(), 1);
+
+        let old = server.apply(vec![
+            PublishElement::new(a.clone(), Bytes::from_static(b"a1")).into(),
+            PublishElement::new(b.clone(), Bytes::from_static(b"b1")).into(),
+        ]).unwrap();
+        assert_eq!(old.len(), 1);
+        assert_eq!(old[0].serial(), 1);
+        assert_eq!(server.serial(), 2);
+        assert_eq!(
+            server.snapshot().uri().as_str(),
+            "https://rrdp.example.com/rrdp/\
+             00000000-0000-0000-0000-000000001234/2/snapshot.xml"
+        );
+
+        server.apply(vec![
+            UpdateElement::new(
+                a.clone(), Hash::from_data(b"a1"), Bytes::from_static(b"a2")
+            ).into(),
+            WithdrawElement::new(b.clone(), Hash::from_data(b"b1")).into(),
+        ]).unwrap();
+        assert_eq!(server.get(&a).unwrap().as_ref(), b"a2");
+        assert!(server.get(&b).is_none());
+
+        // Errors leave the state unchanged.
+        assert!(matches!(
+            server.apply(vec![
+                PublishElement::new(b.clone(), Bytes::new()).into(),
+                PublishElement::new(a.clone(), Bytes::new()).into(),
+            ]),
+            Err(ServerError::ObjectExists(_))
+        ));
+        assert!(matches!(
+            server.apply(vec![
+                WithdrawElement::new(b.clone(), Hash::from_data(b"")).into()
+            ]),
+            Err(ServerError::ObjectMissing(_))
+        ));
+        assert!(matches!(
+            server.apply(vec![
+                WithdrawElement::new(a.clone(), Hash::from_data(b"")).into()
+            ]),
+            Err(ServerError::HashMismatch(_))
+        ));
+        assert!(matches!(
+            server.apply(vec![
+                PublishElement::new(b.clone(), Bytes::new()).into(),
+                WithdrawElement::new(b.clone(), Hash::from_data(b"")).into()
+            ]),
+            Err(ServerError::DuplicateUri(_))
+        ));
+        assert_eq!(server.serial(), 3);
+        assert!(server.get(&b).is_none());
+
+        // The files reference each other correctly.
+        let notify = NotificationFile::parse(
+            server.notification_xml().as_ref()
+        ).unwrap();
+        assert_eq!(notify.session_id(), server.session_id());
+        assert_eq!(notify.serial(), 3);
+        assert_eq!(notify.snapshot().uri(), server.snapshot().uri());
+        assert!(notify.snapshot().hash().matches(server.snapshot().data()));
+        let snapshot = Snapshot::parse(
+            server.snapshot().data().as_ref()
+        ).unwrap();
+        assert_eq!(snapshot.serial(), 3);
+        assert_eq!(snapshot.elements().len(), 2);
+        assert_eq!(notify.deltas().len(), 2);
+        for (info, file) in notify.deltas().iter().zip(server.deltas()) {
+            assert_eq!(info.serial(), file.serial());
+            assert!(info.hash().matches(file.data()));
+            let delta = Delta::parse(file.data().as_ref()).unwrap();
+            assert_eq!(delta.serial(), file.serial());
+        }
+
+        // Restoring results in the same files.
+        let restored = Server::restore(
+            server.base_uri().clone(),
+            snapshot,
+            server.deltas().map(|file| {
+                Delta::parse(file.data().as_ref()).unwrap()
+            }).collect()
+        ).unwrap();
+        assert_eq!(restored.notification_xml(), server.notification_xml());
+
+        let old = server.reset_session(Uuid::from_u128(0x5678));
+        assert_eq!(old.len(), 3);
+        assert_eq!(server.serial(), 1);
+        assert_eq!(server.deltas().count(), 0);
+        assert_eq!(server.len(), 2);
+    }
+
+    #[test]
+    fn prune() {
+        let mut server = server();
+        let small = rsync("rsync://example.com/repo/small.cer");
+        let large = rsync("rsync://example.com/repo/large.cer");
+        let large_data = Bytes::from(vec![0u8; 4096]);
+        server.apply(vec![
+            PublishElement::new(small, Bytes::from(vec![0u8; 200])).into()
+        ]).unwrap();
+        assert_eq!(server.deltas().count(), 1);
+
+        // The two deltas together are larger than the snapshot now.
+        let old = server.apply(vec![
+            PublishElement::new(large.clone(), large_data.clone()).into()
+        ]).unwrap();
+        assert_eq!(
+            old.iter().map(RrdpFile::serial).collect::<Vec<_>>(), [2, 2]
+        );
+        assert_eq!(server.deltas().count(), 1);
+
+        // Withdrawing shrinks the snapshot, so the large delta goes.
+        let old = server.apply(vec![
+            WithdrawElement::new(large, Hash::from_data(&large_data)).into()
+        ]).unwrap();
+        assert_eq!(
+            old.iter().map(RrdpFile::serial).collect::<Vec<_>>(), [3, 3]
+        );
+        assert_eq!(server.deltas().count(), 1);
+        assert_eq!(server.deltas().next().unwrap().serial(), 4);
+        let total: usize = server.deltas().map(RrdpFile::len).sum();
+        assert!(total <= server.snapshot().len());
+    }
+}
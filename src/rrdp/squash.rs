@@ -0,0 +1,460 @@
+//! Combining snapshots and deltas.
+//!
+//! This module provides [`Snapshot::apply_deltas`] which derives the
+//! snapshot at a later serial number from a snapshot and the deltas
+//! following it, and [`Delta::squash`] which combines a run of
+//! consecutive deltas into a single delta with the same effect.
+//!
+//! Both check that the deltas form an unbroken chain of the same session
+//! and that the hashes given in update and withdraw elements match the
+//! content they replace.
+
+use std::{error, fmt};
+use std::collections::HashMap;
+use bytes::Bytes;
+use crate::uri;
+use super::{
+    Delta, DeltaElement, PublishElement, Snapshot, UpdateElement,
+    WithdrawElement,
+};
+
+
+//------------ Snapshot ------------------------------------------------------
+
+/// # Applying Deltas
+///
+impl Snapshot {
+    /// Returns the snapshot resulting from applying a run of deltas.
+    ///
+    /// The deltas can be given in any order but must belong to the
+    /// snapshot’s session and have consecutive serial numbers starting
+    /// right after the snapshot’s serial number. If there are no deltas,
+    /// a copy of the snapshot is returned.
+    ///
+    /// Objects retain their position in the snapshot. Newly published
+    /// objects are added at the end in the order they were published.
+    pub fn apply_deltas<'a>(
+        &self,
+        deltas: impl IntoIterator<Item = &'a Delta>,
+    ) -> Result<Snapshot, ChainError> {
+        let deltas = sorted_chain(deltas, Some(self))?;
+        let mut objects = Objects::default();
+        for item in &self.elements {
+            if objects.set(item.uri(), Some(item.data().clone())).is_some() {
+                return Err(ChainError::ObjectExists(item.uri().clone()))
+            }
+        }
+        let mut serial = self.serial;
+        for delta in deltas {
+            for element in delta.elements() {
+                objects.apply(element)?;
+            }
+            serial = delta.serial;
+        }
+        Ok(Snapshot::new(
+            self.session_id, serial,
+            objects.into_iter().filter_map(|(uri, data)| {
+                data.map(|data| PublishElement::new(uri, data))
+            }).collect()
+        ))
+    }
+}
+
+
+//------------ Delta ---------------------------------------------------------
+
+/// # Squashing Deltas
+///
+impl Delta {
+    /// Combines a run of deltas into a single delta.
+    ///
+    /// The deltas can be given in any order but must belong to the same
+    /// session and have consecutive serial numbers. The resulting delta
+    /// has the serial number of the last delta and contains at most one
+    /// element per object: objects published and then withdrawn again
+    /// are dropped, multiple updates are merged into one, and an object
+    /// withdrawn and then published again becomes an update. Objects whose
+    /// content is unchanged in the end are dropped, too.
+    ///
+    /// Since a delta only carries the serial number it updates to, the
+    /// squashed delta does not chain onto the snapshot preceding the run
+    /// as far as [`Snapshot::apply_deltas`] is concerned.
+    ///
+    /// Returns an error if the deltas are empty, do not form a chain, or
+    /// if their elements contradict each other.
+    pub fn squash<'a>(
+        deltas: impl IntoIterator<Item = &'a Delta>,
+    ) -> Result<Delta, ChainError> {
+        let deltas = sorted_chain(deltas, None)?;
+        let (session_id, serial) = match deltas.last() {
+            Some(delta) => (delta.session_id, delta.serial),
+            None => return Err(ChainError::Empty)
+        };
+
+        // For each object we keep the hash it had before the run, if it
+        // existed, and its current content, if it exists.
+        let mut before = HashMap::new();
+        let mut objects = Objects::default();
+        for delta in deltas {
+            for element in delta.elements() {
+                let uri = element.uri();
+                if !objects.contains(uri) {
+                    let hash = match element {
+                        DeltaElement::Publish(_) => None,
+                        DeltaElement::Update(item) => Some(*item.hash()),
+                        DeltaElement::Withdraw(item) => Some(*item.hash()),
+                    };
+                    before.insert(uri.clone(), hash);
+                    objects.apply_unchecked(element);
+                }
+                else {
+                    objects.apply(element)?;
+                }
+            }
+        }
+
+        Ok(Delta::new(
+            session_id, serial,
+            objects.into_iter().filter_map(|(uri, data)| {
+                match (before.remove(&uri).flatten(), data) {
+                    (None, None) => None,
+                    (None, Some(data)) => {
+                        Some(PublishElement::new(uri, data).into())
+                    }
+                    (Some(hash), Some(data)) => {
+                        if hash.matches(&data) {
+                            None
+                        }
+                        else {
+                            Some(UpdateElement::new(uri, hash, data).into())
+                        }
+                    }
+                    (Some(hash), None) => {
+                        Some(WithdrawElement::new(uri, hash).into())
+                    }
+                }
+            }).collect()
+        ))
+    }
+}
+
+
+//------------ Objects -------------------------------------------------------
+
+/// The changing content of a set of objects.
+///
+/// Keeps the order in which objects were first seen.
+#[derive(Default)]
+struct Objects {
+    /// The objects and their content if they currently exist.
+    items: Vec<(uri::Rsync, Option<Bytes>)>,
+
+    /// The index of each object in `items`.
+    index: HashMap<uri::Rsync, usize>,
+}
+
+impl Objects {
+    /// Returns whether the object has been seen.
+    fn contains(&self, uri: &uri::Rsync) -> bool {
+        self.index.contains_key(uri)
+    }
+
+    /// Returns the current content of an object.
+    fn get(&self, uri: &uri::Rsync) -> Option<&Bytes> {
+        self.index.get(uri).and_then(|idx| self.items[*idx].1.as_ref())
+    }
+
+    /// Sets the content of an object, returning the previous content.
+    fn set(
+        &mut self, uri: &uri::Rsync, data: Option<Bytes>
+    ) -> Option<Bytes> {
+        match self.index.get(uri) {
+            Some(idx) => std::mem::replace(&mut self.items[*idx].1, data),
+            None => {
+                self.index.insert(uri.clone(), self.items.len());
+                self.items.push((uri.clone(), data));
+                None
+            }
+        }
+    }
+
+    /// Applies a delta element after checking it.
+    fn apply(&mut self, element: &DeltaElement) -> Result<(), ChainError> {
+        let uri = element.uri();
+        let hash = match element {
+            DeltaElement::Publish(_) => {
+                if self.get(uri).is_some() {
+                    return Err(ChainError::ObjectExists(uri.clone()))
+                }
+                None
+            }
+            DeltaElement::Update(item) => Some(item.hash()),
+            DeltaElement::Withdraw(item) => Some(item.hash()),
+        };
+        if let Some(hash) = hash {
+            match self.get(uri) {
+                Some(data) => {
+                    if !hash.matches(data) {
+                        return Err(ChainError::HashMismatch(uri.clone()))
+                    }
+                }
+                None => return Err(ChainError::ObjectMissing(uri.clone()))
+            }
+        }
+        self.apply_unchecked(element);
+        Ok(())
+    }
+
+    /// Applies a delta element without checking it.
+    fn apply_unchecked(&mut self, element: &DeltaElement) {
+        match element {
+            DeltaElement::Publish(item) => {
+                self.set(item.uri(), Some(item.data().clone()));
+            }
+            DeltaElement::Update(item) => {
+                self.set(item.uri(), Some(item.data().clone()));
+            }
+            DeltaElement::Withdraw(item) => {
+                self.set(item.uri(), None);
+            }
+        }
+    }
+}
+
+impl IntoIterator for Objects {
+    type Item = (uri::Rsync, Option<Bytes>);
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Sorts deltas by serial number and checks that they form a chain.
+///
+/// If `snapshot` is given, the deltas must belong to its session and
+/// follow its serial number.
+fn sorted_chain<'a>(
+    deltas: impl IntoIterator<Item = &'a Delta>,
+    snapshot: Option<&Snapshot>,
+) -> Result<Vec<&'a Delta>, ChainError> {
+    let mut deltas: Vec<_> = deltas.into_iter().collect();
+    deltas.sort_by_key(|delta| delta.serial);
+    let mut expected = match (snapshot, deltas.first()) {
+        (Some(snapshot), _) => {
+            Some((snapshot.session_id, snapshot.serial.wrapping_add(1)))
+        }
+        (None, Some(delta)) => Some((delta.session_id, delta.serial)),
+        (None, None) => None,
+    };
+    for delta in &deltas {
+        if let Some((session_id, serial)) = expected {
+            if delta.session_id != session_id {
+                return Err(ChainError::SessionMismatch)
+            }
+            if delta.serial != serial {
+                return Err(ChainError::SerialGap {
+                    expected: serial, found: delta.serial
+                })
+            }
+        }
+        expected = Some((delta.session_id, delta.serial.wrapping_add(1)));
+    }
+    Ok(deltas)
+}
+
+
+//------------ ChainError ----------------------------------------------------
+
+/// Snapshots and deltas could not be combined.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChainError {
+    /// There were no deltas to squash.
+    Empty,
+
+    /// A delta belongs to a different session.
+    SessionMismatch,
+
+    /// The serial numbers of the deltas are not consecutive.
+    SerialGap {
+        /// The serial number the next delta should have had.
+        expected: u64,
+
+        /// The serial number the next delta actually had.
+        found: u64,
+    },
+
+    /// An object to be published already exists.
+    ObjectExists(uri::Rsync),
+
+    /// An object to be updated or withdrawn does not exist.
+    ObjectMissing(uri::Rsync),
+
+    /// The hash of an object to be updated or withdrawn does not match.
+    HashMismatch(uri::Rsync),
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChainError::Empty => f.write_str("no deltas"),
+            ChainError::SessionMismatch => {
+                f.write_str("deltas from different sessions")
+            }
+            ChainError::SerialGap { expected, found } => {
+                write!(
+                    f, "expected delta with serial {}, found {}",
+                    expected, found
+                )
+            }
+            ChainError::ObjectExists(ref uri) => {
+                write!(f, "{}: object already exists", uri)
+            }
+            ChainError::ObjectMissing(ref uri) => {
+                write!(f, "{}: object does not exist", uri)
+            }
+            ChainError::HashMismatch(ref uri) => {
+                write!(f, "{}: hash mismatch", uri)
+            }
+        }
+    }
+}
+
+impl error::Error for ChainError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use uuid::Uuid;
+    use super::*;
+    use crate::rrdp::Hash;
+    use crate::rrdp::server::Server;
+
+    fn rsync(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    fn data(s: &'static str) -> Bytes {
+        Bytes::from_static(s.as_bytes())
+    }
+
+    fn parse_snapshot(server: &Server) -> Snapshot {
+        Snapshot::parse(server.snapshot().data().as_ref()).unwrap()
+    }
+
+    fn sorted(snapshot: &Snapshot) -> Vec<(String, Bytes)> {
+        let mut res: Vec<_> = snapshot.elements().iter().map(|item| {
+            (item.uri().to_string(), item.data().clone())
+        }).collect();
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn squash_and_apply() {
+        let a = rsync("rsync://example.com/repo/a.cer");
+        let b = rsync("rsync://example.com/repo/b.roa");
+        let c = rsync("rsync://example.com/repo/c.mft");
+        let d = rsync("rsync://example.com/repo/d.crl");
+        let mut server = Server::from_snapshot(
+            uri::Https::from_str("https://example.com/rrdp/").unwrap(),
+            Snapshot::new(Uuid::from_u128(1), 10, vec![
+                PublishElement::new(a.clone(), data("a")),
+                PublishElement::new(d.clone(), data("d")),
+                PublishElement::new(
+                    rsync("rsync://example.com/repo/large.cer"),
+                    Bytes::from(vec![0u8; 8192])
+                ),
+            ])
+        );
+        let start = parse_snapshot(&server);
+
+        server.apply(vec![
+            UpdateElement::new(a.clone(), Hash::from_data(b"a"), data("a2"))
+                .into(),
+            PublishElement::new(b.clone(), data("b")).into(),
+            WithdrawElement::new(d.clone(), Hash::from_data(b"d")).into(),
+        ]).unwrap();
+        server.apply(vec![
+            UpdateElement::new(a.clone(), Hash::from_data(b"a2"), data("a3"))
+                .into(),
+            WithdrawElement::new(b.clone(), Hash::from_data(b"b")).into(),
+            PublishElement::new(c.clone(), data("c")).into(),
+            PublishElement::new(d.clone(), data("d")).into(),
+        ]).unwrap();
+        let end = parse_snapshot(&server);
+        let deltas: Vec<_> = server.deltas().map(|file| {
+            Delta::parse(file.data().as_ref()).unwrap()
+        }).collect();
+        assert_eq!(deltas.len(), 2);
+
+        let derived = start.apply_deltas(&deltas).unwrap();
+        assert_eq!(derived.serial(), 12);
+        assert_eq!(sorted(&derived), sorted(&end));
+
+        let squashed = Delta::squash(&deltas).unwrap();
+        assert_eq!(squashed.serial(), 12);
+        assert_eq!(
+            squashed.elements(),
+            [
+                UpdateElement::new(
+                    a.clone(), Hash::from_data(b"a"), data("a3")
+                ).into(),
+                PublishElement::new(c.clone(), data("c")).into(),
+            ]
+        );
+        let derived = Snapshot::new(
+            start.session_id(), 11, start.elements().to_vec()
+        ).apply_deltas(Some(&squashed)).unwrap();
+        assert_eq!(sorted(&derived), sorted(&end));
+    }
+
+    #[test]
+    fn chain_errors() {
+        let session = Uuid::from_u128(1);
+        let a = rsync("rsync://example.com/repo/a.cer");
+        let snapshot = Snapshot::new(session, 1, vec![
+            PublishElement::new(a.clone(), data("a"))
+        ]);
+        let publish = Delta::new(session, 2, vec![
+            PublishElement::new(a.clone(), data("a")).into()
+        ]);
+        let update = Delta::new(session, 2, vec![
+            UpdateElement::new(a.clone(), Hash::from_data(b"x"), data("b"))
+                .into()
+        ]);
+        let withdraw = Delta::new(session, 3, vec![
+            WithdrawElement::new(a.clone(), Hash::from_data(b"a")).into()
+        ]);
+        let other = Delta::new(Uuid::from_u128(2), 3, vec![]);
+
+        assert_eq!(
+            snapshot.apply_deltas(Some(&publish)).unwrap_err(),
+            ChainError::ObjectExists(a.clone())
+        );
+        assert_eq!(
+            snapshot.apply_deltas(Some(&update)).unwrap_err(),
+            ChainError::HashMismatch(a.clone())
+        );
+        assert_eq!(
+            snapshot.apply_deltas(Some(&withdraw)).unwrap_err(),
+            ChainError::SerialGap { expected: 2, found: 3 }
+        );
+        assert_eq!(
+            Delta::squash(vec![&update, &other]).unwrap_err(),
+            ChainError::SessionMismatch
+        );
+        assert_eq!(Delta::squash(None).unwrap_err(), ChainError::Empty);
+        let publish = Delta::new(session, 2, vec![
+            PublishElement::new(a, data("a")).into()
+        ]);
+        let squashed = Delta::squash(vec![&withdraw, &publish]).unwrap();
+        assert!(squashed.elements().is_empty());
+    }
+}
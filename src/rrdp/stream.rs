@@ -0,0 +1,539 @@
+//! Streaming RRDP snapshots and deltas into a store.
+//!
+//! Parsing a snapshot into a [`Snapshot`][super::Snapshot] keeps all
+//! objects in memory which, for large repositories, needs a lot of it. The
+//! processors in this module instead write objects into a
+//! [`RepositoryStore`] while the file is being parsed. They only keep a
+//! limited batch of changes in memory and flush it to the store whenever
+//! it becomes too large. The size of each individual object is capped,
+//! too, so memory use stays flat regardless of the size of the file.
+//!
+//! Because changes are written in batches, the store is modified while
+//! processing is still in progress. If processing fails, changes from
+//! earlier batches remain in the store. Use a
+//! [`Transaction`][crate::store::Transaction] or the
+//! [`Updater`][super::update::Updater] if all-or-nothing behaviour is
+//! required.
+
+use std::{error, fmt, io};
+use std::io::Read;
+use uuid::Uuid;
+use crate::uri;
+use crate::store::{Change, ChangeSet, RepositoryStore, StoreError};
+use super::{Hash, ObjectReader, ProcessDelta, ProcessError, ProcessSnapshot};
+
+
+//------------ Configuration Defaults ----------------------------------------
+
+/// The default maximum size of a single object in bytes.
+pub const DEFAULT_MAX_OBJECT_SIZE: usize = 16 * 1024 * 1024;
+
+/// The default size of a batch of changes in bytes.
+pub const DEFAULT_BATCH_SIZE: usize = 4 * 1024 * 1024;
+
+
+//------------ StreamConfig --------------------------------------------------
+
+/// The configuration shared by the streaming processors.
+#[derive(Clone, Copy, Debug)]
+struct StreamConfig {
+    /// The expected session ID and serial number if any.
+    expected: Option<(Uuid, u64)>,
+
+    /// The maximum size of a single object.
+    max_object_size: usize,
+
+    /// The size of the batch of changes before it is flushed.
+    batch_size: usize,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        StreamConfig {
+            expected: None,
+            max_object_size: DEFAULT_MAX_OBJECT_SIZE,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+}
+
+
+//------------ Batch ---------------------------------------------------------
+
+/// A batch of changes waiting to be written to the store.
+#[derive(Debug)]
+struct Batch<'a, S: ?Sized> {
+    /// The store to write to.
+    store: &'a S,
+
+    /// The configuration.
+    config: StreamConfig,
+
+    /// The pending changes.
+    changes: ChangeSet,
+
+    /// The number of bytes in the pending changes.
+    size: usize,
+
+    /// The number of objects published so far.
+    published: usize,
+
+    /// The number of objects withdrawn so far.
+    withdrawn: usize,
+
+    /// The number of bytes published so far.
+    bytes: u64,
+}
+
+impl<'a, S: RepositoryStore + ?Sized> Batch<'a, S> {
+    /// Creates a new, empty batch with the default configuration.
+    fn new(store: &'a S) -> Self {
+        Batch {
+            store,
+            config: StreamConfig::default(),
+            changes: ChangeSet::new(),
+            size: 0,
+            published: 0,
+            withdrawn: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Checks the session ID and serial number.
+    fn meta(&self, session_id: Uuid, serial: u64) -> Result<(), StreamError> {
+        match self.config.expected {
+            Some(expected) if expected != (session_id, serial) => {
+                Err(StreamError::BadMeta)
+            }
+            _ => Ok(())
+        }
+    }
+
+    /// Returns the current content of an object.
+    fn get(
+        &self, uri: &uri::Rsync
+    ) -> Result<Option<bytes::Bytes>, StreamError> {
+        match self.changes.get(uri) {
+            Some(Change::Put(data)) => Ok(Some(data.clone())),
+            Some(Change::Delete) => Ok(None),
+            None => self.store.get(uri).map_err(Into::into)
+        }
+    }
+
+    /// Reads an object and adds it to the batch.
+    fn publish(
+        &mut self, uri: uri::Rsync, data: &mut ObjectReader
+    ) -> Result<(), StreamError> {
+        let limit = self.config.max_object_size;
+        let mut buf = Vec::new();
+        data.take(limit as u64 + 1).read_to_end(&mut buf)?;
+        if buf.len() > limit {
+            return Err(StreamError::ObjectTooLarge(uri))
+        }
+        self.size += buf.len();
+        self.published += 1;
+        self.bytes += buf.len() as u64;
+        self.changes.put(uri, buf.into());
+        self.flush_if_full()
+    }
+
+    /// Adds the deletion of an object to the batch.
+    fn withdraw(&mut self, uri: uri::Rsync) -> Result<(), StreamError> {
+        self.withdrawn += 1;
+        self.changes.delete(uri);
+        self.flush_if_full()
+    }
+
+    /// Flushes the batch if it has become too large.
+    fn flush_if_full(&mut self) -> Result<(), StreamError> {
+        // Count withdrawals, too, so that they don’t pile up forever.
+        if self.size + self.changes.len() >= self.config.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes all pending changes to the store.
+    fn flush(&mut self) -> Result<(), StreamError> {
+        if !self.changes.is_empty() {
+            self.store.apply(&self.changes)?;
+            self.changes = ChangeSet::new();
+            self.size = 0;
+        }
+        Ok(())
+    }
+
+    /// Returns the statistics.
+    fn stats(&self) -> StreamStats {
+        StreamStats {
+            published: self.published,
+            withdrawn: self.withdrawn,
+            bytes: self.bytes,
+        }
+    }
+}
+
+
+//------------ SnapshotStream ------------------------------------------------
+
+/// Processes a snapshot by writing its objects directly into a store.
+///
+/// Objects are written as they are encountered. Objects in the store that
+/// are not part of the snapshot are left untouched.
+#[derive(Debug)]
+pub struct SnapshotStream<'a, S: ?Sized> {
+    /// The pending changes and configuration.
+    batch: Batch<'a, S>,
+}
+
+impl<'a, S: RepositoryStore + ?Sized> SnapshotStream<'a, S> {
+    /// Creates a new processor writing into the given store.
+    pub fn new(store: &'a S) -> Self {
+        SnapshotStream { batch: Batch::new(store) }
+    }
+
+    /// Sets the expected session ID and serial number.
+    ///
+    /// If set, processing fails if the snapshot has different values.
+    pub fn set_expected(&mut self, session_id: Uuid, serial: u64) {
+        self.batch.config.expected = Some((session_id, serial))
+    }
+
+    /// Sets the maximum size of a single object in bytes.
+    ///
+    /// Processing fails with [`StreamError::ObjectTooLarge`] if an object
+    /// is larger. Defaults to [`DEFAULT_MAX_OBJECT_SIZE`].
+    pub fn set_max_object_size(&mut self, size: usize) {
+        self.batch.config.max_object_size = size
+    }
+
+    /// Sets the size of the batch of changes kept in memory in bytes.
+    ///
+    /// Defaults to [`DEFAULT_BATCH_SIZE`].
+    pub fn set_batch_size(&mut self, size: usize) {
+        self.batch.config.batch_size = size
+    }
+
+    /// Writes the remaining changes to the store.
+    ///
+    /// This must be called after successful processing. Returns the
+    /// statistics of the processed file.
+    pub fn finish(mut self) -> Result<StreamStats, StreamError> {
+        self.batch.flush()?;
+        Ok(self.batch.stats())
+    }
+}
+
+impl<'a, S> ProcessSnapshot for SnapshotStream<'a, S>
+where S: RepositoryStore + ?Sized {
+    type Err = StreamError;
+
+    fn meta(
+        &mut self, session_id: Uuid, serial: u64
+    ) -> Result<(), Self::Err> {
+        self.batch.meta(session_id, serial)
+    }
+
+    fn publish(
+        &mut self, uri: uri::Rsync, data: &mut ObjectReader,
+    ) -> Result<(), Self::Err> {
+        self.batch.publish(uri, data)
+    }
+}
+
+
+//------------ DeltaStream ---------------------------------------------------
+
+/// Processes a delta by applying its changes directly to a store.
+///
+/// The hashes given for updated and withdrawn objects are checked against
+/// the content of the store and an object published without a hash must
+/// not exist yet.
+#[derive(Debug)]
+pub struct DeltaStream<'a, S: ?Sized> {
+    /// The pending changes and configuration.
+    batch: Batch<'a, S>,
+}
+
+impl<'a, S: RepositoryStore + ?Sized> DeltaStream<'a, S> {
+    /// Creates a new processor writing into the given store.
+    pub fn new(store: &'a S) -> Self {
+        DeltaStream { batch: Batch::new(store) }
+    }
+
+    /// Sets the expected session ID and serial number.
+    ///
+    /// If set, processing fails if the delta has different values.
+    pub fn set_expected(&mut self, session_id: Uuid, serial: u64) {
+        self.batch.config.expected = Some((session_id, serial))
+    }
+
+    /// Sets the maximum size of a single object in bytes.
+    ///
+    /// Processing fails with [`StreamError::ObjectTooLarge`] if an object
+    /// is larger. Defaults to [`DEFAULT_MAX_OBJECT_SIZE`].
+    pub fn set_max_object_size(&mut self, size: usize) {
+        self.batch.config.max_object_size = size
+    }
+
+    /// Sets the size of the batch of changes kept in memory in bytes.
+    ///
+    /// Defaults to [`DEFAULT_BATCH_SIZE`].
+    pub fn set_batch_size(&mut self, size: usize) {
+        self.batch.config.batch_size = size
+    }
+
+    /// Writes the remaining changes to the store.
+    ///
+    /// This must be called after successful processing. Returns the
+    /// statistics of the processed file.
+    pub fn finish(mut self) -> Result<StreamStats, StreamError> {
+        self.batch.flush()?;
+        Ok(self.batch.stats())
+    }
+}
+
+impl<'a, S> ProcessDelta for DeltaStream<'a, S>
+where S: RepositoryStore + ?Sized {
+    type Err = StreamError;
+
+    fn meta(
+        &mut self, session_id: Uuid, serial: u64
+    ) -> Result<(), Self::Err> {
+        self.batch.meta(session_id, serial)
+    }
+
+    fn publish(
+        &mut self,
+        uri: uri::Rsync,
+        hash: Option<Hash>,
+        data: &mut ObjectReader,
+    ) -> Result<(), Self::Err> {
+        let matches = match (hash, self.batch.get(&uri)?) {
+            (Some(hash), Some(current)) => hash.matches(&current),
+            (None, None) => true,
+            _ => false,
+        };
+        if !matches {
+            return Err(StoreError::HashMismatch(uri).into())
+        }
+        self.batch.publish(uri, data)
+    }
+
+    fn withdraw(
+        &mut self, uri: uri::Rsync, hash: Hash,
+    ) -> Result<(), Self::Err> {
+        match self.batch.get(&uri)? {
+            Some(current) if hash.matches(&current) => {
+                self.batch.withdraw(uri)
+            }
+            _ => Err(StoreError::HashMismatch(uri).into())
+        }
+    }
+}
+
+
+//------------ StreamStats ---------------------------------------------------
+
+/// Statistics about a processed snapshot or delta.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct StreamStats {
+    /// The number of objects published or updated.
+    pub published: usize,
+
+    /// The number of objects withdrawn.
+    pub withdrawn: usize,
+
+    /// The total size of all published objects in bytes.
+    pub bytes: u64,
+}
+
+
+//------------ StreamError ---------------------------------------------------
+
+/// An error happened while streaming RRDP data into a store.
+#[derive(Debug)]
+pub enum StreamError {
+    /// Parsing the file failed.
+    Process(ProcessError),
+
+    /// The session ID or serial number are not as expected.
+    BadMeta,
+
+    /// An object is larger than the configured maximum.
+    ObjectTooLarge(uri::Rsync),
+
+    /// Accessing the store failed.
+    Store(StoreError),
+}
+
+impl From<ProcessError> for StreamError {
+    fn from(err: ProcessError) -> Self {
+        StreamError::Process(err)
+    }
+}
+
+impl From<StoreError> for StreamError {
+    fn from(err: StoreError) -> Self {
+        StreamError::Store(err)
+    }
+}
+
+impl From<io::Error> for StreamError {
+    fn from(err: io::Error) -> Self {
+        StreamError::Process(err.into())
+    }
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamError::Process(ref inner) => inner.fmt(f),
+            StreamError::BadMeta => {
+                f.write_str("unexpected session ID or serial number")
+            }
+            StreamError::ObjectTooLarge(ref uri) => {
+                write!(f, "object {} too large", uri)
+            }
+            StreamError::Store(ref inner) => inner.fmt(f),
+        }
+    }
+}
+
+impl error::Error for StreamError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use bytes::Bytes;
+    use crate::store::MemoryStore;
+    use super::*;
+    use super::super::{
+        Delta, DeltaElement, PublishElement, Snapshot, UpdateElement,
+        WithdrawElement,
+    };
+
+    fn rsync(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    /// A store that records the size of the largest batch applied.
+    #[derive(Default)]
+    struct Recorder {
+        store: MemoryStore,
+        largest: std::sync::Mutex<usize>,
+    }
+
+    impl RepositoryStore for Recorder {
+        fn get(
+            &self, uri: &uri::Rsync
+        ) -> Result<Option<Bytes>, StoreError> {
+            self.store.get(uri)
+        }
+
+        fn list(
+            &self, pub_point: &uri::Rsync
+        ) -> Result<Vec<uri::Rsync>, StoreError> {
+            self.store.list(pub_point)
+        }
+
+        fn list_tree(
+            &self, dir: &uri::Rsync
+        ) -> Result<Vec<uri::Rsync>, StoreError> {
+            self.store.list_tree(dir)
+        }
+
+        fn apply(&self, changes: &ChangeSet) -> Result<(), StoreError> {
+            let mut largest = self.largest.lock().unwrap();
+            *largest = (*largest).max(changes.len());
+            self.store.apply(changes)
+        }
+    }
+
+    fn snapshot_xml(count: usize) -> Vec<u8> {
+        let mut xml = Vec::new();
+        Snapshot::new(
+            Uuid::from_u128(1), 1,
+            (0..count).map(|idx| {
+                PublishElement::new(
+                    rsync(&format!("rsync://example.com/repo/{}.cer", idx)),
+                    Bytes::from(vec![idx as u8; 100])
+                )
+            }).collect()
+        ).write_xml(&mut xml).unwrap();
+        xml
+    }
+
+    #[test]
+    fn snapshot() {
+        let store = Recorder::default();
+        let mut stream = SnapshotStream::new(&store);
+        stream.set_expected(Uuid::from_u128(1), 1);
+        stream.set_batch_size(1000);
+        stream.process(snapshot_xml(100).as_slice()).unwrap();
+        let stats = stream.finish().unwrap();
+        assert_eq!(stats.published, 100);
+        assert_eq!(stats.bytes, 10_000);
+        assert_eq!(store.store.len(), 100);
+        assert!(*store.largest.lock().unwrap() <= 10);
+
+        let mut stream = SnapshotStream::new(&store);
+        stream.set_expected(Uuid::from_u128(1), 2);
+        assert!(matches!(
+            stream.process(snapshot_xml(1).as_slice()),
+            Err(StreamError::BadMeta)
+        ));
+
+        let mut stream = SnapshotStream::new(&store);
+        stream.set_max_object_size(99);
+        assert!(matches!(
+            stream.process(snapshot_xml(1).as_slice()),
+            Err(StreamError::ObjectTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn delta() {
+        let store = MemoryStore::new();
+        let a = rsync("rsync://example.com/repo/a.cer");
+        let b = rsync("rsync://example.com/repo/b.cer");
+        let c = rsync("rsync://example.com/repo/c.cer");
+        store.put(a.clone(), Bytes::from_static(b"a")).unwrap();
+        store.put(b.clone(), Bytes::from_static(b"b")).unwrap();
+
+        let delta = |elements: Vec<DeltaElement>| {
+            let mut xml = Vec::new();
+            Delta::new(
+                Uuid::from_u128(1), 2, elements
+            ).write_xml(&mut xml).unwrap();
+            xml
+        };
+
+        let mut stream = DeltaStream::new(&store);
+        stream.process(delta(vec![
+            UpdateElement::new(
+                a.clone(), Hash::from_data(b"a"), Bytes::from_static(b"a2")
+            ).into(),
+            WithdrawElement::new(b.clone(), Hash::from_data(b"b")).into(),
+            PublishElement::new(c.clone(), Bytes::from_static(b"c")).into(),
+        ]).as_slice()).unwrap();
+        let stats = stream.finish().unwrap();
+        assert_eq!(stats.published, 2);
+        assert_eq!(stats.withdrawn, 1);
+        assert_eq!(store.get(&a).unwrap(), Some(Bytes::from_static(b"a2")));
+        assert_eq!(store.get(&b).unwrap(), None);
+        assert_eq!(store.get(&c).unwrap(), Some(Bytes::from_static(b"c")));
+
+        // Publishing an existing object without a hash fails.
+        let mut stream = DeltaStream::new(&store);
+        assert!(matches!(
+            stream.process(delta(vec![
+                PublishElement::new(c, Bytes::from_static(b"c")).into(),
+            ]).as_slice()),
+            Err(StreamError::Store(StoreError::HashMismatch(_)))
+        ));
+    }
+}
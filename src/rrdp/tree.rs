@@ -0,0 +1,335 @@
+//! Converting rsync directory trees into RRDP.
+//!
+//! Repositories that are only available as a directory tree as used by
+//! rsync can be published via RRDP with the help of this module. A
+//! [`Tree`] holds the content of such a directory tree with each file
+//! mapped to an rsync URI below a base URI. From a tree, the RRDP snapshot
+//! can be created. Given the tree of a previous version of the
+//! repository, the delta between the two can be created, too.
+//!
+//! Since the notification file contains the hashes of the serialized
+//! snapshot and deltas, it is best created via a [`Server`]. A tree can
+//! be turned into a new server via [`Tree::to_server`] and later versions
+//! of the tree can be applied to the server via [`Tree::update_server`].
+
+use std::{error, fmt, fs, io};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use bytes::Bytes;
+use uuid::Uuid;
+use crate::uri;
+use super::{
+    Delta, DeltaElement, Hash, PublishElement, Snapshot, UpdateElement,
+    WithdrawElement,
+};
+use super::server::{RrdpFile, Server, ServerError};
+
+
+//------------ Tree ----------------------------------------------------------
+
+/// The content of a repository directory tree.
+#[derive(Clone, Debug, Default)]
+pub struct Tree {
+    /// The objects of the tree.
+    objects: HashMap<uri::Rsync, Bytes>,
+}
+
+impl Tree {
+    /// Creates a new, empty tree.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a tree from a directory.
+    ///
+    /// All regular files found below `dir` are added to the tree. The URI
+    /// of a file is formed by appending its path relative to `dir` to
+    /// `base`, which should therefore be the URI of a directory.
+    /// Symbolic links and other special files are ignored.
+    ///
+    /// Returns an error if a file name cannot be used in an rsync URI.
+    pub fn read_dir(
+        dir: impl AsRef<Path>, base: &uri::Rsync
+    ) -> Result<Self, TreeError> {
+        let mut res = Self::new();
+        res.add_dir(dir.as_ref(), base)?;
+        Ok(res)
+    }
+
+    /// Adds the content of a directory to the tree.
+    fn add_dir(
+        &mut self, dir: &Path, base: &uri::Rsync
+    ) -> Result<(), TreeError> {
+        let entries = fs::read_dir(dir).map_err(|err| {
+            TreeError::Io(dir.into(), err)
+        })?;
+        for entry in entries {
+            let entry = entry.map_err(|err| TreeError::Io(dir.into(), err))?;
+            let path = entry.path();
+            let file_type = entry.file_type().map_err(|err| {
+                TreeError::Io(path.clone(), err)
+            })?;
+            if !file_type.is_dir() && !file_type.is_file() {
+                continue
+            }
+            let mut name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => return Err(TreeError::BadName(path))
+            };
+            if file_type.is_dir() {
+                name.push('/');
+            }
+            let uri = match base.join(name.as_bytes()) {
+                Ok(uri) => uri,
+                Err(_) => return Err(TreeError::BadName(path))
+            };
+            if file_type.is_dir() {
+                self.add_dir(&path, &uri)?;
+            }
+            else {
+                let data = fs::read(&path).map_err(|err| {
+                    TreeError::Io(path.clone(), err)
+                })?;
+                self.objects.insert(uri, data.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds an object to the tree.
+    ///
+    /// Replaces the content if the object is already present.
+    pub fn insert(&mut self, uri: uri::Rsync, data: Bytes) {
+        self.objects.insert(uri, data);
+    }
+
+    /// Returns the content of an object.
+    pub fn get(&self, uri: &uri::Rsync) -> Option<&Bytes> {
+        self.objects.get(uri)
+    }
+
+    /// Returns the number of objects in the tree.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns whether the tree is empty.
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Returns an iterator over the objects of the tree.
+    pub fn iter(&self) -> impl Iterator<Item = (&uri::Rsync, &Bytes)> {
+        self.objects.iter()
+    }
+
+    /// Creates the RRDP snapshot of the tree.
+    ///
+    /// The objects are ordered by their URI.
+    pub fn snapshot(&self, session_id: Uuid, serial: u64) -> Snapshot {
+        let mut elements: Vec<_> = self.objects.iter().map(|(uri, data)| {
+            PublishElement::new(uri.clone(), data.clone())
+        }).collect();
+        elements.sort_by(|left, right| {
+            left.uri().as_str().cmp(right.uri().as_str())
+        });
+        Snapshot::new(session_id, serial, elements)
+    }
+
+    /// Returns the elements that change `previous` into this tree.
+    ///
+    /// New objects are published, objects with changed content are
+    /// updated, and objects missing from this tree are withdrawn. The
+    /// elements are ordered by their URI.
+    pub fn diff(&self, previous: &Tree) -> Vec<DeltaElement> {
+        diff_objects(&self.objects, previous.objects.iter())
+    }
+
+    /// Creates the RRDP delta from a previous tree to this tree.
+    pub fn delta(
+        &self, previous: &Tree, session_id: Uuid, serial: u64
+    ) -> Delta {
+        Delta::new(session_id, serial, self.diff(previous))
+    }
+
+    /// Creates a new RRDP server publishing this tree.
+    pub fn to_server(
+        &self, base_uri: uri::Https, session_id: Uuid, serial: u64
+    ) -> Server {
+        Server::from_snapshot(base_uri, self.snapshot(session_id, serial))
+    }
+
+    /// Updates an RRDP server to publish this tree.
+    ///
+    /// The changes between the server’s current content and the tree are
+    /// applied to the server. Returns the RRDP files that are not needed
+    /// anymore. See [`Server::apply`] for details.
+    pub fn update_server(
+        &self, server: &mut Server
+    ) -> Result<Vec<RrdpFile>, ServerError> {
+        server.apply(diff_objects(&self.objects, server.objects()))
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns the delta elements changing `previous` into `current`.
+///
+/// The elements are ordered by their URI.
+fn diff_objects<'a>(
+    current: &HashMap<uri::Rsync, Bytes>,
+    previous: impl Iterator<Item = (&'a uri::Rsync, &'a Bytes)>,
+) -> Vec<DeltaElement> {
+    let previous: HashMap<_, _> = previous.collect();
+    let mut res: Vec<DeltaElement> = Vec::new();
+    for (uri, data) in current {
+        match previous.get(uri) {
+            Some(old) => {
+                if *old != data {
+                    res.push(UpdateElement::new(
+                        uri.clone(), Hash::from_data(old), data.clone()
+                    ).into());
+                }
+            }
+            None => {
+                res.push(
+                    PublishElement::new(uri.clone(), data.clone()).into()
+                )
+            }
+        }
+    }
+    for (uri, old) in previous {
+        if !current.contains_key(uri) {
+            res.push(
+                WithdrawElement::new(uri.clone(), Hash::from_data(old)).into()
+            );
+        }
+    }
+    res.sort_by(|left, right| left.uri().as_str().cmp(right.uri().as_str()));
+    res
+}
+
+
+//------------ TreeError -----------------------------------------------------
+
+/// An error happened while reading a directory tree.
+#[derive(Debug)]
+pub enum TreeError {
+    /// Accessing a file or directory failed.
+    Io(PathBuf, io::Error),
+
+    /// A file name cannot be used in an rsync URI.
+    BadName(PathBuf),
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TreeError::Io(ref path, ref err) => {
+                write!(f, "{}: {}", path.display(), err)
+            }
+            TreeError::BadName(ref path) => {
+                write!(f, "{}: illegal file name", path.display())
+            }
+        }
+    }
+}
+
+impl error::Error for TreeError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+    use crate::rrdp::NotificationFile;
+
+    fn rsync(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn read_and_diff() {
+        let dir = std::env::temp_dir().join(format!(
+            "rpki-rrdp-tree-{}", std::process::id()
+        ));
+        fs::create_dir_all(dir.join("ca/child")).unwrap();
+        fs::write(dir.join("ta.cer"), b"ta").unwrap();
+        fs::write(dir.join("ca/ca.mft"), b"mft").unwrap();
+        fs::write(dir.join("ca/child/a.roa"), b"roa").unwrap();
+        let base = rsync("rsync://example.com/repo/");
+        let old = Tree::read_dir(&dir, &base).unwrap();
+        assert_eq!(old.len(), 3);
+        assert_eq!(
+            old.get(&rsync("rsync://example.com/repo/ca/child/a.roa"))
+                .unwrap().as_ref(),
+            b"roa"
+        );
+
+        let snapshot = old.snapshot(Uuid::from_u128(1), 1);
+        assert_eq!(
+            snapshot.elements().iter().map(|item| {
+                item.uri().as_str()
+            }).collect::<Vec<_>>(),
+            [
+                "rsync://example.com/repo/ca/ca.mft",
+                "rsync://example.com/repo/ca/child/a.roa",
+                "rsync://example.com/repo/ta.cer",
+            ]
+        );
+
+        fs::write(dir.join("ca/ca.mft"), b"mft2").unwrap();
+        fs::remove_file(dir.join("ca/child/a.roa")).unwrap();
+        fs::write(dir.join("ca/child/b.roa"), b"roa2").unwrap();
+        let new = Tree::read_dir(&dir, &base).unwrap();
+        let delta = new.delta(&old, Uuid::from_u128(1), 2);
+        assert_eq!(
+            delta.elements(),
+            [
+                UpdateElement::new(
+                    rsync("rsync://example.com/repo/ca/ca.mft"),
+                    Hash::from_data(b"mft"),
+                    Bytes::from_static(b"mft2"),
+                ).into(),
+                WithdrawElement::new(
+                    rsync("rsync://example.com/repo/ca/child/a.roa"),
+                    Hash::from_data(b"roa"),
+                ).into(),
+                PublishElement::new(
+                    rsync("rsync://example.com/repo/ca/child/b.roa"),
+                    Bytes::from_static(b"roa2"),
+                ).into(),
+            ]
+        );
+        let mut derived = old.snapshot(Uuid::from_u128(1), 1).apply_deltas(
+            Some(&delta)
+        ).unwrap().into_elements();
+        derived.sort_by(|left, right| {
+            left.uri().as_str().cmp(right.uri().as_str())
+        });
+        assert_eq!(derived, new.snapshot(Uuid::from_u128(1), 2).elements());
+
+        // Via a server, producing a notification file.
+        let mut server = old.to_server(
+            uri::Https::from_str("https://example.com/rrdp/").unwrap(),
+            Uuid::from_u128(1), 1
+        );
+        new.update_server(&mut server).unwrap();
+        assert_eq!(server.serial(), 2);
+        let notify = NotificationFile::parse(
+            server.notification_xml().as_ref()
+        ).unwrap();
+        assert_eq!(notify.serial(), 2);
+        assert!(notify.snapshot().hash().matches(server.snapshot().data()));
+
+        fs::write(dir.join("bad name"), b"").unwrap();
+        assert!(matches!(
+            Tree::read_dir(&dir, &base),
+            Err(TreeError::BadName(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,903 @@
+//! Updating a repository store via RRDP.
+//!
+//! This module provides the client-side decision logic of RRDP. An
+//! [`Updater`] fetches the notification file of a repository, compares it
+//! with the [`State`] of the last update, and then either applies the
+//! deltas necessary to get from there to the current serial number or
+//! falls back to the snapshot. The RRDP files are fetched through a type
+//! implementing the [`Fetcher`] trait and the objects are written into a
+//! [`RepositoryStore`].
+//!
+//! All changes made by an update are applied to the store in a single
+//! transaction. If an update fails, the store remains unchanged.
+
+use std::{error, fmt, io};
+use std::collections::{HashMap, HashSet};
+use bytes::Bytes;
+use ring::digest;
+use uuid::Uuid;
+use crate::uri;
+use crate::store::{ChangeSet, RepositoryStore, StoreError, Transaction};
+use super::{
+    Hash, NotificationFile, ObjectReader, ProcessDelta, ProcessError,
+    ProcessSnapshot,
+};
+
+
+//------------ Fetcher -------------------------------------------------------
+
+/// A type that can fetch RRDP files.
+///
+/// Normally, this will be an HTTPS client. For testing, [`LocalFetcher`]
+/// provides files from memory.
+pub trait Fetcher {
+    /// The type of the response body.
+    type Response: io::Read;
+
+    /// Fetches the file at the given URI.
+    ///
+    /// The content of the file is read from the returned value. Errors
+    /// while reading it are treated the same as errors returned here.
+    fn fetch(&self, uri: &uri::Https) -> Result<Self::Response, io::Error>;
+}
+
+impl<F: Fetcher + ?Sized> Fetcher for &F {
+    type Response = F::Response;
+
+    fn fetch(&self, uri: &uri::Https) -> Result<Self::Response, io::Error> {
+        (*self).fetch(uri)
+    }
+}
+
+
+//------------ LocalFetcher --------------------------------------------------
+
+/// A fetcher that provides files kept in memory.
+#[derive(Clone, Debug, Default)]
+pub struct LocalFetcher {
+    /// The files keyed by their URI.
+    files: HashMap<uri::Https, Bytes>,
+}
+
+impl LocalFetcher {
+    /// Creates a new fetcher without any files.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces a file.
+    pub fn insert(&mut self, uri: uri::Https, data: Bytes) {
+        self.files.insert(uri, data);
+    }
+
+    /// Removes a file.
+    pub fn remove(&mut self, uri: &uri::Https) {
+        self.files.remove(uri);
+    }
+}
+
+impl Fetcher for LocalFetcher {
+    type Response = io::Cursor<Bytes>;
+
+    fn fetch(&self, uri: &uri::Https) -> Result<Self::Response, io::Error> {
+        self.files.get(uri).cloned().map(io::Cursor::new).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "file not found")
+        })
+    }
+}
+
+
+//------------ State ---------------------------------------------------------
+
+/// The state of a repository after an update.
+///
+/// The state needs to be kept between updates. It is used to decide
+/// whether deltas can be used and which objects to remove from the store
+/// when falling back to a snapshot.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct State {
+    /// The session ID of the last update.
+    session_id: Uuid,
+
+    /// The serial number of the last update.
+    serial: u64,
+
+    /// The URIs of all objects currently published by the repository.
+    objects: HashSet<uri::Rsync>,
+}
+
+impl State {
+    /// Creates a new state from its components.
+    pub fn new(
+        session_id: Uuid, serial: u64, objects: HashSet<uri::Rsync>
+    ) -> Self {
+        State { session_id, serial, objects }
+    }
+
+    /// Returns the session ID of the last update.
+    pub fn session_id(&self) -> Uuid {
+        self.session_id
+    }
+
+    /// Returns the serial number of the last update.
+    pub fn serial(&self) -> u64 {
+        self.serial
+    }
+
+    /// Returns the URIs of the objects published by the repository.
+    pub fn objects(&self) -> &HashSet<uri::Rsync> {
+        &self.objects
+    }
+}
+
+
+//------------ Updater -------------------------------------------------------
+
+/// Updates a repository store via RRDP.
+#[derive(Clone, Debug)]
+pub struct Updater<F, S> {
+    /// The fetcher for RRDP files.
+    fetcher: F,
+
+    /// The store to write objects to.
+    store: S,
+
+    /// The maximum number of deltas to apply in one update.
+    delta_limit: Option<usize>,
+
+    /// Whether to refuse files from a different origin.
+    strict_origin: bool,
+}
+
+impl<F: Fetcher, S: RepositoryStore> Updater<F, S> {
+    /// Creates a new updater from a fetcher and a store.
+    pub fn new(fetcher: F, store: S) -> Self {
+        Updater { fetcher, store, delta_limit: None, strict_origin: false }
+    }
+
+    /// Sets the maximum number of deltas to apply in one update.
+    ///
+    /// If more deltas would be necessary, the snapshot is used instead.
+    /// By default, there is no limit.
+    pub fn set_delta_limit(&mut self, limit: Option<usize>) {
+        self.delta_limit = limit
+    }
+
+    /// Sets whether to refuse files from a different origin.
+    ///
+    /// If enabled, an update fails if the snapshot or any delta listed in
+    /// the notification file has a different origin than the notification
+    /// file itself, as required by the RRDP same-origin rules. By default,
+    /// files from any origin are accepted.
+    pub fn set_strict_origin(&mut self, strict: bool) {
+        self.strict_origin = strict
+    }
+
+    /// Returns a reference to the fetcher.
+    pub fn fetcher(&self) -> &F {
+        &self.fetcher
+    }
+
+    /// Returns a reference to the store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Updates the repository with the given notification URI.
+    ///
+    /// The state of the last update should be given in `state`. If there
+    /// is none, the snapshot is used.
+    ///
+    /// Deltas are used if the session ID of the notification file is
+    /// unchanged and it lists all deltas necessary to get from the serial
+    /// of the last update to the current serial. Otherwise, or if applying
+    /// the deltas fails, the snapshot is used. An error is returned only
+    /// if fetching or processing the notification file or the snapshot
+    /// fails or the changes cannot be applied to the store. In this case,
+    /// the store remains unchanged.
+    pub fn update(
+        &self,
+        notify_uri: &uri::Https,
+        state: Option<&State>,
+    ) -> Result<Update, UpdateError> {
+        let mut notify = self.fetch_notification(notify_uri)?;
+        if self.strict_origin {
+            if let Some(uri) = notify.foreign_uri(notify_uri) {
+                return Err(UpdateError::ForeignOrigin(uri.clone()))
+            }
+        }
+        let state = match state {
+            Some(state) => state,
+            None => {
+                return self.snapshot(&notify, None, SnapshotReason::NoState)
+            }
+        };
+        if notify.session_id() != state.session_id {
+            return self.snapshot(
+                &notify, Some(state), SnapshotReason::SessionChanged
+            )
+        }
+        if notify.serial() == state.serial {
+            return Ok(Update {
+                kind: UpdateKind::Unchanged,
+                state: state.clone(),
+                changes: ChangeSet::new(),
+            })
+        }
+        if notify.serial() < state.serial {
+            return self.snapshot(
+                &notify, Some(state), SnapshotReason::SerialRegressed
+            )
+        }
+        if !self.deltas_usable(&mut notify, state) {
+            return self.snapshot(
+                &notify, Some(state), SnapshotReason::MissingDeltas
+            )
+        }
+        match self.deltas(&notify, state) {
+            Ok(update) => Ok(update),
+            Err(err) => {
+                self.snapshot(
+                    &notify, Some(state),
+                    SnapshotReason::DeltaFailed(err.to_string())
+                )
+            }
+        }
+    }
+
+    /// Fetches and parses the notification file.
+    fn fetch_notification(
+        &self, uri: &uri::Https
+    ) -> Result<NotificationFile, UpdateError> {
+        let response = self.fetcher.fetch(uri).map_err(|err| {
+            UpdateError::Fetch(uri.clone(), err)
+        })?;
+        NotificationFile::parse(io::BufReader::new(response)).map_err(|err| {
+            UpdateError::Process(uri.clone(), err.into())
+        })
+    }
+
+    /// Checks whether the deltas of the notification file can be used.
+    ///
+    /// Sorts and limits the deltas of the notification file.
+    fn deltas_usable(
+        &self, notify: &mut NotificationFile, state: &State
+    ) -> bool {
+        if !notify.sort_and_verify_deltas(self.delta_limit) {
+            return false
+        }
+        match (notify.deltas().first(), notify.deltas().last()) {
+            (Some(first), Some(last)) => {
+                first.serial() <= state.serial + 1
+                && last.serial() == notify.serial()
+            }
+            _ => false
+        }
+    }
+
+    /// Applies all deltas necessary to update from `state`.
+    fn deltas(
+        &self, notify: &NotificationFile, state: &State,
+    ) -> Result<Update, UpdateError> {
+        let mut tx = self.store.transaction();
+        let mut objects = state.objects.clone();
+        let mut count = 0;
+        for info in notify.deltas() {
+            if info.serial() <= state.serial {
+                continue
+            }
+            let mut target = DeltaTarget {
+                tx: &mut tx,
+                session_id: notify.session_id(),
+                serial: info.serial(),
+                objects: &mut objects,
+            };
+            self.process_file(info.uri(), info.hash(), |reader| {
+                ProcessDelta::process(&mut target, reader)
+            })?;
+            count += 1;
+        }
+        Ok(Update {
+            kind: UpdateKind::Deltas(count),
+            state: State {
+                session_id: notify.session_id(),
+                serial: notify.serial(),
+                objects,
+            },
+            changes: tx.commit().map_err(UpdateError::Store)?,
+        })
+    }
+
+    /// Applies the snapshot.
+    fn snapshot(
+        &self,
+        notify: &NotificationFile,
+        state: Option<&State>,
+        reason: SnapshotReason,
+    ) -> Result<Update, UpdateError> {
+        let mut tx = self.store.transaction();
+        let mut target = SnapshotTarget {
+            tx: &mut tx,
+            session_id: notify.session_id(),
+            serial: notify.serial(),
+            objects: HashSet::new(),
+        };
+        let info = notify.snapshot();
+        self.process_file(info.uri(), info.hash(), |reader| {
+            ProcessSnapshot::process(&mut target, reader)
+        })?;
+        let objects = target.objects;
+        if let Some(state) = state {
+            for uri in &state.objects {
+                if !objects.contains(uri) {
+                    tx.delete(uri.clone());
+                }
+            }
+        }
+        Ok(Update {
+            kind: UpdateKind::Snapshot(reason),
+            state: State {
+                session_id: notify.session_id(),
+                serial: notify.serial(),
+                objects,
+            },
+            changes: tx.commit().map_err(UpdateError::Store)?,
+        })
+    }
+
+    /// Fetches and processes a snapshot or delta file.
+    ///
+    /// The hash over the file is checked after processing. Since changes
+    /// are only collected in a transaction, they can still be dropped.
+    fn process_file(
+        &self,
+        uri: &uri::Https,
+        hash: Hash,
+        op: impl FnOnce(
+            &mut io::BufReader<HashingReader<F::Response>>
+        ) -> Result<(), FileError>,
+    ) -> Result<(), UpdateError> {
+        let response = self.fetcher.fetch(uri).map_err(|err| {
+            UpdateError::Fetch(uri.clone(), err)
+        })?;
+        let mut reader = io::BufReader::new(HashingReader::new(response));
+        op(&mut reader).map_err(|err| err.into_update_error(uri))?;
+        // Make sure the entire file has been read before checking the hash.
+        io::copy(&mut reader, &mut io::sink()).map_err(|err| {
+            UpdateError::Fetch(uri.clone(), err)
+        })?;
+        if reader.into_inner().finish() != hash {
+            return Err(UpdateError::HashMismatch(uri.clone()))
+        }
+        Ok(())
+    }
+}
+
+
+//------------ Update --------------------------------------------------------
+
+/// The outcome of a successful update.
+#[derive(Clone, Debug)]
+pub struct Update {
+    /// How the update was performed.
+    pub kind: UpdateKind,
+
+    /// The new state of the repository.
+    ///
+    /// This needs to be given to the next update.
+    pub state: State,
+
+    /// The changes applied to the store.
+    pub changes: ChangeSet,
+}
+
+
+//------------ UpdateKind ----------------------------------------------------
+
+/// How an update was performed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum UpdateKind {
+    /// The repository has not changed since the last update.
+    Unchanged,
+
+    /// The given number of deltas have been applied.
+    Deltas(usize),
+
+    /// The snapshot has been used for the given reason.
+    Snapshot(SnapshotReason),
+}
+
+
+//------------ SnapshotReason ------------------------------------------------
+
+/// The reason for using the snapshot.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SnapshotReason {
+    /// There was no state from a previous update.
+    NoState,
+
+    /// The session ID has changed.
+    SessionChanged,
+
+    /// The serial number is smaller than that of the previous update.
+    SerialRegressed,
+
+    /// The deltas necessary for the update are not available.
+    MissingDeltas,
+
+    /// Applying the deltas failed with the given error.
+    DeltaFailed(String),
+}
+
+
+//------------ SnapshotTarget ------------------------------------------------
+
+/// Processes a snapshot into a transaction.
+struct SnapshotTarget<'a, 's, S: ?Sized> {
+    /// The transaction to add the objects to.
+    tx: &'a mut Transaction<'s, S>,
+
+    /// The expected session ID.
+    session_id: Uuid,
+
+    /// The expected serial number.
+    serial: u64,
+
+    /// The URIs of all objects in the snapshot.
+    objects: HashSet<uri::Rsync>,
+}
+
+impl<'a, 's, S> ProcessSnapshot for SnapshotTarget<'a, 's, S>
+where S: RepositoryStore + ?Sized {
+    type Err = FileError;
+
+    fn meta(
+        &mut self, session_id: Uuid, serial: u64
+    ) -> Result<(), Self::Err> {
+        if session_id != self.session_id || serial != self.serial {
+            return Err(FileError::Meta)
+        }
+        Ok(())
+    }
+
+    fn publish(
+        &mut self,
+        uri: uri::Rsync,
+        data: &mut ObjectReader,
+    ) -> Result<(), Self::Err> {
+        self.objects.insert(uri.clone());
+        ProcessSnapshot::publish(self.tx, uri, data).map_err(Into::into)
+    }
+}
+
+
+//------------ DeltaTarget ---------------------------------------------------
+
+/// Processes a delta into a transaction.
+struct DeltaTarget<'a, 's, S: ?Sized> {
+    /// The transaction to apply the changes to.
+    tx: &'a mut Transaction<'s, S>,
+
+    /// The expected session ID.
+    session_id: Uuid,
+
+    /// The expected serial number.
+    serial: u64,
+
+    /// The URIs of all objects published by the repository.
+    objects: &'a mut HashSet<uri::Rsync>,
+}
+
+impl<'a, 's, S> ProcessDelta for DeltaTarget<'a, 's, S>
+where S: RepositoryStore + ?Sized {
+    type Err = FileError;
+
+    fn meta(
+        &mut self, session_id: Uuid, serial: u64
+    ) -> Result<(), Self::Err> {
+        if session_id != self.session_id || serial != self.serial {
+            return Err(FileError::Meta)
+        }
+        Ok(())
+    }
+
+    fn publish(
+        &mut self,
+        uri: uri::Rsync,
+        hash: Option<Hash>,
+        data: &mut ObjectReader,
+    ) -> Result<(), Self::Err> {
+        self.objects.insert(uri.clone());
+        ProcessDelta::publish(self.tx, uri, hash, data).map_err(Into::into)
+    }
+
+    fn withdraw(
+        &mut self,
+        uri: uri::Rsync,
+        hash: Hash,
+    ) -> Result<(), Self::Err> {
+        self.objects.remove(&uri);
+        ProcessDelta::withdraw(self.tx, uri, hash).map_err(Into::into)
+    }
+}
+
+
+//------------ HashingReader -------------------------------------------------
+
+/// A reader that calculates the SHA-256 hash over all data read.
+struct HashingReader<R> {
+    /// The actual reader.
+    inner: R,
+
+    /// The digest context.
+    context: digest::Context,
+}
+
+impl<R> HashingReader<R> {
+    /// Creates a new hashing reader.
+    fn new(inner: R) -> Self {
+        HashingReader {
+            inner,
+            context: digest::Context::new(&digest::SHA256),
+        }
+    }
+
+    /// Returns the hash over all data read.
+    fn finish(self) -> Hash {
+        let mut res = [0u8; 32];
+        res.copy_from_slice(self.context.finish().as_ref());
+        res.into()
+    }
+}
+
+impl<R: io::Read> io::Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let res = self.inner.read(buf)?;
+        self.context.update(&buf[..res]);
+        Ok(res)
+    }
+}
+
+
+//------------ FileError -----------------------------------------------------
+
+/// An error happened while processing a snapshot or delta file.
+#[derive(Debug)]
+enum FileError {
+    /// Parsing the file failed.
+    Process(ProcessError),
+
+    /// The session ID or serial number of the file are not as expected.
+    Meta,
+
+    /// The store or the transaction rejected a change.
+    Store(StoreError),
+}
+
+impl FileError {
+    /// Converts the error into an update error for the given file.
+    fn into_update_error(self, uri: &uri::Https) -> UpdateError {
+        match self {
+            FileError::Process(err) => UpdateError::Process(uri.clone(), err),
+            FileError::Meta => UpdateError::BadMeta(uri.clone()),
+            FileError::Store(err) => UpdateError::Store(err),
+        }
+    }
+}
+
+impl From<ProcessError> for FileError {
+    fn from(err: ProcessError) -> Self {
+        FileError::Process(err)
+    }
+}
+
+impl From<StoreError> for FileError {
+    fn from(err: StoreError) -> Self {
+        match err {
+            StoreError::Rrdp(err) => FileError::Process(err),
+            err => FileError::Store(err),
+        }
+    }
+}
+
+
+//------------ UpdateError ---------------------------------------------------
+
+/// An error happened while updating a repository.
+#[derive(Debug)]
+pub enum UpdateError {
+    /// Fetching a file failed.
+    Fetch(uri::Https, io::Error),
+
+    /// A file could not be processed.
+    Process(uri::Https, ProcessError),
+
+    /// A file doesn’t match the hash given in the notification file.
+    HashMismatch(uri::Https),
+
+    /// A file has a different session ID or serial than announced.
+    BadMeta(uri::Https),
+
+    /// A file has a different origin than the notification file.
+    ForeignOrigin(uri::Https),
+
+    /// Accessing the store failed.
+    Store(StoreError),
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UpdateError::Fetch(ref uri, ref err) => {
+                write!(f, "failed to fetch {}: {}", uri, err)
+            }
+            UpdateError::Process(ref uri, ref err) => {
+                write!(f, "failed to process {}: {}", uri, err)
+            }
+            UpdateError::HashMismatch(ref uri) => {
+                write!(f, "hash mismatch for {}", uri)
+            }
+            UpdateError::BadMeta(ref uri) => {
+                write!(f, "unexpected session or serial in {}", uri)
+            }
+            UpdateError::ForeignOrigin(ref uri) => {
+                write!(f, "{} has a foreign origin", uri)
+            }
+            UpdateError::Store(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for UpdateError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use crate::store::{Change, MemoryStore};
+    use super::*;
+    use super::super::{
+        Delta, DeltaElement, DeltaInfo, PublishElement, Snapshot,
+        UpdateElement, UriAndHash, WithdrawElement,
+    };
+
+    fn https(s: &str) -> uri::Https {
+        uri::Https::from_str(s).unwrap()
+    }
+
+    fn rsync(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    /// A server side repository for testing.
+    struct Server {
+        fetcher: LocalFetcher,
+        session_id: Uuid,
+        serial: u64,
+        objects: HashMap<uri::Rsync, Bytes>,
+        deltas: Vec<DeltaInfo>,
+    }
+
+    impl Server {
+        fn new() -> Self {
+            let mut res = Server {
+                fetcher: LocalFetcher::new(),
+                session_id: Uuid::from_u128(1),
+                serial: 0,
+                objects: HashMap::new(),
+                deltas: Vec::new(),
+            };
+            res.publish_notification();
+            res
+        }
+
+        fn notify_uri() -> uri::Https {
+            https("https://example.com/notification.xml")
+        }
+
+        fn delta_uri(&self, serial: u64) -> uri::Https {
+            https(&format!(
+                "https://example.com/{}/{}/delta.xml",
+                self.session_id, serial
+            ))
+        }
+
+        /// Applies changes given as (URI, Some(data) or None).
+        fn change(&mut self, changes: &[(&str, Option<&'static [u8]>)]) {
+            self.serial += 1;
+            let elements = changes.iter().map(|&(uri, data)| {
+                let uri = rsync(uri);
+                let old = self.objects.get(&uri).map(|data| {
+                    Hash::from_data(data)
+                });
+                match (data, old) {
+                    (Some(data), None) => {
+                        let data = Bytes::from_static(data);
+                        self.objects.insert(uri.clone(), data.clone());
+                        PublishElement::new(uri, data).into()
+                    }
+                    (Some(data), Some(hash)) => {
+                        let data = Bytes::from_static(data);
+                        self.objects.insert(uri.clone(), data.clone());
+                        UpdateElement::new(uri, hash, data).into()
+                    }
+                    (None, Some(hash)) => {
+                        self.objects.remove(&uri);
+                        WithdrawElement::new(uri, hash).into()
+                    }
+                    (None, None) => panic!("withdrawing missing object")
+                }
+            }).collect::<Vec<DeltaElement>>();
+            let mut xml = Vec::new();
+            Delta::new(
+                self.session_id, self.serial, elements
+            ).write_xml(&mut xml).unwrap();
+            let uri = self.delta_uri(self.serial);
+            self.deltas.push(DeltaInfo::new(
+                self.serial, uri.clone(), Hash::from_data(&xml)
+            ));
+            self.fetcher.insert(uri, xml.into());
+            self.publish_notification();
+        }
+
+        fn reset_session(&mut self) {
+            self.session_id = Uuid::from_u128(
+                self.session_id.as_u128() + 1
+            );
+            self.deltas.clear();
+            self.publish_notification();
+        }
+
+        fn publish_notification(&mut self) {
+            let mut xml = Vec::new();
+            Snapshot::new(
+                self.session_id, self.serial,
+                self.objects.iter().map(|(uri, data)| {
+                    PublishElement::new(uri.clone(), data.clone())
+                }).collect()
+            ).write_xml(&mut xml).unwrap();
+            let snapshot_uri = https(&format!(
+                "https://example.com/{}/{}/snapshot.xml",
+                self.session_id, self.serial
+            ));
+            let snapshot_hash = Hash::from_data(&xml);
+            self.fetcher.insert(snapshot_uri.clone(), xml.into());
+
+            let mut xml = Vec::new();
+            NotificationFile::new(
+                self.session_id, self.serial,
+                UriAndHash::new(snapshot_uri, snapshot_hash),
+                self.deltas.clone()
+            ).write_xml(&mut xml).unwrap();
+            self.fetcher.insert(Self::notify_uri(), xml.into());
+        }
+    }
+
+    #[test]
+    fn update() {
+        let mut server = Server::new();
+        let store = MemoryStore::new();
+        let a = "rsync://example.com/repo/a.cer";
+        let b = "rsync://example.com/repo/b.cer";
+        server.change(&[(a, Some(b"a1")), (b, Some(b"b1"))]);
+
+        // Initial update uses the snapshot.
+        let update = Updater::new(&server.fetcher, &store).update(
+            &Server::notify_uri(), None
+        ).unwrap();
+        assert_eq!(
+            update.kind, UpdateKind::Snapshot(SnapshotReason::NoState)
+        );
+        assert_eq!(update.changes.len(), 2);
+        assert_eq!(update.state.serial(), 1);
+        let state = update.state;
+
+        // Nothing changed.
+        let update = Updater::new(&server.fetcher, &store).update(
+            &Server::notify_uri(), Some(&state)
+        ).unwrap();
+        assert_eq!(update.kind, UpdateKind::Unchanged);
+
+        // Two deltas.
+        server.change(&[(a, Some(b"a2"))]);
+        server.change(&[(b, None)]);
+        let update = Updater::new(&server.fetcher, &store).update(
+            &Server::notify_uri(), Some(&state)
+        ).unwrap();
+        assert_eq!(update.kind, UpdateKind::Deltas(2));
+        assert_eq!(update.changes.len(), 2);
+        assert_eq!(
+            update.changes.get(&rsync(b)), Some(&Change::Delete)
+        );
+        assert_eq!(store.get(&rsync(a)).unwrap().unwrap().as_ref(), b"a2");
+        assert_eq!(store.len(), 1);
+        let state = update.state;
+        assert_eq!(state.objects().len(), 1);
+
+        // New session: snapshot, removing objects no longer present.
+        server.change(&[(b, Some(b"b3"))]);
+        server.reset_session();
+        server.change(&[(a, None)]);
+        let update = Updater::new(&server.fetcher, &store).update(
+            &Server::notify_uri(), Some(&state)
+        ).unwrap();
+        assert_eq!(
+            update.kind, UpdateKind::Snapshot(SnapshotReason::SessionChanged)
+        );
+        assert_eq!(store.get(&rsync(a)).unwrap(), None);
+        assert_eq!(store.get(&rsync(b)).unwrap().unwrap().as_ref(), b"b3");
+    }
+
+    #[test]
+    fn fallback() {
+        let mut server = Server::new();
+        let store = MemoryStore::new();
+        let a = "rsync://example.com/repo/a.cer";
+        server.change(&[(a, Some(b"a1"))]);
+        let state = Updater::new(&server.fetcher, &store).update(
+            &Server::notify_uri(), None
+        ).unwrap().state;
+
+        // A delta with a broken hash falls back to the snapshot.
+        server.change(&[(a, Some(b"a2"))]);
+        let uri = server.delta_uri(2);
+        server.fetcher.insert(uri, Bytes::from_static(b"<foo/>"));
+        let update = Updater::new(&server.fetcher, &store).update(
+            &Server::notify_uri(), Some(&state)
+        ).unwrap();
+        assert!(matches!(
+            update.kind, UpdateKind::Snapshot(SnapshotReason::DeltaFailed(_))
+        ));
+        assert_eq!(store.get(&rsync(a)).unwrap().unwrap().as_ref(), b"a2");
+
+        // A gap in the deltas falls back to the snapshot.
+        server.change(&[(a, Some(b"a3"))]);
+        server.change(&[(a, Some(b"a4"))]);
+        server.deltas.remove(1);
+        server.publish_notification();
+        let update = Updater::new(&server.fetcher, &store).update(
+            &Server::notify_uri(), Some(&update.state)
+        ).unwrap();
+        assert_eq!(
+            update.kind, UpdateKind::Snapshot(SnapshotReason::MissingDeltas)
+        );
+        assert_eq!(store.get(&rsync(a)).unwrap().unwrap().as_ref(), b"a4");
+
+        // A broken snapshot is an error and leaves the store alone.
+        server.change(&[(a, Some(b"a5"))]);
+        let snapshot = server.fetcher.files.keys().find(|uri| {
+            uri.as_str().ends_with("/5/snapshot.xml")
+        }).unwrap().clone();
+        server.fetcher.insert(snapshot, Bytes::from_static(b"<foo/>"));
+        assert!(
+            Updater::new(&server.fetcher, &store).update(
+                &Server::notify_uri(), None
+            ).is_err()
+        );
+        assert_eq!(store.get(&rsync(a)).unwrap().unwrap().as_ref(), b"a4");
+    }
+
+    #[test]
+    fn origin() {
+        let mut server = Server::new();
+        let store = MemoryStore::new();
+        server.change(&[("rsync://example.com/repo/a.cer", Some(b"a1"))]);
+        let notify = server.fetcher.files.get(
+            &Server::notify_uri()
+        ).unwrap().clone();
+        let foreign_uri = https("https://example.net/notification.xml");
+        server.fetcher.insert(foreign_uri.clone(), notify);
+
+        let mut updater = Updater::new(&server.fetcher, &store);
+        updater.set_strict_origin(true);
+        updater.update(&Server::notify_uri(), None).unwrap();
+        assert!(matches!(
+            updater.update(&foreign_uri, None),
+            Err(UpdateError::ForeignOrigin(_))
+        ));
+        updater.set_strict_origin(false);
+        updater.update(&foreign_uri, None).unwrap();
+    }
+}
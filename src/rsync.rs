@@ -0,0 +1,1392 @@
+//! A native rsync client.
+//!
+//! This module implements the client side of the rsync protocol as used
+//! when talking to an rsync daemon, i.e., for `rsync://` URIs. It can
+//! list the modules provided by a server via [`Client::list_modules`] and
+//! fetch a single file or a directory tree via [`Client::fetch`]. The
+//! latter returns a [`Transfer`] that can be applied to a
+//! [`RepositoryStore`], making the store’s content below the fetched URI
+//! identical to that of the server. [`Client::update`] combines both
+//! steps.
+//!
+//! The client speaks protocol version 27 which is supported by all
+//! current rsync implementations. Files are always transferred in full and
+//! only regular files are considered – directories are only used for
+//! recursion and everything else is ignored. The whole-file checksums
+//! provided by the server are not checked since RPKI objects are verified
+//! via the hashes given on manifests anyway.
+//!
+//! Since the content of a remote repository is not to be trusted, the
+//! amount of data accepted from a server can be restricted via
+//! [`Limits`]. File names given by the server are checked and a transfer
+//! fails if they would lead outside of the fetched directory.
+//!
+//! This module is only available with the `"rsync"` feature.
+
+#![cfg(feature = "rsync")]
+
+use std::{cmp, error, fmt, io, str};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::future::Future;
+use bytes::Bytes;
+use futures_util::future::join;
+use log::{info, warn};
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite,
+    AsyncWriteExt, BufReader, BufWriter,
+};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
+use crate::uri;
+use crate::store::{ChangeSet, RepositoryStore, StoreError, Transaction};
+
+
+//------------ Configuration Constants ---------------------------------------
+
+/// The default port of an rsync daemon.
+const DEFAULT_PORT: u16 = 873;
+
+/// The protocol version we speak.
+const PROTOCOL_VERSION: u32 = 27;
+
+/// The maximum length of a line during the daemon handshake.
+const MAX_LINE_LEN: usize = 4096;
+
+/// The maximum length of a file name.
+const MAX_NAME_LEN: usize = 4096;
+
+
+//------------ Protocol Constants --------------------------------------------
+
+/// The prefix of all daemon protocol lines.
+const RSYNCD: &str = "@RSYNCD: ";
+
+/// The value added to the tag of a multiplexed message.
+const MPLEX_BASE: u32 = 7;
+
+/// The multiplexed message tags that carry error messages.
+///
+/// These are MSG_ERROR_XFER, MSG_ERROR, MSG_ERROR_SOCKET, and
+/// MSG_ERROR_UTF8.
+const MSG_ERRORS: [u32; 4] = [1, 3, 5, 8];
+
+/// The multiplexed message tags that carry informational messages.
+///
+/// These are MSG_INFO, MSG_WARNING, and MSG_LOG.
+const MSG_INFOS: [u32; 3] = [2, 4, 6];
+
+/// The index marking the end of a phase.
+const NDX_DONE: i32 = -1;
+
+/// File list flag: the name shares a prefix with the previous name.
+const XMIT_SAME_NAME: u8 = 0x20;
+
+/// File list flag: the name length is encoded as a 32 bit integer.
+const XMIT_LONG_NAME: u8 = 0x40;
+
+/// File list flag: the modification time is that of the previous entry.
+const XMIT_SAME_TIME: u8 = 0x80;
+
+/// File list flag: the mode is that of the previous entry.
+const XMIT_SAME_MODE: u8 = 0x02;
+
+/// The bit mask for the file type in a mode.
+const S_IFMT: u32 = 0o170000;
+
+/// The file type of a regular file.
+const S_IFREG: u32 = 0o100000;
+
+/// The file type of a directory.
+const S_IFDIR: u32 = 0o040000;
+
+
+//------------ Limits --------------------------------------------------------
+
+/// Limits for the data accepted from an rsync server.
+///
+/// By default, no limits are imposed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Limits {
+    /// The maximum size of a single file.
+    max_file_size: Option<u64>,
+
+    /// The maximum size of all files of a transfer.
+    max_total_size: Option<u64>,
+
+    /// The maximum number of entries in the file list of a transfer.
+    max_files: Option<usize>,
+}
+
+impl Limits {
+    /// Returns the maximum size of a single file.
+    pub fn max_file_size(&self) -> Option<u64> {
+        self.max_file_size
+    }
+
+    /// Sets the maximum size of a single file.
+    pub fn set_max_file_size(&mut self, value: Option<u64>) {
+        self.max_file_size = value
+    }
+
+    /// Returns the maximum size of all files of a transfer.
+    pub fn max_total_size(&self) -> Option<u64> {
+        self.max_total_size
+    }
+
+    /// Sets the maximum size of all files of a transfer.
+    pub fn set_max_total_size(&mut self, value: Option<u64>) {
+        self.max_total_size = value
+    }
+
+    /// Returns the maximum number of files of a transfer.
+    ///
+    /// This includes directories.
+    pub fn max_files(&self) -> Option<usize> {
+        self.max_files
+    }
+
+    /// Sets the maximum number of files of a transfer.
+    pub fn set_max_files(&mut self, value: Option<usize>) {
+        self.max_files = value
+    }
+}
+
+
+//------------ Client --------------------------------------------------------
+
+/// An rsync client.
+#[derive(Clone, Debug, Default)]
+pub struct Client {
+    /// The limits for data accepted from the server.
+    limits: Limits,
+
+    /// The maximum time an operation may take.
+    timeout: Option<Duration>,
+}
+
+impl Client {
+    /// Creates a new client without limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new client using the given limits.
+    pub fn with_limits(limits: Limits) -> Self {
+        Client { limits, timeout: None }
+    }
+
+    /// Returns the limits of the client.
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Sets the maximum time an operation may take.
+    ///
+    /// If an operation takes longer, it fails with [`RsyncError::Timeout`].
+    /// By default, there is no timeout.
+    pub fn set_timeout(&mut self, value: Option<Duration>) {
+        self.timeout = value
+    }
+
+    /// Lists the modules provided by an rsync server.
+    ///
+    /// The server is identified by the authority part of an rsync URI,
+    /// i.e., a host name or address optionally followed by a colon and a
+    /// port number.
+    pub async fn list_modules(
+        &self, authority: &str
+    ) -> Result<Vec<ModuleInfo>, RsyncError> {
+        self.timed(async {
+            let sock = TcpStream::connect(socket_addr(authority)).await?;
+            list_modules(sock).await
+        }).await
+    }
+
+    /// Lists the modules provided by the server at the other end of a
+    /// stream.
+    pub async fn list_modules_from<T: AsyncRead + AsyncWrite + Unpin>(
+        &self, stream: T
+    ) -> Result<Vec<ModuleInfo>, RsyncError> {
+        self.timed(list_modules(stream)).await
+    }
+
+    /// Fetches the file or directory tree at the given URI.
+    ///
+    /// If the URI’s path is empty or ends in a slash, the directory is
+    /// fetched recursively. Otherwise only the single file is fetched.
+    pub async fn fetch(
+        &self, uri: &uri::Rsync
+    ) -> Result<Transfer, RsyncError> {
+        self.timed(async {
+            let sock = TcpStream::connect(
+                socket_addr(uri.authority())
+            ).await?;
+            self.transfer(sock, uri).await
+        }).await
+    }
+
+    /// Fetches the file or directory tree at the given URI from the server
+    /// at the other end of a stream.
+    pub async fn fetch_from<T: AsyncRead + AsyncWrite + Unpin>(
+        &self, stream: T, uri: &uri::Rsync
+    ) -> Result<Transfer, RsyncError> {
+        self.timed(self.transfer(stream, uri)).await
+    }
+
+    /// Fetches the given URI and applies the result to a store.
+    ///
+    /// Returns the changes applied to the store. See
+    /// [`Transfer::apply_to`] for details.
+    pub async fn update<S: RepositoryStore + ?Sized>(
+        &self, uri: &uri::Rsync, store: &S
+    ) -> Result<ChangeSet, RsyncError> {
+        self.fetch(uri).await?.apply_to(store).map_err(RsyncError::Store)
+    }
+
+    /// Runs a future, enforcing the timeout if there is one.
+    async fn timed<T>(
+        &self, fut: impl Future<Output = Result<T, RsyncError>>
+    ) -> Result<T, RsyncError> {
+        match self.timeout {
+            Some(value) => {
+                timeout(value, fut).await.map_err(|_| RsyncError::Timeout)?
+            }
+            None => fut.await
+        }
+    }
+
+    /// Performs a transfer.
+    async fn transfer<T: AsyncRead + AsyncWrite + Unpin>(
+        &self, stream: T, uri: &uri::Rsync
+    ) -> Result<Transfer, RsyncError> {
+        let is_dir = uri.path().is_empty() || uri.path().ends_with('/');
+        let base = if is_dir {
+            uri.clone()
+        }
+        else {
+            uri.parent().unwrap_or_else(|| uri.clone())
+        };
+
+        let mut stream = BufReader::new(stream);
+        handshake(&mut stream, uri.module_name()).await?;
+        let mut args = Vec::new();
+        for arg in &[
+            "--server", "--sender", "-r", ".",
+            &format!("{}/{}", uri.module_name(), uri.path())
+        ] {
+            args.extend_from_slice(arg.as_bytes());
+            args.push(b'\n');
+        }
+        args.push(b'\n');
+        stream.write_all(&args).await?;
+        stream.flush().await?;
+
+        // The checksum seed is sent before multiplexing starts. If the
+        // server doesn’t like our arguments, we get an error line instead.
+        let mut seed = [0u8; 4];
+        stream.read_exact(&mut seed).await?;
+        if &seed == b"@ERR" {
+            let line = read_line(&mut stream).await?;
+            return Err(RsyncError::Server(format!("@ERR{}", line)))
+        }
+
+        let (read, write) = tokio::io::split(stream);
+        let mut input = Input::new(read);
+        let mut output = BufWriter::new(write);
+
+        // Empty filter list.
+        output.write_i32_le(0).await?;
+        output.flush().await?;
+
+        let mut entries = self.recv_file_list(&mut input).await?;
+        if input.read_int().await? != 0 {
+            return Err(RsyncError::Incomplete)
+        }
+        sort_file_list(&mut entries);
+        let mut requests = Vec::new();
+        let mut total = 0u64;
+        for (ndx, entry) in entries.iter_mut().enumerate() {
+            if !entry.active || entry.mode & S_IFMT != S_IFREG {
+                continue
+            }
+            if let Some(max) = self.limits.max_file_size {
+                if entry.len > max {
+                    return Err(RsyncError::FileTooLarge(entry.display()))
+                }
+            }
+            total = total.saturating_add(entry.len);
+            if let Some(max) = self.limits.max_total_size {
+                if total > max {
+                    return Err(RsyncError::TooLarge)
+                }
+            }
+            entry.uri = Some(base.join(&entry.name).map_err(|_| {
+                RsyncError::BadName(entry.display())
+            })?);
+            requests.push(ndx as i32);
+        }
+
+        let (sent, files) = join(
+            send_requests(&mut output, &requests),
+            self.recv_files(&mut input, &entries, requests.len()),
+        ).await;
+        sent?;
+        let files = files?;
+
+        output.write_i32_le(NDX_DONE).await?;
+        output.flush().await?;
+        if input.read_int().await? != NDX_DONE {
+            return Err(RsyncError::Protocol("expected end of phase"))
+        }
+        // Statistics: total read, total written, total size.
+        for _ in 0..3 {
+            input.read_longint().await?;
+        }
+        // Final goodbye.
+        output.write_i32_le(NDX_DONE).await?;
+        output.flush().await?;
+
+        Ok(Transfer { base, is_dir, files })
+    }
+
+    /// Receives the file list.
+    async fn recv_file_list<R: AsyncRead + Unpin>(
+        &self, input: &mut Input<R>
+    ) -> Result<Vec<Entry>, RsyncError> {
+        let mut res = Vec::new();
+        let mut name = Vec::new();
+        let mut mode = 0;
+        loop {
+            let flags = input.read_byte().await?;
+            if flags == 0 {
+                break
+            }
+            if let Some(max) = self.limits.max_files {
+                if res.len() >= max {
+                    return Err(RsyncError::TooManyFiles)
+                }
+            }
+            let prefix_len = if flags & XMIT_SAME_NAME != 0 {
+                usize::from(input.read_byte().await?)
+            }
+            else {
+                0
+            };
+            let suffix_len = if flags & XMIT_LONG_NAME != 0 {
+                usize::try_from(input.read_int().await?).map_err(|_| {
+                    RsyncError::Protocol("negative name length")
+                })?
+            }
+            else {
+                usize::from(input.read_byte().await?)
+            };
+            if prefix_len > name.len()
+                || prefix_len + suffix_len > MAX_NAME_LEN
+            {
+                return Err(RsyncError::Protocol("illegal name length"))
+            }
+            name.truncate(prefix_len);
+            name.resize(prefix_len + suffix_len, 0);
+            input.read_exact(&mut name[prefix_len..]).await?;
+            let len = input.read_longint().await?;
+            let len = u64::try_from(len).map_err(|_| {
+                RsyncError::Protocol("negative file length")
+            })?;
+            if flags & XMIT_SAME_TIME == 0 {
+                input.read_int().await?;
+            }
+            if flags & XMIT_SAME_MODE == 0 {
+                mode = input.read_int().await? as u32;
+            }
+            res.push(Entry {
+                name: name.clone(), len, mode, active: true, uri: None
+            });
+        }
+        Ok(res)
+    }
+
+    /// Receives the requested files.
+    async fn recv_files<R: AsyncRead + Unpin>(
+        &self,
+        input: &mut Input<R>,
+        entries: &[Entry],
+        count: usize,
+    ) -> Result<Vec<(uri::Rsync, Bytes)>, RsyncError> {
+        let mut res = Vec::with_capacity(count);
+        let mut last = None;
+        let mut total = 0u64;
+        loop {
+            let ndx = input.read_int().await?;
+            if ndx == NDX_DONE {
+                break
+            }
+            let entry = match usize::try_from(ndx).ok().and_then(|ndx| {
+                entries.get(ndx)
+            }) {
+                Some(entry) => entry,
+                None => return Err(RsyncError::Protocol("illegal index"))
+            };
+            let uri = match (entry.uri.as_ref(), last) {
+                (Some(uri), Some(last)) if ndx > last => uri,
+                (Some(uri), None) => uri,
+                _ => return Err(RsyncError::Protocol("unexpected index"))
+            };
+            last = Some(ndx);
+
+            // Checksum header: count, block length, checksum length,
+            // remainder. We always ask for the whole file.
+            for _ in 0..4 {
+                input.read_int().await?;
+            }
+            let mut data = Vec::new();
+            loop {
+                let len = input.read_int().await?;
+                if len == 0 {
+                    break
+                }
+                if len < 0 {
+                    return Err(RsyncError::Protocol("unexpected block match"))
+                }
+                let len = len as usize;
+                total = total.saturating_add(len as u64);
+                if let Some(max) = self.limits.max_total_size {
+                    if total > max {
+                        return Err(RsyncError::TooLarge)
+                    }
+                }
+                let start = data.len();
+                if let Some(max) = self.limits.max_file_size {
+                    if (start + len) as u64 > max {
+                        return Err(RsyncError::FileTooLarge(entry.display()))
+                    }
+                }
+                data.resize(start + len, 0);
+                input.read_exact(&mut data[start..]).await?;
+            }
+            // The whole-file checksum.
+            let mut sum = [0u8; 16];
+            input.read_exact(&mut sum).await?;
+            res.push((uri.clone(), data.into()));
+        }
+        if res.len() != count {
+            return Err(RsyncError::Incomplete)
+        }
+        Ok(res)
+    }
+}
+
+
+//------------ ModuleInfo ----------------------------------------------------
+
+/// Information about a module provided by an rsync server.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ModuleInfo {
+    /// The name of the module.
+    name: String,
+
+    /// The comment provided for the module.
+    comment: String,
+}
+
+impl ModuleInfo {
+    /// Returns the name of the module.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the comment for the module.
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+}
+
+
+//------------ Transfer ------------------------------------------------------
+
+/// The result of fetching from an rsync server.
+#[derive(Clone, Debug)]
+pub struct Transfer {
+    /// The directory the files are relative to.
+    base: uri::Rsync,
+
+    /// Whether a directory tree was fetched.
+    is_dir: bool,
+
+    /// The files.
+    files: Vec<(uri::Rsync, Bytes)>,
+}
+
+impl Transfer {
+    /// Returns the URI of the directory containing the fetched files.
+    pub fn base(&self) -> &uri::Rsync {
+        &self.base
+    }
+
+    /// Returns whether a whole directory tree was fetched.
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// Returns the fetched files.
+    pub fn files(&self) -> &[(uri::Rsync, Bytes)] {
+        &self.files
+    }
+
+    /// Converts the transfer into the fetched files.
+    pub fn into_files(self) -> Vec<(uri::Rsync, Bytes)> {
+        self.files
+    }
+
+    /// Applies the transfer to a store.
+    ///
+    /// All fetched files are added to the store unless they are already
+    /// present with the same content. If a directory tree was fetched, all
+    /// objects in the store below the directory that were not fetched are
+    /// removed. All changes are applied at once and returned.
+    pub fn apply_to<S: RepositoryStore + ?Sized>(
+        &self, store: &S
+    ) -> Result<ChangeSet, StoreError> {
+        let mut tx = Transaction::new(store);
+        let mut seen = HashSet::new();
+        for (uri, data) in &self.files {
+            if tx.get(uri)?.as_ref() != Some(data) {
+                tx.put(uri.clone(), data.clone());
+            }
+            seen.insert(uri);
+        }
+        if self.is_dir {
+            for uri in store.list_tree(&self.base)? {
+                if !seen.contains(&uri) {
+                    tx.delete(uri)
+                }
+            }
+        }
+        tx.commit()
+    }
+}
+
+
+//------------ Entry ---------------------------------------------------------
+
+/// An entry of the file list.
+#[derive(Clone, Debug)]
+struct Entry {
+    /// The name of the file relative to the transfer’s base.
+    name: Vec<u8>,
+
+    /// The size of the file.
+    len: u64,
+
+    /// The mode of the file.
+    mode: u32,
+
+    /// Whether the entry is used.
+    ///
+    /// This is `false` for duplicates dropped by [`sort_file_list`].
+    active: bool,
+
+    /// The URI of the file if it is a regular file.
+    uri: Option<uri::Rsync>,
+}
+
+impl Entry {
+    /// Returns whether the entry is a directory.
+    fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    /// Returns a printable version of the name.
+    fn display(&self) -> String {
+        String::from_utf8_lossy(&self.name).into_owned()
+    }
+}
+
+
+//------------ Input ---------------------------------------------------------
+
+/// The multiplexed input from the server.
+///
+/// Once the transfer has started, the server sends all data wrapped in
+/// messages. Data is in messages with tag MSG_DATA while other tags carry
+/// error and informational messages that are logged.
+struct Input<R> {
+    /// The underlying reader.
+    reader: R,
+
+    /// The number of octets left of the current data message.
+    remaining: usize,
+}
+
+impl<R: AsyncRead + Unpin> Input<R> {
+    /// Creates a new value from a reader.
+    fn new(reader: R) -> Self {
+        Input { reader, remaining: 0 }
+    }
+
+    /// Reads data to fill the entire buffer.
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), RsyncError> {
+        let mut pos = 0;
+        while pos < buf.len() {
+            if self.remaining == 0 {
+                self.next_message().await?;
+                continue
+            }
+            let len = cmp::min(self.remaining, buf.len() - pos);
+            self.reader.read_exact(&mut buf[pos..pos + len]).await?;
+            pos += len;
+            self.remaining -= len;
+        }
+        Ok(())
+    }
+
+    /// Reads a single octet.
+    async fn read_byte(&mut self) -> Result<u8, RsyncError> {
+        let mut buf = [0u8; 1];
+        self.read_exact(&mut buf).await?;
+        Ok(buf[0])
+    }
+
+    /// Reads a 32 bit integer.
+    async fn read_int(&mut self) -> Result<i32, RsyncError> {
+        let mut buf = [0u8; 4];
+        self.read_exact(&mut buf).await?;
+        Ok(i32::from_le_bytes(buf))
+    }
+
+    /// Reads a 64 bit integer encoded in the pre-30 protocol format.
+    ///
+    /// Values that fit into 31 bits are encoded as a 32 bit integer.
+    /// Larger values are preceded by a 32 bit integer of -1.
+    async fn read_longint(&mut self) -> Result<i64, RsyncError> {
+        let value = self.read_int().await?;
+        if value != -1 {
+            return Ok(value.into())
+        }
+        let mut buf = [0u8; 8];
+        self.read_exact(&mut buf).await?;
+        Ok(i64::from_le_bytes(buf))
+    }
+
+    /// Proceeds to the next data message.
+    async fn next_message(&mut self) -> Result<(), RsyncError> {
+        loop {
+            let header = self.reader.read_u32_le().await?;
+            let tag = match (header >> 24).checked_sub(MPLEX_BASE) {
+                Some(tag) => tag,
+                None => {
+                    return Err(RsyncError::Protocol("bad message header"))
+                }
+            };
+            let len = (header & 0x00FF_FFFF) as usize;
+            if tag == 0 {
+                self.remaining = len;
+                return Ok(())
+            }
+            let mut msg = vec![0u8; len];
+            self.reader.read_exact(&mut msg).await?;
+            let msg = String::from_utf8_lossy(&msg);
+            if MSG_ERRORS.contains(&tag) {
+                warn!("rsync server: {}", msg.trim_end());
+            }
+            else if MSG_INFOS.contains(&tag) {
+                info!("rsync server: {}", msg.trim_end());
+            }
+        }
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns the socket address for an authority.
+///
+/// Adds the default port if the authority doesn’t have one.
+fn socket_addr(authority: &str) -> String {
+    let has_port = match authority.rfind(':') {
+        Some(idx) => {
+            let port = &authority[idx + 1..];
+            !port.is_empty() && port.bytes().all(|ch| ch.is_ascii_digit())
+        }
+        None => false
+    };
+    if has_port {
+        authority.into()
+    }
+    else {
+        format!("{}:{}", authority, DEFAULT_PORT)
+    }
+}
+
+/// Reads a line during the daemon handshake.
+///
+/// Returns the line without the line feed and any carriage return.
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R
+) -> Result<String, RsyncError> {
+    let mut res = Vec::new();
+    loop {
+        let buf = reader.fill_buf().await?;
+        if buf.is_empty() {
+            return Err(
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof, "unexpected end of data"
+                ).into()
+            )
+        }
+        let (len, done) = match buf.iter().position(|&ch| ch == b'\n') {
+            Some(pos) => (pos + 1, true),
+            None => (buf.len(), false),
+        };
+        res.extend_from_slice(&buf[..len]);
+        reader.consume(len);
+        if res.len() > MAX_LINE_LEN {
+            return Err(RsyncError::Protocol("line too long"))
+        }
+        if done {
+            break
+        }
+    }
+    while let Some(b'\n') | Some(b'\r') = res.last() {
+        res.pop();
+    }
+    Ok(String::from_utf8_lossy(&res).into_owned())
+}
+
+/// Exchanges greetings with the server and selects a module.
+///
+/// If `module` is empty, the server will list its modules next. Otherwise
+/// returns once the server has accepted the module.
+async fn handshake<T: AsyncBufRead + AsyncWrite + Unpin>(
+    stream: &mut T, module: &str
+) -> Result<(), RsyncError> {
+    stream.write_all(
+        format!("{}{}.0\n{}\n", RSYNCD, PROTOCOL_VERSION, module).as_bytes()
+    ).await?;
+    stream.flush().await?;
+
+    let greeting = read_line(stream).await?;
+    if let Some(err) = server_error(&greeting) {
+        return Err(err)
+    }
+    let version = greeting.strip_prefix(RSYNCD).and_then(|version| {
+        version.split(|ch: char| !ch.is_ascii_digit()).next()
+    }).and_then(|version| version.parse::<u32>().ok()).ok_or(
+        RsyncError::Protocol("bad greeting")
+    )?;
+    if version < PROTOCOL_VERSION {
+        return Err(RsyncError::Protocol("unsupported protocol version"))
+    }
+    if module.is_empty() {
+        return Ok(())
+    }
+
+    loop {
+        let line = read_line(stream).await?;
+        if let Some(err) = server_error(&line) {
+            return Err(err)
+        }
+        match line.strip_prefix(RSYNCD) {
+            Some("OK") => return Ok(()),
+            Some("EXIT") => {
+                return Err(RsyncError::Protocol("unexpected exit"))
+            }
+            Some(other) if other.starts_with("AUTHREQD") => {
+                return Err(RsyncError::AuthRequired)
+            }
+            _ => {
+                // Message of the day.
+                info!("rsync server: {}", line);
+            }
+        }
+    }
+}
+
+/// Returns the server error contained in a line if there is one.
+fn server_error(line: &str) -> Option<RsyncError> {
+    if line.starts_with("@ERROR") {
+        Some(RsyncError::Server(line.into()))
+    }
+    else {
+        None
+    }
+}
+
+/// Lists the modules of the server at the other end of the stream.
+async fn list_modules<T: AsyncRead + AsyncWrite + Unpin>(
+    stream: T
+) -> Result<Vec<ModuleInfo>, RsyncError> {
+    let mut stream = BufReader::new(stream);
+    handshake(&mut stream, "").await?;
+    let mut res = Vec::new();
+    loop {
+        let line = read_line(&mut stream).await?;
+        if let Some(err) = server_error(&line) {
+            return Err(err)
+        }
+        if line.strip_prefix(RSYNCD) == Some("EXIT") {
+            break
+        }
+        // Module lines have the name, padded with spaces, and the comment
+        // separated by a tab. Everything else is a message of the day.
+        if let Some(idx) = line.find('\t') {
+            res.push(ModuleInfo {
+                name: line[..idx].trim_end().into(),
+                comment: line[idx + 1..].into(),
+            })
+        }
+    }
+    Ok(res)
+}
+
+/// Sorts a received file list and drops duplicate entries.
+///
+/// Files are requested by their index in the sorted list, so the list
+/// needs to be sorted exactly like the server sorts it with rsync’s
+/// `f_name_cmp`. For protocol versions before 29 – and we never speak a
+/// later one – this compares the complete path names byte by byte. Only
+/// later versions sort directories as if their name had a trailing slash.
+/// The sort is stable, so entries with the same name stay in the order
+/// they were received in.
+///
+/// As in rsync’s `clean_flist`, only one of the entries with the same
+/// name is kept: the first one unless a later one is a directory and the
+/// first one is not. The others are marked as inactive rather than
+/// removed since removing them would change the indexes of the entries
+/// that follow.
+fn sort_file_list(entries: &mut [Entry]) {
+    entries.sort_by(|left, right| left.name.cmp(&right.name));
+    let mut keep = 0;
+    for idx in 1..entries.len() {
+        if entries[idx].name != entries[keep].name {
+            keep = idx;
+        }
+        else if entries[idx].is_dir() && !entries[keep].is_dir() {
+            entries[keep].active = false;
+            keep = idx;
+        }
+        else {
+            entries[idx].active = false;
+        }
+    }
+}
+
+/// Sends the requests for all files followed by the end of the phase.
+async fn send_requests<W: AsyncWrite + Unpin>(
+    output: &mut W, requests: &[i32]
+) -> Result<(), RsyncError> {
+    for &ndx in requests {
+        output.write_i32_le(ndx).await?;
+        // Empty checksum header: count, block length, checksum length,
+        // remainder.
+        output.write_all(&[0u8; 16]).await?;
+    }
+    output.write_i32_le(NDX_DONE).await?;
+    output.flush().await?;
+    Ok(())
+}
+
+
+//------------ RsyncError ----------------------------------------------------
+
+/// An error happened while talking to an rsync server.
+#[derive(Debug)]
+pub enum RsyncError {
+    /// An IO error happened.
+    Io(io::Error),
+
+    /// The server reported an error.
+    Server(String),
+
+    /// The server requires authentication.
+    AuthRequired,
+
+    /// The server violated the protocol.
+    Protocol(&'static str),
+
+    /// The server reported that it couldn’t provide all files.
+    Incomplete,
+
+    /// The server provided a file with an illegal name.
+    BadName(String),
+
+    /// A file is larger than allowed.
+    FileTooLarge(String),
+
+    /// The transfer is larger than allowed.
+    TooLarge,
+
+    /// The transfer contains more files than allowed.
+    TooManyFiles,
+
+    /// The operation took too long.
+    Timeout,
+
+    /// Accessing the store failed.
+    Store(StoreError),
+}
+
+impl From<io::Error> for RsyncError {
+    fn from(err: io::Error) -> Self {
+        RsyncError::Io(err)
+    }
+}
+
+impl fmt::Display for RsyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RsyncError::Io(ref err) => err.fmt(f),
+            RsyncError::Server(ref msg) => {
+                write!(f, "server error: {}", msg)
+            }
+            RsyncError::AuthRequired => {
+                f.write_str("server requires authentication")
+            }
+            RsyncError::Protocol(msg) => {
+                write!(f, "protocol error: {}", msg)
+            }
+            RsyncError::Incomplete => {
+                f.write_str("server failed to provide all files")
+            }
+            RsyncError::BadName(ref name) => {
+                write!(f, "illegal file name '{}'", name)
+            }
+            RsyncError::FileTooLarge(ref name) => {
+                write!(f, "file '{}' is too large", name)
+            }
+            RsyncError::TooLarge => f.write_str("transfer too large"),
+            RsyncError::TooManyFiles => f.write_str("too many files"),
+            RsyncError::Timeout => f.write_str("operation timed out"),
+            RsyncError::Store(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for RsyncError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+    use tokio::io::DuplexStream;
+    use crate::store::{Change, MemoryStore};
+
+    /// Writes multiplexed messages.
+    struct Mux<'a>(&'a mut BufReader<DuplexStream>);
+
+    impl<'a> Mux<'a> {
+        async fn msg(
+            &mut self, tag: u32, data: &[u8]
+        ) -> Result<(), RsyncError> {
+            let header = ((tag + MPLEX_BASE) << 24) | data.len() as u32;
+            self.0.write_all(&header.to_le_bytes()).await?;
+            self.0.write_all(data).await?;
+            Ok(())
+        }
+
+        async fn data(&mut self, data: &[u8]) -> Result<(), RsyncError> {
+            self.msg(0, data).await
+        }
+
+        async fn int(&mut self, value: i32) -> Result<(), RsyncError> {
+            self.data(&value.to_le_bytes()).await
+        }
+    }
+
+    /// A minimal rsync daemon serving a module “repo” from memory.
+    ///
+    /// Directories are derived from the file names. Names are used as is
+    /// so they can be used to produce broken file lists. Like a real
+    /// server, the file list is sent in the order the files are given
+    /// and duplicate names are sent as is.
+    ///
+    /// Returns an error if the client hangs up early.
+    async fn serve(
+        stream: DuplexStream, files: &[(&str, &[u8])]
+    ) -> Result<(), RsyncError> {
+        let mut stream = BufReader::new(stream);
+        stream.write_all(b"@RSYNCD: 31.0 sha512 sha256 md5\n").await?;
+        let greeting = read_line(&mut stream).await?;
+        assert!(greeting.starts_with("@RSYNCD: 27."));
+        let module = read_line(&mut stream).await?;
+        if module.is_empty() {
+            stream.write_all(
+                b"Welcome!\nrepo           \tTest repository\n\
+                  other\t\n@RSYNCD: EXIT\n"
+            ).await?;
+            return Ok(())
+        }
+        if module != "repo" {
+            stream.write_all(
+                format!("@ERROR: Unknown module '{}'\n", module).as_bytes()
+            ).await?;
+            return Ok(())
+        }
+        stream.write_all(b"Welcome!\r\n@RSYNCD: OK\n").await?;
+        let mut args = Vec::new();
+        loop {
+            let arg = read_line(&mut stream).await?;
+            if arg.is_empty() {
+                break
+            }
+            args.push(arg);
+        }
+        assert_eq!(&args[..4], &["--server", "--sender", "-r", "."]);
+        let path = args[4].strip_prefix("repo/").unwrap();
+        stream.write_all(b"seed").await?;
+        assert_eq!(stream.read_i32_le().await?, 0);
+
+        // Build the file list: (name, mode, content).
+        let mut entries = Vec::<(String, u32, &[u8])>::new();
+        if path.is_empty() || path.ends_with('/') {
+            entries.push((".".into(), 0o40755, b""));
+            for (name, data) in files {
+                let name = match name.strip_prefix(path) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let mut dir = name;
+                while let Some(idx) = dir.rfind('/') {
+                    dir = &dir[..idx];
+                    if !entries.iter().any(|item| item.0 == dir) {
+                        entries.push((dir.into(), 0o40755, b""));
+                    }
+                }
+                entries.push((name.into(), 0o100644, data));
+            }
+        }
+        else if let Some((_, data)) = files.iter().find(|x| x.0 == path) {
+            let name = path.rsplit('/').next().unwrap();
+            entries.push((name.into(), 0o100644, data));
+        }
+
+        // Send it unsorted with name compression.
+        let mut mux = Mux(&mut stream);
+        mux.msg(2, b"building file list\n").await?;
+        let mut last = "";
+        let mut last_mode = 0;
+        for (ndx, (name, mode, data)) in entries.iter().enumerate() {
+            let prefix = cmp::min(
+                last.bytes().zip(name.bytes()).take_while(|(l, r)| {
+                    l == r
+                }).count(),
+                255
+            );
+            let suffix = &name.as_bytes()[prefix..];
+            let mut flags = 0;
+            let mut buf = Vec::new();
+            if prefix > 0 {
+                flags |= XMIT_SAME_NAME;
+                buf.push(prefix as u8);
+            }
+            if suffix.len() > 255 {
+                flags |= XMIT_LONG_NAME;
+                buf.extend_from_slice(&(suffix.len() as i32).to_le_bytes());
+            }
+            else {
+                buf.push(suffix.len() as u8);
+            }
+            buf.extend_from_slice(suffix);
+            buf.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            if ndx > 0 {
+                flags |= XMIT_SAME_TIME;
+            }
+            else {
+                buf.extend_from_slice(&1_600_000_000i32.to_le_bytes());
+            }
+            if *mode == last_mode {
+                flags |= XMIT_SAME_MODE;
+            }
+            else {
+                buf.extend_from_slice(&mode.to_le_bytes());
+            }
+            if flags == 0 {
+                flags = 0x01;
+            }
+            mux.data(&[flags]).await?;
+            mux.data(&buf).await?;
+            last = name;
+            last_mode = *mode;
+        }
+        mux.data(&[0]).await?;
+        mux.int(0).await?;
+
+        // Indexes refer to the list sorted by full path names.
+        entries.sort_by(|left, right| left.0.cmp(&right.0));
+
+        loop {
+            let ndx = mux.0.read_i32_le().await?;
+            if ndx == NDX_DONE {
+                break
+            }
+            let mut head = [0u8; 16];
+            mux.0.read_exact(&mut head).await?;
+            assert_eq!(head, [0u8; 16]);
+            let data = entries[ndx as usize].2;
+            mux.int(ndx).await?;
+            mux.data(&head).await?;
+            for chunk in data.chunks(32 * 1024) {
+                mux.int(chunk.len() as i32).await?;
+                mux.data(chunk).await?;
+            }
+            mux.int(0).await?;
+            mux.data(&[0xAA; 16]).await?;
+        }
+        mux.int(NDX_DONE).await?;
+        assert_eq!(mux.0.read_i32_le().await?, NDX_DONE);
+        mux.int(NDX_DONE).await?;
+        mux.int(1000).await?;
+        mux.int(-1).await?;
+        mux.data(&5_000_000_000i64.to_le_bytes()).await?;
+        mux.int(2000).await?;
+        assert_eq!(mux.0.read_i32_le().await?, NDX_DONE);
+        Ok(())
+    }
+
+    async fn fetch(
+        client: &Client, uri: &str, files: &[(&str, &[u8])]
+    ) -> Result<Transfer, RsyncError> {
+        let (client_side, server_side) = tokio::io::duplex(1024);
+        let (res, _) = join(
+            client.fetch_from(client_side, &rsync(uri)),
+            serve(server_side, files)
+        ).await;
+        res
+    }
+
+    fn rsync(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    const FILES: &[(&str, &[u8])] = &[
+        ("ta/ta.cer", b"ta certificate"),
+        ("ta/ta.mft", b"ta manifest"),
+        ("ta.b", b"sorts before ta/"),
+        ("ta/child/c.roa", b"child roa"),
+        ("ta/child/empty.crl", b""),
+        ("unrelated/x.cer", b"x"),
+    ];
+
+    #[tokio::test]
+    async fn list_modules() {
+        let (client_side, server_side) = tokio::io::duplex(1024);
+        let (res, _) = join(
+            Client::new().list_modules_from(client_side),
+            serve(server_side, &[])
+        ).await;
+        let res = res.unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].name(), "repo");
+        assert_eq!(res[0].comment(), "Test repository");
+        assert_eq!(res[1].name(), "other");
+        assert_eq!(res[1].comment(), "");
+    }
+
+    #[tokio::test]
+    async fn fetch_tree() {
+        let big = vec![7u8; 100_000];
+        let mut files = FILES.to_vec();
+        files.push(("ta/child/big.cer", &big));
+
+        let transfer = fetch(
+            &Client::new(), "rsync://example.com/repo/ta/", &files
+        ).await.unwrap();
+        assert!(transfer.is_dir());
+        assert_eq!(transfer.base(), &rsync("rsync://example.com/repo/ta/"));
+        let mut got = transfer.files().to_vec();
+        got.sort_by(|left, right| left.0.as_str().cmp(right.0.as_str()));
+        let mut expected = files.iter().filter_map(|(name, data)| {
+            name.strip_prefix("ta/").map(|_| {
+                (
+                    rsync(&format!("rsync://example.com/repo/{}", name)),
+                    Bytes::copy_from_slice(data)
+                )
+            })
+        }).collect::<Vec<_>>();
+        expected.sort_by(|left, right| left.0.as_str().cmp(right.0.as_str()));
+        assert_eq!(got, expected);
+
+        let store = MemoryStore::new();
+        let stale = rsync("rsync://example.com/repo/ta/child/old.roa");
+        let outside = rsync("rsync://example.com/repo/ta.b");
+        let same = rsync("rsync://example.com/repo/ta/ta.cer");
+        store.put(stale.clone(), Bytes::from_static(b"old")).unwrap();
+        store.put(outside.clone(), Bytes::from_static(b"old")).unwrap();
+        store.put(same.clone(), Bytes::from_static(b"ta certificate"))
+            .unwrap();
+        let changes = transfer.apply_to(&store).unwrap();
+        assert_eq!(changes.len(), expected.len());
+        assert!(matches!(changes.get(&stale), Some(Change::Delete)));
+        assert!(changes.get(&same).is_none());
+        assert!(store.get(&stale).unwrap().is_none());
+        assert_eq!(store.get(&outside).unwrap().unwrap().as_ref(), b"old");
+        for (uri, data) in &expected {
+            assert_eq!(store.get(uri).unwrap().as_ref(), Some(data));
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_module() {
+        let transfer = fetch(
+            &Client::new(), "rsync://example.com/repo/", FILES
+        ).await.unwrap();
+        assert!(transfer.is_dir());
+        assert_eq!(transfer.files().len(), FILES.len());
+    }
+
+    #[tokio::test]
+    async fn fetch_file() {
+        let transfer = fetch(
+            &Client::new(), "rsync://example.com/repo/ta/ta.mft", FILES
+        ).await.unwrap();
+        assert!(!transfer.is_dir());
+        assert_eq!(
+            transfer.files(),
+            &[(
+                rsync("rsync://example.com/repo/ta/ta.mft"),
+                Bytes::from_static(b"ta manifest")
+            )]
+        );
+
+        let store = MemoryStore::new();
+        let other = rsync("rsync://example.com/repo/ta/ta.cer");
+        store.put(other.clone(), Bytes::from_static(b"keep")).unwrap();
+        assert_eq!(transfer.apply_to(&store).unwrap().len(), 1);
+        assert!(store.get(&other).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn fetch_duplicates() {
+        // The first of two files is used, a directory beats a file.
+        let transfer = fetch(
+            &Client::new(), "rsync://example.com/repo/",
+            &[
+                ("a.cer", b"first"), ("a.cer", b"second"),
+                ("b/c.cer", b"c"), ("b", b"file"),
+            ]
+        ).await.unwrap();
+        assert_eq!(
+            transfer.files(),
+            &[
+                (
+                    rsync("rsync://example.com/repo/a.cer"),
+                    Bytes::from_static(b"first")
+                ),
+                (
+                    rsync("rsync://example.com/repo/b/c.cer"),
+                    Bytes::from_static(b"c")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn file_list_order() {
+        fn entry(name: &str, mode: u32) -> Entry {
+            Entry {
+                name: name.into(), len: 0, mode, active: true, uri: None
+            }
+        }
+
+        // Protocol 27 sorts by the full path. Notably, the directory “a”
+        // sorts before “a-b” and its content before “a0”.
+        let mut entries = vec![
+            entry("a0", 0o100644), entry("a/b", 0o40755),
+            entry("a", 0o100644), entry("a", 0o40755),
+            entry(".", 0o40755), entry("a.b", 0o100644),
+            entry("a-b", 0o100644), entry("a/b/c", 0o100644),
+            entry("a-b", 0o100644),
+        ];
+        sort_file_list(&mut entries);
+        assert_eq!(
+            entries.iter().map(|entry| {
+                (
+                    str::from_utf8(&entry.name).unwrap(),
+                    entry.is_dir(), entry.active
+                )
+            }).collect::<Vec<_>>(),
+            [
+                (".", true, true),
+                ("a", false, false), ("a", true, true),
+                ("a-b", false, true), ("a-b", false, false),
+                ("a.b", false, true),
+                ("a/b", true, true), ("a/b/c", false, true),
+                ("a0", false, true),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn errors() {
+        assert!(matches!(
+            fetch(&Client::new(), "rsync://example.com/nope/", FILES).await,
+            Err(RsyncError::Server(_))
+        ));
+        assert!(matches!(
+            fetch(
+                &Client::new(), "rsync://example.com/repo/",
+                &[("a/../../evil.cer", b"evil")]
+            ).await,
+            Err(RsyncError::BadName(_))
+        ));
+
+        let mut limits = Limits::default();
+        limits.set_max_files(Some(4));
+        assert!(matches!(
+            fetch(
+                &Client::with_limits(limits),
+                "rsync://example.com/repo/ta/", FILES
+            ).await,
+            Err(RsyncError::TooManyFiles)
+        ));
+
+        let mut limits = Limits::default();
+        limits.set_max_file_size(Some(10));
+        assert!(matches!(
+            fetch(
+                &Client::with_limits(limits),
+                "rsync://example.com/repo/ta/", FILES
+            ).await,
+            Err(RsyncError::FileTooLarge(_))
+        ));
+
+        let mut limits = Limits::default();
+        limits.set_max_total_size(Some(30));
+        assert!(matches!(
+            fetch(
+                &Client::with_limits(limits),
+                "rsync://example.com/repo/ta/", FILES
+            ).await,
+            Err(RsyncError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn socket_addrs() {
+        assert_eq!(socket_addr("example.com"), "example.com:873");
+        assert_eq!(socket_addr("example.com:8873"), "example.com:8873");
+        assert_eq!(socket_addr("[::1]"), "[::1]:873");
+        assert_eq!(socket_addr("[::1]:8873"), "[::1]:8873");
+    }
+
+    /// Fetches from a local rsync daemon.
+    ///
+    /// This expects a module `repo` at `rsync://localhost/repo/`. Since
+    /// every file is requested by its index, this fails if the client
+    /// sorts the file list differently from the daemon.
+    #[tokio::test]
+    #[ignore]
+    async fn local_rsyncd() {
+        let client = Client::new();
+        let modules = client.list_modules("localhost").await.unwrap();
+        assert!(modules.iter().any(|module| module.name() == "repo"));
+        let store = MemoryStore::new();
+        let changes = client.update(
+            &rsync("rsync://localhost/repo/"), &store
+        ).await.unwrap();
+        assert!(!changes.is_empty());
+    }
+}
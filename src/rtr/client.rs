@@ -10,9 +10,13 @@
 //! For more information on how to use the client, see the [`Client`] type.
 use std::{error, fmt, io};
 use std::future::Future;
+use std::cmp;
 use std::marker::Unpin;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
 use tokio::time::{timeout, timeout_at, Duration, Instant};
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use crate::clock::{Clock, SystemClock};
 use super::payload::{Action, Payload, Timing};
 use super::pdu;
 use super::state::State;
@@ -135,8 +139,13 @@ pub struct Client<Sock, Target> {
 
     /// The next time we should be running.
     ///
-    /// If this is None, we should be running now.
-    next_update: Option<Instant>,
+    /// This is kept both as a monotonic instant and as a time of the
+    /// clock. We run at whichever comes first, so a clock moving backwards
+    /// cannot delay an update. If this is None, we should be running now.
+    next_update: Option<(Instant, Option<DateTime<Utc>>)>,
+
+    /// The clock to determine the current time.
+    clock: Arc<dyn Clock + Send + Sync>,
 }
 
 impl<Sock, Target> Client<Sock, Target> {
@@ -163,9 +172,22 @@ impl<Sock, Target> Client<Sock, Target> {
             version: None,
             timing: Timing::default(),
             next_update: None,
+            clock: Arc::new(SystemClock),
         }
     }
 
+    /// Sets the clock used to determine when the next update is due.
+    ///
+    /// An update is due once the refresh interval has passed either on
+    /// this clock or on the system’s monotonic clock. The clock is only
+    /// consulted when an update is started, so moving it forward while the
+    /// client is already waiting does not end the wait early.
+    ///
+    /// By default, the client uses the system clock.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock + Send + Sync>) {
+        self.clock = clock
+    }
+
     /// Returns a reference to the target.
     pub fn target(&self) -> &Target {
         &self.target
@@ -237,9 +259,18 @@ where
     pub async fn update(
         &mut self
     ) -> Result<Target::Update, io::Error> {
-        if let Some(instant) = self.next_update.take() {
+        if let Some((instant, time)) = self.next_update.take() {
+            // A negative duration means the update is overdue already.
+            let deadline = match time {
+                Some(time) => {
+                    let wait = (time - self.clock.now()).to_std()
+                        .unwrap_or_default();
+                    cmp::min(instant, Instant::now() + wait)
+                }
+                None => instant
+            };
             if let Ok(Err(err)) = timeout_at(
-                instant, pdu::SerialNotify::read(&mut self.sock)
+                deadline, pdu::SerialNotify::read(&mut self.sock)
             ).await {
                 return Err(err)
             }
@@ -247,19 +278,24 @@ where
 
         if let Some(state) = self.state {
             if let Some(update) = self.serial(state).await? {
-                self.next_update = Some(
-                    Instant::now() + self.timing.refresh_duration()
-                );
+                self.set_next_update();
                 return Ok(update)
             }
         }
         let res = self.reset().await;
-        self.next_update = Some(
-            Instant::now() + self.timing.refresh_duration()
-        );
+        self.set_next_update();
         res
     }
 
+    /// Sets the time of the next update based on the current timing.
+    fn set_next_update(&mut self) {
+        let refresh = self.timing.refresh_duration();
+        let time = chrono::Duration::from_std(refresh).ok().and_then(
+            |refresh| self.clock.now().checked_add_signed(refresh)
+        );
+        self.next_update = Some((Instant::now() + refresh, time));
+    }
+
 
     /// Perform a serial query.
     ///
@@ -544,3 +580,74 @@ impl fmt::Display for PayloadError {
 
 impl error::Error for PayloadError { }
 
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, DuplexStream};
+    use crate::clock::ManualClock;
+    use super::*;
+    use super::super::state::Serial;
+
+    /// A target that only counts the applied updates.
+    #[derive(Default)]
+    struct Counter(usize);
+
+    impl PayloadTarget for Counter {
+        type Update = Vec<(Action, Payload)>;
+
+        fn start(&mut self, _reset: bool) -> Self::Update {
+            Vec::new()
+        }
+
+        fn apply(
+            &mut self, _update: Self::Update, _timing: Timing
+        ) -> Result<(), PayloadError> {
+            self.0 += 1;
+            Ok(())
+        }
+    }
+
+    /// Answers a query with an empty update for the given state.
+    async fn answer(
+        sock: &mut DuplexStream, query_len: usize, state: State
+    ) -> Result<(), io::Error> {
+        let mut query = vec![0u8; query_len];
+        sock.read_exact(&mut query).await?;
+        pdu::CacheResponse::new(1, state).write(sock).await?;
+        pdu::EndOfData::new(1, state, Timing::default()).write(sock).await
+    }
+
+    #[tokio::test]
+    async fn clock_triggers_update() {
+        let (client_side, mut server_side) = tokio::io::duplex(1024);
+        let clock = ManualClock::new(Utc::now());
+        let mut client = Client::new(client_side, Counter::default(), None);
+        client.set_clock(Arc::new(clock.clone()));
+
+        // The first update is a reset query and happens right away.
+        let state = State::from_parts(7, Serial(1));
+        let (res, served) = tokio::join!(
+            client.step(), answer(&mut server_side, 8, state)
+        );
+        res.unwrap();
+        served.unwrap();
+        assert_eq!(client.target().0, 1);
+
+        // The next one is a serial query once the refresh interval has
+        // passed on the clock rather than an hour from now.
+        clock.advance(chrono::Duration::seconds(
+            i64::from(Timing::default().refresh)
+        ));
+        let state = State::from_parts(7, Serial(2));
+        let (res, served) = timeout(Duration::from_secs(10), async {
+            tokio::join!(client.step(), answer(&mut server_side, 12, state))
+        }).await.expect("update not triggered by clock");
+        res.unwrap();
+        served.unwrap();
+        assert_eq!(client.target().0, 2);
+        assert_eq!(client.state().unwrap().serial(), Serial(2));
+    }
+}
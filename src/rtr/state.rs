@@ -9,7 +9,7 @@
 //! [`State`]: struct.State.html
 
 use std::{cmp, fmt, hash, str};
-use std::time::SystemTime;
+use crate::clock::{Clock, SystemClock};
 
 
 //------------ State ---------------------------------------------------------
@@ -52,12 +52,20 @@ impl State {
     /// The function will use a session ID based on the lower 16 bit of the
     /// current time and an initial serial of `serial`.
     pub fn new_with_serial(serial: Serial) -> Self {
+        Self::new_with_clock(serial, &SystemClock)
+    }
+
+    /// Creates a state value using the given clock.
+    ///
+    /// This is like [`new_with_serial`] but takes the current time for
+    /// picking the session ID from `clock`.
+    ///
+    /// [`new_with_serial`]: #method.new_with_serial
+    pub fn new_with_clock(
+        serial: Serial, clock: &(impl Clock + ?Sized)
+    ) -> Self {
         State {
-            session: {
-                SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH).unwrap()
-                .as_secs() as u16
-            },
+            session: clock.now().timestamp() as u16,
             serial
         }
     }
@@ -290,5 +298,18 @@ mod test {
         assert_eq!(Serial(1).partial_cmp(&Serial(0x8000_0001)), None);
         assert_eq!(Serial(0x8000_0001).partial_cmp(&Serial(1)), None);
     }
+
+    #[test]
+    fn session_from_clock() {
+        use chrono::{TimeZone, Utc};
+        use crate::clock::ManualClock;
+
+        let clock = ManualClock::new(
+            Utc.timestamp_opt(0x1234_5678, 0).unwrap()
+        );
+        let state = State::new_with_clock(Serial(12), &clock);
+        assert_eq!(state.session(), 0x5678);
+        assert_eq!(state.serial(), Serial(12));
+    }
 }
 
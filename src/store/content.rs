@@ -0,0 +1,767 @@
+//! A content-addressed repository store.
+//!
+//! Most objects of the RPKI change rarely. When keeping the history of
+//! repositories, storing each object once per copy wastes a lot of space.
+//! The [`ContentStore`] in this module instead stores the content of
+//! objects under their SHA-256 hash and keeps a separate index mapping
+//! URIs to hashes. Identical content is only ever stored once, no matter
+//! how many URIs or saved snapshots refer to it.
+//!
+//! The directory of a store has the following layout:
+//!
+//! * `objects/` contains the content of all objects. The file for an
+//!   object is named after the hex representation of its hash and placed
+//!   in a subdirectory named after the first two characters of that name.
+//! * `current` is the index of the current content of the store.
+//! * `journal` contains the changes made since `current` was last written.
+//! * `snapshots/` contains the indexes of all saved snapshots.
+//!
+//! An index file has one line per object containing the hex representation
+//! of the hash followed by a single space and the URI.
+//!
+//! The journal has one line per changed object: the line of the object in
+//! the index if it was added or replaced, or a `-` followed by a single
+//! space and the URI if it was deleted. The changes applied together are
+//! followed by a line with a single `.`. Changes that aren’t followed by
+//! such a line are incomplete and ignored. Once the journal has grown
+//! larger than the index, it is merged into `current` and removed.
+//!
+//! This module is only available if the `"rrdp"` feature is enabled.
+
+#![cfg(feature = "rrdp")]
+
+use std::{fs, io};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use bytes::Bytes;
+use crate::uri;
+use crate::rrdp::Hash;
+use super::fs::{is_safe_component, tmp_path};
+use super::{Change, ChangeSet, RepositoryStore, StoreError};
+
+
+//------------ Configuration Constants ---------------------------------------
+
+/// The number of journal entries always allowed before merging.
+///
+/// The journal is merged into the index once it has more entries than the
+/// index plus this value.
+const JOURNAL_SLACK: usize = 1024;
+
+
+//------------ ContentStore --------------------------------------------------
+
+/// A repository store keeping objects by the hash of their content.
+///
+/// Besides acting as a normal [`RepositoryStore`], the current content of
+/// the store can be saved as a named snapshot via
+/// [`save_snapshot`][Self::save_snapshot] and later be accessed via
+/// [`snapshot`][Self::snapshot]. Since a snapshot only consists of its
+/// index, saving it is cheap.
+///
+/// Objects that are no longer referenced by the current content or any
+/// snapshot are only removed by
+/// [`collect_garbage`][Self::collect_garbage]. Content read from the store
+/// is checked against its hash and [`verify`][Self::verify] checks the
+/// whole store.
+///
+/// The store can be shared between threads within a process. It must not
+/// be modified by several processes at the same time.
+#[derive(Debug)]
+pub struct ContentStore {
+    /// The base directory of the store.
+    base: PathBuf,
+
+    /// The index of the current content.
+    current: RwLock<Index>,
+
+    /// The number of entries in the journal.
+    ///
+    /// This is only changed while holding the write lock of `current`.
+    journal_len: AtomicUsize,
+}
+
+/// An index mapping URIs to the hashes of their content.
+type Index = HashMap<uri::Rsync, Hash>;
+
+impl ContentStore {
+    /// Opens the store in the given directory.
+    ///
+    /// The directory is created if it doesn’t exist yet.
+    pub fn open(base: impl Into<PathBuf>) -> Result<Self, StoreError> {
+        let base = base.into();
+        fs::create_dir_all(base.join("objects"))?;
+        fs::create_dir_all(base.join("snapshots"))?;
+        let mut current = read_index(
+            &base.join("current")
+        )?.unwrap_or_default();
+        let res = ContentStore {
+            base, current: RwLock::new(Index::new()),
+            journal_len: AtomicUsize::new(0),
+        };
+        if replay_journal(&res.journal_path(), &mut current)? {
+            res.merge_journal(&current)?;
+        }
+        *res.write() = current;
+        Ok(res)
+    }
+
+    /// Returns the base directory of the store.
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    /// Returns the number of objects in the current content.
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    /// Returns whether the current content is empty.
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    /// Returns the hash of the current content of an object.
+    pub fn get_hash(&self, uri: &uri::Rsync) -> Option<Hash> {
+        self.read().get(uri).copied()
+    }
+
+    /// Saves the current content as a snapshot with the given name.
+    ///
+    /// Names may only contain ASCII letters, digits, and the characters
+    /// `-`, `_`, and `.` and must not start with a dot. An existing
+    /// snapshot of the same name is replaced.
+    pub fn save_snapshot(&self, name: &str) -> Result<(), StoreError> {
+        let path = self.snapshot_path(name)?;
+        write_index(&path, &self.read())
+    }
+
+    /// Returns the snapshot with the given name.
+    ///
+    /// Returns `Ok(None)` if there is no such snapshot.
+    pub fn snapshot(
+        &self, name: &str
+    ) -> Result<Option<ContentSnapshot<'_>>, StoreError> {
+        Ok(read_index(&self.snapshot_path(name)?)?.map(|index| {
+            ContentSnapshot { store: self, index }
+        }))
+    }
+
+    /// Returns the names of all snapshots in alphabetical order.
+    pub fn snapshots(&self) -> Result<Vec<String>, StoreError> {
+        let mut res = Vec::new();
+        for entry in fs::read_dir(self.base.join("snapshots"))? {
+            if let Ok(name) = entry?.file_name().into_string() {
+                if is_snapshot_name(&name) {
+                    res.push(name)
+                }
+            }
+        }
+        res.sort();
+        Ok(res)
+    }
+
+    /// Removes the snapshot with the given name.
+    ///
+    /// The content of its objects is only removed by the next garbage
+    /// collection.
+    pub fn remove_snapshot(&self, name: &str) -> Result<(), StoreError> {
+        match fs::remove_file(self.snapshot_path(name)?) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into())
+        }
+    }
+
+    /// Removes all content not referenced by the current content or any
+    /// snapshot.
+    ///
+    /// Returns the number of objects removed.
+    pub fn collect_garbage(&self) -> Result<usize, StoreError> {
+        // Hold the write lock so no new content is added meanwhile.
+        let current = self.write();
+        let used = self.referenced(&current)?;
+        let mut removed = 0;
+        for (hash, path) in self.stored_objects()? {
+            if !hash.map(|hash| used.contains(&hash)).unwrap_or(false) {
+                fs::remove_file(path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Checks the integrity of the store.
+    ///
+    /// Every stored object is checked against its hash and every hash
+    /// referenced by the current content or a snapshot must be present.
+    pub fn verify(&self) -> Result<IntegrityReport, StoreError> {
+        let current = self.read();
+        let mut report = IntegrityReport::default();
+        let mut present = HashSet::new();
+        for (hash, path) in self.stored_objects()? {
+            let hash = match hash {
+                Some(hash) => hash,
+                None => {
+                    report.unknown.push(path);
+                    continue
+                }
+            };
+            if hash.matches(&fs::read(&path)?) {
+                present.insert(hash);
+            }
+            else {
+                report.corrupt.push(hash);
+            }
+        }
+        let mut missing: Vec<_> = self.referenced(&current)?.into_iter()
+            .filter(|hash| !present.contains(hash))
+            .collect();
+        missing.retain(|hash| !report.corrupt.contains(hash));
+        report.missing = missing;
+        Ok(report)
+    }
+
+    /// Returns all hashes referenced by the current content or a snapshot.
+    fn referenced(
+        &self, current: &Index
+    ) -> Result<HashSet<Hash>, StoreError> {
+        let mut res: HashSet<_> = current.values().copied().collect();
+        for name in self.snapshots()? {
+            if let Some(index) = read_index(&self.snapshot_path(&name)?)? {
+                res.extend(index.values().copied())
+            }
+        }
+        Ok(res)
+    }
+
+    /// Returns the hashes and paths of all stored objects.
+    ///
+    /// The hash is `None` for files that don’t have a valid name.
+    fn stored_objects(
+        &self
+    ) -> Result<Vec<(Option<Hash>, PathBuf)>, StoreError> {
+        let mut res = Vec::new();
+        for dir in fs::read_dir(self.base.join("objects"))? {
+            let dir = dir?;
+            if !dir.file_type()?.is_dir() {
+                continue
+            }
+            for entry in fs::read_dir(dir.path())? {
+                let entry = entry?;
+                let hash = entry.file_name().to_str().and_then(|name| {
+                    Hash::from_str(name).ok()
+                });
+                res.push((hash, entry.path()))
+            }
+        }
+        Ok(res)
+    }
+
+    /// Reads the content of an object and checks it against its hash.
+    fn read_object(
+        &self, uri: &uri::Rsync, hash: Hash
+    ) -> Result<Bytes, StoreError> {
+        let data = match fs::read(self.object_path(hash)) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(StoreError::HashMismatch(uri.clone()))
+            }
+            Err(err) => return Err(err.into())
+        };
+        if !hash.matches(&data) {
+            return Err(StoreError::HashMismatch(uri.clone()))
+        }
+        Ok(data.into())
+    }
+
+    /// Writes the content of an object unless it is already present.
+    ///
+    /// An existing file is only kept if its content matches the hash, so
+    /// a truncated or otherwise corrupt file is replaced.
+    fn write_object(&self, data: &[u8]) -> Result<Hash, StoreError> {
+        let hash = Hash::from_data(data);
+        let path = self.object_path(hash);
+        let present = match fs::metadata(&path) {
+            Ok(meta) if meta.len() == data.len() as u64 => {
+                hash.matches(&fs::read(&path)?)
+            }
+            Ok(_) => false,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => false,
+            Err(err) => return Err(err.into())
+        };
+        if !present {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let tmp = tmp_path(&path);
+            fs::write(&tmp, data)?;
+            fs::rename(&tmp, &path)?;
+        }
+        Ok(hash)
+    }
+
+    /// Appends a set of changes to the journal.
+    ///
+    /// If writing fails, the journal is truncated to its previous length
+    /// so that no partial changes are left behind.
+    fn append_journal(
+        &self, entries: &[(&uri::Rsync, Option<Hash>)]
+    ) -> Result<(), StoreError> {
+        let mut file = fs::OpenOptions::new()
+            .create(true).append(true)
+            .open(self.journal_path())?;
+        let len = file.metadata()?.len();
+        if let Err(err) = write_journal(&mut file, entries) {
+            let _ = file.set_len(len);
+            return Err(err.into())
+        }
+        Ok(())
+    }
+
+    /// Writes the given index as the current index and removes the journal.
+    fn merge_journal(&self, index: &Index) -> Result<(), StoreError> {
+        write_index(&self.base.join("current"), index)?;
+        match fs::remove_file(self.journal_path()) {
+            Ok(()) => { }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => { }
+            Err(err) => return Err(err.into())
+        }
+        self.journal_len.store(0, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the path of the journal.
+    fn journal_path(&self) -> PathBuf {
+        self.base.join("journal")
+    }
+
+    /// Returns the path of the file for the object with the given hash.
+    fn object_path(&self, hash: Hash) -> PathBuf {
+        let name = hash.to_string();
+        self.base.join("objects").join(&name[..2]).join(name)
+    }
+
+    /// Returns the path of the index file for the given snapshot.
+    fn snapshot_path(&self, name: &str) -> Result<PathBuf, StoreError> {
+        if !is_snapshot_name(name) {
+            return Err(StoreError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput, "invalid snapshot name"
+            )))
+        }
+        Ok(self.base.join("snapshots").join(name))
+    }
+
+    /// Locks the current index for reading.
+    fn read(&self) -> RwLockReadGuard<'_, Index> {
+        self.current.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Locks the current index for writing.
+    fn write(&self) -> RwLockWriteGuard<'_, Index> {
+        self.current.write().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl RepositoryStore for ContentStore {
+    fn get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, StoreError> {
+        match self.get_hash(uri) {
+            Some(hash) => self.read_object(uri, hash).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    fn list(
+        &self, pub_point: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError> {
+        Ok(list_index(&self.read(), pub_point, false))
+    }
+
+    fn list_tree(
+        &self, dir: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError> {
+        Ok(list_index(&self.read(), dir, true))
+    }
+
+    /// Applies a set of changes.
+    ///
+    /// New content is written first. The changes only become visible once
+    /// they have been added to the journal, so a failure leaves the current
+    /// content unchanged.
+    fn apply(&self, changes: &ChangeSet) -> Result<(), StoreError> {
+        let mut current = self.write();
+        let mut entries = Vec::with_capacity(changes.len());
+        for (uri, change) in changes {
+            entries.push((uri, match change {
+                Change::Put(data) => Some(self.write_object(data)?),
+                Change::Delete => None,
+            }));
+        }
+        if entries.is_empty() {
+            return Ok(())
+        }
+        self.append_journal(&entries)?;
+        for (uri, hash) in entries {
+            match hash {
+                Some(hash) => {
+                    current.insert(uri.clone(), hash);
+                }
+                None => {
+                    current.remove(uri);
+                }
+            }
+        }
+        let journal_len = self.journal_len.fetch_add(
+            changes.len(), Ordering::Relaxed
+        ) + changes.len();
+        if journal_len > current.len() + JOURNAL_SLACK {
+            // The changes are safe in the journal already, so if merging
+            // fails we just try again next time.
+            let _ = self.merge_journal(&current);
+        }
+        Ok(())
+    }
+}
+
+
+//------------ ContentSnapshot -----------------------------------------------
+
+/// A saved snapshot of a content store.
+#[derive(Clone, Debug)]
+pub struct ContentSnapshot<'a> {
+    /// The store the snapshot belongs to.
+    store: &'a ContentStore,
+
+    /// The index of the snapshot.
+    index: Index,
+}
+
+impl<'a> ContentSnapshot<'a> {
+    /// Returns the number of objects in the snapshot.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns whether the snapshot is empty.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the hash of an object in the snapshot.
+    pub fn get_hash(&self, uri: &uri::Rsync) -> Option<Hash> {
+        self.index.get(uri).copied()
+    }
+
+    /// Returns the content of an object in the snapshot.
+    pub fn get(
+        &self, uri: &uri::Rsync
+    ) -> Result<Option<Bytes>, StoreError> {
+        match self.get_hash(uri) {
+            Some(hash) => self.store.read_object(uri, hash).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    /// Returns the URIs of all objects directly within a publication point.
+    pub fn list(&self, pub_point: &uri::Rsync) -> Vec<uri::Rsync> {
+        list_index(&self.index, pub_point, false)
+    }
+
+    /// Returns the URIs of all objects within a directory tree.
+    pub fn list_tree(&self, dir: &uri::Rsync) -> Vec<uri::Rsync> {
+        list_index(&self.index, dir, true)
+    }
+
+    /// Returns an iterator over the URIs and hashes of all objects.
+    pub fn iter(&self) -> impl Iterator<Item = (&uri::Rsync, Hash)> + '_ {
+        self.index.iter().map(|(uri, hash)| (uri, *hash))
+    }
+}
+
+
+//------------ IntegrityReport -----------------------------------------------
+
+/// The result of checking the integrity of a content store.
+#[derive(Clone, Debug, Default)]
+pub struct IntegrityReport {
+    /// Stored objects whose content doesn’t match their hash.
+    pub corrupt: Vec<Hash>,
+
+    /// Referenced objects that are not stored.
+    pub missing: Vec<Hash>,
+
+    /// Files in the object directory that aren’t named after a hash.
+    pub unknown: Vec<PathBuf>,
+}
+
+impl IntegrityReport {
+    /// Returns whether no problems have been found.
+    pub fn is_ok(&self) -> bool {
+        self.corrupt.is_empty() && self.missing.is_empty()
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns whether a string can be used as a snapshot name.
+fn is_snapshot_name(name: &str) -> bool {
+    is_safe_component(name)
+    && !name.starts_with('.')
+    && name.bytes().all(|ch| {
+        ch.is_ascii_alphanumeric() || ch == b'-' || ch == b'_' || ch == b'.'
+    })
+}
+
+/// Returns the URIs in an index within a directory.
+fn list_index(
+    index: &Index, dir: &uri::Rsync, recursive: bool
+) -> Vec<uri::Rsync> {
+    index.keys().filter(|uri| {
+        match uri.relative_to(dir) {
+            Some(path) => {
+                !path.is_empty() && (recursive || !path.contains('/'))
+            }
+            None => false
+        }
+    }).cloned().collect()
+}
+
+/// Reads an index file.
+///
+/// Returns `Ok(None)` if the file doesn’t exist.
+fn read_index(path: &Path) -> Result<Option<Index>, StoreError> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(None)
+        }
+        Err(err) => return Err(err.into())
+    };
+    let mut res = Index::new();
+    for line in io::BufReader::new(file).lines() {
+        let line = line?;
+        let parsed = line.split_once(' ').and_then(|(hash, uri)| {
+            Some((
+                uri::Rsync::from_str(uri).ok()?, Hash::from_str(hash).ok()?
+            ))
+        });
+        match parsed {
+            Some((uri, hash)) => {
+                res.insert(uri, hash);
+            }
+            None => {
+                return Err(StoreError::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid index file {}", path.display())
+                )))
+            }
+        }
+    }
+    Ok(Some(res))
+}
+
+/// Applies the complete changes in a journal file to an index.
+///
+/// Returns whether the journal file exists.
+fn replay_journal(
+    path: &Path, index: &mut Index
+) -> Result<bool, StoreError> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(false)
+        }
+        Err(err) => return Err(err.into())
+    };
+    let invalid = || {
+        StoreError::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid journal file {}", path.display())
+        ))
+    };
+    let mut batch = Vec::new();
+    for line in io::BufReader::new(file).split(b'\n') {
+        let line = line?;
+        if line != b"." {
+            batch.push(line);
+            continue
+        }
+        for line in batch.drain(..) {
+            let line = String::from_utf8(line).map_err(|_| invalid())?;
+            let (hash, uri) = line.split_once(' ').ok_or_else(invalid)?;
+            let uri = uri::Rsync::from_str(uri).map_err(|_| invalid())?;
+            if hash == "-" {
+                index.remove(&uri);
+            }
+            else {
+                index.insert(
+                    uri, Hash::from_str(hash).map_err(|_| invalid())?
+                );
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Writes a set of changes to a journal file.
+fn write_journal(
+    file: &mut fs::File, entries: &[(&uri::Rsync, Option<Hash>)]
+) -> Result<(), io::Error> {
+    let mut buf = io::BufWriter::new(&mut *file);
+    for (uri, hash) in entries {
+        match hash {
+            Some(hash) => writeln!(buf, "{} {}", hash, uri)?,
+            None => writeln!(buf, "- {}", uri)?,
+        }
+    }
+    buf.write_all(b".\n")?;
+    buf.into_inner().map_err(io::Error::from)?.sync_data()
+}
+
+/// Writes an index file.
+///
+/// The file is written under a temporary name first and then moved into
+/// place.
+fn write_index(path: &Path, index: &Index) -> Result<(), StoreError> {
+    let mut lines: Vec<_> = index.iter().map(|(uri, hash)| {
+        format!("{} {}\n", hash, uri)
+    }).collect();
+    lines.sort();
+    let tmp = tmp_path(path);
+    {
+        let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+        for line in lines {
+            file.write_all(line.as_bytes())?;
+        }
+        file.into_inner().map_err(io::Error::from)?.sync_all()?;
+    }
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::test::{check_store, rsync};
+
+    fn base(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "rpki-content-store-{}-{}", name, std::process::id()
+        ))
+    }
+
+    #[test]
+    fn store() {
+        let base = base("store");
+        let store = ContentStore::open(&base).unwrap();
+        check_store(&store);
+        assert_eq!(store.len(), 2);
+
+        // Reopening keeps the content.
+        let store = ContentStore::open(&base).unwrap();
+        assert_eq!(store.len(), 2);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn snapshots_and_garbage() {
+        let base = base("snapshots");
+        let store = ContentStore::open(&base).unwrap();
+        let a = rsync("rsync://example.com/repo/a.cer");
+        let b = rsync("rsync://example.com/repo/b.cer");
+        let data = Bytes::from_static(b"foo");
+        let hash = Hash::from_data(&data);
+
+        store.put(a.clone(), data.clone()).unwrap();
+        store.put(b.clone(), data.clone()).unwrap();
+        store.save_snapshot("one").unwrap();
+        assert_eq!(store.stored_objects().unwrap().len(), 1);
+
+        store.put(a.clone(), Bytes::from_static(b"bar")).unwrap();
+        store.delete(b.clone()).unwrap();
+        store.save_snapshot("two").unwrap();
+        assert_eq!(store.snapshots().unwrap(), ["one", "two"]);
+        assert!(store.save_snapshot("../x").is_err());
+
+        let one = store.snapshot("one").unwrap().unwrap();
+        assert_eq!(one.get(&b).unwrap(), Some(data.clone()));
+        assert_eq!(one.get_hash(&a), Some(hash));
+        assert_eq!(one.list(&rsync("rsync://example.com/repo/")).len(), 2);
+        assert!(store.snapshot("three").unwrap().is_none());
+
+        assert_eq!(store.collect_garbage().unwrap(), 0);
+        store.remove_snapshot("one").unwrap();
+        assert_eq!(store.collect_garbage().unwrap(), 1);
+        assert_eq!(store.get(&a).unwrap(), Some(Bytes::from_static(b"bar")));
+        assert!(store.verify().unwrap().is_ok());
+
+        // Corrupt the remaining object.
+        let bar = Hash::from_data(b"bar");
+        fs::write(store.object_path(bar), b"baz").unwrap();
+        assert!(matches!(store.get(&a), Err(StoreError::HashMismatch(_))));
+        let report = store.verify().unwrap();
+        assert_eq!(report.corrupt, [bar]);
+        assert!(report.missing.is_empty());
+
+        fs::remove_file(store.object_path(bar)).unwrap();
+        let report = store.verify().unwrap();
+        assert!(report.corrupt.is_empty());
+        assert_eq!(report.missing, [bar]);
+
+        // Putting the content again repairs the store, also if the file
+        // is truncated.
+        store.put(a.clone(), Bytes::from_static(b"bar")).unwrap();
+        assert!(store.verify().unwrap().is_ok());
+        fs::write(store.object_path(bar), b"ba").unwrap();
+        store.put(b.clone(), Bytes::from_static(b"bar")).unwrap();
+        assert!(store.verify().unwrap().is_ok());
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn journal() {
+        let base = base("journal");
+        let store = ContentStore::open(&base).unwrap();
+        let uri = |idx: usize| {
+            rsync(&format!("rsync://example.com/repo/{}.cer", idx))
+        };
+
+        // Single changes only go to the journal until it is merged.
+        for _ in 0..=JOURNAL_SLACK {
+            store.put(uri(0), Bytes::from_static(b"foo")).unwrap();
+        }
+        assert!(!base.join("current").exists());
+        store.put(uri(0), Bytes::from_static(b"bar")).unwrap();
+        assert!(base.join("current").exists());
+        assert!(!base.join("journal").exists());
+
+        store.put(uri(1), Bytes::from_static(b"foo")).unwrap();
+        store.put(uri(2), Bytes::from_static(b"foo")).unwrap();
+        store.delete(uri(1)).unwrap();
+
+        // An incomplete change at the end is ignored and reopening merges
+        // the journal.
+        let mut journal = fs::OpenOptions::new().append(true).open(
+            base.join("journal")
+        ).unwrap();
+        journal.write_all(format!("- {}\n", uri(2)).as_bytes()).unwrap();
+        drop(journal);
+        let store = ContentStore::open(&base).unwrap();
+        assert!(!base.join("journal").exists());
+        assert_eq!(store.len(), 2);
+        assert_eq!(
+            store.get(&uri(0)).unwrap(), Some(Bytes::from_static(b"bar"))
+        );
+        assert_eq!(store.get(&uri(1)).unwrap(), None);
+        assert!(store.get(&uri(2)).unwrap().is_some());
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
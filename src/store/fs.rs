@@ -0,0 +1,498 @@
+//! A repository store keeping objects in the file system.
+
+use std::{fs, io};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use bytes::Bytes;
+use crate::uri;
+use super::{Change, ChangeSet, RepositoryStore, StoreError};
+
+
+//------------ FsStore -------------------------------------------------------
+
+/// A repository store keeping objects in a directory tree.
+///
+/// The tree is laid out the same way as when fetching repositories via
+/// rsync: the object `rsync://host/module/path` is kept in the file
+/// `host/module/path` below the base directory. The host name is
+/// converted to lowercase.
+///
+/// Objects whose URI contains path components that are not safe to use as
+/// file names cannot be stored. These are components starting with a dot
+/// and components containing control characters, slashes, backslashes,
+/// colons, or asterisks. As a consequence, objects from rsync URIs with an
+/// explicit port cannot be stored either.
+///
+/// Changes are applied by first writing all new content into temporary
+/// files next to their final location. Then the files of objects that are
+/// deleted or replaced are moved aside and the temporary files are moved
+/// into place. If any of this fails, all steps taken so far are undone and
+/// the store remains unchanged. Only if the process terminates while
+/// files are being moved, the store may be left with part of the changes
+/// applied. Temporary files and files moved aside have names starting
+/// with a dot and are never listed.
+///
+/// The store can be shared between threads within a process. It must not
+/// be modified by several processes at the same time.
+#[derive(Debug)]
+pub struct FsStore {
+    /// The base directory of the store.
+    base: PathBuf,
+
+    /// A lock to serialize changes.
+    lock: Mutex<()>,
+}
+
+impl FsStore {
+    /// Creates a new store using the given base directory.
+    ///
+    /// The directory is created when the first object is stored.
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        FsStore { base: base.into(), lock: Mutex::new(()) }
+    }
+
+    /// Returns the base directory of the store.
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    /// Returns the path of the file or directory for the given URI.
+    ///
+    /// Returns an error if the URI cannot safely be turned into a path
+    /// below the base directory.
+    pub fn path(&self, uri: &uri::Rsync) -> Result<PathBuf, StoreError> {
+        let authority = uri.canonical_authority();
+        let mut res = self.base.clone();
+        for &component in &[authority.as_ref(), uri.module_name()] {
+            if !is_safe_component(component) {
+                return Err(StoreError::BadUri(uri.clone()))
+            }
+            res.push(component);
+        }
+        for component in uri.path().split('/') {
+            if component.is_empty() {
+                // Only possible at the end for a directory.
+                continue
+            }
+            if !is_safe_component(component) {
+                return Err(StoreError::BadUri(uri.clone()))
+            }
+            res.push(component);
+        }
+        Ok(res)
+    }
+
+    /// Returns the path of the file for an object.
+    fn object_path(&self, uri: &uri::Rsync) -> Result<PathBuf, StoreError> {
+        if uri.path().is_empty() || uri.path().ends_with('/') {
+            return Err(StoreError::BadUri(uri.clone()))
+        }
+        self.path(uri)
+    }
+
+    /// Adds all files in a directory to `res`.
+    ///
+    /// If `recursive` is `true`, descends into subdirectories.
+    fn list_dir(
+        dir: &Path,
+        uri: &uri::Rsync,
+        recursive: bool,
+        res: &mut Vec<uri::Rsync>,
+    ) -> Result<(), StoreError> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(())
+            }
+            Err(err) => return Err(err.into())
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = match entry.file_name().into_string() {
+                Ok(name) if !name.starts_with('.') => name,
+                _ => continue,
+            };
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                if recursive {
+                    let mut dir_name = name.clone();
+                    dir_name.push('/');
+                    if let Ok(sub) = uri.join(dir_name.as_bytes()) {
+                        Self::list_dir(&entry.path(), &sub, true, res)?;
+                    }
+                }
+            }
+            else if file_type.is_file() {
+                if let Ok(uri) = uri.join(name.as_bytes()) {
+                    res.push(uri)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the URIs of all objects in the store.
+    pub fn list_all(&self) -> Result<Vec<uri::Rsync>, StoreError> {
+        let mut res = Vec::new();
+        for host in Self::dir_names(&self.base)? {
+            let host_path = self.base.join(&host);
+            for module in Self::dir_names(&host_path)? {
+                let uri = format!("rsync://{}/{}/", host, module);
+                if let Ok(uri) = uri::Rsync::from_string(uri) {
+                    Self::list_dir(
+                        &host_path.join(&module), &uri, true, &mut res
+                    )?;
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Returns the names of the visible subdirectories of a directory.
+    fn dir_names(dir: &Path) -> Result<Vec<String>, StoreError> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Vec::new())
+            }
+            Err(err) => return Err(err.into())
+        };
+        let mut res = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue
+            }
+            if let Ok(name) = entry.file_name().into_string() {
+                if is_safe_component(&name) {
+                    res.push(name)
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Locks the store for making changes.
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl RepositoryStore for FsStore {
+    fn get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, StoreError> {
+        match fs::read(self.object_path(uri)?) {
+            Ok(data) => Ok(Some(data.into())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into())
+        }
+    }
+
+    fn list(
+        &self, pub_point: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError> {
+        let mut res = Vec::new();
+        Self::list_dir(
+            &self.path(pub_point)?, &dir_uri(pub_point)?, false, &mut res
+        )?;
+        Ok(res)
+    }
+
+    fn list_tree(
+        &self, dir: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError> {
+        let mut res = Vec::new();
+        Self::list_dir(&self.path(dir)?, &dir_uri(dir)?, true, &mut res)?;
+        Ok(res)
+    }
+
+    fn apply(&self, changes: &ChangeSet) -> Result<(), StoreError> {
+        let _lock = self.lock();
+        let mut staging = Staging::default();
+        let res = staging.stage(self, changes).and_then(|_| {
+            staging.commit().map_err(Into::into)
+        });
+        match res {
+            Ok(()) => staging.clean_up(),
+            Err(_) => staging.roll_back(),
+        }
+        res
+    }
+}
+
+
+//------------ Staging -------------------------------------------------------
+
+/// The changes to a file system store while being applied.
+#[derive(Default)]
+struct Staging {
+    /// The temporary files with new content and their final paths.
+    written: Vec<(PathBuf, PathBuf)>,
+
+    /// The paths of files to be deleted.
+    deleted: Vec<PathBuf>,
+
+    /// The steps taken while moving files into place.
+    journal: Vec<Step>,
+}
+
+/// A step taken while moving files into place.
+enum Step {
+    /// An existing file has been moved aside to the first path.
+    MovedAside(PathBuf, PathBuf),
+
+    /// A new file has been moved into place at the path.
+    Placed(PathBuf),
+}
+
+impl Staging {
+    /// Writes all new content into temporary files.
+    fn stage(
+        &mut self, store: &FsStore, changes: &ChangeSet
+    ) -> Result<(), StoreError> {
+        for (uri, change) in changes {
+            let path = store.object_path(uri)?;
+            match change {
+                Change::Put(data) => {
+                    let tmp = tmp_path(&path);
+                    self.written.push((tmp.clone(), path));
+                    if let Some(parent) = tmp.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::write(&tmp, data)?;
+                }
+                Change::Delete => self.deleted.push(path)
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves existing files aside and new files into place.
+    fn commit(&mut self) -> Result<(), io::Error> {
+        for path in &self.deleted {
+            move_aside(path, &mut self.journal)?;
+        }
+        for (tmp, path) in &self.written {
+            move_aside(path, &mut self.journal)?;
+            fs::rename(tmp, path)?;
+            self.journal.push(Step::Placed(path.clone()));
+        }
+        Ok(())
+    }
+
+    /// Removes the files moved aside after successfully committing.
+    fn clean_up(self) {
+        for step in self.journal {
+            if let Step::MovedAside(aside, _) = step {
+                let _ = fs::remove_file(aside);
+            }
+        }
+    }
+
+    /// Undoes all steps taken so far.
+    ///
+    /// This is best effort. Errors are ignored. Temporary files already
+    /// moved into place are gone and fail to be removed silently.
+    fn roll_back(self) {
+        for (tmp, _) in self.written {
+            let _ = fs::remove_file(tmp);
+        }
+        for step in self.journal.into_iter().rev() {
+            match step {
+                Step::MovedAside(aside, path) => {
+                    let _ = fs::rename(aside, path);
+                }
+                Step::Placed(path) => {
+                    let _ = fs::remove_file(path);
+                }
+            }
+        }
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Moves an existing file out of the way, recording it in the journal.
+fn move_aside(path: &Path, journal: &mut Vec<Step>) -> Result<(), io::Error> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("{} is a directory", path.display())
+            ))
+        }
+        Ok(_) => { }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(())
+        }
+        Err(err) => return Err(err)
+    }
+    let aside = aside_path(path);
+    fs::rename(path, &aside)?;
+    journal.push(Step::MovedAside(aside, path.into()));
+    Ok(())
+}
+
+/// Returns whether a path component can safely be used.
+///
+/// Besides components that would escape the directory, this rejects
+/// components starting with a dot since these are used for temporary
+/// files and are never listed. It also rejects components containing
+/// control characters or characters with a special meaning on some
+/// systems, namely colons, asterisks, and backslashes.
+pub(super) fn is_safe_component(component: &str) -> bool {
+    !component.is_empty()
+    && !component.starts_with('.')
+    && !component.chars().any(|ch| {
+        ch.is_control() || matches!(ch, '/' | '\\' | ':' | '*')
+    })
+}
+
+/// Returns the URI of a directory with a trailing slash.
+fn dir_uri(uri: &uri::Rsync) -> Result<uri::Rsync, StoreError> {
+    if uri.path().is_empty() || uri.path().ends_with('/') {
+        Ok(uri.clone())
+    }
+    else {
+        let mut res = uri.as_str().to_string();
+        res.push('/');
+        uri::Rsync::from_string(res).map_err(|_| {
+            StoreError::BadUri(uri.clone())
+        })
+    }
+}
+
+/// Returns the path of the temporary file for a file.
+pub(super) fn tmp_path(path: &Path) -> PathBuf {
+    hidden_path(path, ".tmp")
+}
+
+/// Returns the path a file is moved to while being replaced or deleted.
+fn aside_path(path: &Path) -> PathBuf {
+    hidden_path(path, ".old")
+}
+
+/// Returns the path of a hidden file with the given suffix for a file.
+fn hidden_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    if let Some(file_name) = path.file_name() {
+        name.push(file_name);
+    }
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::test::rsync;
+
+    #[test]
+    fn store() {
+        let base = std::env::temp_dir().join(format!(
+            "rpki-fs-store-{}", std::process::id()
+        ));
+        let store = FsStore::new(&base);
+        super::super::test::check_store(&store);
+        assert!(
+            base.join("example.com/repo/ca/child/c.roa").is_file()
+        );
+        assert!(matches!(
+            store.get(&rsync("rsync://example.com/repo/ca/")),
+            Err(StoreError::BadUri(_))
+        ));
+        assert!(matches!(
+            store.put(rsync("rsync://example.com/../x.cer"), Bytes::new()),
+            Err(StoreError::BadUri(_))
+        ));
+        assert!(matches!(
+            store.put(rsync("rsync://example.com/repo/.x.cer"), Bytes::new()),
+            Err(StoreError::BadUri(_))
+        ));
+        assert!(matches!(
+            store.put(
+                rsync("rsync://example.com:873/repo/x.cer"), Bytes::new()
+            ),
+            Err(StoreError::BadUri(_))
+        ));
+        assert_eq!(store.list_all().unwrap().len(), 2);
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn safe_components() {
+        for &component in &["x.cer", "example.com", "a-b_c~d", "x.cer.tmp"] {
+            assert!(is_safe_component(component), "{}", component);
+        }
+        for &component in &[
+            "", ".", "..", ".x.cer", "a/b", "a\\b", "c:", "x:y.cer", "*.cer",
+            "x\ny", "x\ty", "x\0y", "x\u{7f}y", "x\u{85}y",
+        ] {
+            assert!(!is_safe_component(component), "{:?}", component);
+        }
+    }
+
+    #[test]
+    fn apply_rolls_back() {
+        let base = std::env::temp_dir().join(format!(
+            "rpki-fs-store-rollback-{}", std::process::id()
+        ));
+        let store = FsStore::new(&base);
+        let a = rsync("rsync://example.com/repo/ca/a.cer");
+        let b = rsync("rsync://example.com/repo/ca/b.roa");
+        let c = rsync("rsync://example.com/repo/ca/sub/c.roa");
+        store.put(a.clone(), Bytes::from_static(b"a")).unwrap();
+        store.put(b.clone(), Bytes::from_static(b"b")).unwrap();
+        store.put(c.clone(), Bytes::from_static(b"c")).unwrap();
+
+        // Replacing the directory "sub" with a file fails only when
+        // moving files into place, after the other changes may have been
+        // made already.
+        let mut changes = ChangeSet::new();
+        changes.put(a.clone(), Bytes::from_static(b"new a"));
+        changes.delete(b.clone());
+        changes.put(
+            rsync("rsync://example.com/repo/ca/d.mft"),
+            Bytes::from_static(b"d")
+        );
+        changes.put(
+            rsync("rsync://example.com/repo/ca/sub"),
+            Bytes::from_static(b"sub")
+        );
+        assert!(store.apply(&changes).is_err());
+        assert_eq!(store.get(&a).unwrap(), Some(Bytes::from_static(b"a")));
+        assert_eq!(store.get(&b).unwrap(), Some(Bytes::from_static(b"b")));
+        assert_eq!(store.get(&c).unwrap(), Some(Bytes::from_static(b"c")));
+        assert_eq!(store.list_all().unwrap().len(), 3);
+        let mut names = fs::read_dir(base.join("example.com/repo/ca"))
+            .unwrap().map(|entry| {
+                entry.unwrap().file_name().into_string().unwrap()
+            }).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["a.cer", "b.roa", "sub"]);
+
+        // Successful changes leave no files moved aside behind.
+        let mut changes = ChangeSet::new();
+        changes.put(a.clone(), Bytes::from_static(b"new a"));
+        changes.delete(b.clone());
+        changes.put(
+            rsync("rsync://example.com/repo/ca/d.mft"),
+            Bytes::from_static(b"d")
+        );
+        store.apply(&changes).unwrap();
+        assert_eq!(
+            store.get(&a).unwrap(), Some(Bytes::from_static(b"new a"))
+        );
+        assert_eq!(store.get(&b).unwrap(), None);
+        let mut names = fs::read_dir(base.join("example.com/repo/ca"))
+            .unwrap().map(|entry| {
+                entry.unwrap().file_name().into_string().unwrap()
+            }).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["a.cer", "d.mft", "sub"]);
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
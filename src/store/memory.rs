@@ -0,0 +1,109 @@
+//! A repository store keeping all objects in memory.
+
+use std::collections::HashMap;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use bytes::Bytes;
+use crate::uri;
+use super::{Change, ChangeSet, RepositoryStore, StoreError};
+
+
+//------------ MemoryStore ---------------------------------------------------
+
+/// A repository store keeping all objects in memory.
+///
+/// The store can be shared between threads.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    /// The objects keyed by their URI.
+    objects: RwLock<HashMap<uri::Rsync, Bytes>>,
+}
+
+impl MemoryStore {
+    /// Creates a new, empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of objects in the store.
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    /// Returns whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    /// Removes all objects from the store.
+    pub fn clear(&self) {
+        self.write().clear()
+    }
+
+    /// Returns the URIs of all objects for which `op` returns `true`.
+    fn filter(&self, op: impl Fn(&uri::Rsync) -> bool) -> Vec<uri::Rsync> {
+        self.read().keys().filter(|uri| op(uri)).cloned().collect()
+    }
+
+    /// Locks the objects for reading.
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<uri::Rsync, Bytes>> {
+        self.objects.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Locks the objects for writing.
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<uri::Rsync, Bytes>> {
+        self.objects.write().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl RepositoryStore for MemoryStore {
+    fn get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, StoreError> {
+        Ok(self.read().get(uri).cloned())
+    }
+
+    fn list(
+        &self, pub_point: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError> {
+        Ok(self.filter(|uri| {
+            match uri.relative_to(pub_point) {
+                Some(path) => !path.is_empty() && !path.contains('/'),
+                None => false
+            }
+        }))
+    }
+
+    fn list_tree(
+        &self, dir: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError> {
+        Ok(self.filter(|uri| dir.is_parent_of(uri)))
+    }
+
+    fn apply(&self, changes: &ChangeSet) -> Result<(), StoreError> {
+        let mut objects = self.write();
+        for (uri, change) in changes {
+            match change {
+                Change::Put(data) => {
+                    objects.insert(uri.clone(), data.clone());
+                }
+                Change::Delete => {
+                    objects.remove(uri);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn store() {
+        let store = MemoryStore::new();
+        super::super::test::check_store(&store);
+        assert_eq!(store.len(), 2);
+    }
+}
@@ -0,0 +1,537 @@
+//! Storing repository objects.
+//!
+//! Objects published in RPKI repositories are identified by their rsync
+//! URI, regardless of whether they have been collected via rsync or RRDP.
+//! This module provides the [`RepositoryStore`] trait for types that keep
+//! such objects and can hand them out again.
+//!
+//! Changes to a store are collected into a [`ChangeSet`] and applied as a
+//! whole. A [`Transaction`] helps with building a change set while seeing
+//! the changes already made. With the `"rrdp"` feature enabled, a
+//! transaction can directly process RRDP snapshot and delta files.
+//!
+//! Two implementations of the trait are provided: [`MemoryStore`] keeps
+//! all objects in memory and is mostly useful for testing, while
+//! [`FsStore`] keeps objects in a directory tree laid out the same way as
+//! a tree fetched via rsync. With the `"rrdp"` feature enabled,
+//! [`ContentStore`] keeps objects by the hash of their content which
+//! allows keeping many snapshots of repositories cheaply.
+
+#[cfg(feature = "rrdp")]
+pub use self::content::ContentStore;
+pub use self::fs::FsStore;
+pub use self::memory::MemoryStore;
+
+pub mod content;
+pub mod fs;
+pub mod memory;
+
+use std::{error, fmt, io};
+use std::collections::hash_map;
+use std::collections::HashMap;
+use std::sync::Arc;
+use bytes::Bytes;
+use crate::uri;
+
+
+//------------ RepositoryStore -----------------------------------------------
+
+/// A type that stores repository objects.
+///
+/// Objects are identified by their rsync URI. A publication point is a
+/// directory of objects, identified by an rsync URI ending in a slash.
+///
+/// All methods take `&self`. Implementations that can be shared between
+/// threads need to take care of synchronization internally.
+pub trait RepositoryStore {
+    /// Returns the content of the object with the given URI.
+    ///
+    /// Returns `Ok(None)` if the store doesn’t have the object.
+    fn get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, StoreError>;
+
+    /// Returns the URIs of all objects directly within a publication point.
+    ///
+    /// Objects in subdirectories of the publication point are not
+    /// included. The URIs are returned in no particular order.
+    fn list(
+        &self, pub_point: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError>;
+
+    /// Returns the URIs of all objects within a directory and all its
+    /// subdirectories.
+    fn list_tree(
+        &self, dir: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError>;
+
+    /// Applies a set of changes.
+    ///
+    /// Either all changes are applied or, if an error is returned, none of
+    /// them. Deleting an object the store doesn’t have is not an error.
+    ///
+    /// Stores keeping their data persistently may not be able to keep
+    /// this guarantee if the process terminates while changes are being
+    /// applied.
+    fn apply(&self, changes: &ChangeSet) -> Result<(), StoreError>;
+
+    /// Adds or replaces a single object.
+    fn put(&self, uri: uri::Rsync, data: Bytes) -> Result<(), StoreError> {
+        let mut changes = ChangeSet::new();
+        changes.put(uri, data);
+        self.apply(&changes)
+    }
+
+    /// Deletes a single object.
+    fn delete(&self, uri: uri::Rsync) -> Result<(), StoreError> {
+        let mut changes = ChangeSet::new();
+        changes.delete(uri);
+        self.apply(&changes)
+    }
+
+    /// Starts a new transaction for the store.
+    fn transaction(&self) -> Transaction<'_, Self> {
+        Transaction::new(self)
+    }
+}
+
+impl<S: RepositoryStore + ?Sized> RepositoryStore for &S {
+    fn get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, StoreError> {
+        (*self).get(uri)
+    }
+
+    fn list(
+        &self, pub_point: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError> {
+        (*self).list(pub_point)
+    }
+
+    fn list_tree(
+        &self, dir: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError> {
+        (*self).list_tree(dir)
+    }
+
+    fn apply(&self, changes: &ChangeSet) -> Result<(), StoreError> {
+        (*self).apply(changes)
+    }
+}
+
+impl<S: RepositoryStore + ?Sized> RepositoryStore for Arc<S> {
+    fn get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, StoreError> {
+        self.as_ref().get(uri)
+    }
+
+    fn list(
+        &self, pub_point: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError> {
+        self.as_ref().list(pub_point)
+    }
+
+    fn list_tree(
+        &self, dir: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError> {
+        self.as_ref().list_tree(dir)
+    }
+
+    fn apply(&self, changes: &ChangeSet) -> Result<(), StoreError> {
+        self.as_ref().apply(changes)
+    }
+}
+
+
+//------------ ChangeSet -----------------------------------------------------
+
+/// A set of changes to a repository store.
+///
+/// The set contains at most one change per object. Adding another change
+/// for an object replaces the earlier one.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ChangeSet {
+    /// The changes keyed by the URI of the object.
+    changes: HashMap<uri::Rsync, Change>,
+}
+
+impl ChangeSet {
+    /// Creates a new, empty change set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces an object.
+    pub fn put(&mut self, uri: uri::Rsync, data: Bytes) {
+        self.changes.insert(uri, Change::Put(data));
+    }
+
+    /// Deletes an object.
+    pub fn delete(&mut self, uri: uri::Rsync) {
+        self.changes.insert(uri, Change::Delete);
+    }
+
+    /// Returns the change for an object if there is one.
+    pub fn get(&self, uri: &uri::Rsync) -> Option<&Change> {
+        self.changes.get(uri)
+    }
+
+    /// Returns the number of changes in the set.
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Returns whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns an iterator over the changes.
+    pub fn iter(&self) -> hash_map::Iter<'_, uri::Rsync, Change> {
+        self.changes.iter()
+    }
+
+    /// Adds all changes from another set.
+    ///
+    /// Changes in `other` replace those for the same objects in `self`.
+    pub fn merge(&mut self, other: ChangeSet) {
+        self.changes.extend(other.changes)
+    }
+}
+
+impl IntoIterator for ChangeSet {
+    type Item = (uri::Rsync, Change);
+    type IntoIter = hash_map::IntoIter<uri::Rsync, Change>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ChangeSet {
+    type Item = (&'a uri::Rsync, &'a Change);
+    type IntoIter = hash_map::Iter<'a, uri::Rsync, Change>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.iter()
+    }
+}
+
+
+//------------ Change --------------------------------------------------------
+
+/// A change to a single object.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Change {
+    /// The object is added or replaced with the given content.
+    Put(Bytes),
+
+    /// The object is deleted.
+    Delete,
+}
+
+
+//------------ Transaction ---------------------------------------------------
+
+/// A transaction on a repository store.
+///
+/// A transaction collects changes which are only applied to the store
+/// when [`commit`][Self::commit] is called. Reading objects through the
+/// transaction returns the changed content. Dropping a transaction
+/// without committing discards all changes.
+#[derive(Debug)]
+pub struct Transaction<'a, S: ?Sized> {
+    /// The store to apply the changes to.
+    store: &'a S,
+
+    /// The changes made so far.
+    changes: ChangeSet,
+}
+
+impl<'a, S: RepositoryStore + ?Sized> Transaction<'a, S> {
+    /// Creates a new transaction for the given store.
+    pub fn new(store: &'a S) -> Self {
+        Transaction { store, changes: ChangeSet::new() }
+    }
+
+    /// Returns the content of an object including any changes.
+    pub fn get(
+        &self, uri: &uri::Rsync
+    ) -> Result<Option<Bytes>, StoreError> {
+        match self.changes.get(uri) {
+            Some(Change::Put(data)) => Ok(Some(data.clone())),
+            Some(Change::Delete) => Ok(None),
+            None => self.store.get(uri),
+        }
+    }
+
+    /// Adds or replaces an object.
+    pub fn put(&mut self, uri: uri::Rsync, data: Bytes) {
+        self.changes.put(uri, data)
+    }
+
+    /// Deletes an object.
+    pub fn delete(&mut self, uri: uri::Rsync) {
+        self.changes.delete(uri)
+    }
+
+    /// Returns the changes made so far.
+    pub fn changes(&self) -> &ChangeSet {
+        &self.changes
+    }
+
+    /// Applies all changes to the store.
+    ///
+    /// Returns the changes that have been applied.
+    pub fn commit(self) -> Result<ChangeSet, StoreError> {
+        self.store.apply(&self.changes)?;
+        Ok(self.changes)
+    }
+}
+
+
+//--- ProcessSnapshot and ProcessDelta
+
+#[cfg(feature = "rrdp")]
+impl<'a, S: RepositoryStore + ?Sized> crate::rrdp::ProcessSnapshot
+for Transaction<'a, S> {
+    type Err = StoreError;
+
+    fn meta(
+        &mut self, _session_id: uuid::Uuid, _serial: u64
+    ) -> Result<(), Self::Err> {
+        Ok(())
+    }
+
+    fn publish(
+        &mut self,
+        uri: uri::Rsync,
+        data: &mut crate::rrdp::ObjectReader,
+    ) -> Result<(), Self::Err> {
+        let mut buf = Vec::new();
+        io::Read::read_to_end(data, &mut buf)?;
+        self.put(uri, buf.into());
+        Ok(())
+    }
+}
+
+/// Processing a delta into a transaction.
+///
+/// The hashes given for updated and withdrawn objects are checked against
+/// the content of the objects in the transaction and an object published
+/// without a hash must not exist yet. Otherwise, processing fails with
+/// [`StoreError::HashMismatch`].
+#[cfg(feature = "rrdp")]
+impl<'a, S: RepositoryStore + ?Sized> crate::rrdp::ProcessDelta
+for Transaction<'a, S> {
+    type Err = StoreError;
+
+    fn meta(
+        &mut self, _session_id: uuid::Uuid, _serial: u64
+    ) -> Result<(), Self::Err> {
+        Ok(())
+    }
+
+    fn publish(
+        &mut self,
+        uri: uri::Rsync,
+        hash: Option<crate::rrdp::Hash>,
+        data: &mut crate::rrdp::ObjectReader,
+    ) -> Result<(), Self::Err> {
+        let current = self.get(&uri)?;
+        let matches = match (hash, current) {
+            (Some(hash), Some(current)) => hash.matches(&current),
+            (None, None) => true,
+            _ => false,
+        };
+        if !matches {
+            return Err(StoreError::HashMismatch(uri))
+        }
+        let mut buf = Vec::new();
+        io::Read::read_to_end(data, &mut buf)?;
+        self.put(uri, buf.into());
+        Ok(())
+    }
+
+    fn withdraw(
+        &mut self,
+        uri: uri::Rsync,
+        hash: crate::rrdp::Hash,
+    ) -> Result<(), Self::Err> {
+        match self.get(&uri)? {
+            Some(current) if hash.matches(&current) => {
+                self.delete(uri);
+                Ok(())
+            }
+            _ => Err(StoreError::HashMismatch(uri))
+        }
+    }
+}
+
+
+//------------ StoreError ----------------------------------------------------
+
+/// An error happened while accessing a repository store.
+#[derive(Debug)]
+pub enum StoreError {
+    /// An IO error happened.
+    Io(io::Error),
+
+    /// The URI cannot be used with the store.
+    BadUri(uri::Rsync),
+
+    /// The content of an object doesn’t match the expected hash.
+    HashMismatch(uri::Rsync),
+
+    /// Processing RRDP data failed.
+    #[cfg(feature = "rrdp")]
+    Rrdp(crate::rrdp::ProcessError),
+}
+
+impl From<io::Error> for StoreError {
+    fn from(err: io::Error) -> Self {
+        StoreError::Io(err)
+    }
+}
+
+#[cfg(feature = "rrdp")]
+impl From<crate::rrdp::ProcessError> for StoreError {
+    fn from(err: crate::rrdp::ProcessError) -> Self {
+        StoreError::Rrdp(err)
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::Io(ref inner) => inner.fmt(f),
+            StoreError::BadUri(ref uri) => {
+                write!(f, "cannot store object {}", uri)
+            }
+            StoreError::HashMismatch(ref uri) => {
+                write!(f, "hash mismatch for {}", uri)
+            }
+            #[cfg(feature = "rrdp")]
+            StoreError::Rrdp(ref inner) => inner.fmt(f),
+        }
+    }
+}
+
+impl error::Error for StoreError { }
+
+impl From<StoreError> for io::Error {
+    fn from(err: StoreError) -> Self {
+        match err {
+            StoreError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::Other, err),
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    pub fn rsync(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    fn sorted(mut list: Vec<uri::Rsync>) -> Vec<String> {
+        let mut res: Vec<_> = list.drain(..).map(|uri| {
+            uri.to_string()
+        }).collect();
+        res.sort();
+        res
+    }
+
+    /// Runs the common tests for a store implementation.
+    pub fn check_store(store: &impl RepositoryStore) {
+        let dir = rsync("rsync://example.com/repo/ca/");
+        let a = dir.join(b"a.cer").unwrap();
+        let b = dir.join(b"b.roa").unwrap();
+        let c = dir.join(b"child/c.roa").unwrap();
+
+        assert_eq!(store.get(&a).unwrap(), None);
+        assert!(store.list(&dir).unwrap().is_empty());
+
+        store.put(a.clone(), Bytes::from_static(b"a")).unwrap();
+        let mut tx = store.transaction();
+        tx.put(b.clone(), Bytes::from_static(b"b"));
+        tx.put(c.clone(), Bytes::from_static(b"c"));
+        tx.delete(a.clone());
+        assert_eq!(tx.get(&a).unwrap(), None);
+        assert_eq!(tx.get(&b).unwrap(), Some(Bytes::from_static(b"b")));
+        assert_eq!(store.get(&b).unwrap(), None);
+        drop(tx);
+        assert_eq!(store.get(&a).unwrap(), Some(Bytes::from_static(b"a")));
+        assert_eq!(store.get(&b).unwrap(), None);
+
+        let mut tx = store.transaction();
+        tx.put(b.clone(), Bytes::from_static(b"b"));
+        tx.put(c.clone(), Bytes::from_static(b"c"));
+        assert_eq!(tx.commit().unwrap().len(), 2);
+        assert_eq!(store.get(&b).unwrap(), Some(Bytes::from_static(b"b")));
+        assert_eq!(
+            sorted(store.list(&dir).unwrap()),
+            ["rsync://example.com/repo/ca/a.cer",
+             "rsync://example.com/repo/ca/b.roa"]
+        );
+        assert_eq!(
+            sorted(store.list_tree(&dir).unwrap()),
+            ["rsync://example.com/repo/ca/a.cer",
+             "rsync://example.com/repo/ca/b.roa",
+             "rsync://example.com/repo/ca/child/c.roa"]
+        );
+        assert_eq!(
+            sorted(store.list_tree(
+                &rsync("rsync://EXAMPLE.com/repo/")
+            ).unwrap()).len(),
+            3
+        );
+
+        store.delete(a.clone()).unwrap();
+        store.delete(a.clone()).unwrap();
+        assert_eq!(store.get(&a).unwrap(), None);
+        assert_eq!(store.list(&dir).unwrap().len(), 1);
+    }
+
+    #[cfg(feature = "rrdp")]
+    #[test]
+    fn process_rrdp() {
+        use crate::rrdp::{Hash, ProcessDelta, ProcessSnapshot};
+
+        let store = MemoryStore::new();
+        let mut tx = store.transaction();
+        ProcessSnapshot::process(&mut tx, include_bytes!(
+            "../../test-data/ripe-snapshot.xml"
+        ).as_ref()).unwrap();
+        let changes = tx.commit().unwrap();
+        assert!(!changes.is_empty());
+
+        let (uri, data) = changes.iter().find_map(|(uri, change)| {
+            match change {
+                Change::Put(data) => Some((uri.clone(), data.clone())),
+                Change::Delete => None,
+            }
+        }).unwrap();
+        let hash = Hash::from_data(&data);
+        let delta = format!(
+            "<delta xmlns=\"http://www.ripe.net/rpki/rrdp\" version=\"1\" \
+             session_id=\"335f1543-f6a2-4d7d-8a70-f8d6b4b8aa4d\" \
+             serial=\"2\">\
+             <withdraw uri=\"{}\" hash=\"{}\"/></delta>",
+            uri, hash
+        );
+
+        let mut tx = store.transaction();
+        ProcessDelta::process(&mut tx, delta.as_bytes()).unwrap();
+        assert_eq!(tx.commit().unwrap().get(&uri), Some(&Change::Delete));
+        assert_eq!(store.get(&uri).unwrap(), None);
+
+        // Withdrawing again fails and leaves the store alone.
+        let mut tx = store.transaction();
+        assert!(matches!(
+            ProcessDelta::process(&mut tx, delta.as_bytes()),
+            Err(StoreError::HashMismatch(_))
+        ));
+    }
+}
@@ -581,6 +581,33 @@ impl Https {
         }
     }
 
+    /// Returns the port of the URI.
+    ///
+    /// If the URI does not contain a port, returns the default port 443.
+    /// Returns `None` if the port given is not a valid port number.
+    pub fn port(&self) -> Option<u16> {
+        match split_port(self.authority()).1 {
+            Some(port) => port.parse().ok(),
+            None => Some(443)
+        }
+    }
+
+    /// Returns whether the URI has the same origin as another URI.
+    ///
+    /// Two URIs have the same origin if their scheme, the host part of
+    /// their canonical authority, and their port are the same. If either
+    /// port is invalid, the URIs never have the same origin.
+    pub fn same_origin(&self, other: &Https) -> bool {
+        let port = match self.port() {
+            Some(port) => port,
+            None => return false
+        };
+        self.scheme() == other.scheme()
+            && split_port(&self.canonical_authority()).0
+                == split_port(&other.canonical_authority()).0
+            && other.port() == Some(port)
+    }
+
     #[cfg(feature = "repository")]
     pub fn encode_general_name(&self) -> impl encode::Values + '_ {
         self.encode_as(Tag::CTX_6)
@@ -867,6 +894,19 @@ pub fn starts_with_ignore_case(s: &[u8], expected: &[u8]) -> bool {
     }
 }
 
+/// Splits an authority into the host and the optional port.
+///
+/// An empty port is treated as no port.
+fn split_port(authority: &str) -> (&str, Option<&str>) {
+    match authority.rfind(':') {
+        Some(idx) => {
+            let port = &authority[idx + 1..];
+            (&authority[..idx], Some(port).filter(|port| !port.is_empty()))
+        }
+        None => (authority, None)
+    }
+}
+
 pub fn check_uri_ascii<S: AsRef<[u8]>>(slice: S) -> Result<(), Error> {
     if slice.as_ref().iter().all(|&ch| is_u8_uri_ascii(ch)) {
         Ok(())
@@ -1558,4 +1598,25 @@ mod tests {
         assert_eq!(uri_one_level_no_trail.parent().unwrap(), uri_one_level);
         assert_eq!(uri_two_level.parent().unwrap(), uri_one_level);
     }
+
+    #[test]
+    fn https_same_origin() {
+        fn https(s: &str) -> Https {
+            Https::from_str(s).unwrap()
+        }
+
+        let base = https("https://Example.com/notification.xml");
+        assert_eq!(base.port(), Some(443));
+        assert!(base.same_origin(&https("https://example.COM/a/b.xml")));
+        assert!(base.same_origin(&https("https://example.com:443/a.xml")));
+        assert!(!base.same_origin(&https("https://example.com:8443/a.xml")));
+        assert!(!base.same_origin(&https("https://example.net/a.xml")));
+        assert!(!base.same_origin(&https("https://sub.example.com/a.xml")));
+        assert!(!base.same_origin(&https("https://example.com:x/a.xml")));
+
+        let port = https("https://example.com:8443/notification.xml");
+        assert_eq!(port.port(), Some(8443));
+        assert!(port.same_origin(&https("https://EXAMPLE.com:8443/a")));
+        assert!(!port.same_origin(&https("https://example.com/a")));
+    }
 }
@@ -0,0 +1,1095 @@
+//! Walking the tree of CAs.
+//!
+//! This module contains the [`Validator`] which performs the actual
+//! validation and the [`TrustAnchor`] type describing where it starts.
+
+use std::{error, fmt, io};
+use std::sync::Arc;
+use bytes::Bytes;
+use crate::uri;
+use crate::clock::{Clock, SystemClock};
+use crate::repository::aspa::Aspa;
+use crate::repository::cert::{Cert, ResourceCert};
+use crate::repository::crl::Crl;
+use crate::repository::crypto::{KeyIdentifier, VerificationCache};
+use crate::repository::manifest::Manifest;
+use crate::repository::roa::Roa;
+use crate::repository::tal::{Tal, TalInfo, TalUri};
+use crate::repository::x509::{Time, ValidationError, Validity};
+use super::report::{
+    AspaPayload, ObjectKind, ObjectReport, ObjectStatus, PubPointReport,
+    PubPointStatus, Report, TrustAnchorReport, Vrp
+};
+use super::lastgood::{LastKnownGood, StoredPoint};
+use super::pubpoint;
+use super::scheduler;
+use super::source::ObjectSource;
+
+
+//------------ TrustAnchor ---------------------------------------------------
+
+/// A trust anchor to start validation from.
+///
+/// A trust anchor consists of the trust anchor certificate and information
+/// about the TAL it was derived from. The certificate has not been
+/// validated yet – this will happen as part of a validation run, since
+/// whether it is valid depends on the time of validation.
+#[derive(Clone, Debug)]
+pub struct TrustAnchor {
+    /// The trust anchor certificate.
+    cert: Cert,
+
+    /// Information about the TAL.
+    tal: Arc<TalInfo>,
+
+    /// The rsync URI the certificate was taken from, if known.
+    uri: Option<uri::Rsync>,
+}
+
+impl TrustAnchor {
+    /// Creates a new trust anchor from a certificate and TAL information.
+    pub fn new(cert: Cert, tal: Arc<TalInfo>) -> Self {
+        TrustAnchor { cert, tal, uri: None }
+    }
+
+    /// Creates a new trust anchor with the URI of the certificate.
+    pub fn with_uri(cert: Cert, tal: Arc<TalInfo>, uri: uri::Rsync) -> Self {
+        TrustAnchor { cert, tal, uri: Some(uri) }
+    }
+
+    /// Loads the trust anchor described by a TAL from an object source.
+    ///
+    /// The rsync URIs of the TAL are tried in order. The first certificate
+    /// that can be decoded and whose public key matches the key given in
+    /// the TAL is used.
+    pub fn from_tal(
+        tal: &Tal, source: &impl ObjectSource
+    ) -> Result<Self, TrustAnchorError> {
+        let mut res = TrustAnchorError::NotFound;
+        for uri in tal.uris() {
+            let uri = match *uri {
+                TalUri::Rsync(ref uri) => uri,
+                _ => continue
+            };
+            let data = match source.load(uri) {
+                Ok(Some(data)) => data,
+                Ok(None) => continue,
+                Err(err) => {
+                    res = TrustAnchorError::Io(err);
+                    continue
+                }
+            };
+            match Self::decode_cert(tal, data) {
+                Ok(cert) => {
+                    return Ok(TrustAnchor::with_uri(
+                        cert, tal.info().clone(), uri.clone()
+                    ))
+                }
+                Err(err) => res = err,
+            }
+        }
+        Err(res)
+    }
+
+    /// Decodes a trust anchor certificate and checks it against a TAL.
+    ///
+    /// This only checks that the certificate’s public key is the one given
+    /// in the TAL. The certificate itself is not validated.
+    pub(crate) fn decode_cert(
+        tal: &Tal, data: Bytes
+    ) -> Result<Cert, TrustAnchorError> {
+        let cert = Cert::decode(data).map_err(|_| {
+            TrustAnchorError::Malformed
+        })?;
+        if cert.subject_public_key_info() != tal.key_info() {
+            return Err(TrustAnchorError::KeyMismatch)
+        }
+        Ok(cert)
+    }
+
+    /// Returns the trust anchor certificate.
+    pub fn cert(&self) -> &Cert {
+        &self.cert
+    }
+
+    /// Returns information about the TAL.
+    pub fn tal(&self) -> &Arc<TalInfo> {
+        &self.tal
+    }
+
+    /// Returns the URI of the certificate if known.
+    pub fn uri(&self) -> Option<&uri::Rsync> {
+        self.uri.as_ref()
+    }
+}
+
+
+//------------ Validator -----------------------------------------------------
+
+/// The default maximum depth of the CA tree.
+const DEFAULT_MAX_DEPTH: usize = 32;
+
+/// A validator for complete repositories.
+///
+/// The validator walks the tree of CAs starting at a set of trust anchors
+/// and collects all the valid RPKI payload. The current time is taken from
+/// a [`Clock`] which by default is the system clock. Alternatively,
+/// [`validate_at`][Self::validate_at] can be used to validate the data as
+/// it would have been validated at a given point in time.
+///
+/// Publication points are treated according to RFC 9286: if the manifest
+/// is invalid or stale, if the CRL is not exactly the one referenced by
+/// the manifest’s EE certificate, or if any file listed on the manifest is
+/// missing or doesn’t match its hash, the whole publication point is
+/// rejected. If a [`LastKnownGood`] store is provided, the last copy of
+/// the publication point that did validate is used instead as long as it
+/// is still valid.
+///
+/// Publication points and the objects they contain can be processed in
+/// parallel on a number of threads set via
+/// [`set_threads`][Self::set_threads]. The resulting report is the same
+/// no matter how many threads are used.
+#[derive(Clone)]
+pub struct Validator {
+    /// Should we be strict when decoding and validating objects?
+    strict: bool,
+
+    /// The maximum depth of the CA tree below a trust anchor.
+    max_depth: usize,
+
+    /// The number of threads to use for validation.
+    threads: usize,
+
+    /// The cache for signature verification if one should be used.
+    verification_cache: Option<Arc<VerificationCache>>,
+
+    /// The store for last known good publication points if one is used.
+    last_good: Option<Arc<LastKnownGood>>,
+
+    /// The clock to take the current time from.
+    clock: Arc<dyn Clock + Send + Sync>,
+}
+
+impl Validator {
+    /// Creates a new validator with default settings.
+    pub fn new() -> Self {
+        Validator {
+            strict: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            threads: 1,
+            verification_cache: None,
+            last_good: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets whether objects should be decoded and validated strictly.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict
+    }
+
+    /// Sets the maximum depth of the CA tree below a trust anchor.
+    ///
+    /// CA certificates below this depth are rejected.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth
+    }
+
+    /// Sets the number of threads used for validation.
+    ///
+    /// Independent publication points and batches of objects are
+    /// distributed over this many threads. With a value of zero or one,
+    /// which is the default, validation happens on the calling thread.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads
+    }
+
+    /// Sets the cache for signature verification.
+    ///
+    /// If a cache is given, signatures of objects that have been verified
+    /// before are not verified again. All other checks are still
+    /// performed.
+    pub fn set_verification_cache(
+        &mut self, cache: Option<Arc<VerificationCache>>
+    ) {
+        self.verification_cache = cache
+    }
+
+    /// Sets the store for last known good publication points.
+    ///
+    /// If a store is given, every successfully validated publication
+    /// point is kept in the store. If a publication point fails
+    /// validation later, its copy in the store is validated and used
+    /// instead if possible.
+    pub fn set_last_known_good(&mut self, store: Option<Arc<LastKnownGood>>) {
+        self.last_good = store
+    }
+
+    /// Sets the clock used to determine the current time.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock + Send + Sync>) {
+        self.clock = clock
+    }
+
+    /// Validates the repositories below the given trust anchors.
+    ///
+    /// The current time is taken from the validator’s clock.
+    pub fn validate(
+        &self,
+        trust_anchors: &[TrustAnchor],
+        source: &(impl ObjectSource + Sync),
+    ) -> Report {
+        self.validate_at(trust_anchors, source, self.now())
+    }
+
+    /// Returns the current time according to the validator’s clock.
+    pub(crate) fn now(&self) -> Time {
+        Time::from_clock(&self.clock)
+    }
+
+    /// Returns the number of threads to use.
+    pub(crate) fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Validates the repositories as of the given time.
+    pub fn validate_at(
+        &self,
+        trust_anchors: &[TrustAnchor],
+        source: &(impl ObjectSource + Sync),
+        now: Time,
+    ) -> Report {
+        let mut vrps = Vec::new();
+        let mut aspas = Vec::new();
+        let mut ta_reports = Vec::new();
+        let mut pub_points = Vec::new();
+        let mut objects = Vec::new();
+
+        let mut jobs = Vec::new();
+        for (idx, ta) in trust_anchors.iter().enumerate() {
+            let (status, task) = match self.process_ta(ta, now) {
+                Ok(task) => (ObjectStatus::Valid, Some(task)),
+                Err(err) => (ObjectStatus::Invalid(err.into()), None)
+            };
+            ta_reports.push(TrustAnchorReport {
+                info: ta.tal.clone(),
+                uri: ta.uri.clone(),
+                status
+            });
+            if let Some(task) = task {
+                jobs.push(Job::Ca { path: vec![idx], task: task.into() });
+            }
+        }
+
+        // Jobs finish in any order when run on multiple threads. Sorting
+        // the outcomes by their path keeps the report deterministic.
+        let mut outcomes = scheduler::run(self.threads, jobs, |job, new| {
+            self.process_job(job, source, now, new)
+        });
+        outcomes.sort_by(|left, right| left.0.cmp(&right.0));
+        for (_, outcome) in outcomes {
+            vrps.extend(outcome.vrps);
+            aspas.extend(outcome.aspas);
+            objects.extend(outcome.objects);
+            if let Some(report) = outcome.pub_point {
+                pub_points.push(report);
+            }
+        }
+
+        Report::new(now, vrps, aspas, ta_reports, pub_points, objects)
+    }
+
+    /// Processes a single job of a validation run.
+    ///
+    /// Returns the path of the job and its outcome. New jobs are added to
+    /// `new`.
+    fn process_job(
+        &self,
+        job: Job,
+        source: &impl ObjectSource,
+        now: Time,
+        new: &mut Vec<Job>,
+    ) -> (Vec<usize>, CaOutcome) {
+        match job {
+            Job::Ca { path, task } => {
+                let (res, point) = self.process_pub_point(
+                    *task, source, now
+                );
+                if let Some((point, files)) = point {
+                    let point = Arc::new(point);
+                    let mut files = files.into_iter().peekable();
+                    let mut idx = 0;
+                    while files.peek().is_some() {
+                        new.push(Job::Objects {
+                            path: child_path(&path, idx),
+                            point: point.clone(),
+                            files: files.by_ref().take(
+                                OBJECT_BATCH_SIZE
+                            ).collect(),
+                        });
+                        idx += 1;
+                    }
+                }
+                (path, res)
+            }
+            Job::Objects { path, point, files } => {
+                let mut res = CaOutcome::default();
+                self.process_objects(&point, files, now, &mut res);
+                new.extend(res.children.drain(..).enumerate().map(
+                    |(idx, task)| {
+                        Job::Ca {
+                            path: child_path(&path, idx), task: task.into()
+                        }
+                    }
+                ));
+                (path, res)
+            }
+        }
+    }
+
+    /// Validates the trust anchor certificate.
+    pub(crate) fn process_ta(
+        &self, ta: &TrustAnchor, now: Time
+    ) -> Result<CaTask, &'static str> {
+        let cert = ta.cert.clone().validate_ta_at(
+            ta.tal.clone(), self.strict, now
+        ).map_err(|_| "trust anchor certificate invalid")?;
+        Ok(CaTask { ancestors: Vec::new(), cert })
+    }
+
+    /// Processes the publication point of a single CA.
+    ///
+    /// This processes the publication point and all its objects in one go
+    /// and only depends on the CA certificate, the source, and the time.
+    pub(crate) fn process_ca(
+        &self,
+        task: CaTask,
+        source: &impl ObjectSource,
+        now: Time,
+    ) -> CaOutcome {
+        let (mut res, point) = self.process_pub_point(task, source, now);
+        if let Some((point, files)) = point {
+            self.process_objects(&point, files, now, &mut res);
+        }
+        res
+    }
+
+    /// Loads the publication point of a CA.
+    ///
+    /// Returns the outcome with the reports for the publication point and
+    /// its manifest. If the publication point is current or the last
+    /// known good copy can be used, also returns what’s needed to process
+    /// the files listed on the manifest.
+    fn process_pub_point(
+        &self,
+        task: CaTask,
+        source: &impl ObjectSource,
+        now: Time,
+    ) -> (CaOutcome, Option<(CaPoint, ObjectList)>) {
+        let mut res = CaOutcome::default();
+        let (ca_repository, manifest_uri) = match (
+            task.cert.ca_repository(), task.cert.rpki_manifest()
+        ) {
+            (Some(repo), Some(mft)) => (repo.clone(), mft.clone()),
+            _ => return (res, None)
+        };
+        let rpki_notify = task.cert.rpki_notify().cloned();
+
+        let (point, status) = match self.load_pub_point(
+            &task.cert, &ca_repository, &manifest_uri, source, now, &mut res
+        ) {
+            Ok(point) => {
+                if let Some(store) = self.last_good.as_ref() {
+                    store.update(point.to_stored(manifest_uri.clone()));
+                }
+                res.objects.push(ObjectReport {
+                    uri: manifest_uri.clone(),
+                    kind: ObjectKind::Manifest,
+                    status: ObjectStatus::Valid,
+                });
+                (point, PubPointStatus::Current)
+            }
+            Err(err) => {
+                res.objects.push(ObjectReport {
+                    uri: manifest_uri.clone(),
+                    kind: ObjectKind::Manifest,
+                    status: ObjectStatus::Invalid(err.clone()),
+                });
+                let stored = self.last_good.as_ref().and_then(|store| {
+                    store.get(&manifest_uri)
+                });
+                let point = stored.and_then(|stored| {
+                    self.load_pub_point(
+                        &task.cert, &ca_repository, &manifest_uri,
+                        stored.as_ref(), now, &mut res
+                    ).ok()
+                });
+                match point {
+                    Some(point) => {
+                        (point, PubPointStatus::LastKnownGood(err))
+                    }
+                    None => {
+                        res.pub_point = Some(PubPointReport {
+                            ca_repository,
+                            rpki_notify,
+                            manifest: manifest_uri,
+                            status: PubPointStatus::Failed(err),
+                        });
+                        return (res, None)
+                    }
+                }
+            }
+        };
+
+        res.pub_point = Some(PubPointReport {
+            ca_repository,
+            rpki_notify,
+            manifest: manifest_uri,
+            status,
+        });
+
+        let mut ancestors = task.ancestors;
+        ancestors.push(task.cert.subject_key_identifier());
+        (res, Some((
+            CaPoint {
+                cert: task.cert, ancestors,
+                crl_uri: point.crl_uri, crl: point.crl
+            },
+            point.files
+        )))
+    }
+
+    /// Processes files of a current publication point.
+    fn process_objects(
+        &self,
+        point: &CaPoint,
+        files: ObjectList,
+        now: Time,
+        res: &mut CaOutcome,
+    ) {
+        for (uri, data) in files {
+            let kind = ObjectKind::from_uri(&uri);
+            let status = match self.process_object(
+                kind, data, &point.cert, &point.crl_uri, &point.crl,
+                &point.ancestors, now, res
+            ) {
+                Ok(status) => status,
+                Err(err) => ObjectStatus::Invalid(err.into()),
+            };
+            res.objects.push(ObjectReport { uri, kind, status });
+        }
+    }
+
+    /// Loads and checks the manifest, CRL, and files of a CA.
+    fn load_pub_point(
+        &self,
+        issuer: &ResourceCert,
+        ca_repository: &uri::Rsync,
+        manifest_uri: &uri::Rsync,
+        source: &impl ObjectSource,
+        now: Time,
+        res: &mut CaOutcome,
+    ) -> Result<PubPoint, String> {
+        let manifest_data = load(source, manifest_uri)?.ok_or_else(|| {
+            String::from("manifest not found")
+        })?;
+        let manifest = Manifest::decode(
+            manifest_data.clone(), self.strict
+        ).map_err(|_| {
+            String::from("manifest cannot be decoded")
+        })?;
+        res.note_validity(manifest.cert().validity(), now);
+        res.note_time(manifest.content().this_update(), now);
+        res.note_time(manifest.content().next_update(), now);
+        let (ee_cert, content) = manifest.clone().validate_at_cached(
+            issuer, self.strict, now, self.verification_cache.as_deref()
+        ).map_err(|_| String::from("manifest invalid"))?;
+        if content.is_stale_at(now) {
+            return Err("manifest is stale".into())
+        }
+        let crl_uri = ee_cert.crl_uri().cloned().ok_or_else(|| {
+            String::from("manifest EE certificate without CRL URI")
+        })?;
+
+        let previous = self.last_good.as_ref().and_then(|store| {
+            store.get(manifest_uri)
+        }).and_then(|point| {
+            Manifest::decode(point.manifest().clone(), self.strict).ok()
+        });
+        let check = pubpoint::check(
+            &manifest, ca_repository, source,
+            previous.as_ref().map(Manifest::content)
+        ).map_err(|err| {
+            format!("failed to load files listed on manifest: {}", err)
+        })?;
+        if let Some(issue) = check.first_error(self.strict) {
+            return Err(issue.to_string())
+        }
+        let crl_data = check.crl().map(|(_, data)| {
+            data.clone()
+        }).ok_or_else(|| {
+            String::from("CRL not listed on manifest")
+        })?;
+        let files = check.into_files();
+
+        let mut crl = Crl::decode(crl_data).map_err(|_| {
+            String::from("CRL cannot be decoded")
+        })?;
+        res.note_time(crl.this_update(), now);
+        res.note_time(crl.next_update(), now);
+        if crl.validate_cached(
+            issuer.subject_public_key_info(),
+            self.verification_cache.as_deref()
+        ).is_err() {
+            return Err("CRL invalid".into())
+        }
+        if crl.is_stale_at(now) {
+            return Err("CRL is stale".into())
+        }
+        crl.cache_serials();
+        if crl.contains(ee_cert.serial_number()) {
+            return Err("manifest EE certificate has been revoked".into())
+        }
+
+        let expires = if content.next_update() < crl.next_update() {
+            content.next_update()
+        }
+        else {
+            crl.next_update()
+        };
+        Ok(PubPoint { manifest: manifest_data, expires, crl_uri, crl, files })
+    }
+
+    /// Processes a single object of a publication point.
+    #[allow(clippy::too_many_arguments)]
+    fn process_object(
+        &self,
+        kind: ObjectKind,
+        data: Bytes,
+        issuer: &ResourceCert,
+        crl_uri: &uri::Rsync,
+        crl: &Crl,
+        ancestors: &[KeyIdentifier],
+        now: Time,
+        res: &mut CaOutcome,
+    ) -> Result<ObjectStatus, &'static str> {
+        let cache = self.verification_cache.as_deref();
+        let check_crl = |cert: &Cert| {
+            if cert.crl_uri() != Some(crl_uri) {
+                return Err(ValidationError)
+            }
+            if crl.contains(cert.serial_number()) {
+                return Err(ValidationError)
+            }
+            Ok(())
+        };
+
+        match kind {
+            ObjectKind::Certificate => {
+                let cert = Cert::decode(data).map_err(|_| {
+                    "certificate cannot be decoded"
+                })?;
+                res.note_validity(cert.validity(), now);
+                if cert.is_ca() {
+                    if ancestors.contains(&cert.subject_key_identifier()) {
+                        return Err("certificate loop")
+                    }
+                    if ancestors.len() > self.max_depth {
+                        return Err("maximum CA depth exceeded")
+                    }
+                    check_crl(&cert).map_err(|_| "certificate revoked")?;
+                    let cert = cert.validate_ca_at_cached(
+                        issuer, self.strict, now, cache
+                    ).map_err(|_| "CA certificate invalid")?;
+                    res.children.push(CaTask {
+                        ancestors: ancestors.into(), cert
+                    });
+                }
+                else {
+                    check_crl(&cert).map_err(|_| "certificate revoked")?;
+                    cert.validate_router_at_cached(
+                        issuer, self.strict, now, cache
+                    ).map_err(|_| "router certificate invalid")?;
+                }
+            }
+            ObjectKind::Roa => {
+                let roa = Roa::decode(data, self.strict).map_err(|_| {
+                    "ROA cannot be decoded"
+                })?;
+                res.note_validity(roa.cert().validity(), now);
+                let (_, route) = roa.process_at_cached(
+                    issuer, self.strict, now, cache, check_crl
+                ).map_err(|_| "ROA invalid")?;
+                res.vrps.extend(route.iter().map(|addr| Vrp {
+                    asn: route.as_id(),
+                    addr: addr.address(),
+                    prefix_len: addr.address_length(),
+                    max_len: addr.max_length(),
+                }));
+            }
+            ObjectKind::Aspa => {
+                let aspa = Aspa::decode(data, self.strict).map_err(|_| {
+                    "ASPA cannot be decoded"
+                })?;
+                res.note_validity(aspa.cert().validity(), now);
+                let (_, aspa) = aspa.process_at_cached(
+                    issuer, self.strict, now, cache, check_crl
+                ).map_err(|_| "ASPA invalid")?;
+                res.aspas.push(AspaPayload {
+                    customer: aspa.customer_as(),
+                    providers: aspa.provider_as_set().iter().map(|item| {
+                        item.provider()
+                    }).collect(),
+                });
+            }
+            ObjectKind::Crl => { }
+            ObjectKind::Manifest => {
+                return Err("additional manifest")
+            }
+            ObjectKind::Gbr | ObjectKind::Other => {
+                return Ok(ObjectStatus::Skipped("not evaluated".into()))
+            }
+        }
+        Ok(ObjectStatus::Valid)
+    }
+}
+
+
+//--- Default
+
+impl Default for Validator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+//--- Debug
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Validator")
+            .field("strict", &self.strict)
+            .field("max_depth", &self.max_depth)
+            .field("threads", &self.threads)
+            .field("verification_cache", &self.verification_cache.is_some())
+            .field("last_good", &self.last_good.is_some())
+            .finish()
+    }
+}
+
+
+//------------ CaTask --------------------------------------------------------
+
+/// A CA whose publication point still needs to be processed.
+#[derive(Clone, Debug)]
+pub(crate) struct CaTask {
+    /// The key identifiers of all CAs above this one.
+    pub ancestors: Vec<KeyIdentifier>,
+
+    /// The validated CA certificate.
+    pub cert: ResourceCert,
+}
+
+
+//------------ CaOutcome -----------------------------------------------------
+
+/// The result of processing the publication point of a CA.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CaOutcome {
+    /// The child CAs to process next.
+    pub children: Vec<CaTask>,
+
+    /// The route origins found.
+    pub vrps: Vec<Vrp>,
+
+    /// The provider authorizations found.
+    pub aspas: Vec<AspaPayload>,
+
+    /// The report for the publication point.
+    pub pub_point: Option<PubPointReport>,
+
+    /// The reports for the objects.
+    pub objects: Vec<ObjectReport>,
+
+    /// The earliest time after `now` when the outcome may be different.
+    ///
+    /// This is the earliest start or end of the validity of any of the
+    /// objects considered that lies in the future. If it is `None`, the
+    /// outcome will never change with time.
+    pub next_change: Option<Time>,
+}
+
+impl CaOutcome {
+    /// Notes a time when the outcome may change.
+    fn note_time(&mut self, time: Time, now: Time) {
+        if time > now && self.next_change.map(|t| time < t).unwrap_or(true) {
+            self.next_change = Some(time)
+        }
+    }
+
+    /// Notes both ends of a validity period.
+    fn note_validity(&mut self, validity: Validity, now: Time) {
+        self.note_time(validity.not_before(), now);
+        self.note_time(validity.not_after(), now);
+    }
+}
+
+
+//------------ PubPoint ------------------------------------------------------
+
+/// The checked content of a publication point.
+struct PubPoint {
+    /// The content of the manifest.
+    manifest: Bytes,
+
+    /// The time when the manifest or CRL become stale.
+    expires: Time,
+
+    /// The URI of the CRL.
+    crl_uri: uri::Rsync,
+
+    /// The CRL.
+    crl: Crl,
+
+    /// The URIs and content of all files listed on the manifest.
+    files: ObjectList,
+}
+
+impl PubPoint {
+    /// Converts the publication point into a last known good copy.
+    fn to_stored(&self, manifest_uri: uri::Rsync) -> StoredPoint {
+        StoredPoint::new(
+            manifest_uri, self.manifest.clone(),
+            self.files.iter().cloned().collect(),
+            self.expires
+        )
+    }
+}
+
+/// A list of URIs and content of objects.
+type ObjectList = Vec<(uri::Rsync, Bytes)>;
+
+
+//------------ CaPoint -------------------------------------------------------
+
+/// A CA with a current publication point.
+///
+/// This is everything needed to process the objects of the publication
+/// point. It is shared between the jobs processing these objects.
+struct CaPoint {
+    /// The validated CA certificate.
+    cert: ResourceCert,
+
+    /// The key identifiers of the CA and all CAs above it.
+    ancestors: Vec<KeyIdentifier>,
+
+    /// The URI of the CA’s CRL.
+    crl_uri: uri::Rsync,
+
+    /// The CA’s CRL.
+    crl: Crl,
+}
+
+
+//------------ Job -----------------------------------------------------------
+
+/// The maximum number of objects processed by a single job.
+const OBJECT_BATCH_SIZE: usize = 64;
+
+/// A unit of work for the scheduler.
+///
+/// Each job has a path that describes its position in the tree: the index
+/// of the trust anchor followed by the index of each job that produced
+/// the next. Sorting outcomes by their path results in the same order no
+/// matter in which order the jobs actually ran.
+enum Job {
+    /// Load the publication point of a CA.
+    Ca {
+        path: Vec<usize>,
+        task: Box<CaTask>,
+    },
+
+    /// Process a batch of objects of a publication point.
+    Objects {
+        path: Vec<usize>,
+        point: Arc<CaPoint>,
+        files: ObjectList,
+    },
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns the path of the `idx`th job produced by the job at `path`.
+fn child_path(path: &[usize], idx: usize) -> Vec<usize> {
+    let mut res = Vec::with_capacity(path.len() + 1);
+    res.extend_from_slice(path);
+    res.push(idx);
+    res
+}
+
+/// Loads an object, converting errors into a reason string.
+fn load(
+    source: &impl ObjectSource, uri: &uri::Rsync
+) -> Result<Option<Bytes>, String> {
+    source.load(uri).map_err(|err| {
+        format!("failed to load {}: {}", uri, err)
+    })
+}
+
+
+//------------ TrustAnchorError ----------------------------------------------
+
+/// Loading a trust anchor certificate failed.
+#[derive(Debug)]
+pub enum TrustAnchorError {
+    /// None of the TAL’s rsync URIs led to a certificate.
+    NotFound,
+
+    /// The certificate could not be decoded.
+    Malformed,
+
+    /// The certificate’s key did not match the key of the TAL.
+    KeyMismatch,
+
+    /// The certificate is not a valid trust anchor certificate.
+    Invalid(ValidationError),
+
+    /// Loading the certificate failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for TrustAnchorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TrustAnchorError::NotFound
+                => f.write_str("trust anchor certificate not found"),
+            TrustAnchorError::Malformed
+                => f.write_str("malformed trust anchor certificate"),
+            TrustAnchorError::KeyMismatch
+                => f.write_str("trust anchor key does not match TAL"),
+            TrustAnchorError::Invalid(_)
+                => f.write_str("invalid trust anchor certificate"),
+            TrustAnchorError::Io(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for TrustAnchorError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(all(test, feature = "softkeys"))]
+mod signer_test {
+    use std::slice;
+    use chrono::Duration;
+    use bytes::Bytes;
+    use crate::clock::ManualClock;
+    use super::super::testrepo::{TestRepo, Which};
+    use super::*;
+
+    #[test]
+    fn validate_current() {
+        let repo = TestRepo::new();
+        let report = Validator::new().validate_at(
+            slice::from_ref(&repo.ta), &repo.archive,
+            TestRepo::base_time() + Duration::days(1)
+        );
+        assert!(report.trust_anchors()[0].status.is_valid());
+        assert_eq!(report.pub_points().len(), 2);
+        assert!(report.pub_points().iter().all(|item| {
+            item.status.is_current()
+        }));
+        assert!(report.objects().iter().filter(|item| {
+            item.kind != ObjectKind::Other
+        }).all(|item| item.status.is_valid()));
+        assert_eq!(
+            report.vrps(),
+            &[Vrp {
+                asn: 64496.into(),
+                addr: "192.0.2.0".parse().unwrap(),
+                prefix_len: 24, max_len: 24
+            }]
+        );
+        assert_eq!(
+            report.aspas(),
+            &[AspaPayload {
+                customer: 64496.into(),
+                providers: vec![64497.into(), 64498.into()]
+            }]
+        );
+    }
+
+    #[test]
+    fn validate_with_clock() {
+        let repo = TestRepo::new();
+        let clock = ManualClock::new(
+            (TestRepo::base_time() + Duration::days(1)).into()
+        );
+        let mut validator = Validator::new();
+        validator.set_clock(Arc::new(clock.clone()));
+        let tas = slice::from_ref(&repo.ta);
+        assert_eq!(validator.validate(tas, &repo.archive).vrps().len(), 1);
+
+        // Past the manifests’ next update everything is gone.
+        clock.advance(Duration::days(7));
+        let report = validator.validate(tas, &repo.archive);
+        assert!(report.vrps().is_empty());
+        assert_eq!(report.pub_points().len(), 1);
+        assert!(!report.pub_points()[0].status.is_current());
+    }
+
+    #[test]
+    fn validate_threaded() {
+        let repo = TestRepo::new();
+        let now = TestRepo::base_time() + Duration::days(1);
+        let tas = [repo.ta.clone(), repo.ta.clone()];
+        let single = Validator::new().validate_at(&tas, &repo.archive, now);
+        let mut validator = Validator::new();
+        validator.set_threads(4);
+        for _ in 0..4 {
+            let multi = validator.validate_at(&tas, &repo.archive, now);
+            assert_eq!(single.vrps(), multi.vrps());
+            assert_eq!(single.aspas(), multi.aspas());
+            assert_eq!(
+                format!("{:?}", single.pub_points()),
+                format!("{:?}", multi.pub_points())
+            );
+            assert_eq!(
+                format!("{:?}", single.objects()),
+                format!("{:?}", multi.objects())
+            );
+        }
+        assert_eq!(single.pub_points().len(), 4);
+    }
+
+    #[test]
+    fn validate_with_verification_cache() {
+        let mut repo = TestRepo::new();
+        let now = TestRepo::base_time() + Duration::days(1);
+        let cache = Arc::new(VerificationCache::new());
+        let mut validator = Validator::new();
+        validator.set_verification_cache(Some(cache.clone()));
+        let ta = repo.ta.clone();
+        let tas = slice::from_ref(&ta);
+
+        let first = validator.validate_at(tas, &repo.archive, now);
+        assert_eq!(first.vrps().len(), 1);
+        let len = cache.len();
+        assert!(len > 0);
+        let second = validator.validate_at(tas, &repo.archive, now);
+        assert_eq!(first.vrps(), second.vrps());
+        assert_eq!(first.aspas(), second.aspas());
+        assert_eq!(cache.len(), len);
+
+        // Validity and revocation are still checked for cached objects.
+        let report = validator.validate_at(
+            tas, &repo.archive, now + Duration::days(400)
+        );
+        assert!(report.vrps().is_empty());
+        let child = repo.archive.get(&Which::Child.cert()).unwrap().clone();
+        let serial = Cert::decode(child.clone()).unwrap().serial_number();
+        repo.publish_with_revoked(
+            Which::Ta, 2, now, now + Duration::days(7),
+            vec![("child.cer", child)], vec![serial]
+        );
+        let report = validator.validate_at(tas, &repo.archive, now);
+        assert!(report.vrps().is_empty());
+    }
+
+    #[test]
+    fn validate_before_creation() {
+        let repo = TestRepo::new();
+        let report = Validator::new().validate_at(
+            slice::from_ref(&repo.ta), &repo.archive,
+            TestRepo::base_time() - Duration::days(2)
+        );
+        assert!(!report.trust_anchors()[0].status.is_valid());
+        assert!(report.pub_points().is_empty());
+        assert!(report.vrps().is_empty());
+    }
+
+    #[test]
+    fn hash_mismatch_rejects_pub_point() {
+        let mut repo = TestRepo::new();
+        repo.archive.insert(
+            Which::Child.ca_repository().join(b"roa.roa").unwrap(),
+            Bytes::from_static(b"foo")
+        );
+        let report = Validator::new().validate_at(
+            slice::from_ref(&repo.ta), &repo.archive,
+            TestRepo::base_time() + Duration::days(1)
+        );
+        assert!(report.vrps().is_empty());
+        assert!(report.aspas().is_empty());
+        let point = report.pub_points().iter().find(|item| {
+            item.manifest == Which::Child.manifest()
+        }).unwrap();
+        assert!(!point.status.is_current());
+    }
+
+    #[test]
+    fn last_known_good() {
+        let mut repo = TestRepo::new();
+        let now = TestRepo::base_time() + Duration::days(1);
+        let store = Arc::new(LastKnownGood::new());
+        let mut validator = Validator::new();
+        validator.set_last_known_good(Some(store.clone()));
+        let ta = repo.ta.clone();
+        let tas = slice::from_ref(&ta);
+
+        let report = validator.validate_at(tas, &repo.archive, now);
+        assert_eq!(report.vrps().len(), 1);
+        assert_eq!(store.len(), 2);
+
+        // Break the child’s publication point. The stored copy is used.
+        repo.archive.insert(
+            Which::Child.ca_repository().join(b"roa.roa").unwrap(),
+            Bytes::from_static(b"foo")
+        );
+        let report = validator.validate_at(tas, &repo.archive, now);
+        assert_eq!(report.vrps().len(), 1);
+        assert_eq!(report.aspas().len(), 1);
+        let point = report.pub_points().iter().find(|item| {
+            item.manifest == Which::Child.manifest()
+        }).unwrap();
+        assert!(!point.status.is_current());
+        assert!(point.status.is_used());
+        assert!(matches!(point.status, PubPointStatus::LastKnownGood(_)));
+
+        // Once the stored copy is stale, it can’t be used anymore.
+        let report = validator.validate_at(
+            tas, &repo.archive, now + Duration::days(7)
+        );
+        assert!(report.vrps().is_empty());
+        store.prune(now + Duration::days(7));
+        assert!(store.is_empty());
+
+        // Without the store, the publication point simply fails.
+        let report = Validator::new().validate_at(tas, &repo.archive, now);
+        assert!(report.vrps().is_empty());
+    }
+
+    #[test]
+    fn revoked_child() {
+        let mut repo = TestRepo::new();
+        let now = TestRepo::base_time();
+        let child = repo.make_child_cert(TestRepo::cert_validity());
+        let serial = Cert::decode(child.clone()).unwrap().serial_number();
+        repo.publish_with_revoked(
+            Which::Ta, 2, now, now + Duration::days(7),
+            vec![("child.cer", child)], vec![serial]
+        );
+        let report = Validator::new().validate_at(
+            slice::from_ref(&repo.ta), &repo.archive, now + Duration::days(1)
+        );
+        assert!(report.vrps().is_empty());
+        assert_eq!(
+            report.object(
+                &Which::Ta.ca_repository().join(b"child.cer").unwrap()
+            ).unwrap().status,
+            ObjectStatus::Invalid("certificate revoked".into())
+        );
+    }
+}
@@ -0,0 +1,1103 @@
+//! Scheduling repository fetches.
+//!
+//! Before publication points can be validated, the repositories they live
+//! in need to be fetched. This module provides a [`FetchScheduler`] that
+//! keeps track of all repositories discovered during validation and
+//! decides when and how each of them is to be refreshed. The actual
+//! fetching is left to a type implementing [`RepositoryFetcher`].
+//!
+//! Repositories are identified by a [`RepositoryKey`]. CAs that announce an
+//! RRDP notification URI via `rpki_notify` share the repository of that
+//! URI while all other CAs are fetched via rsync and share the repository
+//! of their rsync module.
+//!
+//! RRDP repositories are always fetched via RRDP first. Once the number
+//! of consecutive RRDP failures reaches a threshold, the rsync modules of
+//! the CAs using the repository are fetched as a fallback whenever RRDP
+//! fails again. Failed fetches are retried after a delay that doubles with
+//! each consecutive failure up to a maximum. Successful fetches are
+//! repeated after the refresh interval.
+//!
+//! Fetches run on a configurable number of threads, but at most a given
+//! number of fetches run against the same host at the same time.
+
+use std::{cmp, fmt};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use crate::uri;
+use crate::clock::{Clock, SystemClock};
+use crate::repository::cert::Cert;
+use super::report::Report;
+
+
+//------------ Configuration Defaults ----------------------------------------
+
+/// The default time between successful fetches of a repository.
+pub const DEFAULT_REFRESH: Duration = Duration::from_secs(600);
+
+/// The default delay after the first failed fetch.
+pub const DEFAULT_MIN_BACKOFF: Duration = Duration::from_secs(60);
+
+/// The default maximum delay after failed fetches.
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(3600);
+
+/// The default number of RRDP failures before falling back to rsync.
+pub const DEFAULT_FALLBACK_THRESHOLD: usize = 3;
+
+/// The default number of concurrent fetches against the same host.
+pub const DEFAULT_MAX_PER_HOST: usize = 2;
+
+/// The longest delay until the next fetch.
+///
+/// Longer delays are cut down to this value to keep times representable.
+const MAX_DELAY: Duration = Duration::from_secs(100 * 365 * 24 * 3600);
+
+
+//------------ RepositoryFetcher ---------------------------------------------
+
+/// A type that can fetch repositories.
+///
+/// The fetcher is expected to write the fetched objects to wherever the
+/// validator will later find them. Errors are reported as a string
+/// describing what went wrong.
+pub trait RepositoryFetcher {
+    /// Fetches the RRDP repository with the given notification URI.
+    fn fetch_rrdp(&self, notify_uri: &uri::Https) -> Result<(), String>;
+
+    /// Fetches the given rsync module.
+    fn fetch_rsync(&self, module: &uri::Rsync) -> Result<(), String>;
+}
+
+impl<F: RepositoryFetcher + ?Sized> RepositoryFetcher for &F {
+    fn fetch_rrdp(&self, notify_uri: &uri::Https) -> Result<(), String> {
+        (*self).fetch_rrdp(notify_uri)
+    }
+
+    fn fetch_rsync(&self, module: &uri::Rsync) -> Result<(), String> {
+        (*self).fetch_rsync(module)
+    }
+}
+
+
+//------------ RepositoryKey -------------------------------------------------
+
+/// The identity of a repository.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum RepositoryKey {
+    /// An RRDP repository identified by its notification URI.
+    Rrdp(uri::Https),
+
+    /// An rsync-only repository identified by its module.
+    ///
+    /// The URI is the canonical module URI, i.e., it has an empty path and
+    /// a lowercase authority.
+    Rsync(uri::Rsync),
+}
+
+impl RepositoryKey {
+    /// Returns the key for a CA with the given repository URIs.
+    pub fn for_ca(
+        rpki_notify: Option<&uri::Https>, ca_repository: &uri::Rsync
+    ) -> Self {
+        match rpki_notify {
+            Some(uri) => RepositoryKey::Rrdp(uri.clone()),
+            None => RepositoryKey::Rsync(rsync_module(ca_repository)),
+        }
+    }
+
+    /// Returns the host the repository is fetched from first.
+    pub fn host(&self) -> String {
+        match *self {
+            RepositoryKey::Rrdp(ref uri) => {
+                uri.canonical_authority().into_owned()
+            }
+            RepositoryKey::Rsync(ref uri) => {
+                uri.canonical_authority().into_owned()
+            }
+        }
+    }
+}
+
+impl fmt::Display for RepositoryKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RepositoryKey::Rrdp(ref uri) => uri.fmt(f),
+            RepositoryKey::Rsync(ref uri) => uri.fmt(f),
+        }
+    }
+}
+
+
+//------------ FetchMethod ---------------------------------------------------
+
+/// The method used to successfully fetch a repository.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FetchMethod {
+    /// The repository was fetched via RRDP.
+    Rrdp,
+
+    /// RRDP failed and the rsync modules were fetched instead.
+    RsyncFallback,
+
+    /// The rsync-only repository was fetched via rsync.
+    Rsync,
+}
+
+
+//------------ RepositoryStatus ----------------------------------------------
+
+/// The fetch status of a repository.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RepositoryStatus {
+    /// The time the repository is to be fetched next.
+    ///
+    /// If this is `None`, the repository has never been fetched and is
+    /// due immediately.
+    pub next_due: Option<DateTime<Utc>>,
+
+    /// The time of the last attempt to fetch the repository.
+    pub last_attempt: Option<DateTime<Utc>>,
+
+    /// The time of the last successful fetch.
+    pub last_success: Option<DateTime<Utc>>,
+
+    /// The method used for the last successful fetch.
+    pub last_method: Option<FetchMethod>,
+
+    /// The number of consecutive failed fetches.
+    pub failures: usize,
+
+    /// The number of consecutive failed RRDP fetches.
+    ///
+    /// This is always zero for rsync-only repositories.
+    pub rrdp_failures: usize,
+
+    /// The errors of the last attempt.
+    pub errors: Vec<String>,
+}
+
+impl RepositoryStatus {
+    /// Returns whether the repository is due at the given time.
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.next_due.map(|due| due <= now).unwrap_or(true)
+    }
+}
+
+
+//------------ FetchReport ---------------------------------------------------
+
+/// The outcome of fetching a repository.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FetchReport {
+    /// The repository.
+    pub key: RepositoryKey,
+
+    /// The method that succeeded or `None` if the fetch failed.
+    pub method: Option<FetchMethod>,
+
+    /// The errors that occurred.
+    ///
+    /// This can be non-empty even if the fetch succeeded when RRDP failed
+    /// but the rsync fallback worked.
+    pub errors: Vec<String>,
+
+    /// The time the repository is to be fetched next.
+    pub next_due: DateTime<Utc>,
+}
+
+impl FetchReport {
+    /// Returns whether the repository was fetched successfully.
+    pub fn is_success(&self) -> bool {
+        self.method.is_some()
+    }
+}
+
+
+//------------ FetchScheduler ------------------------------------------------
+
+/// Decides when and how to fetch repositories.
+pub struct FetchScheduler {
+    /// The known repositories.
+    repositories: HashMap<RepositoryKey, Repository>,
+
+    /// The clock to take the current time from.
+    clock: Arc<dyn Clock + Send + Sync>,
+
+    /// The time between successful fetches.
+    refresh: Duration,
+
+    /// The delay after the first failed fetch.
+    min_backoff: Duration,
+
+    /// The maximum delay after failed fetches.
+    max_backoff: Duration,
+
+    /// The number of RRDP failures before falling back to rsync.
+    fallback_threshold: usize,
+
+    /// The maximum number of concurrent fetches against a host.
+    max_per_host: usize,
+
+    /// The number of threads to use for fetching.
+    threads: usize,
+}
+
+impl Default for FetchScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FetchScheduler {
+    /// Creates a new scheduler with default settings.
+    ///
+    /// The scheduler uses the system clock and a single thread.
+    pub fn new() -> Self {
+        FetchScheduler {
+            repositories: HashMap::new(),
+            clock: Arc::new(SystemClock),
+            refresh: DEFAULT_REFRESH,
+            min_backoff: DEFAULT_MIN_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            fallback_threshold: DEFAULT_FALLBACK_THRESHOLD,
+            max_per_host: DEFAULT_MAX_PER_HOST,
+            threads: 1,
+        }
+    }
+
+    /// Sets the clock used to determine the current time.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock + Send + Sync>) {
+        self.clock = clock
+    }
+
+    /// Sets the time between successful fetches of a repository.
+    pub fn set_refresh(&mut self, refresh: Duration) {
+        self.refresh = refresh
+    }
+
+    /// Sets the minimum and maximum delay after failed fetches.
+    ///
+    /// The delay after the first failure is `min`. It doubles with every
+    /// further consecutive failure but never exceeds `max`.
+    pub fn set_backoff(&mut self, min: Duration, max: Duration) {
+        self.min_backoff = min;
+        self.max_backoff = max;
+    }
+
+    /// Sets the number of consecutive RRDP failures before using rsync.
+    ///
+    /// If this is zero, rsync is used whenever RRDP fails.
+    pub fn set_fallback_threshold(&mut self, threshold: usize) {
+        self.fallback_threshold = threshold
+    }
+
+    /// Sets the maximum number of concurrent fetches against a host.
+    ///
+    /// A value of zero is treated as one.
+    pub fn set_max_per_host(&mut self, max: usize) {
+        self.max_per_host = cmp::max(max, 1)
+    }
+
+    /// Sets the number of threads used for fetching.
+    ///
+    /// If this is zero or one, all fetches happen on the current thread.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads
+    }
+
+    /// Adds the repository of a CA.
+    ///
+    /// If the CA provides an RRDP notification URI in `rpki_notify`, it
+    /// uses the RRDP repository of that URI with the rsync module of
+    /// `ca_repository` as a fallback. Otherwise, it uses the rsync module
+    /// of `ca_repository`. Adding a repository that is already known only
+    /// adds the rsync module to its fallbacks.
+    ///
+    /// Returns the key of the repository.
+    pub fn add(
+        &mut self,
+        rpki_notify: Option<&uri::Https>,
+        ca_repository: &uri::Rsync,
+    ) -> RepositoryKey {
+        let key = RepositoryKey::for_ca(rpki_notify, ca_repository);
+        let repository = self.repositories.entry(key.clone()).or_default();
+        if rpki_notify.is_some() {
+            repository.modules.insert(rsync_module(ca_repository));
+        }
+        key
+    }
+
+    /// Adds the repository of a CA certificate.
+    ///
+    /// Does nothing if the certificate has no CA repository.
+    pub fn add_cert(&mut self, cert: &Cert) -> Option<RepositoryKey> {
+        cert.ca_repository().map(|repository| {
+            self.add(cert.rpki_notify(), repository)
+        })
+    }
+
+    /// Adds the repositories of all publication points in a report.
+    pub fn add_report(&mut self, report: &Report) {
+        for point in report.pub_points() {
+            self.add(point.rpki_notify.as_ref(), &point.ca_repository);
+        }
+    }
+
+    /// Removes a repository.
+    pub fn remove(&mut self, key: &RepositoryKey) {
+        self.repositories.remove(key);
+    }
+
+    /// Returns an iterator over the keys of all known repositories.
+    pub fn keys(&self) -> impl Iterator<Item = &RepositoryKey> {
+        self.repositories.keys()
+    }
+
+    /// Returns the status of a repository.
+    pub fn status(&self, key: &RepositoryKey) -> Option<&RepositoryStatus> {
+        self.repositories.get(key).map(|repository| &repository.status)
+    }
+
+    /// Returns the keys of all repositories due at the current time.
+    ///
+    /// The keys are returned sorted by their due time with new
+    /// repositories first.
+    pub fn due(&self) -> Vec<RepositoryKey> {
+        let now = self.clock.now();
+        let mut res: Vec<_> = self.repositories.iter().filter(|item| {
+            item.1.status.is_due(now)
+        }).collect();
+        res.sort_by(|left, right| {
+            left.1.status.next_due.cmp(&right.1.status.next_due).then_with(
+                || left.0.to_string().cmp(&right.0.to_string())
+            )
+        });
+        res.into_iter().map(|item| item.0.clone()).collect()
+    }
+
+    /// Returns the earliest time any repository is due.
+    ///
+    /// Returns `None` if there are no repositories. Repositories that
+    /// have never been fetched are due at the current time.
+    pub fn next_due(&self) -> Option<DateTime<Utc>> {
+        let now = self.clock.now();
+        self.repositories.values().map(|repository| {
+            repository.status.next_due.unwrap_or(now)
+        }).min()
+    }
+
+    /// Fetches all repositories that are due.
+    ///
+    /// Returns a report for every repository that was fetched, sorted by
+    /// the repository key.
+    pub fn run<F: RepositoryFetcher + Sync>(
+        &mut self, fetcher: &F
+    ) -> Vec<FetchReport> {
+        let jobs: VecDeque<_> = self.due().into_iter().map(|key| {
+            let modules = self.repositories[&key].modules.iter().cloned();
+            let mut modules: Vec<_> = modules.collect();
+            modules.sort_by(|left, right| left.as_str().cmp(right.as_str()));
+            let rrdp_failures = self.repositories[&key].status.rrdp_failures;
+            Job { key, modules, rrdp_failures }
+        }).collect();
+        let pool = Pool::new(jobs, self.max_per_host);
+        let pool = &pool;
+        let threads = cmp::max(self.threads, 1);
+        let this = &*self;
+        let outcomes: Vec<_> = if threads == 1 {
+            pool.work(this, fetcher)
+        }
+        else {
+            crossbeam_utils::thread::scope(|scope| {
+                let workers: Vec<_> = (0..threads).map(|_| {
+                    scope.spawn(move |_| pool.work(this, fetcher))
+                }).collect();
+                workers.into_iter().flat_map(|worker| {
+                    match worker.join() {
+                        Ok(res) => res,
+                        Err(err) => std::panic::resume_unwind(err)
+                    }
+                }).collect()
+            }).unwrap_or_else(|err| std::panic::resume_unwind(err))
+        };
+
+        let mut res: Vec<_> = outcomes.into_iter().map(|outcome| {
+            self.finish(outcome)
+        }).collect();
+        res.sort_by(|left, right| {
+            left.key.to_string().cmp(&right.key.to_string())
+        });
+        res
+    }
+
+    /// Fetches a single repository.
+    ///
+    /// The slot for the repository’s host is released before falling back
+    /// to rsync.
+    fn fetch(
+        &self,
+        job: Job,
+        slot: HostSlot<'_>,
+        pool: &Pool,
+        fetcher: &impl RepositoryFetcher,
+    ) -> Outcome {
+        let mut errors = Vec::new();
+        let res = match job.key {
+            RepositoryKey::Rrdp(ref notify_uri) => {
+                fetcher.fetch_rrdp(notify_uri).map_err(|err| {
+                    format!("{}: {}", notify_uri, err)
+                })
+            }
+            RepositoryKey::Rsync(ref module) => {
+                fetcher.fetch_rsync(module).map_err(|err| {
+                    format!("{}: {}", module, err)
+                })
+            }
+        };
+        drop(slot);
+        let method = match (res, &job.key) {
+            (Ok(()), RepositoryKey::Rrdp(_)) => Some(FetchMethod::Rrdp),
+            (Ok(()), RepositoryKey::Rsync(_)) => Some(FetchMethod::Rsync),
+            (Err(err), RepositoryKey::Rrdp(_)) => {
+                errors.push(err);
+                if job.rrdp_failures + 1 >= self.fallback_threshold
+                    && !job.modules.is_empty()
+                    && self.fetch_modules(
+                        &job.modules, pool, fetcher, &mut errors
+                    )
+                {
+                    Some(FetchMethod::RsyncFallback)
+                }
+                else {
+                    None
+                }
+            }
+            (Err(err), RepositoryKey::Rsync(_)) => {
+                errors.push(err);
+                None
+            }
+        };
+        Outcome { key: job.key, method, errors, when: self.clock.now() }
+    }
+
+    /// Fetches the rsync modules of an RRDP repository.
+    ///
+    /// Returns whether all modules were fetched successfully.
+    fn fetch_modules(
+        &self,
+        modules: &[uri::Rsync],
+        pool: &Pool,
+        fetcher: &impl RepositoryFetcher,
+        errors: &mut Vec<String>,
+    ) -> bool {
+        let mut res = true;
+        for module in modules {
+            let _slot = pool.acquire(
+                module.canonical_authority().into_owned()
+            );
+            if let Err(err) = fetcher.fetch_rsync(module) {
+                errors.push(format!("{}: {}", module, err));
+                res = false;
+            }
+        }
+        res
+    }
+
+    /// Updates the status of a repository after a fetch.
+    fn finish(&mut self, outcome: Outcome) -> FetchReport {
+        let refresh = self.refresh;
+        let (min_backoff, max_backoff) = (self.min_backoff, self.max_backoff);
+        let backoff = |failures: usize| {
+            backoff(min_backoff, max_backoff, failures)
+        };
+        let repository = self.repositories.entry(
+            outcome.key.clone()
+        ).or_default();
+        let status = &mut repository.status;
+        status.last_attempt = Some(outcome.when);
+        let delay = match outcome.method {
+            Some(FetchMethod::Rrdp) | Some(FetchMethod::Rsync) => {
+                status.failures = 0;
+                status.rrdp_failures = 0;
+                refresh
+            }
+            Some(FetchMethod::RsyncFallback) => {
+                status.failures = 0;
+                status.rrdp_failures += 1;
+                cmp::max(refresh, backoff(status.rrdp_failures))
+            }
+            None => {
+                status.failures += 1;
+                if let RepositoryKey::Rrdp(_) = outcome.key {
+                    status.rrdp_failures += 1;
+                }
+                backoff(status.failures)
+            }
+        };
+        if outcome.method.is_some() {
+            status.last_success = Some(outcome.when);
+            status.last_method = outcome.method;
+        }
+        let next_due = outcome.when + chrono::Duration::from_std(
+            cmp::min(delay, MAX_DELAY)
+        ).unwrap_or_else(|_| chrono::Duration::zero());
+        status.next_due = Some(next_due);
+        status.errors = outcome.errors.clone();
+        FetchReport {
+            key: outcome.key,
+            method: outcome.method,
+            errors: outcome.errors,
+            next_due,
+        }
+    }
+}
+
+impl fmt::Debug for FetchScheduler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FetchScheduler")
+            .field("repositories", &self.repositories)
+            .field("refresh", &self.refresh)
+            .field("min_backoff", &self.min_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("fallback_threshold", &self.fallback_threshold)
+            .field("max_per_host", &self.max_per_host)
+            .field("threads", &self.threads)
+            .finish()
+    }
+}
+
+
+//------------ Repository ----------------------------------------------------
+
+/// Everything the scheduler knows about a repository.
+#[derive(Clone, Debug, Default)]
+struct Repository {
+    /// The rsync modules to fall back to.
+    ///
+    /// This is only used for RRDP repositories.
+    modules: HashSet<uri::Rsync>,
+
+    /// The fetch status.
+    status: RepositoryStatus,
+}
+
+
+
+//------------ Job and Outcome -----------------------------------------------
+
+/// A repository to be fetched.
+struct Job {
+    /// The repository.
+    key: RepositoryKey,
+
+    /// The rsync modules to fall back to, sorted.
+    modules: Vec<uri::Rsync>,
+
+    /// The number of consecutive RRDP failures before this fetch.
+    rrdp_failures: usize,
+}
+
+/// The outcome of fetching a repository.
+struct Outcome {
+    /// The repository.
+    key: RepositoryKey,
+
+    /// The method that succeeded if any.
+    method: Option<FetchMethod>,
+
+    /// The errors that occurred.
+    errors: Vec<String>,
+
+    /// The time the fetch finished.
+    when: DateTime<Utc>,
+}
+
+
+//------------ Pool ----------------------------------------------------------
+
+/// The jobs of a run shared between the worker threads.
+struct Pool {
+    /// The state protected by a mutex.
+    state: Mutex<PoolState>,
+
+    /// Signals that a host slot was released.
+    released: Condvar,
+
+    /// The maximum number of concurrent fetches per host.
+    max_per_host: usize,
+}
+
+/// The mutable state of a pool.
+struct PoolState {
+    /// The jobs not yet started.
+    jobs: VecDeque<Job>,
+
+    /// The number of active fetches per host.
+    active: HashMap<String, usize>,
+}
+
+impl Pool {
+    /// Creates a new pool.
+    fn new(jobs: VecDeque<Job>, max_per_host: usize) -> Self {
+        Pool {
+            state: Mutex::new(PoolState { jobs, active: HashMap::new() }),
+            released: Condvar::new(),
+            max_per_host,
+        }
+    }
+
+    /// Runs jobs until there are none left.
+    fn work(
+        &self, scheduler: &FetchScheduler, fetcher: &impl RepositoryFetcher
+    ) -> Vec<Outcome> {
+        let mut res = Vec::new();
+        while let Some((job, slot)) = self.next_job() {
+            res.push(scheduler.fetch(job, slot, self, fetcher));
+        }
+        res
+    }
+
+    /// Takes the next job whose host has a free slot.
+    ///
+    /// Blocks until there is such a job. Returns `None` once all jobs
+    /// have been taken. Returns the job together with the acquired slot
+    /// for its host.
+    fn next_job(&self) -> Option<(Job, HostSlot<'_>)> {
+        let mut state = self.state.lock().expect("poisoned lock");
+        loop {
+            if state.jobs.is_empty() {
+                return None
+            }
+            let idx = state.jobs.iter().position(|job| {
+                state.active.get(&job.key.host()).copied().unwrap_or(0)
+                    < self.max_per_host
+            });
+            if let Some(idx) = idx {
+                let job = state.jobs.remove(idx)?;
+                let host = job.key.host();
+                *state.active.entry(host.clone()).or_default() += 1;
+                return Some((job, HostSlot { pool: self, host }))
+            }
+            state = self.released.wait(state).expect("poisoned lock");
+        }
+    }
+
+    /// Acquires a slot for a host, waiting until one is free.
+    fn acquire(&self, host: String) -> HostSlot<'_> {
+        let mut state = self.state.lock().expect("poisoned lock");
+        while state.active.get(&host).copied().unwrap_or(0)
+            >= self.max_per_host
+        {
+            state = self.released.wait(state).expect("poisoned lock");
+        }
+        *state.active.entry(host.clone()).or_default() += 1;
+        HostSlot { pool: self, host }
+    }
+
+    /// Releases a slot for a host.
+    fn release(&self, host: &str) {
+        // This is called while unwinding if a fetcher panics, so don’t
+        // panic again on a poisoned lock.
+        let mut state = self.state.lock().unwrap_or_else(|err| {
+            err.into_inner()
+        });
+        if let Some(count) = state.active.get_mut(host) {
+            *count = count.saturating_sub(1);
+        }
+        self.released.notify_all();
+    }
+}
+
+
+//------------ HostSlot ------------------------------------------------------
+
+/// An acquired slot for fetching from a host.
+///
+/// The slot is released when the value is dropped, including when a
+/// fetcher panics, so that other workers waiting for the host don’t
+/// block forever.
+struct HostSlot<'a> {
+    /// The pool the slot belongs to.
+    pool: &'a Pool,
+
+    /// The host of the slot.
+    host: String,
+}
+
+impl<'a> Drop for HostSlot<'a> {
+    fn drop(&mut self) {
+        self.pool.release(&self.host)
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns the delay after the given number of consecutive failures.
+fn backoff(min: Duration, max: Duration, failures: usize) -> Duration {
+    let shift = cmp::min(failures.saturating_sub(1), 31) as u32;
+    min.checked_mul(1 << shift).map(|delay| cmp::min(delay, max))
+        .unwrap_or(max)
+}
+
+/// Returns the canonical module URI for an rsync URI.
+fn rsync_module(uri: &uri::Rsync) -> uri::Rsync {
+    uri::Rsync::from_string(
+        uri.canonical_module().into_owned()
+    ).unwrap_or_else(|_| uri.clone())
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::{panic, thread};
+    use std::str::FromStr;
+    use chrono::TimeZone;
+    use crate::clock::ManualClock;
+    use super::*;
+
+    fn https(s: &str) -> uri::Https {
+        uri::Https::from_str(s).unwrap()
+    }
+
+    fn rsync(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    fn minutes(value: u64) -> Duration {
+        Duration::from_secs(value * 60)
+    }
+
+    /// A fetcher that fails for a configurable set of URIs.
+    #[derive(Default)]
+    struct FakeFetcher {
+        /// The URIs that fail.
+        failing: Mutex<HashSet<String>>,
+
+        /// The URIs fetched in order.
+        log: Mutex<Vec<String>>,
+
+        /// How long fetches from each host take.
+        delay: HashMap<String, Duration>,
+
+        /// The URIs whose fetch has finished in order.
+        finished: Mutex<Vec<String>>,
+
+        /// The number of active and maximum active fetches per host.
+        active: Mutex<HashMap<String, (usize, usize)>>,
+
+        /// A URI for which the fetcher panics.
+        panicking: Option<String>,
+    }
+
+    impl FakeFetcher {
+        fn set_failing(&self, uri: &str, failing: bool) {
+            let mut set = self.failing.lock().unwrap();
+            if failing {
+                set.insert(uri.into());
+            }
+            else {
+                set.remove(uri);
+            }
+        }
+
+        fn take_log(&self) -> Vec<String> {
+            let mut log = self.log.lock().unwrap();
+            log.sort();
+            log.drain(..).collect()
+        }
+
+        fn max_active(&self, host: &str) -> usize {
+            self.active.lock().unwrap().get(host).map(|x| x.1).unwrap_or(0)
+        }
+
+        fn fetch(&self, host: &str, uri: &str) -> Result<(), String> {
+            self.log.lock().unwrap().push(uri.into());
+            if self.panicking.as_deref() == Some(uri) {
+                panic!("fetching {}", uri)
+            }
+            {
+                let mut active = self.active.lock().unwrap();
+                let entry = active.entry(host.into()).or_default();
+                entry.0 += 1;
+                entry.1 = cmp::max(entry.0, entry.1);
+            }
+            if let Some(delay) = self.delay.get(host) {
+                thread::sleep(*delay);
+            }
+            self.active.lock().unwrap().get_mut(host).unwrap().0 -= 1;
+            self.finished.lock().unwrap().push(uri.into());
+            if self.failing.lock().unwrap().contains(uri) {
+                Err("failed".into())
+            }
+            else {
+                Ok(())
+            }
+        }
+    }
+
+    impl RepositoryFetcher for FakeFetcher {
+        fn fetch_rrdp(&self, uri: &uri::Https) -> Result<(), String> {
+            self.fetch(uri.authority(), uri.as_str())
+        }
+
+        fn fetch_rsync(&self, uri: &uri::Rsync) -> Result<(), String> {
+            self.fetch(&uri.canonical_authority(), uri.as_str())
+        }
+    }
+
+    fn scheduler() -> (FetchScheduler, ManualClock) {
+        // 2023-01-01T00:00:00Z
+        let clock = ManualClock::new(
+            Utc.timestamp_opt(1_672_531_200, 0).unwrap()
+        );
+        let mut scheduler = FetchScheduler::new();
+        scheduler.set_clock(Arc::new(clock.clone()));
+        scheduler.set_refresh(minutes(10));
+        scheduler.set_backoff(minutes(1), minutes(4));
+        scheduler.set_fallback_threshold(2);
+        (scheduler, clock)
+    }
+
+    #[test]
+    fn repository_keys() {
+        let (mut scheduler, _) = scheduler();
+        let notify = https("https://rrdp.example.net/notification.xml");
+        let rrdp = scheduler.add(
+            Some(&notify), &rsync("rsync://rsync.example.net/a/ca1/")
+        );
+        assert_eq!(
+            scheduler.add(
+                Some(&notify), &rsync("rsync://rsync.example.net/b/ca2/")
+            ),
+            rrdp
+        );
+        let plain = scheduler.add(
+            None, &rsync("rsync://Example.ORG/module/ca/sub/")
+        );
+        assert_eq!(
+            plain, RepositoryKey::Rsync(rsync("rsync://example.org/module/"))
+        );
+        assert_eq!(
+            scheduler.add(None, &rsync("rsync://example.org/module/other/")),
+            plain
+        );
+        assert_eq!(scheduler.keys().count(), 2);
+        assert_eq!(scheduler.due().len(), 2);
+
+        let fetcher = FakeFetcher::default();
+        let reports = scheduler.run(&fetcher);
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(FetchReport::is_success));
+        assert_eq!(
+            fetcher.take_log(),
+            [
+                "https://rrdp.example.net/notification.xml",
+                "rsync://example.org/module/",
+            ]
+        );
+        assert_eq!(
+            scheduler.status(&rrdp).unwrap().last_method,
+            Some(FetchMethod::Rrdp)
+        );
+        assert_eq!(
+            scheduler.status(&plain).unwrap().last_method,
+            Some(FetchMethod::Rsync)
+        );
+        assert!(scheduler.due().is_empty());
+    }
+
+    #[test]
+    fn backoff_and_fallback() {
+        let (mut scheduler, clock) = scheduler();
+        let start = clock.now();
+        let notify = "https://rrdp.example.net/notification.xml";
+        let key = scheduler.add(
+            Some(&https(notify)), &rsync("rsync://rsync.example.net/a/ca/")
+        );
+        let fetcher = FakeFetcher::default();
+        fetcher.set_failing(notify, true);
+
+        // First failure: no fallback yet, retry after a minute.
+        let reports = scheduler.run(&fetcher);
+        assert_eq!(reports[0].method, None);
+        assert_eq!(reports[0].errors.len(), 1);
+        assert_eq!(reports[0].next_due, start + chrono::Duration::minutes(1));
+        assert_eq!(fetcher.take_log(), [notify]);
+        assert!(scheduler.run(&fetcher).is_empty());
+        assert_eq!(scheduler.next_due(), Some(reports[0].next_due));
+
+        // Second failure reaches the threshold: rsync is used.
+        clock.advance(chrono::Duration::minutes(1));
+        let reports = scheduler.run(&fetcher);
+        assert_eq!(reports[0].method, Some(FetchMethod::RsyncFallback));
+        assert_eq!(reports[0].errors.len(), 1);
+        assert_eq!(
+            fetcher.take_log(), [notify, "rsync://rsync.example.net/a/"]
+        );
+        assert_eq!(
+            reports[0].next_due, clock.now() + chrono::Duration::minutes(10)
+        );
+        let status = scheduler.status(&key).unwrap();
+        assert_eq!(status.failures, 0);
+        assert_eq!(status.rrdp_failures, 2);
+
+        // Now rsync fails, too: back off exponentially up to the maximum.
+        fetcher.set_failing("rsync://rsync.example.net/a/", true);
+        for &delay in &[1, 2, 4, 4] {
+            clock.set(scheduler.next_due().unwrap());
+            let reports = scheduler.run(&fetcher);
+            assert_eq!(reports[0].method, None);
+            assert_eq!(reports[0].errors.len(), 2);
+            assert_eq!(
+                reports[0].next_due,
+                clock.now() + chrono::Duration::minutes(delay)
+            );
+        }
+        assert_eq!(scheduler.status(&key).unwrap().failures, 4);
+        assert_eq!(scheduler.status(&key).unwrap().rrdp_failures, 6);
+        let last_success = scheduler.status(&key).unwrap().last_success;
+        assert_eq!(last_success, Some(start + chrono::Duration::minutes(1)));
+
+        // RRDP recovers.
+        fetcher.set_failing(notify, false);
+        clock.set(scheduler.next_due().unwrap());
+        fetcher.take_log();
+        let reports = scheduler.run(&fetcher);
+        assert_eq!(reports[0].method, Some(FetchMethod::Rrdp));
+        assert!(reports[0].errors.is_empty());
+        assert_eq!(fetcher.take_log(), [notify]);
+        let status = scheduler.status(&key).unwrap();
+        assert_eq!(status.failures, 0);
+        assert_eq!(status.rrdp_failures, 0);
+        assert_eq!(status.last_success, Some(clock.now()));
+    }
+
+    #[test]
+    fn rsync_backoff() {
+        let (mut scheduler, clock) = scheduler();
+        let key = scheduler.add(None, &rsync("rsync://example.org/m/ca/"));
+        let fetcher = FakeFetcher::default();
+        fetcher.set_failing("rsync://example.org/m/", true);
+        for &delay in &[1, 2, 4] {
+            let reports = scheduler.run(&fetcher);
+            assert_eq!(
+                reports[0].next_due,
+                clock.now() + chrono::Duration::minutes(delay)
+            );
+            clock.set(reports[0].next_due);
+        }
+        let status = scheduler.status(&key).unwrap();
+        assert_eq!(status.failures, 3);
+        assert_eq!(status.rrdp_failures, 0);
+        assert_eq!(status.last_success, None);
+    }
+
+    #[test]
+    fn per_host_limit() {
+        let (mut scheduler, _) = scheduler();
+        for i in 0..6 {
+            scheduler.add(
+                Some(&https(&format!("https://a.example/{}.xml", i))),
+                &rsync("rsync://a.example/repo/"),
+            );
+            scheduler.add(
+                None, &rsync(&format!("rsync://b.example/m{}/", i))
+            );
+        }
+        scheduler.set_threads(4);
+        scheduler.set_max_per_host(2);
+        let mut fetcher = FakeFetcher::default();
+        for &host in &["a.example", "b.example"] {
+            fetcher.delay.insert(host.into(), Duration::from_millis(50));
+        }
+        let reports = scheduler.run(&fetcher);
+        assert_eq!(reports.len(), 12);
+        assert!(reports.iter().all(FetchReport::is_success));
+        assert_eq!(fetcher.take_log().len(), 12);
+        assert_eq!(fetcher.max_active("a.example"), 2);
+        assert_eq!(fetcher.max_active("b.example"), 2);
+    }
+
+    #[test]
+    fn saturated_host_doesnt_block() {
+        // The slow host a.example takes up two threads while the other two
+        // threads fetch everything from b.example.
+        let (mut scheduler, _) = scheduler();
+        for i in 0..4 {
+            scheduler.add(
+                None, &rsync(&format!("rsync://a.example/m{}/", i))
+            );
+            scheduler.add(
+                None, &rsync(&format!("rsync://b.example/m{}/", i))
+            );
+        }
+        scheduler.set_threads(4);
+        scheduler.set_max_per_host(2);
+        let mut fetcher = FakeFetcher::default();
+        fetcher.delay.insert("a.example".into(), Duration::from_millis(500));
+        let reports = scheduler.run(&fetcher);
+        assert_eq!(reports.len(), 8);
+        assert!(reports.iter().all(FetchReport::is_success));
+        let finished = fetcher.finished.lock().unwrap();
+        assert!(
+            finished[..4].iter().all(|uri| uri.contains("b.example")),
+            "{:?}", finished
+        );
+        assert_eq!(fetcher.max_active("a.example"), 2);
+    }
+
+    #[test]
+    fn panicking_fetcher() {
+        let (mut scheduler, _) = scheduler();
+        for i in 0..4 {
+            scheduler.add(
+                None, &rsync(&format!("rsync://a.example/m{}/", i))
+            );
+        }
+        scheduler.set_threads(2);
+        scheduler.set_max_per_host(1);
+        let fetcher = FakeFetcher {
+            panicking: Some("rsync://a.example/m0/".into()),
+            .. Default::default()
+        };
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            scheduler.run(&fetcher)
+        }));
+        assert!(res.is_err());
+        assert_eq!(fetcher.take_log().len(), 4);
+    }
+}
+
+#[cfg(all(test, feature = "softkeys"))]
+mod signer_test {
+    use std::slice;
+    use crate::validation::engine::Validator;
+    use crate::validation::testrepo::TestRepo;
+    use super::*;
+
+    #[test]
+    fn add_report() {
+        let repo = TestRepo::new();
+        let report = Validator::new().validate_at(
+            slice::from_ref(&repo.ta), &repo.archive,
+            TestRepo::base_time() + chrono::Duration::days(1)
+        );
+        let mut scheduler = FetchScheduler::new();
+        scheduler.add_report(&report);
+        assert_eq!(
+            scheduler.keys().collect::<Vec<_>>(),
+            [&RepositoryKey::Rsync(
+                uri::Rsync::from_string("rsync://example.com/repo/".into())
+                    .unwrap()
+            )]
+        );
+        assert!(report.pub_points().iter().all(|point| {
+            point.rpki_notify.is_none()
+        }));
+    }
+}
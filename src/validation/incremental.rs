@@ -0,0 +1,419 @@
+//! Incremental revalidation.
+//!
+//! Between two validation runs, typically only a small number of
+//! publication points change. The [`IncrementalValidator`] in this module
+//! keeps the outcome of each publication point from the previous run and
+//! only revalidates those publication points that have been marked as
+//! changed – together with everything below them whose CA certificate
+//! changed as a result. Everything else is taken from the cache.
+//!
+//! Changes are not detected automatically. Instead, the URIs of all
+//! objects that have been added, updated, or removed have to be reported
+//! via [`mark_changed`][IncrementalValidator::mark_changed] or, for
+//! changes received via RRDP,
+//! [`mark_delta`][IncrementalValidator::mark_delta].
+//!
+//! Cached results are also only used as long as the time of validation
+//! hasn’t passed the start or end of the validity of any object involved,
+//! so expiring objects are handled correctly even without any changes.
+
+use std::collections::{HashMap, HashSet};
+use bytes::Bytes;
+use crate::uri;
+use crate::repository::x509::Time;
+use super::engine::{CaOutcome, CaTask, TrustAnchor, Validator};
+use super::report::{ObjectStatus, Report, TrustAnchorReport};
+use super::scheduler;
+use super::source::ObjectSource;
+
+
+//------------ IncrementalValidator ------------------------------------------
+
+/// A validator that reuses the results of earlier runs.
+///
+/// The validator wraps a regular [`Validator`] which is used for all
+/// publication points that need revalidation and determines all settings
+/// such as strictness, thread count, and the clock.
+#[derive(Debug)]
+pub struct IncrementalValidator {
+    /// The validator for publication points that need revalidation.
+    validator: Validator,
+
+    /// The outcomes of the last run keyed by the encoded CA certificate.
+    cache: HashMap<Bytes, Vec<CacheEntry>>,
+
+    /// The URIs of all objects marked as changed since the last run.
+    changed_uris: HashSet<uri::Rsync>,
+
+    /// The directories of all objects marked as changed.
+    changed_dirs: HashSet<uri::Rsync>,
+
+    /// The number of publication points taken from the cache last time.
+    reused: usize,
+
+    /// The number of publication points revalidated last time.
+    revalidated: usize,
+}
+
+impl IncrementalValidator {
+    /// Creates a new incremental validator based on the given validator.
+    pub fn new(validator: Validator) -> Self {
+        IncrementalValidator {
+            validator,
+            cache: HashMap::new(),
+            changed_uris: HashSet::new(),
+            changed_dirs: HashSet::new(),
+            reused: 0,
+            revalidated: 0,
+        }
+    }
+
+    /// Returns a reference to the underlying validator.
+    pub fn validator(&self) -> &Validator {
+        &self.validator
+    }
+
+    /// Returns a mutable reference to the underlying validator.
+    ///
+    /// Since changing the settings of the validator may change the
+    /// outcome of validation, this clears the cache.
+    pub fn validator_mut(&mut self) -> &mut Validator {
+        self.mark_all_changed();
+        &mut self.validator
+    }
+
+    /// Marks the object with the given URI as changed.
+    ///
+    /// This needs to be called for every object that has been added,
+    /// updated, or removed since the last validation run.
+    pub fn mark_changed(&mut self, uri: &uri::Rsync) {
+        if let Some(dir) = uri.parent() {
+            self.changed_dirs.insert(dir);
+        }
+        self.changed_uris.insert(uri.clone());
+    }
+
+    /// Marks all objects touched by an RRDP delta as changed.
+    #[cfg(feature = "rrdp")]
+    pub fn mark_delta(&mut self, delta: &crate::rrdp::Delta) {
+        for element in delta.elements() {
+            self.mark_changed(element.uri())
+        }
+    }
+
+    /// Marks everything as changed.
+    ///
+    /// The next run will revalidate all publication points.
+    pub fn mark_all_changed(&mut self) {
+        self.cache.clear();
+        self.changed_uris.clear();
+        self.changed_dirs.clear();
+    }
+
+    /// Returns the number of publication points reused in the last run.
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+
+    /// Returns the number of publication points revalidated in the last run.
+    pub fn revalidated(&self) -> usize {
+        self.revalidated
+    }
+
+    /// Validates the repositories below the given trust anchors.
+    ///
+    /// The current time is taken from the validator’s clock.
+    pub fn validate(
+        &mut self,
+        trust_anchors: &[TrustAnchor],
+        source: &(impl ObjectSource + Sync),
+    ) -> Report {
+        let now = self.validator.now();
+        self.validate_at(trust_anchors, source, now)
+    }
+
+    /// Validates the repositories as of the given time.
+    ///
+    /// Afterwards, the cache contains exactly the publication points
+    /// encountered during this run and the set of changed objects is
+    /// cleared.
+    pub fn validate_at(
+        &mut self,
+        trust_anchors: &[TrustAnchor],
+        source: &(impl ObjectSource + Sync),
+        now: Time,
+    ) -> Report {
+        let mut ta_reports = Vec::new();
+        let mut jobs = Vec::new();
+        for (idx, ta) in trust_anchors.iter().enumerate() {
+            let (status, task) = match self.validator.process_ta(ta, now) {
+                Ok(task) => (ObjectStatus::Valid, Some(task)),
+                Err(err) => (ObjectStatus::Invalid(err.into()), None)
+            };
+            ta_reports.push(TrustAnchorReport {
+                info: ta.tal().clone(),
+                uri: ta.uri().cloned(),
+                status
+            });
+            if let Some(task) = task {
+                jobs.push(Job { path: vec![idx], task: task.into() });
+            }
+        }
+
+        let mut results = scheduler::run(
+            self.validator.threads(), jobs, |job, new| {
+                self.process_job(job, source, now, new)
+            }
+        );
+        results.sort_by(|left, right| left.path.cmp(&right.path));
+
+        let mut vrps = Vec::new();
+        let mut aspas = Vec::new();
+        let mut pub_points = Vec::new();
+        let mut objects = Vec::new();
+        let mut cache: HashMap<_, Vec<_>> = HashMap::new();
+        self.reused = 0;
+        self.revalidated = 0;
+        for item in results {
+            if item.reused {
+                self.reused += 1;
+            }
+            else {
+                self.revalidated += 1;
+            }
+            vrps.extend_from_slice(&item.entry.outcome.vrps);
+            aspas.extend_from_slice(&item.entry.outcome.aspas);
+            objects.extend_from_slice(&item.entry.outcome.objects);
+            pub_points.extend(item.entry.outcome.pub_point.clone());
+            cache.entry(item.key).or_default().push(item.entry);
+        }
+        self.cache = cache;
+        self.changed_uris.clear();
+        self.changed_dirs.clear();
+
+        Report::new(now, vrps, aspas, ta_reports, pub_points, objects)
+    }
+
+    /// Processes the publication point of a single CA.
+    fn process_job(
+        &self,
+        job: Job,
+        source: &impl ObjectSource,
+        now: Time,
+        new: &mut Vec<Job>,
+    ) -> JobResult {
+        let Job { path, task } = job;
+        let key = task.cert.to_captured().into_bytes();
+        let (entry, reused) = match self.lookup(&key, &task, now) {
+            Some(entry) => (entry.clone(), true),
+            None => {
+                let outcome = self.validator.process_ca(
+                    (*task).clone(), source, now
+                );
+                (CacheEntry { task: *task, now, outcome }, false)
+            }
+        };
+        new.extend(entry.outcome.children.iter().enumerate().map(
+            |(idx, task)| {
+                let mut path = path.clone();
+                path.push(idx);
+                Job { path, task: task.clone().into() }
+            }
+        ));
+        JobResult { path, key, entry, reused }
+    }
+
+    /// Returns a cache entry that can be reused for the task.
+    fn lookup(
+        &self, key: &Bytes, task: &CaTask, now: Time
+    ) -> Option<&CacheEntry> {
+        let entry = self.cache.get(key)?.iter().find(|entry| {
+            entry.is_same_task(task)
+        })?;
+        if now < entry.now {
+            return None
+        }
+        if let Some(next_change) = entry.outcome.next_change {
+            if now >= next_change {
+                return None
+            }
+        }
+        if let Some(uri) = task.cert.ca_repository() {
+            if self.changed_dirs.contains(uri) {
+                return None
+            }
+        }
+        if let Some(uri) = task.cert.rpki_manifest() {
+            if self.changed_uris.contains(uri) {
+                return None
+            }
+        }
+        Some(entry)
+    }
+}
+
+
+//------------ CacheEntry ----------------------------------------------------
+
+/// The cached outcome of a publication point.
+#[derive(Clone, Debug)]
+struct CacheEntry {
+    /// The task the outcome was created for.
+    task: CaTask,
+
+    /// The time the outcome was created at.
+    now: Time,
+
+    /// The outcome.
+    outcome: CaOutcome,
+}
+
+impl CacheEntry {
+    /// Returns whether the entry was created for an identical task.
+    ///
+    /// The encoded certificates are known to be identical already. But
+    /// the resources resolved from the issuer, the TAL, and the path to
+    /// the trust anchor need to be the same, too.
+    fn is_same_task(&self, task: &CaTask) -> bool {
+        self.task.ancestors == task.ancestors
+            && self.task.cert.tal().name() == task.cert.tal().name()
+            && self.task.cert.v4_resources() == task.cert.v4_resources()
+            && self.task.cert.v6_resources() == task.cert.v6_resources()
+            && self.task.cert.as_resources() == task.cert.as_resources()
+    }
+}
+
+
+//------------ Job and JobResult ---------------------------------------------
+
+/// A publication point to process.
+struct Job {
+    /// The path of the job used for sorting results.
+    path: Vec<usize>,
+
+    /// The CA to process.
+    task: Box<CaTask>,
+}
+
+/// The result of processing a job.
+struct JobResult {
+    /// The path of the job.
+    path: Vec<usize>,
+
+    /// The cache key.
+    key: Bytes,
+
+    /// The cache entry to keep for next time.
+    entry: CacheEntry,
+
+    /// Was the entry taken from the cache?
+    reused: bool,
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(all(test, feature = "softkeys"))]
+mod signer_test {
+    use std::slice;
+    use chrono::Duration;
+    use super::super::testrepo::{TestRepo, Which};
+    use super::*;
+
+    #[test]
+    fn reuse_unchanged() {
+        let mut repo = TestRepo::new();
+        let now = TestRepo::base_time() + Duration::days(1);
+        let ta = repo.ta.clone();
+        let tas = slice::from_ref(&ta);
+        let mut validator = IncrementalValidator::new(Validator::new());
+
+        let first = validator.validate_at(tas, &repo.archive, now);
+        assert_eq!(validator.reused(), 0);
+        assert_eq!(validator.revalidated(), 2);
+        assert_eq!(first.vrps().len(), 1);
+
+        let second = validator.validate_at(
+            tas, &repo.archive, now + Duration::hours(1)
+        );
+        assert_eq!(validator.reused(), 2);
+        assert_eq!(first.vrps(), second.vrps());
+        assert_eq!(first.aspas(), second.aspas());
+
+        // Changing the child’s publication point revalidates only that.
+        let roa = repo.make_roa(64497, TestRepo::cert_validity());
+        repo.publish(
+            Which::Child, 2, now, now + Duration::days(7),
+            vec![("roa.roa", roa)]
+        );
+        validator.mark_changed(&Which::Child.manifest());
+        validator.mark_changed(
+            &Which::Child.ca_repository().join(b"roa.roa").unwrap()
+        );
+        validator.mark_changed(
+            &Which::Child.ca_repository().join(b"aspa.asa").unwrap()
+        );
+        let third = validator.validate_at(
+            tas, &repo.archive, now + Duration::hours(2)
+        );
+        assert_eq!(validator.reused(), 1);
+        assert_eq!(validator.revalidated(), 1);
+        assert_eq!(third.vrps()[0].asn, 64497.into());
+        assert!(third.aspas().is_empty());
+
+        // The result is the same as a full validation.
+        let full = Validator::new().validate_at(
+            tas, &repo.archive, now + Duration::hours(2)
+        );
+        assert_eq!(full.vrps(), third.vrps());
+        assert_eq!(
+            format!("{:?}", full.objects()),
+            format!("{:?}", third.objects())
+        );
+    }
+
+    #[test]
+    fn changed_parent_revalidates_child_cert() {
+        let mut repo = TestRepo::new();
+        let now = TestRepo::base_time() + Duration::days(1);
+        let ta = repo.ta.clone();
+        let tas = slice::from_ref(&ta);
+        let mut validator = IncrementalValidator::new(Validator::new());
+        validator.validate_at(tas, &repo.archive, now);
+
+        // A new child certificate with the same key leads to a different
+        // cache key for the child.
+        let child = repo.make_child_cert(TestRepo::cert_validity());
+        repo.publish(
+            Which::Ta, 2, now, now + Duration::days(7),
+            vec![("child.cer", child)]
+        );
+        validator.mark_changed(&Which::Ta.manifest());
+        let report = validator.validate_at(tas, &repo.archive, now);
+        assert_eq!(validator.reused(), 0);
+        assert_eq!(validator.revalidated(), 2);
+        assert_eq!(report.vrps().len(), 1);
+    }
+
+    #[test]
+    fn expiry_invalidates_cache() {
+        let repo = TestRepo::new();
+        let now = TestRepo::base_time() + Duration::days(1);
+        let ta = repo.ta.clone();
+        let tas = slice::from_ref(&ta);
+        let mut validator = IncrementalValidator::new(Validator::new());
+        validator.validate_at(tas, &repo.archive, now);
+
+        // Past the manifests’ next update, nothing can be reused.
+        let report = validator.validate_at(
+            tas, &repo.archive, now + Duration::days(7)
+        );
+        assert_eq!(validator.reused(), 0);
+        assert!(report.vrps().is_empty());
+
+        // Going back in time doesn’t reuse anything either.
+        let report = validator.validate_at(tas, &repo.archive, now);
+        assert_eq!(validator.reused(), 0);
+        assert_eq!(report.vrps().len(), 1);
+    }
+}
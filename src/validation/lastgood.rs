@@ -0,0 +1,141 @@
+//! Keeping the last known good copy of publication points.
+//!
+//! RFC 9286 requires a relying party to treat a publication point whose
+//! manifest or CRL is invalid as a failed fetch and to continue using the
+//! data it previously validated for as long as that data is still valid.
+//! The [`LastKnownGood`] store in this module keeps a copy of every
+//! publication point that has been validated successfully so that the
+//! validator can fall back to it.
+
+use std::io;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use bytes::Bytes;
+use crate::uri;
+use crate::repository::x509::Time;
+use super::source::ObjectSource;
+
+
+//------------ LastKnownGood -------------------------------------------------
+
+/// A store for the last known good copies of publication points.
+///
+/// The store is filled by the [`Validator`][super::Validator] whenever a
+/// publication point was validated successfully. If it later fails to
+/// validate, the copy is validated again in its place. Since the copy
+/// is validated as of the current time, it will only be used until its
+/// manifest or CRL become stale or any of its objects expire.
+///
+/// The store can be shared between threads and validation runs.
+#[derive(Debug, Default)]
+pub struct LastKnownGood {
+    /// The stored publication points keyed by their manifest URI.
+    points: Mutex<HashMap<uri::Rsync, Arc<StoredPoint>>>,
+}
+
+impl LastKnownGood {
+    /// Creates a new, empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of publication points in the store.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Returns the stored copy of a publication point.
+    pub fn get(&self, manifest_uri: &uri::Rsync) -> Option<Arc<StoredPoint>> {
+        self.lock().get(manifest_uri).cloned()
+    }
+
+    /// Removes the stored copy of a publication point.
+    pub fn remove(&self, manifest_uri: &uri::Rsync) {
+        self.lock().remove(manifest_uri);
+    }
+
+    /// Removes all stored copies.
+    pub fn clear(&self) {
+        self.lock().clear()
+    }
+
+    /// Removes all copies that can no longer be used at the given time.
+    pub fn prune(&self, now: Time) {
+        self.lock().retain(|_, point| point.expires > now)
+    }
+
+    /// Stores a validated publication point.
+    pub(crate) fn update(&self, point: StoredPoint) {
+        self.lock().insert(point.manifest_uri.clone(), Arc::new(point));
+    }
+
+    /// Locks the stored points.
+    fn lock(&self) -> MutexGuard<'_, HashMap<uri::Rsync, Arc<StoredPoint>>> {
+        self.points.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+
+//------------ StoredPoint ---------------------------------------------------
+
+/// The stored copy of a publication point.
+///
+/// This contains the manifest and all the files listed on it. It can be
+/// used as an object source for validating the publication point again.
+#[derive(Clone, Debug)]
+pub struct StoredPoint {
+    /// The URI of the manifest.
+    manifest_uri: uri::Rsync,
+
+    /// The content of the manifest.
+    manifest: Bytes,
+
+    /// The URIs and content of all files listed on the manifest.
+    files: HashMap<uri::Rsync, Bytes>,
+
+    /// The time when the manifest or CRL become stale.
+    expires: Time,
+}
+
+impl StoredPoint {
+    /// Creates a new stored publication point.
+    pub(crate) fn new(
+        manifest_uri: uri::Rsync,
+        manifest: Bytes,
+        files: HashMap<uri::Rsync, Bytes>,
+        expires: Time,
+    ) -> Self {
+        StoredPoint { manifest_uri, manifest, files, expires }
+    }
+
+    /// Returns the URI of the manifest.
+    pub fn manifest_uri(&self) -> &uri::Rsync {
+        &self.manifest_uri
+    }
+
+    /// Returns the content of the manifest.
+    pub fn manifest(&self) -> &Bytes {
+        &self.manifest
+    }
+
+    /// Returns the time when the manifest or CRL become stale.
+    pub fn expires(&self) -> Time {
+        self.expires
+    }
+}
+
+impl ObjectSource for StoredPoint {
+    fn load(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, io::Error> {
+        if *uri == self.manifest_uri {
+            Ok(Some(self.manifest.clone()))
+        }
+        else {
+            Ok(self.files.get(uri).cloned())
+        }
+    }
+}
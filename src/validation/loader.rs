@@ -0,0 +1,454 @@
+//! Loading trust anchor certificates.
+//!
+//! A TAL lists one or more URIs from which the trust anchor certificate can
+//! be fetched together with the public key the certificate must have. The
+//! [`TaLoader`] in this module fetches the certificate via a type
+//! implementing [`TaFetcher`], trying the URIs of the TAL in order until it
+//! finds a certificate that can be decoded, has the key given in the TAL,
+//! and is a valid trust anchor certificate at the current time.
+//!
+//! The loader keeps the last good certificate for each TAL. If none of the
+//! URIs provide a usable certificate, this copy is used instead as long as
+//! it is still valid. Every attempt is recorded in the returned [`TaLoad`]
+//! so it is clear which URI was used and why the others were not.
+
+use std::{fmt, io};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use bytes::Bytes;
+use log::info;
+use crate::clock::{Clock, SystemClock};
+use crate::repository::tal::{Tal, TalInfo, TalUri};
+use crate::repository::x509::Time;
+use super::engine::{TrustAnchor, TrustAnchorError};
+
+
+//------------ TaFetcher -----------------------------------------------------
+
+/// A type that can fetch trust anchor certificates.
+///
+/// The fetcher is given the URIs exactly as listed in the TAL, i.e., it
+/// needs to be able to deal with both rsync and HTTPS URIs.
+pub trait TaFetcher {
+    /// Fetches the content of the file at the given URI.
+    fn fetch(&self, uri: &TalUri) -> Result<Bytes, io::Error>;
+}
+
+impl<F: TaFetcher + ?Sized> TaFetcher for &F {
+    fn fetch(&self, uri: &TalUri) -> Result<Bytes, io::Error> {
+        (*self).fetch(uri)
+    }
+}
+
+impl TaFetcher for HashMap<TalUri, Bytes> {
+    fn fetch(&self, uri: &TalUri) -> Result<Bytes, io::Error> {
+        self.get(uri).cloned().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "file not found")
+        })
+    }
+}
+
+
+//------------ TaLoader ------------------------------------------------------
+
+/// Loads trust anchor certificates for TALs.
+pub struct TaLoader<F> {
+    /// The fetcher for certificates.
+    fetcher: F,
+
+    /// The clock to take the current time from.
+    clock: Arc<dyn Clock + Send + Sync>,
+
+    /// Whether to validate certificates in strict mode.
+    strict: bool,
+
+    /// Whether to try HTTPS URIs before rsync URIs.
+    prefer_https: bool,
+
+    /// The last good certificate for each TAL keyed by the TAL name.
+    last_good: Mutex<HashMap<String, (TalUri, Bytes)>>,
+}
+
+impl<F: TaFetcher> TaLoader<F> {
+    /// Creates a new loader using the given fetcher.
+    ///
+    /// The loader uses the system clock, doesn’t use strict validation,
+    /// and tries the URIs in the order given in the TAL.
+    pub fn new(fetcher: F) -> Self {
+        TaLoader {
+            fetcher,
+            clock: Arc::new(SystemClock),
+            strict: false,
+            prefer_https: false,
+            last_good: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the clock used to determine the current time.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock + Send + Sync>) {
+        self.clock = clock
+    }
+
+    /// Sets whether certificates are validated in strict mode.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict
+    }
+
+    /// Sets whether HTTPS URIs are tried before rsync URIs.
+    ///
+    /// See [`Tal::prefer_https`] for details.
+    pub fn set_prefer_https(&mut self, prefer_https: bool) {
+        self.prefer_https = prefer_https
+    }
+
+    /// Returns a reference to the fetcher.
+    pub fn fetcher(&self) -> &F {
+        &self.fetcher
+    }
+
+    /// Returns the last good certificate for a TAL.
+    ///
+    /// Returns the URI the certificate was fetched from and its content.
+    pub fn last_good(&self, tal: &Tal) -> Option<(TalUri, Bytes)> {
+        self.last_good.lock().expect("poisoned lock").get(
+            tal.info().name()
+        ).cloned()
+    }
+
+    /// Sets the last good certificate for a TAL.
+    ///
+    /// This can be used to restore a copy kept from an earlier run. The
+    /// certificate is checked when it is used.
+    pub fn set_last_good(&self, tal: &Tal, uri: TalUri, data: Bytes) {
+        self.last_good.lock().expect("poisoned lock").insert(
+            tal.info().name().into(), (uri, data)
+        );
+    }
+
+    /// Loads the trust anchor for a TAL.
+    ///
+    /// The current time is taken from the loader’s clock.
+    pub fn load(&self, tal: &Tal) -> TaLoad {
+        self.load_at(tal, Time::from_clock(&self.clock))
+    }
+
+    /// Loads the trust anchor for a TAL as if it were the given time.
+    pub fn load_at(&self, tal: &Tal, now: Time) -> TaLoad {
+        let mut attempts = Vec::new();
+        let uris: Vec<_> = if self.prefer_https {
+            let mut tal = tal.clone();
+            tal.prefer_https();
+            tal.uris().cloned().collect()
+        }
+        else {
+            tal.uris().cloned().collect()
+        };
+        for uri in uris {
+            let data = match self.fetcher.fetch(&uri) {
+                Ok(data) => data,
+                Err(err) => {
+                    info!(
+                        "{}: failed to fetch {}: {}",
+                        tal.info().name(), uri, err
+                    );
+                    attempts.push(TaAttempt {
+                        uri, result: Err(TrustAnchorError::Io(err))
+                    });
+                    continue
+                }
+            };
+            match self.check(tal, &uri, data.clone(), now) {
+                Ok(ta) => {
+                    self.set_last_good(tal, uri.clone(), data);
+                    attempts.push(TaAttempt {
+                        uri: uri.clone(), result: Ok(())
+                    });
+                    return TaLoad {
+                        tal: tal.info().clone(),
+                        trust_anchor: Some(ta),
+                        uri: Some(uri),
+                        from_last_good: false,
+                        attempts,
+                    }
+                }
+                Err(err) => {
+                    info!("{}: rejected {}: {}", tal.info().name(), uri, err);
+                    attempts.push(TaAttempt { uri, result: Err(err) });
+                }
+            }
+        }
+
+        let last_good = self.last_good(tal).and_then(|(uri, data)| {
+            match self.check(tal, &uri, data, now) {
+                Ok(ta) => Some((uri, ta)),
+                Err(err) => {
+                    info!(
+                        "{}: rejected last good copy from {}: {}",
+                        tal.info().name(), uri, err
+                    );
+                    None
+                }
+            }
+        });
+        let (uri, trust_anchor) = match last_good {
+            Some((uri, ta)) => (Some(uri), Some(ta)),
+            None => (None, None),
+        };
+        TaLoad {
+            tal: tal.info().clone(),
+            from_last_good: trust_anchor.is_some(),
+            trust_anchor,
+            uri,
+            attempts,
+        }
+    }
+
+    /// Checks that a certificate is a valid trust anchor for a TAL.
+    fn check(
+        &self, tal: &Tal, uri: &TalUri, data: Bytes, now: Time
+    ) -> Result<TrustAnchor, TrustAnchorError> {
+        let cert = TrustAnchor::decode_cert(tal, data)?;
+        cert.clone().validate_ta_at(
+            tal.info().clone(), self.strict, now
+        ).map_err(TrustAnchorError::Invalid)?;
+        Ok(match *uri {
+            TalUri::Rsync(ref uri) => {
+                TrustAnchor::with_uri(cert, tal.info().clone(), uri.clone())
+            }
+            TalUri::Https(_) => TrustAnchor::new(cert, tal.info().clone()),
+        })
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for TaLoader<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TaLoader")
+            .field("fetcher", &self.fetcher)
+            .field("strict", &self.strict)
+            .field("prefer_https", &self.prefer_https)
+            .field("last_good", &self.last_good)
+            .finish()
+    }
+}
+
+
+//------------ TaLoad --------------------------------------------------------
+
+/// The result of loading the trust anchor for a TAL.
+#[derive(Debug)]
+pub struct TaLoad {
+    /// Information about the TAL.
+    tal: Arc<TalInfo>,
+
+    /// The trust anchor if one could be loaded.
+    trust_anchor: Option<TrustAnchor>,
+
+    /// The URI the trust anchor certificate was fetched from.
+    uri: Option<TalUri>,
+
+    /// Whether the last good copy was used.
+    from_last_good: bool,
+
+    /// The attempts to fetch the certificate in order.
+    attempts: Vec<TaAttempt>,
+}
+
+impl TaLoad {
+    /// Returns information about the TAL.
+    pub fn tal(&self) -> &Arc<TalInfo> {
+        &self.tal
+    }
+
+    /// Returns the trust anchor if one could be loaded.
+    pub fn trust_anchor(&self) -> Option<&TrustAnchor> {
+        self.trust_anchor.as_ref()
+    }
+
+    /// Converts the value into the trust anchor if one could be loaded.
+    pub fn into_trust_anchor(self) -> Option<TrustAnchor> {
+        self.trust_anchor
+    }
+
+    /// Returns the URI the trust anchor certificate was fetched from.
+    ///
+    /// If the last good copy was used, this is the URI that copy was
+    /// originally fetched from.
+    pub fn uri(&self) -> Option<&TalUri> {
+        self.uri.as_ref()
+    }
+
+    /// Returns whether the last good copy of the certificate was used.
+    pub fn is_last_good(&self) -> bool {
+        self.from_last_good
+    }
+
+    /// Returns the attempts to fetch the certificate in order.
+    ///
+    /// The last attempt is the successful one unless all of them failed.
+    pub fn attempts(&self) -> &[TaAttempt] {
+        &self.attempts
+    }
+}
+
+
+//------------ TaAttempt -----------------------------------------------------
+
+/// An attempt to fetch a trust anchor certificate from one URI.
+#[derive(Debug)]
+pub struct TaAttempt {
+    /// The URI tried.
+    pub uri: TalUri,
+
+    /// The outcome of the attempt.
+    pub result: Result<(), TrustAnchorError>,
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    const TA: &[u8] = include_bytes!("../../test-data/ta.cer");
+
+    /// Returns a TAL with the key of the test TA and the given URIs.
+    fn tal(uris: &[&str]) -> Tal {
+        let data = include_str!("../../test-data/ripe.tal");
+        let key = data.split("\n\n").nth(1).unwrap();
+        let mut text = String::new();
+        for uri in uris {
+            text.push_str(uri);
+            text.push('\n');
+        }
+        text.push('\n');
+        text.push_str(key);
+        Tal::read_named("test".into(), &mut text.as_bytes()).unwrap()
+    }
+
+    fn uri(s: &str) -> TalUri {
+        TalUri::from_str(s).unwrap()
+    }
+
+    fn now() -> Time {
+        Time::utc(2020, 11, 1, 12, 0, 0)
+    }
+
+    #[test]
+    fn fallback_and_reporting() {
+        let tal = tal(&[
+            "rsync://missing.example/ta/ta.cer",
+            "rsync://garbage.example/ta/ta.cer",
+            "https://wrong.example/ta.cer",
+            "https://good.example/ta.cer",
+            "rsync://unused.example/ta/ta.cer",
+        ]);
+        let mut files = HashMap::new();
+        files.insert(
+            uri("rsync://garbage.example/ta/ta.cer"),
+            Bytes::from_static(b"garbage")
+        );
+        files.insert(
+            uri("https://wrong.example/ta.cer"),
+            Bytes::from_static(include_bytes!("../../test-data/ca1.cer"))
+        );
+        files.insert(
+            uri("https://good.example/ta.cer"), Bytes::from_static(TA)
+        );
+        files.insert(
+            uri("rsync://unused.example/ta/ta.cer"), Bytes::from_static(TA)
+        );
+        let loader = TaLoader::new(files);
+
+        let load = loader.load_at(&tal, now());
+        assert!(!load.is_last_good());
+        assert_eq!(load.uri(), Some(&uri("https://good.example/ta.cer")));
+        let ta = load.trust_anchor().unwrap();
+        assert_eq!(ta.cert().subject_public_key_info(), tal.key_info());
+        assert!(ta.uri().is_none());
+        let attempts = load.attempts();
+        assert_eq!(attempts.len(), 4);
+        assert!(matches!(attempts[0].result, Err(TrustAnchorError::Io(_))));
+        assert!(matches!(
+            attempts[1].result, Err(TrustAnchorError::Malformed)
+        ));
+        assert!(matches!(
+            attempts[2].result, Err(TrustAnchorError::KeyMismatch)
+        ));
+        assert!(attempts[3].result.is_ok());
+        assert_eq!(
+            loader.last_good(&tal),
+            Some((uri("https://good.example/ta.cer"), Bytes::from_static(TA)))
+        );
+
+        // Not yet valid.
+        let load = loader.load_at(&tal, Time::utc(2010, 1, 1, 0, 0, 0));
+        assert!(load.trust_anchor().is_none());
+        assert!(matches!(
+            load.attempts()[3].result, Err(TrustAnchorError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn prefer_https() {
+        let tal = tal(&[
+            "rsync://example.net/ta/ta.cer",
+            "https://example.net/ta.cer",
+        ]);
+        let mut files = HashMap::new();
+        files.insert(uri("rsync://example.net/ta/ta.cer"), Bytes::from(TA));
+        files.insert(uri("https://example.net/ta.cer"), Bytes::from(TA));
+        let mut loader = TaLoader::new(files);
+
+        let load = loader.load_at(&tal, now());
+        assert_eq!(load.uri(), Some(&uri("rsync://example.net/ta/ta.cer")));
+        assert_eq!(
+            load.trust_anchor().unwrap().uri().map(|uri| uri.as_str()),
+            Some("rsync://example.net/ta/ta.cer")
+        );
+
+        loader.set_prefer_https(true);
+        let load = loader.load_at(&tal, now());
+        assert_eq!(load.uri(), Some(&uri("https://example.net/ta.cer")));
+        assert_eq!(load.attempts().len(), 1);
+    }
+
+    #[test]
+    fn last_good() {
+        let tal = tal(&["https://example.net/ta.cer"]);
+        let mut files = HashMap::new();
+        let loader = TaLoader::new(&files);
+        let load = loader.load_at(&tal, now());
+        assert!(load.trust_anchor().is_none());
+        assert!(load.uri().is_none());
+
+        files.insert(uri("https://example.net/ta.cer"), Bytes::from(TA));
+        let loader = TaLoader::new(&files);
+        assert!(loader.load_at(&tal, now()).trust_anchor().is_some());
+        let (cached_uri, cached) = loader.last_good(&tal).unwrap();
+
+        // Now the fetcher has nothing but the loader remembers.
+        let empty = HashMap::new();
+        let loader = TaLoader::new(&empty);
+        loader.set_last_good(&tal, cached_uri, cached);
+        let load = loader.load_at(&tal, now());
+        assert!(load.is_last_good());
+        assert!(load.trust_anchor().is_some());
+        assert_eq!(load.uri(), Some(&uri("https://example.net/ta.cer")));
+        assert_eq!(load.attempts().len(), 1);
+        assert!(load.attempts()[0].result.is_err());
+
+        // The last good copy isn’t used once it has expired.
+        let load = loader.load_at(&tal, Time::utc(2200, 1, 1, 0, 0, 0));
+        assert!(!load.is_last_good());
+        assert!(load.trust_anchor().is_none());
+
+        // Nor if it has the wrong key.
+        loader.set_last_good(
+            &tal, uri("https://example.net/ta.cer"),
+            Bytes::from_static(include_bytes!("../../test-data/ca1.cer"))
+        );
+        assert!(loader.load_at(&tal, now()).trust_anchor().is_none());
+    }
+}
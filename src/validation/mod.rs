@@ -0,0 +1,50 @@
+//! Validation of complete RPKI repositories.
+//!
+//! While the [`repository`][crate::repository] module provides the means to
+//! validate individual objects, this module puts these together and walks
+//! the tree of CAs starting at a set of trust anchors, validating all
+//! publication points it encounters along the way.
+//!
+//! The objects themselves are taken from an [`ObjectSource`]. This can be a
+//! local copy of the repositories or an [`Archive`] of objects collected at
+//! some earlier time. Validation is performed by a [`Validator`] which
+//! produces a [`Report`] containing the validated RPKI payload as well as
+//! the result for every object considered.
+//!
+//! Because the validator can be told which point in time to consider the
+//! current time, validating an archive allows answering the question which
+//! payload a relying party would have produced at that time.
+//!
+//! When validating repeatedly, an [`IncrementalValidator`] can be used to
+//! only revalidate those publication points that have changed since the
+//! previous run. The repositories discovered during validation can be
+//! handed to a [`FetchScheduler`] which decides when and how to refresh
+//! them. The trust anchors to start from can be fetched via a
+//! [`TaLoader`].
+
+#![cfg(feature = "validation")]
+
+pub use self::engine::{TrustAnchor, TrustAnchorError, Validator};
+pub use self::fetch::{FetchScheduler, RepositoryFetcher};
+pub use self::incremental::IncrementalValidator;
+pub use self::lastgood::LastKnownGood;
+pub use self::loader::{TaFetcher, TaLoad, TaLoader};
+pub use self::pubpoint::{PubPointCheck, PubPointIssue};
+pub use self::report::{
+    AspaPayload, ObjectKind, ObjectReport, ObjectStatus, PubPointReport,
+    PubPointStatus, Report, TrustAnchorReport, Vrp
+};
+pub use self::source::{Archive, ObjectSource};
+
+pub mod engine;
+pub mod fetch;
+pub mod incremental;
+pub mod lastgood;
+pub mod loader;
+pub mod pubpoint;
+pub mod report;
+mod scheduler;
+pub mod source;
+
+#[cfg(all(test, feature = "softkeys"))]
+pub(crate) mod testrepo;
@@ -0,0 +1,482 @@
+//! Consistency checks for publication points.
+//!
+//! [`Manifest::validate_at`] only checks the manifest itself. RFC 9286
+//! additionally requires the manifest and the files it lists to form a
+//! consistent whole: every file needs to be present with the hash given on
+//! the manifest, there needs to be exactly one CRL which must be the one
+//! the manifest’s EE certificate points to, and the manifest number must
+//! increase with every new manifest.
+//!
+//! The function [`check`] performs all these checks for a manifest and a
+//! set of files and returns a [`PubPointCheck`] describing what it found.
+//!
+//! [`Manifest::validate_at`]: crate::repository::Manifest::validate_at
+
+use std::{fmt, io};
+use std::collections::HashSet;
+use bytes::Bytes;
+use crate::uri;
+use crate::repository::lint::{is_usable_file_name, is_valid_file_name};
+use crate::repository::manifest::{Manifest, ManifestContent, ManifestHash};
+use crate::repository::x509::Serial;
+use super::source::ObjectSource;
+
+
+//------------ check ---------------------------------------------------------
+
+/// Checks the consistency of a publication point.
+///
+/// The manifest is checked against the files available from `source`. The
+/// file names on the manifest are taken relative to `ca_repository`, the
+/// directory of the publication point.
+///
+/// If the manifest previously seen for the publication point is given via
+/// `previous`, the manifest number of `manifest` is checked against it. An
+/// unchanged manifest is fine, but a different manifest needs to have a
+/// larger number.
+///
+/// The manifest itself should have been validated before. Its content is
+/// not checked again.
+///
+/// Returns an error if `source` fails to provide a file.
+pub fn check(
+    manifest: &Manifest,
+    ca_repository: &uri::Rsync,
+    source: &impl ObjectSource,
+    previous: Option<&ManifestContent>,
+) -> Result<PubPointCheck, io::Error> {
+    let content = manifest.content();
+    let mut res = PubPointCheck {
+        manifest_number: content.manifest_number(),
+        crl: None,
+        files: Vec::new(),
+        issues: Vec::new(),
+    };
+    let mut names = HashSet::new();
+    let mut crls = Vec::new();
+
+    for item in content.iter() {
+        let (name, hash) = item.into_pair();
+        if !names.insert(name.clone()) {
+            res.issues.push(PubPointIssue::DuplicateName(name));
+            continue
+        }
+        let uri = match ca_repository.join(&name) {
+            Ok(uri) if is_usable_file_name(&name) => uri,
+            _ => {
+                res.issues.push(PubPointIssue::UnusableName(name));
+                continue
+            }
+        };
+        if !is_valid_file_name(&name) {
+            res.issues.push(PubPointIssue::IllegalName(name.clone()));
+        }
+        let is_crl = name.ends_with(b".crl");
+        if is_crl {
+            crls.push(uri.clone());
+        }
+        let data = match source.load(&uri)? {
+            Some(data) => data,
+            None => {
+                res.issues.push(PubPointIssue::MissingFile(uri));
+                continue
+            }
+        };
+        if ManifestHash::new(
+            hash, content.file_hash_alg()
+        ).verify(&data).is_err() {
+            res.issues.push(PubPointIssue::HashMismatch(uri));
+            continue
+        }
+        if is_crl {
+            res.crl = Some(res.files.len());
+        }
+        res.files.push((uri, data));
+    }
+
+    let crl_uri = manifest.cert().crl_uri();
+    match crls.len() {
+        0 => res.issues.push(PubPointIssue::NoCrl),
+        1 => {
+            let listed = crls.pop().unwrap();
+            if Some(&listed) != crl_uri {
+                res.issues.push(PubPointIssue::CrlUriMismatch {
+                    listed, expected: crl_uri.cloned()
+                });
+                res.crl = None;
+            }
+        }
+        _ => {
+            res.issues.push(PubPointIssue::MultipleCrls(crls));
+            res.crl = None;
+        }
+    }
+
+    if let Some(previous) = previous {
+        let current = content.manifest_number();
+        let previous_number = previous.manifest_number();
+        if current < previous_number {
+            res.issues.push(PubPointIssue::NumberDecreased {
+                previous: previous_number, current
+            });
+        }
+        else if
+            current == previous_number && !same_content(content, previous)
+        {
+            res.issues.push(PubPointIssue::NumberReused(current));
+        }
+    }
+
+    Ok(res)
+}
+
+/// Returns whether two manifests have the same content.
+fn same_content(left: &ManifestContent, right: &ManifestContent) -> bool {
+    left.this_update() == right.this_update()
+    && left.next_update() == right.next_update()
+    && left.iter().map(|item| item.into_pair()).eq(
+        right.iter().map(|item| item.into_pair())
+    )
+}
+
+
+//------------ PubPointCheck -------------------------------------------------
+
+/// The result of checking the consistency of a publication point.
+#[derive(Clone, Debug)]
+pub struct PubPointCheck {
+    /// The manifest number of the checked manifest.
+    manifest_number: Serial,
+
+    /// The index of the CRL in `files` if it is acceptable.
+    crl: Option<usize>,
+
+    /// The files that are present and have the correct hash.
+    files: Vec<(uri::Rsync, Bytes)>,
+
+    /// The issues found.
+    issues: Vec<PubPointIssue>,
+}
+
+impl PubPointCheck {
+    /// Returns the manifest number of the checked manifest.
+    pub fn manifest_number(&self) -> Serial {
+        self.manifest_number
+    }
+
+    /// Returns whether no issues have been found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Returns whether the publication point can be used.
+    ///
+    /// If `strict` is `false`, issues that RFC 9286 does not require to
+    /// reject a publication point for are ignored.
+    pub fn is_acceptable(&self, strict: bool) -> bool {
+        self.first_error(strict).is_none()
+    }
+
+    /// Returns the first issue that makes the publication point unusable.
+    pub fn first_error(&self, strict: bool) -> Option<&PubPointIssue> {
+        self.issues.iter().find(|issue| strict || !issue.is_relaxable())
+    }
+
+    /// Returns the issues found.
+    pub fn issues(&self) -> &[PubPointIssue] {
+        &self.issues
+    }
+
+    /// Returns the URI and content of the CRL.
+    ///
+    /// Returns `None` if the manifest doesn’t list exactly one CRL, if it
+    /// isn’t the one referenced by the manifest’s EE certificate, or if it
+    /// is missing or has the wrong hash.
+    pub fn crl(&self) -> Option<&(uri::Rsync, Bytes)> {
+        self.crl.map(|idx| &self.files[idx])
+    }
+
+    /// Returns the files that are present and have the correct hash.
+    ///
+    /// This includes the CRL. The files are in the order of the manifest.
+    pub fn files(&self) -> &[(uri::Rsync, Bytes)] {
+        &self.files
+    }
+
+    /// Converts the result into the list of files.
+    pub fn into_files(self) -> Vec<(uri::Rsync, Bytes)> {
+        self.files
+    }
+}
+
+
+//------------ PubPointIssue -------------------------------------------------
+
+/// An issue found while checking a publication point.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PubPointIssue {
+    /// A file name appears more than once on the manifest.
+    DuplicateName(Bytes),
+
+    /// A file name can’t safely be used to refer to a file.
+    ///
+    /// This happens if the name refers to something outside the
+    /// publication point or contains control characters. Such files are
+    /// not loaded and the issue can’t be relaxed.
+    UnusableName(Bytes),
+
+    /// A file name doesn’t have the format required by RFC 9286.
+    IllegalName(Bytes),
+
+    /// A file listed on the manifest is not present.
+    MissingFile(uri::Rsync),
+
+    /// A file doesn’t have the hash given on the manifest.
+    HashMismatch(uri::Rsync),
+
+    /// The manifest doesn’t list a CRL.
+    NoCrl,
+
+    /// The manifest lists more than one CRL.
+    MultipleCrls(Vec<uri::Rsync>),
+
+    /// The CRL listed is not the one given in the EE certificate.
+    CrlUriMismatch {
+        listed: uri::Rsync,
+        expected: Option<uri::Rsync>,
+    },
+
+    /// The manifest number is smaller than that of the previous manifest.
+    NumberDecreased {
+        previous: Serial,
+        current: Serial,
+    },
+
+    /// A different manifest with the same manifest number has been seen.
+    NumberReused(Serial),
+}
+
+impl PubPointIssue {
+    /// Returns whether the issue can be ignored in relaxed mode.
+    ///
+    /// This is only the case for file names with illegal characters which
+    /// some CAs are known to use. Names that can’t safely be used as file
+    /// names are reported as [`UnusableName`][Self::UnusableName] which is
+    /// never relaxable.
+    pub fn is_relaxable(&self) -> bool {
+        matches!(*self, PubPointIssue::IllegalName(_))
+    }
+}
+
+impl fmt::Display for PubPointIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::PubPointIssue::*;
+
+        match *self {
+            DuplicateName(ref name) => {
+                write!(
+                    f, "file name '{}' listed more than once",
+                    String::from_utf8_lossy(name)
+                )
+            }
+            UnusableName(ref name) => {
+                write!(
+                    f, "unusable file name '{}'",
+                    String::from_utf8_lossy(name)
+                )
+            }
+            IllegalName(ref name) => {
+                write!(
+                    f, "illegal file name '{}'",
+                    String::from_utf8_lossy(name)
+                )
+            }
+            MissingFile(ref uri) => {
+                write!(f, "file {} listed on manifest not found", uri)
+            }
+            HashMismatch(ref uri) => write!(f, "hash mismatch for {}", uri),
+            NoCrl => f.write_str("CRL not listed on manifest"),
+            MultipleCrls(ref uris) => {
+                f.write_str("multiple CRLs listed on manifest:")?;
+                for uri in uris {
+                    write!(f, " {}", uri)?;
+                }
+                Ok(())
+            }
+            CrlUriMismatch { ref listed, expected: Some(ref expected) } => {
+                write!(
+                    f, "unexpected CRL {}, expected {}", listed, expected
+                )
+            }
+            CrlUriMismatch { ref listed, expected: None } => {
+                write!(
+                    f, "unexpected CRL {}, manifest EE certificate without \
+                    CRL URI", listed
+                )
+            }
+            NumberDecreased { previous, current } => {
+                write!(
+                    f, "manifest number {} smaller than previous {}",
+                    current, previous
+                )
+            }
+            NumberReused(number) => {
+                write!(f, "manifest number {} reused", number)
+            }
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(all(test, feature = "softkeys"))]
+mod signer_test {
+    use chrono::Duration;
+    use super::super::testrepo::{TestRepo, Which};
+    use super::*;
+
+    fn manifest(repo: &TestRepo, which: Which) -> Manifest {
+        Manifest::decode(
+            repo.archive.get(&which.manifest()).unwrap().clone(), true
+        ).unwrap()
+    }
+
+    fn check_repo(
+        repo: &TestRepo, previous: Option<&Manifest>
+    ) -> PubPointCheck {
+        check(
+            &manifest(repo, Which::Child), &Which::Child.ca_repository(),
+            &repo.archive, previous.map(Manifest::content)
+        ).unwrap()
+    }
+
+    fn publish(
+        repo: &mut TestRepo, number: u64, files: Vec<(&str, Bytes)>
+    ) {
+        let now = TestRepo::base_time();
+        repo.publish(
+            Which::Child, number, now, now + Duration::days(7), files
+        );
+    }
+
+    #[test]
+    fn consistent() {
+        let repo = TestRepo::new();
+        let res = check_repo(&repo, None);
+        assert!(res.is_ok(), "{:?}", res.issues());
+        assert_eq!(res.crl().unwrap().0, Which::Child.crl());
+        assert_eq!(res.files().len(), 3);
+    }
+
+    #[test]
+    fn missing_and_mismatched_files() {
+        let mut repo = TestRepo::new();
+        let roa = Which::Child.ca_repository().join(b"roa.roa").unwrap();
+        let aspa = Which::Child.ca_repository().join(b"aspa.asa").unwrap();
+        repo.archive.remove(&roa);
+        repo.archive.insert(aspa.clone(), Bytes::from_static(b"foo"));
+        let res = check_repo(&repo, None);
+        assert_eq!(
+            res.issues(),
+            &[
+                PubPointIssue::MissingFile(roa),
+                PubPointIssue::HashMismatch(aspa),
+            ]
+        );
+        assert!(!res.is_acceptable(false));
+        assert_eq!(res.files().len(), 1);
+        assert!(res.crl().is_some());
+
+        repo.archive.remove(&Which::Child.crl());
+        assert!(check_repo(&repo, None).crl().is_none());
+    }
+
+    #[test]
+    fn names_and_crls() {
+        let mut repo = TestRepo::new();
+        let data = Bytes::from_static(b"foo");
+        publish(&mut repo, 2, vec![
+            ("bad+name.roa", data.clone()),
+            ("foo.roa", data.clone()),
+            ("foo.roa", data.clone()),
+        ]);
+        let res = check_repo(&repo, None);
+        assert_eq!(
+            res.issues(),
+            &[
+                PubPointIssue::IllegalName(
+                    Bytes::from_static(b"bad+name.roa")
+                ),
+                PubPointIssue::DuplicateName(Bytes::from_static(b"foo.roa")),
+            ]
+        );
+        assert_eq!(res.first_error(true), Some(&res.issues()[0]));
+        assert_eq!(res.first_error(false), Some(&res.issues()[1]));
+
+        publish(&mut repo, 3, vec![("other.crl", data)]);
+        let res = check_repo(&repo, None);
+        assert_eq!(
+            res.issues(),
+            &[PubPointIssue::MultipleCrls(vec![
+                Which::Child.crl(),
+                Which::Child.ca_repository().join(b"other.crl").unwrap(),
+            ])]
+        );
+        assert!(res.crl().is_none());
+    }
+
+    #[test]
+    fn unusable_names() {
+        let mut repo = TestRepo::new();
+        let data = Bytes::from_static(b"foo");
+        let names = [
+            "..", ".", "sub/foo.roa", "..\\foo.roa", "foo\n.roa", "foo\0.roa"
+        ];
+        publish(
+            &mut repo, 2,
+            names.iter().map(|&name| (name, data.clone())).collect()
+        );
+        let res = check_repo(&repo, None);
+        assert_eq!(
+            res.issues(),
+            names.iter().map(|name| {
+                PubPointIssue::UnusableName(
+                    Bytes::copy_from_slice(name.as_bytes())
+                )
+            }).collect::<Vec<_>>()
+        );
+        assert_eq!(res.first_error(false), Some(&res.issues()[0]));
+        assert_eq!(res.files().len(), 1);
+    }
+
+    #[test]
+    fn manifest_number() {
+        let mut repo = TestRepo::new();
+        let first = manifest(&repo, Which::Child);
+        assert!(check_repo(&repo, Some(&first)).is_ok());
+
+        // Same number, different content.
+        publish(&mut repo, 1, Vec::new());
+        assert_eq!(
+            check_repo(&repo, Some(&first)).issues(),
+            &[PubPointIssue::NumberReused(1u64.into())]
+        );
+
+        publish(&mut repo, 5, Vec::new());
+        let fifth = manifest(&repo, Which::Child);
+        publish(&mut repo, 4, Vec::new());
+        assert_eq!(
+            check_repo(&repo, Some(&fifth)).issues(),
+            &[PubPointIssue::NumberDecreased {
+                previous: 5u64.into(), current: 4u64.into()
+            }]
+        );
+
+        let now = TestRepo::base_time() + Duration::hours(1);
+        repo.publish(
+            Which::Child, 6, now, now + Duration::days(7), Vec::new()
+        );
+        assert!(check_repo(&repo, Some(&fifth)).is_ok());
+    }
+}
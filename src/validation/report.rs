@@ -0,0 +1,319 @@
+//! The result of a validation run.
+//!
+//! A validation run produces a [`Report`]. It contains the validated RPKI
+//! payload – route origins in the form of [`Vrp`]s and provider
+//! authorizations as [`AspaPayload`]s – as well as information on what
+//! happened to each trust anchor, publication point, and object that was
+//! encountered during the run.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+use crate::uri;
+use crate::repository::resources::Asn;
+use crate::repository::tal::TalInfo;
+use crate::repository::x509::Time;
+
+
+//------------ Report --------------------------------------------------------
+
+/// The outcome of a validation run.
+///
+/// All lists in the report are sorted, so that two runs over the same data
+/// at the same time produce identical reports.
+#[derive(Clone, Debug)]
+pub struct Report {
+    /// The time used as the current time during validation.
+    now: Time,
+
+    /// The validated route origins.
+    vrps: Vec<Vrp>,
+
+    /// The validated provider authorizations.
+    aspas: Vec<AspaPayload>,
+
+    /// The reports for the trust anchors.
+    trust_anchors: Vec<TrustAnchorReport>,
+
+    /// The reports for all publication points.
+    pub_points: Vec<PubPointReport>,
+
+    /// The reports for all objects.
+    objects: Vec<ObjectReport>,
+}
+
+impl Report {
+    /// Creates a new report from its parts.
+    ///
+    /// The lists are sorted and duplicate payload is removed.
+    pub(crate) fn new(
+        now: Time,
+        mut vrps: Vec<Vrp>,
+        mut aspas: Vec<AspaPayload>,
+        trust_anchors: Vec<TrustAnchorReport>,
+        mut pub_points: Vec<PubPointReport>,
+        mut objects: Vec<ObjectReport>,
+    ) -> Self {
+        vrps.sort();
+        vrps.dedup();
+        aspas.sort();
+        aspas.dedup();
+        pub_points.sort_by(|left, right| {
+            left.manifest.as_str().cmp(right.manifest.as_str())
+        });
+        objects.sort_by(|left, right| {
+            left.uri.as_str().cmp(right.uri.as_str())
+        });
+        Report { now, vrps, aspas, trust_anchors, pub_points, objects }
+    }
+
+    /// Returns the time that was considered the current time.
+    pub fn now(&self) -> Time {
+        self.now
+    }
+
+    /// Returns the validated route origins.
+    pub fn vrps(&self) -> &[Vrp] {
+        &self.vrps
+    }
+
+    /// Returns the validated provider authorizations.
+    pub fn aspas(&self) -> &[AspaPayload] {
+        &self.aspas
+    }
+
+    /// Returns the reports for the trust anchors.
+    pub fn trust_anchors(&self) -> &[TrustAnchorReport] {
+        &self.trust_anchors
+    }
+
+    /// Returns the reports for the publication points.
+    pub fn pub_points(&self) -> &[PubPointReport] {
+        &self.pub_points
+    }
+
+    /// Returns the reports for all objects.
+    pub fn objects(&self) -> &[ObjectReport] {
+        &self.objects
+    }
+
+    /// Returns the report for the object with the given URI.
+    pub fn object(&self, uri: &uri::Rsync) -> Option<&ObjectReport> {
+        self.objects.iter().find(|item| item.uri == *uri)
+    }
+}
+
+
+//------------ Vrp -----------------------------------------------------------
+
+/// A validated ROA payload.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Vrp {
+    /// The AS number allowed to originate the prefix.
+    pub asn: Asn,
+
+    /// The address of the prefix.
+    pub addr: IpAddr,
+
+    /// The length of the prefix.
+    pub prefix_len: u8,
+
+    /// The maximum length of announced prefixes.
+    pub max_len: u8,
+}
+
+impl fmt::Display for Vrp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "{}/{}-{} => {}",
+            self.addr, self.prefix_len, self.max_len, self.asn
+        )
+    }
+}
+
+
+//------------ AspaPayload ---------------------------------------------------
+
+/// The validated payload of an ASPA object.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct AspaPayload {
+    /// The customer AS.
+    pub customer: Asn,
+
+    /// The authorized provider ASes.
+    pub providers: Vec<Asn>,
+}
+
+
+//------------ TrustAnchorReport ---------------------------------------------
+
+/// What happened to a trust anchor.
+#[derive(Clone, Debug)]
+pub struct TrustAnchorReport {
+    /// Information about the trust anchor.
+    pub info: Arc<TalInfo>,
+
+    /// The URI the certificate was taken from if known.
+    pub uri: Option<uri::Rsync>,
+
+    /// The status of the trust anchor certificate.
+    pub status: ObjectStatus,
+}
+
+
+//------------ PubPointReport ------------------------------------------------
+
+/// What happened to a publication point.
+#[derive(Clone, Debug)]
+pub struct PubPointReport {
+    /// The rsync URI of the CA’s repository directory.
+    pub ca_repository: uri::Rsync,
+
+    /// The URI of the RRDP notification file of the CA’s repository.
+    pub rpki_notify: Option<uri::Https>,
+
+    /// The rsync URI of the manifest.
+    pub manifest: uri::Rsync,
+
+    /// The status of the publication point.
+    pub status: PubPointStatus,
+}
+
+
+//------------ PubPointStatus ------------------------------------------------
+
+/// The status of a publication point.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PubPointStatus {
+    /// The publication point was current and has been used.
+    Current,
+
+    /// The current publication point was rejected but the last known
+    /// good copy has been used instead.
+    ///
+    /// The string contains the reason for rejecting the current
+    /// publication point.
+    LastKnownGood(String),
+
+    /// The publication point was rejected.
+    ///
+    /// The string contains a reason.
+    Failed(String),
+}
+
+impl PubPointStatus {
+    /// Returns whether the current publication point was used.
+    pub fn is_current(&self) -> bool {
+        matches!(*self, PubPointStatus::Current)
+    }
+
+    /// Returns whether the publication point was used at all.
+    ///
+    /// This is the case if either the current publication point or the
+    /// last known good copy was used.
+    pub fn is_used(&self) -> bool {
+        !matches!(*self, PubPointStatus::Failed(_))
+    }
+}
+
+
+//------------ ObjectReport --------------------------------------------------
+
+/// What happened to an object.
+#[derive(Clone, Debug)]
+pub struct ObjectReport {
+    /// The rsync URI of the object.
+    pub uri: uri::Rsync,
+
+    /// The kind of object.
+    pub kind: ObjectKind,
+
+    /// The status of the object.
+    pub status: ObjectStatus,
+}
+
+
+//------------ ObjectKind ----------------------------------------------------
+
+/// The kind of a repository object.
+///
+/// The kind is derived from the file extension of the object’s URI.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ObjectKind {
+    /// A CA or router certificate.
+    Certificate,
+
+    /// A manifest.
+    Manifest,
+
+    /// A certificate revocation list.
+    Crl,
+
+    /// A route origin authorization.
+    Roa,
+
+    /// An autonomous system provider authorization.
+    Aspa,
+
+    /// A Ghostbuster record.
+    Gbr,
+
+    /// Any other object.
+    Other,
+}
+
+impl ObjectKind {
+    /// Determines the kind of an object from its URI.
+    pub fn from_uri(uri: &uri::Rsync) -> Self {
+        if uri.ends_with(".cer") {
+            ObjectKind::Certificate
+        }
+        else if uri.ends_with(".mft") {
+            ObjectKind::Manifest
+        }
+        else if uri.ends_with(".crl") {
+            ObjectKind::Crl
+        }
+        else if uri.ends_with(".roa") {
+            ObjectKind::Roa
+        }
+        else if uri.ends_with(".asa") {
+            ObjectKind::Aspa
+        }
+        else if uri.ends_with(".gbr") {
+            ObjectKind::Gbr
+        }
+        else {
+            ObjectKind::Other
+        }
+    }
+}
+
+
+//------------ ObjectStatus --------------------------------------------------
+
+/// The status of an object.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ObjectStatus {
+    /// The object is valid and its content has been used.
+    Valid,
+
+    /// The object is invalid.
+    ///
+    /// The string contains a reason.
+    Invalid(String),
+
+    /// The object has not been considered.
+    ///
+    /// This happens for objects of kinds that are not evaluated or if the
+    /// publication point as a whole was rejected. The string contains a
+    /// reason.
+    Skipped(String),
+}
+
+impl ObjectStatus {
+    /// Returns whether the object was valid.
+    pub fn is_valid(&self) -> bool {
+        matches!(*self, ObjectStatus::Valid)
+    }
+}
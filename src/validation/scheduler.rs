@@ -0,0 +1,265 @@
+//! A simple work-stealing scheduler.
+//!
+//! Validation produces new work while it runs: every CA certificate found
+//! leads to another publication point to process. The scheduler in this
+//! module runs such self-expanding work on a fixed number of threads.
+//!
+//! Each worker thread has its own queue. New tasks produced by a task are
+//! added to the queue of the thread that ran it and are taken from there
+//! last-in-first-out, which keeps the work of a thread close together.
+//! Threads that run out of work steal the oldest task from the queue of
+//! another thread.
+//!
+//! The order in which tasks are run and thus results are produced is not
+//! deterministic when more than one thread is used. Callers need to sort
+//! the results if they care.
+
+use std::thread;
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+
+//------------ run -----------------------------------------------------------
+
+/// How long an idle worker waits before looking for work again.
+///
+/// Workers are woken up when new work arrives, so this only is a safety
+/// net for the case where a wake-up was missed.
+const IDLE_WAIT: Duration = Duration::from_millis(10);
+
+/// Runs a set of tasks and all tasks they produce on `threads` threads.
+///
+/// The closure `op` is run for each task. Any tasks it adds to the vec
+/// passed to it are scheduled, too. The function returns once all tasks
+/// have been run and returns the results of all of them in no particular
+/// order.
+///
+/// If `threads` is zero or one, all tasks are run on the current thread.
+pub fn run<T, R, F>(threads: usize, tasks: Vec<T>, op: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T, &mut Vec<T>) -> R + Sync,
+{
+    if threads <= 1 {
+        return run_single(tasks, op)
+    }
+
+    let pool = Pool::new(threads, tasks);
+    let pool = &pool;
+    let op = &op;
+    crossbeam_utils::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads).map(|idx| {
+            scope.spawn(move |_| pool.work(idx, op))
+        }).collect();
+        workers.into_iter().flat_map(|worker| {
+            match worker.join() {
+                Ok(res) => res,
+                Err(err) => std::panic::resume_unwind(err)
+            }
+        }).collect()
+    }).unwrap_or_else(|err| std::panic::resume_unwind(err))
+}
+
+/// Runs all tasks on the current thread.
+fn run_single<T, R, F>(tasks: Vec<T>, op: F) -> Vec<R>
+where F: Fn(T, &mut Vec<T>) -> R {
+    let mut queue: Vec<_> = tasks.into_iter().rev().collect();
+    let mut new = Vec::new();
+    let mut res = Vec::new();
+    while let Some(task) = queue.pop() {
+        res.push(op(task, &mut new));
+        queue.extend(new.drain(..).rev());
+    }
+    res
+}
+
+
+//------------ Pool ----------------------------------------------------------
+
+/// The state shared between the workers.
+struct Pool<T> {
+    /// The queues of the workers.
+    queues: Vec<Mutex<VecDeque<T>>>,
+
+    /// The number of tasks that are either queued or currently running.
+    ///
+    /// Once this drops to zero, all work is done.
+    pending: AtomicUsize,
+
+    /// Whether a task has panicked and all workers should stop.
+    aborted: AtomicBool,
+
+    /// A mutex for idle workers to wait on.
+    idle: Mutex<()>,
+
+    /// The condition variable to wake up idle workers.
+    wake: Condvar,
+}
+
+impl<T> Pool<T> {
+    /// Creates a new pool with the initial tasks spread over the queues.
+    fn new(threads: usize, tasks: Vec<T>) -> Self {
+        let mut queues: Vec<_> = (0..threads).map(|_| {
+            VecDeque::new()
+        }).collect();
+        let pending = tasks.len();
+        for (idx, task) in tasks.into_iter().enumerate() {
+            queues[idx % threads].push_back(task)
+        }
+        Pool {
+            queues: queues.into_iter().map(Mutex::new).collect(),
+            pending: AtomicUsize::new(pending),
+            aborted: AtomicBool::new(false),
+            idle: Mutex::new(()),
+            wake: Condvar::new(),
+        }
+    }
+
+    /// Runs the worker with the given index until all work is done.
+    fn work<R, F>(&self, idx: usize, op: &F) -> Vec<R>
+    where F: Fn(T, &mut Vec<T>) -> R {
+        let mut res = Vec::new();
+        let mut new = Vec::new();
+        loop {
+            if self.is_done() {
+                return res
+            }
+            let task = match self.next_task(idx) {
+                Some(task) => task,
+                None => {
+                    let guard = self.idle.lock().unwrap_or_else(|err| {
+                        err.into_inner()
+                    });
+                    if self.is_done() {
+                        return res
+                    }
+                    drop(self.wake.wait_timeout(guard, IDLE_WAIT));
+                    continue
+                }
+            };
+            let abort = AbortGuard(self);
+            res.push(op(task, &mut new));
+            drop(abort);
+            if !new.is_empty() {
+                self.pending.fetch_add(new.len(), Ordering::AcqRel);
+                self.lock_queue(idx).extend(new.drain(..));
+                self.wake.notify_all();
+            }
+            if self.pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+                // That was the last task. Wake everyone so they can leave.
+                let _guard = self.idle.lock();
+                self.wake.notify_all();
+            }
+        }
+    }
+
+    /// Returns whether the workers should stop.
+    ///
+    /// This is the case when all work is done or a task has panicked.
+    fn is_done(&self) -> bool {
+        self.aborted.load(Ordering::Acquire)
+            || self.pending.load(Ordering::Acquire) == 0
+    }
+
+    /// Returns the next task for the worker with the given index.
+    ///
+    /// Tries the worker’s own queue first and then steals from the other
+    /// queues.
+    fn next_task(&self, idx: usize) -> Option<T> {
+        if let Some(task) = self.lock_queue(idx).pop_back() {
+            return Some(task)
+        }
+        let len = self.queues.len();
+        (1..len).find_map(|offset| {
+            self.lock_queue((idx + offset) % len).pop_front()
+        })
+    }
+
+    /// Locks the queue with the given index.
+    ///
+    /// A poisoned lock is ignored since the panic will be propagated
+    /// when the worker is joined anyway.
+    fn lock_queue(
+        &self, idx: usize
+    ) -> std::sync::MutexGuard<'_, VecDeque<T>> {
+        self.queues[idx].lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+
+//------------ AbortGuard ----------------------------------------------------
+
+/// Stops all workers if dropped while a task panics.
+///
+/// Without this, the task would never be marked as done and the other
+/// workers would wait for it forever instead of letting the panic be
+/// propagated.
+struct AbortGuard<'a, T>(&'a Pool<T>);
+
+impl<'a, T> Drop for AbortGuard<'a, T> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.aborted.store(true, Ordering::Release);
+            let _guard = self.0.idle.lock();
+            self.0.wake.notify_all();
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Runs a binary tree of the given depth and returns the sorted results.
+    fn tree(threads: usize, depth: u32) -> Vec<u32> {
+        let mut res = run(threads, vec![1u32], |node, new| {
+            if node < (1 << depth) {
+                new.push(node * 2);
+                new.push(node * 2 + 1);
+            }
+            node
+        });
+        res.sort_unstable();
+        res
+    }
+
+    #[test]
+    fn single_and_multi_threaded() {
+        let expected: Vec<u32> = (1..(1 << 11)).collect();
+        assert_eq!(tree(0, 10), expected);
+        assert_eq!(tree(1, 10), expected);
+        assert_eq!(tree(4, 10), expected);
+        assert_eq!(tree(16, 10), expected);
+    }
+
+    #[test]
+    fn panicking_task() {
+        for threads in &[1, 4] {
+            let res = std::panic::catch_unwind(|| {
+                run(*threads, vec![1u32], |node, new| {
+                    if node == 100 {
+                        panic!("task failed");
+                    }
+                    if node < 1 << 10 {
+                        new.push(node * 2);
+                        new.push(node * 2 + 1);
+                    }
+                    node
+                })
+            });
+            assert!(res.is_err());
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let res: Vec<()> = run(4, Vec::<()>::new(), |_, _| ());
+        assert!(res.is_empty());
+    }
+}
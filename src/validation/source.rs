@@ -0,0 +1,159 @@
+//! Sources of repository objects.
+//!
+//! Validation needs access to the objects published by the CAs. This
+//! module defines the trait [`ObjectSource`] abstracting over where these
+//! objects are kept and provides the type [`Archive`], an in-memory
+//! collection of objects that can, for instance, be created from an RRDP
+//! snapshot kept for later analysis. The repository stores of the
+//! [`store`][crate::store] module can be used as sources, too.
+
+use std::io;
+use std::collections::HashMap;
+use std::collections::hash_map;
+use bytes::Bytes;
+use crate::uri;
+use crate::store::{FsStore, MemoryStore, RepositoryStore};
+
+
+//------------ ObjectSource --------------------------------------------------
+
+/// A type that provides access to repository objects.
+///
+/// Objects are identified by their rsync URI. Sources are not required to
+/// actually use rsync for collecting the objects.
+pub trait ObjectSource {
+    /// Returns the content of the object with the given URI.
+    ///
+    /// If the source doesn’t know of the object, returns `Ok(None)`. An
+    /// error should be returned if the source was unable to determine
+    /// whether it has the object or not.
+    fn load(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, io::Error>;
+}
+
+impl<S: ObjectSource + ?Sized> ObjectSource for &S {
+    fn load(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, io::Error> {
+        (*self).load(uri)
+    }
+}
+
+impl ObjectSource for HashMap<uri::Rsync, Bytes> {
+    fn load(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, io::Error> {
+        Ok(self.get(uri).cloned())
+    }
+}
+
+impl ObjectSource for MemoryStore {
+    fn load(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, io::Error> {
+        self.get(uri).map_err(Into::into)
+    }
+}
+
+impl ObjectSource for FsStore {
+    fn load(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, io::Error> {
+        self.get(uri).map_err(Into::into)
+    }
+}
+
+#[cfg(feature = "rrdp")]
+impl ObjectSource for crate::store::ContentStore {
+    fn load(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, io::Error> {
+        self.get(uri).map_err(Into::into)
+    }
+}
+
+#[cfg(feature = "rrdp")]
+impl<'a> ObjectSource for crate::store::content::ContentSnapshot<'a> {
+    fn load(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, io::Error> {
+        self.get(uri).map_err(Into::into)
+    }
+}
+
+
+//------------ Archive -------------------------------------------------------
+
+/// An in-memory collection of repository objects.
+///
+/// An archive keeps the content of objects keyed by their rsync URI. It can
+/// be filled manually via [`insert`][Self::insert] or from any iterator
+/// over pairs of URIs and content. If the `"rrdp"` feature is enabled, it
+/// can also be created from an RRDP snapshot.
+#[derive(Clone, Debug, Default)]
+pub struct Archive {
+    /// The objects keyed by their URI.
+    objects: HashMap<uri::Rsync, Bytes>,
+}
+
+impl Archive {
+    /// Creates a new, empty archive.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an archive with the content of an RRDP snapshot.
+    #[cfg(feature = "rrdp")]
+    pub fn from_snapshot(snapshot: &crate::rrdp::Snapshot) -> Self {
+        snapshot.elements().iter().map(|item| {
+            (item.uri().clone(), item.data().clone())
+        }).collect()
+    }
+
+    /// Adds an object to the archive.
+    ///
+    /// Returns the previous content of the object if there was any.
+    pub fn insert(&mut self, uri: uri::Rsync, data: Bytes) -> Option<Bytes> {
+        self.objects.insert(uri, data)
+    }
+
+    /// Removes an object from the archive.
+    ///
+    /// Returns the content of the object if there was one.
+    pub fn remove(&mut self, uri: &uri::Rsync) -> Option<Bytes> {
+        self.objects.remove(uri)
+    }
+
+    /// Returns the content of an object if it is present.
+    pub fn get(&self, uri: &uri::Rsync) -> Option<&Bytes> {
+        self.objects.get(uri)
+    }
+
+    /// Returns the number of objects in the archive.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns whether the archive is empty.
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Returns an iterator over the URIs and content of all objects.
+    pub fn iter(&self) -> hash_map::Iter<'_, uri::Rsync, Bytes> {
+        self.objects.iter()
+    }
+}
+
+
+//--- FromIterator and Extend
+
+impl std::iter::FromIterator<(uri::Rsync, Bytes)> for Archive {
+    fn from_iter<I>(iter: I) -> Self
+    where I: IntoIterator<Item = (uri::Rsync, Bytes)> {
+        Archive { objects: iter.into_iter().collect() }
+    }
+}
+
+impl Extend<(uri::Rsync, Bytes)> for Archive {
+    fn extend<I>(&mut self, iter: I)
+    where I: IntoIterator<Item = (uri::Rsync, Bytes)> {
+        self.objects.extend(iter)
+    }
+}
+
+
+//--- ObjectSource
+
+impl ObjectSource for Archive {
+    fn load(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, io::Error> {
+        Ok(self.objects.get(uri).cloned())
+    }
+}
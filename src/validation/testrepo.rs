@@ -0,0 +1,321 @@
+//! A small repository for testing.
+//!
+//! This module is only available in tests with the `"softkeys"` feature
+//! enabled. It builds a repository consisting of a trust anchor and a
+//! single child CA publishing a ROA and an ASPA.
+
+use std::str::FromStr;
+use std::net::Ipv4Addr;
+use bytes::Bytes;
+use chrono::Duration;
+use crate::uri;
+use crate::repository::aspa::{AspaBuilder, ProviderAs};
+use crate::repository::cert::{KeyUsage, Overclaim, TbsCert};
+use crate::repository::crl::{CrlEntry, TbsCertList};
+use crate::repository::crypto::{DigestAlgorithm, PublicKeyFormat, Signer};
+use crate::repository::crypto::softsigner::{KeyId, OpenSslSigner};
+use crate::repository::manifest::{FileAndHash, ManifestContent};
+use crate::repository::resources::{Asn, Prefix};
+use crate::repository::roa::RoaBuilder;
+use crate::repository::sigobj::SignedObjectBuilder;
+use crate::repository::tal::TalInfo;
+use crate::repository::x509::{Serial, Time, Validity};
+use super::engine::TrustAnchor;
+use super::source::Archive;
+
+
+//------------ Which ---------------------------------------------------------
+
+/// Which of the two CAs of the test repository to use.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Which {
+    Ta,
+    Child,
+}
+
+impl Which {
+    /// Returns the repository directory of the CA.
+    pub fn ca_repository(self) -> uri::Rsync {
+        match self {
+            Which::Ta => rsync("rsync://example.com/repo/ta/"),
+            Which::Child => rsync("rsync://example.com/repo/child/"),
+        }
+    }
+
+    /// Returns the URI of the CA’s manifest.
+    pub fn manifest(self) -> uri::Rsync {
+        self.ca_repository().join(b"ca.mft").unwrap()
+    }
+
+    /// Returns the URI of the CA’s CRL.
+    pub fn crl(self) -> uri::Rsync {
+        self.ca_repository().join(b"ca.crl").unwrap()
+    }
+
+    /// Returns the URI of the CA’s certificate.
+    pub fn cert(self) -> uri::Rsync {
+        match self {
+            Which::Ta => rsync("rsync://example.com/ta/ta.cer"),
+            Which::Child => Which::Ta.ca_repository().join(
+                b"child.cer"
+            ).unwrap()
+        }
+    }
+}
+
+
+//------------ TestRepo ------------------------------------------------------
+
+/// A test repository.
+pub struct TestRepo {
+    pub signer: OpenSslSigner,
+    pub ta_key: KeyId,
+    pub child_key: KeyId,
+    pub ta: TrustAnchor,
+    pub archive: Archive,
+    next_serial: u64,
+}
+
+impl TestRepo {
+    /// The time the repository was created.
+    pub fn base_time() -> Time {
+        Time::utc(2021, 1, 1, 0, 0, 0)
+    }
+
+    /// Creates the repository.
+    ///
+    /// Both CAs publish manifest number 1 issued at the base time and valid
+    /// for a week.
+    pub fn new() -> Self {
+        let signer = OpenSslSigner::new();
+        let ta_key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let child_key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+
+        let ta_pubkey = signer.get_key_info(&ta_key).unwrap();
+        let mut cert = TbsCert::new(
+            1u64.into(), ta_pubkey.to_subject_name(), Self::cert_validity(),
+            None, ta_pubkey, KeyUsage::Ca, Overclaim::Refuse
+        );
+        cert.set_basic_ca(Some(true));
+        cert.set_ca_repository(Some(Which::Ta.ca_repository()));
+        cert.set_rpki_manifest(Some(Which::Ta.manifest()));
+        cert.build_v4_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_v6_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_as_resource_blocks(|b| b.push((Asn::MIN, Asn::MAX)));
+        let cert = cert.into_cert(&signer, &ta_key).unwrap();
+        let ta = TrustAnchor::with_uri(
+            cert.clone(), TalInfo::from_name("test".into()).into_arc(),
+            Which::Ta.cert()
+        );
+
+        let mut res = TestRepo {
+            signer, ta_key, child_key, ta,
+            archive: Archive::new(),
+            next_serial: 2,
+        };
+        res.archive.insert(
+            Which::Ta.cert(), cert.to_captured().into_bytes()
+        );
+
+        let now = Self::base_time();
+        let child = res.make_child_cert(Self::cert_validity());
+        res.publish(
+            Which::Ta, 1, now, now + Duration::days(7),
+            vec![("child.cer", child)]
+        );
+        let roa = res.make_roa(64496, Validity::new(
+            now, now + Duration::days(365)
+        ));
+        let aspa = res.make_aspa(64496, &[64497, 64498]);
+        res.publish(
+            Which::Child, 1, now, now + Duration::days(7),
+            vec![("roa.roa", roa), ("aspa.asa", aspa)]
+        );
+        res
+    }
+
+    /// Returns the validity used for the CA certificates.
+    pub fn cert_validity() -> Validity {
+        Validity::new(
+            Self::base_time() - Duration::days(1),
+            Self::base_time() + Duration::days(365)
+        )
+    }
+
+    /// Returns a new serial number.
+    pub fn next_serial(&mut self) -> u64 {
+        self.next_serial += 1;
+        self.next_serial
+    }
+
+    /// Returns the key of a CA.
+    pub fn key(&self, which: Which) -> &KeyId {
+        match which {
+            Which::Ta => &self.ta_key,
+            Which::Child => &self.child_key,
+        }
+    }
+
+    /// Creates the certificate for the child CA.
+    pub fn make_child_cert(&mut self, validity: Validity) -> Bytes {
+        let serial = self.next_serial();
+        let ta_pubkey = self.signer.get_key_info(&self.ta_key).unwrap();
+        let pubkey = self.signer.get_key_info(&self.child_key).unwrap();
+        let mut cert = TbsCert::new(
+            serial.into(), ta_pubkey.to_subject_name(), validity,
+            None, pubkey, KeyUsage::Ca, Overclaim::Refuse
+        );
+        cert.set_basic_ca(Some(true));
+        cert.set_authority_key_identifier(Some(ta_pubkey.key_identifier()));
+        cert.set_crl_uri(Some(Which::Ta.crl()));
+        cert.set_ca_issuer(Some(Which::Ta.cert()));
+        cert.set_ca_repository(Some(Which::Child.ca_repository()));
+        cert.set_rpki_manifest(Some(Which::Child.manifest()));
+        cert.build_v4_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_v6_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_as_resource_blocks(|b| b.push((Asn::MIN, Asn::MAX)));
+        let cert = cert.into_cert(&self.signer, &self.ta_key).unwrap();
+        cert.to_captured().into_bytes()
+    }
+
+    /// Creates a ROA for 192.0.2.0/24 issued by the child CA.
+    pub fn make_roa(&mut self, asn: u32, validity: Validity) -> Bytes {
+        let serial = self.next_serial();
+        let mut roa = RoaBuilder::new(asn.into());
+        roa.push_v4_addr(Ipv4Addr::new(192, 0, 2, 0), 24, None);
+        let roa = roa.finalize(
+            self.sigobj(Which::Child, serial, validity, "roa.roa"),
+            &self.signer, &self.child_key
+        ).unwrap();
+        roa.to_captured().into_bytes()
+    }
+
+    /// Creates an ASPA issued by the child CA.
+    pub fn make_aspa(&mut self, customer: u32, providers: &[u32]) -> Bytes {
+        let serial = self.next_serial();
+        let validity = Validity::new(
+            Self::base_time(), Self::base_time() + Duration::days(365)
+        );
+        let aspa = AspaBuilder::new(
+            customer.into(),
+            providers.iter().map(|asn| {
+                ProviderAs::new(Asn::from(*asn))
+            }).collect()
+        ).unwrap();
+        let aspa = aspa.finalize(
+            self.sigobj(Which::Child, serial, validity, "aspa.asa"),
+            &self.signer, &self.child_key
+        ).unwrap();
+        aspa.to_captured().into_bytes()
+    }
+
+    /// Creates a manifest and CRL and adds them and the files to the archive.
+    ///
+    /// Objects previously published by the CA are removed from the archive
+    /// first.
+    pub fn publish(
+        &mut self,
+        which: Which,
+        number: u64,
+        this_update: Time,
+        next_update: Time,
+        files: Vec<(&str, Bytes)>,
+    ) {
+        self.publish_with_revoked(
+            which, number, this_update, next_update, files, Vec::new()
+        )
+    }
+
+    /// Publishes with the given serial numbers on the CRL.
+    pub fn publish_with_revoked(
+        &mut self,
+        which: Which,
+        number: u64,
+        this_update: Time,
+        next_update: Time,
+        files: Vec<(&str, Bytes)>,
+        revoked: Vec<Serial>,
+    ) {
+        let ca_repository = which.ca_repository();
+        let old: Vec<_> = self.archive.iter().filter_map(|(uri, _)| {
+            if ca_repository.is_parent_of(uri) {
+                Some(uri.clone())
+            }
+            else {
+                None
+            }
+        }).collect();
+        for uri in old {
+            self.archive.remove(&uri);
+        }
+
+        let pubkey = self.signer.get_key_info(self.key(which)).unwrap();
+        let crl = TbsCertList::new(
+            Default::default(), pubkey.to_subject_name(),
+            this_update, next_update,
+            revoked.into_iter().map(|serial| {
+                CrlEntry::new(serial, this_update)
+            }).collect::<Vec<_>>(),
+            pubkey.key_identifier(), number.into()
+        ).into_crl(&self.signer, self.key(which)).unwrap();
+        let crl = crl.to_captured().into_bytes();
+
+        let mut list = vec![
+            FileAndHash::new(Bytes::from_static(b"ca.crl"), hash(&crl))
+        ];
+        list.extend(files.iter().map(|(name, data)| {
+            FileAndHash::new(
+                Bytes::copy_from_slice(name.as_bytes()), hash(data)
+            )
+        }));
+        let serial = self.next_serial();
+        let content = ManifestContent::new(
+            number.into(), this_update, next_update,
+            DigestAlgorithm::default(), list.iter()
+        );
+        let manifest = content.into_manifest(
+            self.sigobj(
+                which, serial, Validity::new(this_update, next_update),
+                "ca.mft"
+            ),
+            &self.signer, self.key(which)
+        ).unwrap();
+
+        self.archive.insert(
+            which.manifest(), manifest.to_captured().into_bytes()
+        );
+        self.archive.insert(which.crl(), crl);
+        for (name, data) in files {
+            // Names that don’t make valid URIs can only be on the manifest.
+            if let Ok(uri) = ca_repository.join(name.as_bytes()) {
+                self.archive.insert(uri, data);
+            }
+        }
+    }
+
+    /// Returns the signed object builder for an object of a CA.
+    fn sigobj(
+        &self, which: Which, serial: u64, validity: Validity, name: &str
+    ) -> SignedObjectBuilder {
+        SignedObjectBuilder::new(
+            serial.into(), validity, which.crl(), which.cert(),
+            which.ca_repository().join(name.as_bytes()).unwrap()
+        )
+    }
+
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Creates an rsync URI from a string.
+pub fn rsync(s: &str) -> uri::Rsync {
+    uri::Rsync::from_str(s).unwrap()
+}
+
+/// Returns the manifest hash of some data.
+pub fn hash(data: &[u8]) -> Bytes {
+    Bytes::copy_from_slice(
+        DigestAlgorithm::default().digest(data).as_ref()
+    )
+}
@@ -1,30 +1,84 @@
-
 use std::{error, fmt, io, str};
 use std::borrow::Cow;
 use bytes::Bytes;
 use quick_xml::events::{BytesStart, Event};
+#[cfg(feature = "async")]
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
 
 /// An XML reader.
 ///
 /// This struct holds all state necessary for parsing an XML document.
 pub struct Reader<R: io::BufRead> {
-    reader: quick_xml::Reader<R>,
+    reader: quick_xml::Reader<LimitedRead<R>>,
     buf: Vec<u8>,
     ns_buf: Vec<u8>,
+
+    /// The limits imposed on the document.
+    limits: Limits,
+
+    /// The number of elements seen so far.
+    elements: usize,
+
+    /// The current nesting depth.
+    depth: usize,
 }
 
 impl<R: io::BufRead> Reader<R> {
     /// Creates a new reader from an underlying reader.
+    ///
+    /// The reader will not impose any limits on the document.
     pub fn new(reader: R) -> Self {
-        let mut reader = quick_xml::Reader::from_reader(reader);
+        Self::with_limits(reader, Limits::default())
+    }
+
+    /// Creates a new reader that enforces the given limits.
+    ///
+    /// If the document exceeds any of the limits, parsing will fail with
+    /// the error specific to the limit.
+    pub fn with_limits(reader: R, limits: Limits) -> Self {
+        let mut reader = quick_xml::Reader::from_reader(
+            LimitedRead::new(reader, limits.max_document_size)
+        );
         reader.trim_text(true);
         Reader {
             reader,
             buf: Vec::new(),
             ns_buf: Vec::new(),
+            limits,
+            elements: 0,
+            depth: 0,
         }
     }
 
+    /// Returns the limits imposed by the reader.
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Reads the next event resolving namespaces.
+    fn read_namespaced_event(
+        &mut self
+    ) -> Result<(Option<&[u8]>, Event<'_>), Error> {
+        self.buf.clear();
+        let (ns, event) = self.reader.read_namespaced_event(
+            &mut self.buf, &mut self.ns_buf
+        )?;
+        self.limits.check_event(
+            &event, &mut self.elements, &mut self.depth
+        )?;
+        Ok((ns, event))
+    }
+
+    /// Reads the next event without resolving namespaces.
+    fn read_event(&mut self) -> Result<Event<'_>, Error> {
+        self.buf.clear();
+        let event = self.reader.read_event(&mut self.buf)?;
+        self.limits.check_event(
+            &event, &mut self.elements, &mut self.depth
+        )?;
+        Ok(event)
+    }
+
     /// Parse the start of the document.
     ///
     /// This is like `Content::take_element` except that it also happily
@@ -32,10 +86,7 @@ impl<R: io::BufRead> Reader<R> {
     pub fn start<F, E>(&mut self, op: F) -> Result<Content, E>
     where F: FnOnce(Element) -> Result<(), E>, E: From<Error> {
         loop {
-            self.buf.clear();
-            let (ns, event) = self.reader.read_namespaced_event(
-                &mut self.buf, &mut self.ns_buf
-            ).map_err(Into::into)?;
+            let (ns, event) = self.read_namespaced_event()?;
             match event {
                 Event::Start(start) => {
                     op(Element::new(start, ns))?;
@@ -60,8 +111,7 @@ impl<R: io::BufRead> Reader<R> {
     /// This checks that the next non-comment event to be the end of file.
     pub fn end(&mut self) -> Result<(), Error> {
         loop {
-            self.buf.clear();
-            match self.reader.read_event(&mut self.buf)? {
+            match self.read_event()? {
                 Event::Eof => return Ok(()),
                 Event::Comment(_) => { }
                 _ => return Err(Error::Malformed)
@@ -71,6 +121,583 @@ impl<R: io::BufRead> Reader<R> {
 }
 
 
+//------------ AsyncReader ---------------------------------------------------
+
+/// An XML reader for asynchronous input.
+///
+/// The XML parser used underneath only supports synchronous input. An
+/// async reader therefore splits the document into fragments which are
+/// read completely before being parsed synchronously. Only one fragment
+/// is kept in memory at a time.
+///
+/// Each fragment is presented as a synchronous [`Reader`] for a document
+/// made of the start of the original document up to and including the
+/// start tag of its outermost element followed by the next child element
+/// of the outermost element or, for the last fragment, the remainder of
+/// the document. A fragment is processed by calling [`Reader::start`]
+/// followed by [`Content::take_opt_element`]. If the latter returns
+/// `None`, the fragment is the last one and [`Reader::end`] should be
+/// called to check the end of the document.
+///
+/// The limits for the number of elements and the document size are
+/// enforced for the whole document by the async reader itself. All other
+/// limits are enforced for each fragment by its reader.
+#[cfg(feature = "async")]
+pub struct AsyncReader<R> {
+    /// The underlying reader.
+    reader: R,
+
+    /// The limits imposed on the document.
+    limits: Limits,
+
+    /// The start of the document up to the end of the outermost start tag.
+    head: Vec<u8>,
+
+    /// The data read but not yet returned as part of a fragment.
+    buf: Vec<u8>,
+
+    /// The length of the fragment returned last at the start of `buf`.
+    returned: usize,
+
+    /// The position in `buf` up to which markup has been examined.
+    scanned: usize,
+
+    /// The nesting depth of elements at `scanned`.
+    depth: usize,
+
+    /// Which part of the document we are currently reading.
+    part: Part,
+
+    /// The number of bytes read so far.
+    size: usize,
+
+    /// The number of elements seen so far.
+    elements: usize,
+
+    /// Has the underlying reader reached its end?
+    eof: bool,
+}
+
+/// The part of the document an async reader is currently reading.
+#[cfg(feature = "async")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Part {
+    /// Everything up to the start tag of the outermost element.
+    Head,
+
+    /// The content of the outermost element.
+    Content,
+
+    /// Everything after the end of the outermost element.
+    Tail,
+
+    /// All fragments have been returned.
+    Done,
+}
+
+/// The synchronous reader used for a fragment.
+#[cfg(feature = "async")]
+pub type Fragment<'a> = io::Chain<&'a [u8], &'a [u8]>;
+
+#[cfg(feature = "async")]
+impl<R: AsyncBufRead + Unpin> AsyncReader<R> {
+    /// Creates a new async reader from an underlying reader.
+    ///
+    /// The reader will not impose any limits on the document.
+    pub fn new(reader: R) -> Self {
+        Self::with_limits(reader, Limits::default())
+    }
+
+    /// Creates a new async reader that enforces the given limits.
+    pub fn with_limits(reader: R, limits: Limits) -> Self {
+        AsyncReader {
+            reader,
+            limits,
+            head: Vec::new(),
+            buf: Vec::new(),
+            returned: 0,
+            scanned: 0,
+            depth: 0,
+            part: Part::Head,
+            size: 0,
+            elements: 0,
+            eof: false,
+        }
+    }
+
+    /// Returns the limits imposed by the reader.
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Reads the next fragment of the document.
+    ///
+    /// Returns `Ok(None)` after the last fragment has been returned.
+    pub async fn next_fragment(
+        &mut self
+    ) -> Result<Option<Reader<Fragment<'_>>>, Error> {
+        self.buf.drain(..self.returned);
+        self.scanned -= self.returned;
+        self.returned = 0;
+        if self.part == Part::Done {
+            return Ok(None)
+        }
+        loop {
+            if let Some(end) = self.scan()? {
+                self.returned = end;
+                break
+            }
+            if self.eof {
+                self.part = Part::Done;
+                self.returned = self.buf.len();
+                break
+            }
+            self.fill().await?;
+        }
+        Ok(Some(Reader::with_limits(
+            io::Read::chain(
+                self.head.as_slice(), &self.buf[..self.returned]
+            ),
+            self.limits
+        )))
+    }
+
+    /// Reads more data into the buffer.
+    async fn fill(&mut self) -> Result<(), Error> {
+        let data = self.reader.fill_buf().await.map_err(|err| {
+            Error::from(quick_xml::Error::Io(err))
+        })?;
+        if data.is_empty() {
+            self.eof = true;
+            return Ok(())
+        }
+        let len = data.len();
+        self.size += len;
+        if let Some(max) = self.limits.max_document_size {
+            if self.size > max {
+                return Err(Error::DocumentTooLarge)
+            }
+        }
+        self.buf.extend_from_slice(data);
+        self.reader.consume(len);
+        Ok(())
+    }
+
+    /// Examines the buffered data for the end of a fragment.
+    ///
+    /// Returns the end of the fragment in the buffer if there is a
+    /// complete one.
+    fn scan(&mut self) -> Result<Option<usize>, Error> {
+        loop {
+            let data = &self.buf[self.scanned..];
+            if data.is_empty() {
+                return Ok(None)
+            }
+            if data[0] != b'<' {
+                match data.iter().position(|&ch| ch == b'<') {
+                    Some(pos) => self.scanned += pos,
+                    None => {
+                        self.scanned = self.buf.len();
+                        return Ok(None)
+                    }
+                }
+                continue
+            }
+            let (len, markup) = match Markup::scan(data) {
+                Some(res) => res,
+                None => return Ok(None)
+            };
+            self.scanned += len;
+            if self.part == Part::Tail {
+                continue
+            }
+            match markup {
+                Markup::Other => { }
+                Markup::Start | Markup::Empty => {
+                    self.elements += 1;
+                    if let Some(max) = self.limits.max_elements {
+                        if self.elements > max {
+                            return Err(Error::TooManyElements)
+                        }
+                    }
+                    if self.part == Part::Head {
+                        self.head = self.buf.drain(..self.scanned).collect();
+                        self.scanned = 0;
+                        if markup == Markup::Start {
+                            self.part = Part::Content;
+                            self.depth = 1;
+                        }
+                        else {
+                            self.part = Part::Tail;
+                        }
+                    }
+                    else if markup == Markup::Start {
+                        self.depth += 1;
+                    }
+                    else if self.depth == 1 {
+                        return Ok(Some(self.scanned))
+                    }
+                }
+                Markup::End => {
+                    if self.part == Part::Head {
+                        return Err(Error::Malformed)
+                    }
+                    self.depth -= 1;
+                    if self.depth == 1 {
+                        return Ok(Some(self.scanned))
+                    }
+                    else if self.depth == 0 {
+                        self.part = Part::Tail;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R> fmt::Debug for AsyncReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncReader")
+            .field("limits", &self.limits)
+            .field("size", &self.size)
+            .field("elements", &self.elements)
+            .finish()
+    }
+}
+
+
+//------------ Markup --------------------------------------------------------
+
+/// The kind of markup found by an async reader.
+#[cfg(feature = "async")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Markup {
+    /// A start tag.
+    Start,
+
+    /// An empty element tag.
+    Empty,
+
+    /// An end tag.
+    End,
+
+    /// A comment, CDATA section, processing instruction, or declaration.
+    Other,
+}
+
+#[cfg(feature = "async")]
+impl Markup {
+    /// Scans markup at the start of `data`.
+    ///
+    /// Returns the length and kind of the markup or `None` if `data`
+    /// doesn’t contain all of it.
+    fn scan(data: &[u8]) -> Option<(usize, Self)> {
+        const COMMENT: &[u8] = b"<!--";
+        const CDATA: &[u8] = b"<![CDATA[";
+
+        if data.starts_with(COMMENT) {
+            Self::find(data, COMMENT.len(), b"-->", Markup::Other)
+        }
+        else if data.starts_with(CDATA) {
+            Self::find(data, CDATA.len(), b"]]>", Markup::Other)
+        }
+        else if data.starts_with(b"<?") {
+            Self::find(data, 2, b"?>", Markup::Other)
+        }
+        else if COMMENT.starts_with(data) || CDATA.starts_with(data) {
+            // Can’t decide yet.
+            None
+        }
+        else if data.starts_with(b"<!") {
+            // A declaration. These can contain bracketed sections.
+            let mut brackets = 0usize;
+            data.iter().position(|&ch| match ch {
+                b'[' => { brackets += 1; false }
+                b']' => { brackets = brackets.saturating_sub(1); false }
+                b'>' => brackets == 0,
+                _ => false
+            }).map(|pos| (pos + 1, Markup::Other))
+        }
+        else if data.starts_with(b"</") {
+            Self::find(data, 2, b">", Markup::End)
+        }
+        else {
+            // A start tag. Ignore ‘>’ in quoted attribute values.
+            let mut quote = None;
+            let pos = data.iter().position(|&ch| match quote {
+                Some(q) => {
+                    if ch == q {
+                        quote = None
+                    }
+                    false
+                }
+                None => match ch {
+                    b'"' | b'\'' => { quote = Some(ch); false }
+                    b'>' => true,
+                    _ => false
+                }
+            })?;
+            if data[pos - 1] == b'/' {
+                Some((pos + 1, Markup::Empty))
+            }
+            else {
+                Some((pos + 1, Markup::Start))
+            }
+        }
+    }
+
+    /// Finds the end of markup with the given terminator.
+    fn find(
+        data: &[u8], start: usize, end: &[u8], markup: Self
+    ) -> Option<(usize, Self)> {
+        data[start..].windows(end.len()).position(|window| {
+            window == end
+        }).map(|pos| (start + pos + end.len(), markup))
+    }
+}
+
+
+//------------ Limits --------------------------------------------------------
+
+/// Limits imposed on an XML document during parsing.
+///
+/// Documents received from the network may be crafted to exhaust the
+/// resources of the parser. A value of this type describes how much a
+/// document is allowed to use. Each limit is optional and all limits are
+/// absent in the default value.
+///
+/// If a document exceeds one of the limits, parsing fails with an error
+/// that is distinct for each of the limits. The size limits are given in
+/// bytes of the document as it was received, i.e., before any escape
+/// sequences are resolved.
+///
+/// Only the document size is enforced while reading. All other limits are
+/// checked after the XML parser has produced the respective element or
+/// text, so the parser may have buffered text content or a start tag
+/// larger than the limit by the time it is rejected. In order to bound
+/// the memory used for parsing, the document size needs to be limited,
+/// too.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Limits {
+    /// The maximum number of elements in the document.
+    max_elements: Option<usize>,
+
+    /// The maximum size of the text content of an element.
+    max_object_size: Option<usize>,
+
+    /// The maximum size of the entire document.
+    max_document_size: Option<usize>,
+
+    /// The maximum nesting depth of elements.
+    max_depth: Option<usize>,
+
+    /// The maximum length of an attribute value.
+    max_attribute_len: Option<usize>,
+}
+
+impl Limits {
+    /// Creates a new value without any limits.
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Returns the maximum number of elements in the document.
+    pub fn max_elements(&self) -> Option<usize> {
+        self.max_elements
+    }
+
+    /// Sets the maximum number of elements in the document.
+    pub fn set_max_elements(&mut self, value: Option<usize>) {
+        self.max_elements = value
+    }
+
+    /// Returns the maximum size of the text content of an element.
+    pub fn max_object_size(&self) -> Option<usize> {
+        self.max_object_size
+    }
+
+    /// Sets the maximum size of the text content of an element.
+    ///
+    /// For RRDP and the publication protocol, this limits the size of the
+    /// Base64 encoded objects.
+    ///
+    /// The limit is checked only once the complete text has been read.
+    /// Use [`set_max_document_size`][Self::set_max_document_size] to limit
+    /// the amount of data buffered before that.
+    pub fn set_max_object_size(&mut self, value: Option<usize>) {
+        self.max_object_size = value
+    }
+
+    /// Returns the maximum size of the entire document.
+    pub fn max_document_size(&self) -> Option<usize> {
+        self.max_document_size
+    }
+
+    /// Sets the maximum size of the entire document.
+    pub fn set_max_document_size(&mut self, value: Option<usize>) {
+        self.max_document_size = value
+    }
+
+    /// Returns the maximum nesting depth of elements.
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    /// Sets the maximum nesting depth of elements.
+    ///
+    /// The outermost element has a depth of one.
+    pub fn set_max_depth(&mut self, value: Option<usize>) {
+        self.max_depth = value
+    }
+
+    /// Returns the maximum length of an attribute value.
+    pub fn max_attribute_len(&self) -> Option<usize> {
+        self.max_attribute_len
+    }
+
+    /// Sets the maximum length of an attribute value.
+    pub fn set_max_attribute_len(&mut self, value: Option<usize>) {
+        self.max_attribute_len = value
+    }
+
+    /// Checks an event against the limits.
+    ///
+    /// Updates the element count and nesting depth along the way.
+    fn check_event(
+        &self, event: &Event, elements: &mut usize, depth: &mut usize
+    ) -> Result<(), Error> {
+        match *event {
+            Event::Start(ref start) => {
+                self.check_start(start, elements, *depth + 1)?;
+                *depth += 1;
+            }
+            Event::Empty(ref start) => {
+                self.check_start(start, elements, *depth + 1)?;
+            }
+            Event::End(_) => {
+                *depth = depth.saturating_sub(1);
+            }
+            Event::Text(ref text) | Event::CData(ref text)
+                if exceeds(text.escaped().len(), self.max_object_size) =>
+            {
+                return Err(Error::ObjectTooLarge)
+            }
+            _ => { }
+        }
+        Ok(())
+    }
+
+    /// Checks the start of an element against the limits.
+    fn check_start(
+        &self, start: &BytesStart, elements: &mut usize, depth: usize
+    ) -> Result<(), Error> {
+        *elements += 1;
+        if exceeds(*elements, self.max_elements) {
+            return Err(Error::TooManyElements)
+        }
+        if exceeds(depth, self.max_depth) {
+            return Err(Error::TooDeep)
+        }
+        if self.max_attribute_len.is_some() {
+            for attr in start.attributes().flatten() {
+                if exceeds(attr.value.len(), self.max_attribute_len) {
+                    return Err(Error::AttributeTooLong)
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether `value` exceeds the optional `limit`.
+fn exceeds(value: usize, limit: Option<usize>) -> bool {
+    match limit {
+        Some(limit) => value > limit,
+        None => false
+    }
+}
+
+
+//------------ LimitedRead ---------------------------------------------------
+
+/// A buffered reader that fails if more than a given amount is read.
+struct LimitedRead<R> {
+    /// The underlying reader.
+    reader: R,
+
+    /// The number of bytes that may still be read, if limited.
+    remaining: Option<usize>,
+}
+
+impl<R> LimitedRead<R> {
+    fn new(reader: R, limit: Option<usize>) -> Self {
+        LimitedRead { reader, remaining: limit }
+    }
+}
+
+impl<R: io::Read> io::Read for LimitedRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let len = match self.remaining {
+            Some(0) => {
+                let mut probe = [0u8];
+                if self.reader.read(&mut probe)? == 0 {
+                    return Ok(0)
+                }
+                return Err(DocumentTooLarge.into())
+            }
+            Some(remaining) => buf.len().min(remaining),
+            None => buf.len()
+        };
+        let res = self.reader.read(&mut buf[..len])?;
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining -= res;
+        }
+        Ok(res)
+    }
+}
+
+impl<R: io::BufRead> io::BufRead for LimitedRead<R> {
+    fn fill_buf(&mut self) -> Result<&[u8], io::Error> {
+        let buf = self.reader.fill_buf()?;
+        match self.remaining {
+            Some(_) if buf.is_empty() => Ok(buf),
+            Some(0) => Err(DocumentTooLarge.into()),
+            Some(remaining) => Ok(&buf[..buf.len().min(remaining)]),
+            None => Ok(buf)
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt);
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining = remaining.saturating_sub(amt);
+        }
+    }
+}
+
+
+//------------ DocumentTooLarge ----------------------------------------------
+
+/// The I/O error payload used when the document size limit is exceeded.
+#[derive(Debug)]
+struct DocumentTooLarge;
+
+impl From<DocumentTooLarge> for io::Error {
+    fn from(err: DocumentTooLarge) -> Self {
+        io::Error::new(io::ErrorKind::Other, err)
+    }
+}
+
+impl fmt::Display for DocumentTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("document too large")
+    }
+}
+
+impl error::Error for DocumentTooLarge { }
+
+
 //------------ Element -------------------------------------------------------
 
 /// The start of an element.
@@ -129,10 +756,7 @@ impl Content {
         }
 
         loop {
-            reader.buf.clear();
-            let (ns, event) = reader.reader.read_namespaced_event(
-                &mut reader.buf, &mut reader.ns_buf
-            ).map_err(Into::into)?;
+            let (ns, event) = reader.read_namespaced_event()?;
             match event {
                 Event::Start(start) => {
                     op(Element::new(start, ns))?;
@@ -167,10 +791,7 @@ impl Content {
         }
 
         loop {
-            reader.buf.clear();
-            let (ns, event) = reader.reader.read_namespaced_event(
-                &mut reader.buf, &mut reader.ns_buf
-            ).map_err(Into::into)?;
+            let (ns, event) = reader.read_namespaced_event()?;
             match event {
                 Event::Start(start) => {
                     op(Element::new(start, ns))?;
@@ -209,10 +830,7 @@ impl Content {
         }
 
         loop {
-            reader.buf.clear();
-            let event = reader.reader.read_event(
-                &mut reader.buf
-            ).map_err(Into::into)?;
+            let event = reader.read_event()?;
             match event {
                 Event::Text(text) => {
                     return op(Text(text))
@@ -232,8 +850,7 @@ impl Content {
         }
 
         loop {
-            reader.buf.clear();
-            match reader.reader.read_event(&mut reader.buf)? {
+            match reader.read_event()? {
                 Event::End(_) => {
                     self.empty = true;
                     return Ok(())
@@ -259,10 +876,7 @@ impl Content {
         }
 
         loop {
-            reader.buf.clear();
-            let event = reader.reader.read_event(
-                &mut reader.buf
-            ).map_err(Into::into)?;
+            let event = reader.read_event()?;
             match event {
                 Event::Text(text) => {
                     let res = op(Text(text))?;
@@ -292,10 +906,7 @@ impl Content {
         }
 
         loop {
-            reader.buf.clear();
-            let event = reader.reader.read_event(
-                &mut reader.buf
-            )?;
+            let event = reader.read_event()?;
             match event {
                 Event::Text(_text) => {
                     self.take_end(reader)?;
@@ -461,10 +1072,32 @@ impl<'a> Text<'a> {
 pub enum Error {
     Xml(quick_xml::Error),
     Malformed,
+
+    /// The document contains more elements than allowed.
+    TooManyElements,
+
+    /// The text content of an element is larger than allowed.
+    ObjectTooLarge,
+
+    /// The document is larger than allowed.
+    DocumentTooLarge,
+
+    /// Elements are nested deeper than allowed.
+    TooDeep,
+
+    /// An attribute value is longer than allowed.
+    AttributeTooLong,
 }
 
 impl From<quick_xml::Error> for Error {
     fn from(err: quick_xml::Error) -> Self {
+        if let quick_xml::Error::Io(ref err) = err {
+            if err.get_ref().map(|err| err.is::<DocumentTooLarge>())
+                .unwrap_or(false)
+            {
+                return Error::DocumentTooLarge
+            }
+        }
         Error::Xml(err)
     }
 }
@@ -474,6 +1107,15 @@ impl fmt::Display for Error {
         match *self {
             Error::Xml(ref err) => err.fmt(f),
             Error::Malformed => f.write_str("malformed XML"),
+            Error::TooManyElements => f.write_str("too many XML elements"),
+            Error::ObjectTooLarge => {
+                f.write_str("XML text content too large")
+            }
+            Error::DocumentTooLarge => f.write_str("XML document too large"),
+            Error::TooDeep => f.write_str("XML elements nested too deeply"),
+            Error::AttributeTooLong => {
+                f.write_str("XML attribute value too long")
+            }
         }
     }
 }
@@ -3,6 +3,8 @@ use std::io;
 use std::io::Write as _;
 use std::fmt::Write as _;
 use super::decode::Name;
+#[cfg(feature = "async")]
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 
 //------------ Writer --------------------------------------------------------
@@ -134,6 +136,134 @@ impl<W: io::Write> io::Write for Writer<W> {
 }
 
 
+//------------ AsyncWriter ---------------------------------------------------
+
+/// Wraps an async writer for producing XML.
+///
+/// Since elements are written via closures, the XML is first written into
+/// a buffer using a regular [`Writer`] and then written to the async
+/// writer via [`write_buffered`][Self::write_buffered]. In order to be
+/// able to write large documents without keeping all of it in memory,
+/// elements can be started and ended explicitly via
+/// [`start_element`][Self::start_element] and
+/// [`end_element`][Self::end_element], with their content being added
+/// piecemeal via [`content`][Self::content].
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub struct AsyncWriter<W> {
+    /// The wrapped async writer.
+    target: W,
+
+    /// The writer for the buffer.
+    writer: Writer<Vec<u8>>,
+
+    /// The tags of all elements started but not yet ended.
+    open: Vec<Name<'static, 'static>>,
+}
+
+#[cfg(feature = "async")]
+impl<W: AsyncWrite + Unpin> AsyncWriter<W> {
+    /// Creates a new XML writer by wrapping an async writer.
+    ///
+    /// The writer will use an indent string of two spaces.
+    pub fn new(target: W) -> Self {
+        AsyncWriter {
+            target,
+            writer: Writer::new(Vec::new()),
+            open: Vec::new(),
+        }
+    }
+
+    /// Change the indent string.
+    pub fn set_indent(&mut self, s: &'static str) {
+        self.writer.set_indent(s)
+    }
+
+    /// Starts an element.
+    ///
+    /// The closure receives the new element for adding attributes. The
+    /// start tag is completed once it returns. The element stays open
+    /// until [`end_element`][Self::end_element] is called.
+    pub fn start_element<'s>(
+        &'s mut self,
+        tag: Name<'static, 'static>,
+        op: impl FnOnce(
+            Element<'s, Vec<u8>>
+        ) -> Result<Element<'s, Vec<u8>>, io::Error>,
+    ) -> Result<(), io::Error> {
+        op(Element::start(&mut self.writer, tag, false)?)?.open()?;
+        self.open.push(tag);
+        Ok(())
+    }
+
+    /// Adds content to the most recently started element.
+    ///
+    /// If no element has been started, the content is added at the top
+    /// level of the document.
+    pub fn content(
+        &mut self,
+        op: impl FnOnce(&mut Content<Vec<u8>>) -> Result<(), io::Error>
+    ) -> Result<(), io::Error> {
+        op(&mut Content { writer: &mut self.writer, inline: false })?;
+        self.check_error()
+    }
+
+    /// Ends the most recently started element.
+    pub fn end_element(&mut self) -> Result<(), io::Error> {
+        let tag = match self.open.pop() {
+            Some(tag) => tag,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput, "no open XML element"
+                ))
+            }
+        };
+        self.writer.dedent();
+        let mut element = std::mem::ManuallyDrop::new(Element {
+            writer: &mut self.writer, tag, inline: false, empty: false
+        });
+        element.end()
+    }
+
+    /// Returns the number of bytes currently buffered.
+    pub fn buffered(&self) -> usize {
+        self.writer.wrapped.len()
+    }
+
+    /// Writes all buffered data to the async writer.
+    pub async fn write_buffered(&mut self) -> Result<(), io::Error> {
+        self.check_error()?;
+        self.target.write_all(&self.writer.wrapped).await?;
+        self.writer.wrapped.clear();
+        Ok(())
+    }
+
+    /// Concludes writing and returns the async writer.
+    ///
+    /// All remaining buffered data is written and the async writer is
+    /// flushed. Returns an error if there are still elements that have
+    /// not been ended.
+    pub async fn done(mut self) -> Result<W, io::Error> {
+        if !self.open.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput, "unclosed XML element"
+            ))
+        }
+        self.write_buffered().await?;
+        self.target.flush().await?;
+        Ok(self.target)
+    }
+
+    /// Returns an error stored by the buffer’s writer.
+    fn check_error(&mut self) -> Result<(), io::Error> {
+        match self.writer.error.take() {
+            Some(err) => Err(err),
+            None => Ok(())
+        }
+    }
+}
+
+
 //------------ Element -------------------------------------------------------
 
 /// An XML element in the process of being written.
@@ -215,6 +345,17 @@ impl<'a, W: io::Write> Element<'a, W> {
         Ok(self)
     }
 
+    /// Completes the start tag and leaves the element open.
+    ///
+    /// The element’s end tag has to be written separately.
+    #[cfg(feature = "async")]
+    fn open(self) -> Result<(), io::Error> {
+        let mut element = std::mem::ManuallyDrop::new(self);
+        element.writer.write_all(b">")?;
+        element.writer.indent();
+        Ok(())
+    }
+
     /// Writes the end of the element.
     fn end(&mut self) -> Result<(), io::Error> {
         if self.empty {
@@ -351,9 +492,12 @@ pub trait Text {
     fn write_base64(
         &self, target: &mut impl io::Write
     ) -> Result<(), io::Error> {
-        self.write_raw(
-            &mut base64::write::EncoderWriter::new(target, base64::STANDARD)
-        )
+        let mut encoder = base64::write::EncoderWriter::new(
+            WriteAll(target), base64::STANDARD
+        );
+        self.write_raw(&mut encoder)?;
+        encoder.finish()?;
+        Ok(())
     }
 }
 
@@ -515,3 +659,25 @@ impl TextEscape {
         Ok(())
     }
 }
+
+
+//------------ WriteAll ------------------------------------------------------
+
+/// A writer that always writes all data to the underlying writer.
+///
+/// The base64 encoder returns `Ok(0)` from its `write` method if the
+/// underlying writer didn’t accept all previous data in one go which makes
+/// `write_all` fail. This wrapper avoids this by never accepting data only
+/// partially.
+struct WriteAll<W>(W);
+
+impl<W: io::Write> io::Write for WriteAll<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        self.0.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        self.0.flush()
+    }
+}